@@ -0,0 +1,15 @@
+//! Compares encoder presets on the synthetic sample images, plus any PNG
+//! files given as arguments: `cargo bench --features bench -- a.png b.png`
+
+use png::bench::{compare_presets, load_image, presets, sample_images};
+
+fn main() -> Result<(), png::PngError> {
+    let mut images = sample_images(512, 512)?;
+    for arg in std::env::args().skip(1).filter(|a| !a.starts_with("--")) {
+        images.push(load_image(arg)?);
+    }
+    for result in compare_presets(&images, &presets(), 5)? {
+        println!("{}", result);
+    }
+    Ok(())
+}
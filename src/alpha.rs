@@ -0,0 +1,149 @@
+use crate::color::Color;
+use crate::decoder::expand_transparency;
+use crate::error::PngError;
+use crate::{BitDepth, ColorType, PngImage};
+
+impl PngImage {
+    /// A grayscale image of the alpha channel, at the same bit depth (8 for
+    /// indexed images, whose alpha comes from the palette). Images without
+    /// alpha give a fully opaque mask.
+    pub fn extract_alpha(&self) -> Result<PngImage, PngError> {
+        self.check_complete()?;
+        let mut source = self.clone();
+        if source.color_type == ColorType::Indexed {
+            source.expand_palette()?;
+        }
+        let bit_depth = source.bit_depth;
+        let mut mask =
+            PngImage::with_bit_depth(self.width, self.height, ColorType::Grayscale, bit_depth)?;
+
+        let sample_size = bit_depth.bytes_per_sample();
+        let channels = source.color_type.channels();
+        match source.color_type {
+            ColorType::GrayscaleAlpha | ColorType::Rgba => {
+                for pixel in source.data.chunks_exact(channels * sample_size) {
+                    mask.data
+                        .extend_from_slice(&pixel[(channels - 1) * sample_size..])?;
+                }
+            }
+            _ => {
+                let opaque = bit_depth.max_value();
+                let sample = if bit_depth == BitDepth::Sixteen {
+                    opaque.to_be_bytes().to_vec()
+                } else {
+                    vec![opaque as u8]
+                };
+                mask.data
+                    .replace(sample.repeat(self.width as usize * self.height as usize))?;
+            }
+        }
+        Ok(mask)
+    }
+
+    /// Replaces the alpha channel with the samples of `mask`, a grayscale
+    /// image of the same size at any bit depth. Images without alpha gain
+    /// it first, with indexed images expanded to RGBA and lower bit depths
+    /// raised to 8.
+    pub fn apply_alpha_mask(&mut self, mask: &PngImage) -> Result<(), PngError> {
+        if mask.color_type != ColorType::Grayscale {
+            return Err(PngError::ColorTypeError);
+        }
+        if (mask.width, mask.height) != (self.width, self.height) {
+            return Err(PngError::ImageSizeMismatch {
+                expected: (self.width, self.height),
+                actual: (mask.width, mask.height),
+            });
+        }
+        self.check_complete()?;
+        mask.check_complete()?;
+
+        match self.color_type {
+            ColorType::Grayscale => self.convert_to(ColorType::GrayscaleAlpha)?,
+            ColorType::Rgb | ColorType::Indexed => self.convert_to(ColorType::Rgba)?,
+            _ => {}
+        }
+
+        let max = self.bit_depth.max_value() as u32;
+        let mask_max = mask.bit_depth.max_value() as u32;
+        let alpha = mask
+            .data
+            .chunks_exact(mask.bit_depth.bytes_per_sample())
+            .map(|sample| {
+                let value = match sample {
+                    [hi, lo] => u16::from_be_bytes([*hi, *lo]) as u32,
+                    _ => sample[0] as u32,
+                };
+                ((value * max + mask_max / 2) / mask_max) as u16
+            });
+
+        let sample_size = self.bit_depth.bytes_per_sample();
+        let pixel_size = self.bytes_per_pixel();
+        for (pixel, alpha) in self.data.chunks_exact_mut(pixel_size).zip(alpha) {
+            let sample = &mut pixel[pixel_size - sample_size..];
+            if sample_size == 2 {
+                sample.copy_from_slice(&alpha.to_be_bytes());
+            } else {
+                sample[0] = alpha as u8;
+            }
+        }
+        Ok(())
+    }
+
+    /// Converts the image to RGBA and makes every pixel whose red, green,
+    /// and blue are each within `tolerance` of `key` fully transparent,
+    /// as for sprite sheets on a magenta background. `key`'s alpha is
+    /// ignored and `tolerance` is in 8-bit units, scaled for 16-bit images.
+    pub fn color_key_to_alpha(&mut self, key: Color, tolerance: u8) -> Result<(), PngError> {
+        let (r, g, b, _) = key.to_rgba().ok_or(PngError::ColorTypeError)?;
+        self.check_complete()?;
+        self.convert_to(ColorType::Rgba)?;
+
+        let sixteen = self.bit_depth == BitDepth::Sixteen;
+        let scale = if sixteen { 257 } else { 1 };
+        let key = [r, g, b].map(|v| v as i32 * scale);
+        let tolerance = tolerance as i32 * scale;
+        let pixel_size = self.bytes_per_pixel();
+        for pixel in self.data.chunks_exact_mut(pixel_size) {
+            let matches = (0..3).all(|c| {
+                let value = if sixteen {
+                    u16::from_be_bytes([pixel[c * 2], pixel[c * 2 + 1]]) as i32
+                } else {
+                    pixel[c] as i32
+                };
+                (value - key[c]).abs() <= tolerance
+            });
+            if matches {
+                let alpha = if sixteen { 6 } else { 3 };
+                pixel[alpha..].fill(0);
+            }
+        }
+        Ok(())
+    }
+
+    /// Composites the image over `background` and drops its alpha, leaving
+    /// grayscale or RGB. Palette alpha and tRNS transparent colors are
+    /// flattened too; opaque images are left alone.
+    pub fn flatten(&mut self, background: Color) -> Result<(), PngError> {
+        background.to_rgba().ok_or(PngError::ColorTypeError)?;
+        let key = self
+            .chunks
+            .iter()
+            .find(|c| &c.chunk_type == b"tRNS")
+            .map(|c| c.data.clone());
+        let color_type = match self.color_type {
+            ColorType::Grayscale | ColorType::Rgb => {
+                let Some(key) = key else {
+                    return Ok(());
+                };
+                let color_type = self.color_type;
+                self.chunks.retain(|c| &c.chunk_type != b"tRNS");
+                expand_transparency(self, &key)?;
+                color_type
+            }
+            ColorType::Indexed if key.is_none() => return Ok(()),
+            ColorType::GrayscaleAlpha => ColorType::Grayscale,
+            _ => ColorType::Rgb,
+        };
+        self.convert_to_over(color_type, background)
+    }
+}
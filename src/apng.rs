@@ -0,0 +1,825 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+use crate::chunks::{Chunk, ChunkReader, ChunkWriter, PNG_SIGNATURE};
+use crate::decoder::decompress_image_data;
+use crate::error::PngError;
+use crate::indexed::{median_cut, nearest_entry};
+use crate::info::ImageHeader;
+use crate::limits::DecodeLimits;
+use crate::options::EncodeOptions;
+use crate::progress::ProgressHook;
+use crate::report::AnimationSize;
+use crate::{image_size, BitDepth, ColorType, PngImage};
+
+/// What happens to a frame's region before the next frame is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisposeOp {
+    /// Leave the canvas as it is
+    None,
+    /// Clear the region to fully transparent black
+    Background,
+    /// Restore the region to what it was before this frame
+    Previous,
+}
+
+/// How a frame is combined with the canvas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendOp {
+    /// Overwrite the region, including alpha
+    Source,
+    /// Alpha-composite the frame over the region
+    Over,
+}
+
+/// One frame of an animated PNG, positioned on the animation's canvas.
+#[derive(Clone)]
+pub struct Frame {
+    pub image: PngImage,
+    pub x_offset: u32,
+    pub y_offset: u32,
+    /// The frame is shown for `delay_num / delay_den` seconds; a zero
+    /// denominator means 1/100 s units
+    pub delay_num: u16,
+    pub delay_den: u16,
+    pub dispose_op: DisposeOp,
+    pub blend_op: BlendOp,
+}
+
+impl Frame {
+    /// A frame at the canvas origin shown for `delay_ms` milliseconds.
+    pub fn new(image: PngImage, delay_ms: u16) -> Self {
+        Self {
+            image,
+            x_offset: 0,
+            y_offset: 0,
+            delay_num: delay_ms,
+            delay_den: 1000,
+            dispose_op: DisposeOp::None,
+            blend_op: BlendOp::Source,
+        }
+    }
+
+    /// Bytes this frame adds to an encoded animation: its fcTL chunk and
+    /// its compressed pixels in an fdAT chunk (or IDAT for the first frame,
+    /// 4 bytes less). Capture tools can total these as frames arrive to
+    /// watch the file size without re-encoding earlier frames.
+    pub fn encoded_size(&self, options: &EncodeOptions) -> Result<u64, PngError> {
+        let compressed = self.image.compress_image_data(options)?.len() as u64;
+        Ok(CHUNK_OVERHEAD + FCTL_LENGTH + CHUNK_OVERHEAD + SEQUENCE_LENGTH + compressed)
+    }
+
+    /// The display time in milliseconds.
+    pub fn delay_ms(&self) -> f64 {
+        let den = if self.delay_den == 0 {
+            100
+        } else {
+            self.delay_den
+        };
+        self.delay_num as f64 * 1000.0 / den as f64
+    }
+}
+
+/// An animated PNG (APNG). Every frame shares the first frame's color type
+/// and bit depth, and the first frame covers the whole canvas so that
+/// viewers without APNG support show it as a still image.
+#[derive(Clone)]
+pub struct Animation {
+    width: u32,
+    height: u32,
+    /// Times to play the animation; 0 loops forever
+    pub num_plays: u32,
+    frames: Vec<Frame>,
+}
+
+impl Animation {
+    pub fn new(width: u32, height: u32) -> Result<Self, PngError> {
+        if width == 0 || height == 0 || width > 0x7FFF_FFFF || height > 0x7FFF_FFFF {
+            return Err(PngError::InvalidDimensions(width, height));
+        }
+        Ok(Self {
+            width,
+            height,
+            num_plays: 0,
+            frames: Vec::new(),
+        })
+    }
+
+    /// Builds an animation whose canvas is the size of the first frame.
+    pub fn from_frames(frames: Vec<Frame>) -> Result<Self, PngError> {
+        let first = frames.first().ok_or_else(|| {
+            PngError::Animation("An animation needs at least one frame".to_string())
+        })?;
+        let mut animation = Self::new(first.image.width, first.image.height)?;
+        for frame in frames {
+            animation.add_frame(frame)?;
+        }
+        Ok(animation)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// How many frames [`Animation::write_with_options`] writes, once
+    /// repeated frames are merged into the one before them.
+    pub fn written_frame_count(&self) -> Result<usize, PngError> {
+        Ok(self.merge_repeats()?.len())
+    }
+
+    fn check_bounds(&self, frame: &Frame) -> Result<(), PngError> {
+        let image = &frame.image;
+        let fits_x = frame.x_offset as u64 + image.width as u64 <= self.width as u64;
+        let fits_y = frame.y_offset as u64 + image.height as u64 <= self.height as u64;
+        if !fits_x || !fits_y {
+            return Err(PngError::Animation(format!(
+                "{}x{} frame at ({}, {}) does not fit the {}x{} canvas",
+                image.width, image.height, frame.x_offset, frame.y_offset, self.width, self.height
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn add_frame(&mut self, frame: Frame) -> Result<(), PngError> {
+        self.check_bounds(&frame)?;
+        let image = &frame.image;
+
+        match self.frames.first() {
+            None => {
+                if (frame.x_offset, frame.y_offset) != (0, 0)
+                    || (image.width, image.height) != (self.width, self.height)
+                {
+                    return Err(PngError::Animation(
+                        "The first frame must cover the whole canvas".to_string(),
+                    ));
+                }
+            }
+            Some(first) => {
+                let first = &first.image;
+                if (image.color_type, image.bit_depth) != (first.color_type, first.bit_depth) {
+                    return Err(PngError::Animation(format!(
+                        "Frame is {:?} at {} bits, but the animation is {:?} at {} bits",
+                        image.color_type,
+                        image.bit_depth.bits(),
+                        first.color_type,
+                        first.bit_depth.bits()
+                    )));
+                }
+                if image.palette() != first.palette() {
+                    return Err(PngError::InvalidPalette(
+                        "Every frame must use the first frame's palette".to_string(),
+                    ));
+                }
+            }
+        }
+
+        self.frames.push(frame);
+        Ok(())
+    }
+
+    pub fn write_to_file<W: Write>(&self, writer: &mut W) -> Result<(), PngError> {
+        self.write_with_options(writer, &EncodeOptions::default())
+    }
+
+    /// Encodes the animation. A frame that repeats the one before it, and
+    /// would leave the canvas as it is, isn't encoded again; the earlier
+    /// frame is shown for both delays instead, when the sum fits fcTL's
+    /// 16-bit fraction.
+    pub fn write_with_options<W: Write>(
+        &self,
+        writer: &mut W,
+        options: &EncodeOptions,
+    ) -> Result<(), PngError> {
+        let first = &self
+            .frames
+            .first()
+            .ok_or_else(|| {
+                PngError::Animation("An animation needs at least one frame".to_string())
+            })?
+            .image;
+        for frame in &self.frames {
+            frame.image.check_samples(&frame.image.data)?;
+        }
+        if first.color_type == ColorType::Indexed {
+            let Some(palette) = &first.palette else {
+                return Err(PngError::InvalidPalette(
+                    "Palette required for indexed color".to_string(),
+                ));
+            };
+            // Only the first frame's palette is written, so every frame's
+            // indices must fit it
+            for frame in &self.frames {
+                frame.image.check_indices(palette.len() / 3)?;
+            }
+        }
+
+        writer.write_all(&PNG_SIGNATURE)?;
+        ChunkWriter::write_chunk(writer, b"IHDR", &first.generate_ihdr())?;
+
+        let frames = self.merge_repeats()?;
+        let mut actl = Vec::with_capacity(8);
+        actl.extend_from_slice(&(frames.len() as u32).to_be_bytes());
+        actl.extend_from_slice(&self.num_plays.to_be_bytes());
+        ChunkWriter::write_chunk(writer, b"acTL", &actl)?;
+
+        if let Some(palette) = &first.palette {
+            ChunkWriter::write_chunk(writer, b"PLTE", palette)?;
+        }
+        // One tRNS covers every frame, whether palette alpha or a color key
+        if let Some(transparency) = first.chunks.iter().find(|c| &c.chunk_type == b"tRNS") {
+            ChunkWriter::write_chunk(writer, b"tRNS", &transparency.data)?;
+        }
+
+        // fcTL and fdAT chunks share one sequence counter
+        let mut sequence = 0u32;
+        for (i, written) in frames.iter().enumerate() {
+            ChunkWriter::write_chunk(writer, b"fcTL", &written.frame_control(sequence))?;
+            sequence += 1;
+
+            let compressed = written.frame.image.compress_image_data(options)?;
+            if i == 0 {
+                ChunkWriter::write_chunk(writer, b"IDAT", &compressed)?;
+            } else {
+                let mut fdat = Vec::with_capacity(compressed.len() + 4);
+                fdat.extend_from_slice(&sequence.to_be_bytes());
+                fdat.extend_from_slice(&compressed);
+                ChunkWriter::write_chunk(writer, b"fdAT", &fdat)?;
+                sequence += 1;
+            }
+        }
+
+        ChunkWriter::write_chunk(writer, b"IEND", &[])?;
+        Ok(())
+    }
+
+    /// Compresses every frame as [`Animation::write_with_options`] would,
+    /// without writing anything, and reports the size of each frame and of
+    /// the whole stream. Repeated frames merged into the one before them
+    /// aren't listed.
+    pub fn estimate_size(&self, options: &EncodeOptions) -> Result<AnimationSize, PngError> {
+        let first = &self
+            .frames
+            .first()
+            .ok_or_else(|| {
+                PngError::Animation("An animation needs at least one frame".to_string())
+            })?
+            .image;
+        let mut frame_bytes = self
+            .merge_repeats()?
+            .iter()
+            .map(|written| written.frame.encoded_size(options))
+            .collect::<Result<Vec<_>, _>>()?;
+        // The first frame's data is in IDAT, which has no sequence number
+        frame_bytes[0] -= SEQUENCE_LENGTH;
+
+        let transparency = first
+            .chunks
+            .iter()
+            .find(|c| &c.chunk_type == b"tRNS")
+            .map_or(0, |c| CHUNK_OVERHEAD + c.data.len() as u64);
+        let palette = first
+            .palette
+            .as_ref()
+            .map_or(0, |p| CHUNK_OVERHEAD + p.len() as u64);
+        let total_bytes = PNG_SIGNATURE.len() as u64
+            + (CHUNK_OVERHEAD + 13)
+            + (CHUNK_OVERHEAD + 8)
+            + palette
+            + transparency
+            + frame_bytes.iter().sum::<u64>()
+            + CHUNK_OVERHEAD;
+
+        Ok(AnimationSize {
+            frame_bytes,
+            total_bytes,
+            duration_ms: self.frames.iter().map(Frame::delay_ms).sum(),
+        })
+    }
+
+    // The frames to write, with each run of identical frames folded into
+    // its first. A repeat is only dropped when drawing it again couldn't
+    // change the canvas: the frame before it isn't disposed of, and it
+    // either replaces its region or has no partly transparent pixels to
+    // compound.
+    fn merge_repeats(&self) -> Result<Vec<WrittenFrame<'_>>, PngError> {
+        let mut written: Vec<WrittenFrame> = Vec::with_capacity(self.frames.len());
+        for frame in &self.frames {
+            if let Some(last) = written.last_mut() {
+                let repeats = last.dispose_op == DisposeOp::None
+                    && (frame.x_offset, frame.y_offset)
+                        == (last.frame.x_offset, last.frame.y_offset)
+                    && frame.blend_op == last.frame.blend_op
+                    && frame.image == last.frame.image
+                    && (frame.blend_op == BlendOp::Source || !has_partial_alpha(&frame.image)?);
+                let delay = add_delays(
+                    (last.delay_num, last.delay_den),
+                    (frame.delay_num, frame.delay_den),
+                );
+                if let (true, Some((delay_num, delay_den))) = (repeats, delay) {
+                    last.delay_num = delay_num;
+                    last.delay_den = delay_den;
+                    last.dispose_op = frame.dispose_op;
+                    continue;
+                }
+            }
+            written.push(WrittenFrame {
+                frame,
+                delay_num: frame.delay_num,
+                delay_den: frame.delay_den,
+                dispose_op: frame.dispose_op,
+            });
+        }
+        Ok(written)
+    }
+
+    /// Reads an APNG. A plain PNG is returned as a one-frame animation, and
+    /// a default image that isn't part of the animation is skipped.
+    pub fn read_from_file<R: Read>(reader: R) -> Result<Self, PngError> {
+        let mut chunks = ChunkReader::new(reader)?;
+
+        let mut header: Option<ImageHeader> = None;
+        let mut palette = None;
+        let mut transparency = None;
+        let mut num_plays = 0;
+        let mut animated = false;
+        let mut next_sequence = 0;
+        // The frame being collected and its compressed data
+        let mut pending: Option<(FrameControl, Vec<u8>)> = None;
+        let mut default_image = Vec::new();
+        let mut frames = Vec::new();
+
+        while let Some(chunk) = chunks.next_chunk()? {
+            chunk.verify_crc()?;
+
+            match &chunk.chunk_type {
+                b"IHDR" => header = Some(ImageHeader::parse(&chunk.data)?),
+                b"PLTE" => palette = Some(chunk.data),
+                b"tRNS" => transparency = Some(chunk.data),
+                b"acTL" => {
+                    if chunk.data.len() != 8 {
+                        return Err(PngError::Decode("acTL must be 8 bytes".to_string()));
+                    }
+                    animated = true;
+                    num_plays = be_u32(&chunk.data[4..8]);
+                }
+                b"fcTL" => {
+                    let control = FrameControl::parse(&chunk.data)?;
+                    check_sequence(control.sequence, &mut next_sequence)?;
+                    if let Some(done) = pending.take() {
+                        frames.push(done);
+                    }
+                    pending = Some((control, Vec::new()));
+                }
+                b"IDAT" => match pending.as_mut() {
+                    Some((_, data)) => data.extend_from_slice(&chunk.data),
+                    None => default_image.extend_from_slice(&chunk.data),
+                },
+                b"fdAT" => {
+                    if chunk.data.len() < 4 {
+                        return Err(PngError::Decode("fdAT chunk is truncated".to_string()));
+                    }
+                    check_sequence(be_u32(&chunk.data[0..4]), &mut next_sequence)?;
+                    let (_, data) = pending.as_mut().ok_or(PngError::UnexpectedChunk {
+                        chunk_type: chunk.chunk_type,
+                        offset: chunk.offset,
+                        reason: "fdAT without a preceding fcTL",
+                    })?;
+                    data.extend_from_slice(&chunk.data[4..]);
+                }
+                _ => {}
+            }
+        }
+        if let Some(done) = pending.take() {
+            frames.push(done);
+        }
+
+        let header = header.ok_or(PngError::MissingChunk {
+            chunk_type: *b"IHDR",
+        })?;
+        let decode_frame = |width: u32, height: u32, data: &[u8]| -> Result<PngImage, PngError> {
+            DecodeLimits::default().check_dimensions(width, height)?;
+            let mut image =
+                PngImage::with_bit_depth(width, height, header.color_type, header.bit_depth)?;
+            if header.color_type == ColorType::Indexed {
+                let palette = palette.as_ref().ok_or(PngError::MissingChunk {
+                    chunk_type: *b"PLTE",
+                })?;
+                image.set_palette(palette)?;
+                if let Some(alpha) = &transparency {
+                    image.chunks.push(Chunk::new(*b"tRNS", alpha.clone()));
+                }
+            }
+            let frame_header = ImageHeader {
+                width,
+                height,
+                ..header
+            };
+            image.data =
+                decompress_image_data(&frame_header, data, &mut ProgressHook::new())?.into();
+            Ok(image)
+        };
+
+        let mut animation = Animation::new(header.width, header.height)?;
+        animation.num_plays = num_plays;
+
+        if !animated || frames.is_empty() {
+            let image = decode_frame(header.width, header.height, &default_image)?;
+            animation.frames.push(Frame::new(image, 0));
+            return Ok(animation);
+        }
+
+        for (control, data) in frames {
+            let image = decode_frame(control.width, control.height, &data)?;
+            let frame = Frame {
+                image,
+                x_offset: control.x_offset,
+                y_offset: control.y_offset,
+                delay_num: control.delay_num,
+                delay_den: control.delay_den,
+                dispose_op: control.dispose_op,
+                blend_op: control.blend_op,
+            };
+            animation.check_bounds(&frame)?;
+            animation.frames.push(frame);
+        }
+        Ok(animation)
+    }
+
+    /// Converts every frame to 8-bit indexed color with one shared palette,
+    /// chosen from the colors of all frames together so that no color
+    /// shifts from one frame to the next. Up to 256 distinct colors are kept
+    /// exactly; beyond that the palette is picked by median cut and each
+    /// pixel takes its nearest entry.
+    pub fn to_indexed(&self) -> Result<Self, PngError> {
+        let mut frames = Vec::with_capacity(self.frames.len());
+        let mut histogram: HashMap<[u8; 4], u64> = HashMap::new();
+        for frame in &self.frames {
+            let mut image = frame.image.clone();
+            image.convert_to(ColorType::Rgba)?;
+            image.convert_bit_depth(BitDepth::Eight)?;
+            for pixel in image.data.chunks_exact(4) {
+                *histogram
+                    .entry([pixel[0], pixel[1], pixel[2], pixel[3]])
+                    .or_default() += 1;
+            }
+            frames.push(image);
+        }
+
+        // Translucent entries first keeps the tRNS chunk short
+        let mut palette = median_cut(&histogram, 256);
+        palette.sort_by_key(|c| c[3] == 255);
+        let entries: Vec<(u8, u8, u8, u8)> =
+            palette.iter().map(|&[r, g, b, a]| (r, g, b, a)).collect();
+
+        let mut lookup: HashMap<[u8; 4], u8> = HashMap::new();
+        let mut animation = Self::new(self.width, self.height)?;
+        animation.num_plays = self.num_plays;
+        for (frame, rgba) in self.frames.iter().zip(frames) {
+            let indices = rgba
+                .data
+                .chunks_exact(4)
+                .map(|pixel| {
+                    let color = [pixel[0], pixel[1], pixel[2], pixel[3]];
+                    *lookup
+                        .entry(color)
+                        .or_insert_with(|| nearest_entry(&palette, color))
+                })
+                .collect();
+            let mut image = PngImage::new(rgba.width, rgba.height, ColorType::Indexed)?;
+            image.set_palette_rgba(&entries)?;
+            image.data.replace(indices)?;
+            animation.frames.push(Frame {
+                image,
+                x_offset: frame.x_offset,
+                y_offset: frame.y_offset,
+                delay_num: frame.delay_num,
+                delay_den: frame.delay_den,
+                dispose_op: frame.dispose_op,
+                blend_op: frame.blend_op,
+            });
+        }
+        Ok(animation)
+    }
+
+    /// Renders every frame onto the canvas as a viewer would, returning full
+    /// size 8-bit RGBA images.
+    pub fn composite_frames(&self) -> Result<Vec<PngImage>, PngError> {
+        let mut canvas = vec![0u8; image_size(self.width, self.height, 4)?];
+        let width = self.width as usize;
+        let mut rendered = Vec::with_capacity(self.frames.len());
+
+        for (i, frame) in self.frames.iter().enumerate() {
+            let mut image = frame.image.clone();
+            image.convert_to(ColorType::Rgba)?;
+            image.convert_bit_depth(BitDepth::Eight)?;
+
+            let (fx, fy) = (frame.x_offset as usize, frame.y_offset as usize);
+            let (fw, fh) = (image.width as usize, image.height as usize);
+            let saved = (frame.dispose_op == DisposeOp::Previous).then(|| canvas.clone());
+
+            for y in 0..fh {
+                for x in 0..fw {
+                    let src = &image.data[(y * fw + x) * 4..][..4];
+                    let dst = &mut canvas[((fy + y) * width + fx + x) * 4..][..4];
+                    match frame.blend_op {
+                        BlendOp::Source => dst.copy_from_slice(src),
+                        BlendOp::Over => blend_over(dst, src),
+                    }
+                }
+            }
+
+            let mut output = PngImage::new(self.width, self.height, ColorType::Rgba)?;
+            output.data = canvas.clone().into();
+            rendered.push(output);
+
+            // The first frame treats Previous like Background
+            let dispose = match (frame.dispose_op, &saved) {
+                (DisposeOp::Previous, Some(saved)) if i > 0 => {
+                    canvas.copy_from_slice(saved);
+                    continue;
+                }
+                (DisposeOp::Previous, _) => DisposeOp::Background,
+                (op, _) => op,
+            };
+            if dispose == DisposeOp::Background {
+                for y in fy..fy + fh {
+                    canvas[(y * width + fx) * 4..(y * width + fx + fw) * 4].fill(0);
+                }
+            }
+        }
+
+        Ok(rendered)
+    }
+}
+
+// Length, type, and CRC around each chunk's data
+const CHUNK_OVERHEAD: u64 = 12;
+// fcTL data and the sequence number that starts each fdAT
+const FCTL_LENGTH: u64 = 26;
+const SEQUENCE_LENGTH: u64 = 4;
+
+// Non-premultiplied "over" compositing of `src` onto `dst`
+fn blend_over(dst: &mut [u8], src: &[u8]) {
+    let sa = src[3] as u32;
+    if sa == 255 {
+        dst.copy_from_slice(src);
+        return;
+    }
+    if sa == 0 {
+        return;
+    }
+
+    let da = dst[3] as u32;
+    let out_a = sa * 255 + da * (255 - sa);
+    for c in 0..3 {
+        let blended = src[c] as u32 * sa * 255 + dst[c] as u32 * da * (255 - sa);
+        dst[c] = ((blended + out_a / 2) / out_a) as u8;
+    }
+    dst[3] = ((out_a + 127) / 255) as u8;
+}
+
+struct FrameControl {
+    sequence: u32,
+    width: u32,
+    height: u32,
+    x_offset: u32,
+    y_offset: u32,
+    delay_num: u16,
+    delay_den: u16,
+    dispose_op: DisposeOp,
+    blend_op: BlendOp,
+}
+
+impl FrameControl {
+    fn parse(data: &[u8]) -> Result<Self, PngError> {
+        if data.len() != 26 {
+            return Err(PngError::Decode(format!(
+                "fcTL must be 26 bytes, got {}",
+                data.len()
+            )));
+        }
+        let dispose_op = match data[24] {
+            0 => DisposeOp::None,
+            1 => DisposeOp::Background,
+            2 => DisposeOp::Previous,
+            op => return Err(PngError::Decode(format!("Invalid dispose op {}", op))),
+        };
+        let blend_op = match data[25] {
+            0 => BlendOp::Source,
+            1 => BlendOp::Over,
+            op => return Err(PngError::Decode(format!("Invalid blend op {}", op))),
+        };
+
+        Ok(Self {
+            sequence: be_u32(&data[0..4]),
+            width: be_u32(&data[4..8]),
+            height: be_u32(&data[8..12]),
+            x_offset: be_u32(&data[12..16]),
+            y_offset: be_u32(&data[16..20]),
+            delay_num: u16::from_be_bytes([data[20], data[21]]),
+            delay_den: u16::from_be_bytes([data[22], data[23]]),
+            dispose_op,
+            blend_op,
+        })
+    }
+}
+
+// A frame as it is written, taking the delay and disposal of any repeats
+// merged into it
+struct WrittenFrame<'a> {
+    frame: &'a Frame,
+    delay_num: u16,
+    delay_den: u16,
+    dispose_op: DisposeOp,
+}
+
+impl WrittenFrame<'_> {
+    fn frame_control(&self, sequence: u32) -> Vec<u8> {
+        let frame = self.frame;
+        let mut data = Vec::with_capacity(26);
+        data.extend_from_slice(&sequence.to_be_bytes());
+        data.extend_from_slice(&frame.image.width.to_be_bytes());
+        data.extend_from_slice(&frame.image.height.to_be_bytes());
+        data.extend_from_slice(&frame.x_offset.to_be_bytes());
+        data.extend_from_slice(&frame.y_offset.to_be_bytes());
+        data.extend_from_slice(&self.delay_num.to_be_bytes());
+        data.extend_from_slice(&self.delay_den.to_be_bytes());
+        data.push(self.dispose_op as u8);
+        data.push(frame.blend_op as u8);
+        data
+    }
+}
+
+// Whether any pixel is neither fully transparent nor fully opaque
+fn has_partial_alpha(image: &PngImage) -> Result<bool, PngError> {
+    Ok(image
+        .extract_alpha()?
+        .samples()
+        .iter()
+        .any(|&alpha| alpha != 0.0 && alpha != 1.0))
+}
+
+// The sum of two fcTL delays as a fraction that still fits in 16 bits
+fn add_delays(a: (u16, u16), b: (u16, u16)) -> Option<(u16, u16)> {
+    fn gcd(a: u64, b: u64) -> u64 {
+        if b == 0 {
+            a
+        } else {
+            gcd(b, a % b)
+        }
+    }
+    // A zero denominator means hundredths of a second
+    let den = |d: u16| if d == 0 { 100 } else { d as u64 };
+    let (a_num, a_den) = (a.0 as u64, den(a.1));
+    let (b_num, b_den) = (b.0 as u64, den(b.1));
+    let common = a_den / gcd(a_den, b_den) * b_den;
+    let num = a_num * (common / a_den) + b_num * (common / b_den);
+    let divisor = gcd(num, common);
+    Some((
+        u16::try_from(num / divisor).ok()?,
+        u16::try_from(common / divisor).ok()?,
+    ))
+}
+
+fn check_sequence(sequence: u32, expected: &mut u32) -> Result<(), PngError> {
+    if sequence != *expected {
+        return Err(PngError::Decode(format!(
+            "Out-of-order APNG sequence number {} (expected {})",
+            sequence, expected
+        )));
+    }
+    *expected += 1;
+    Ok(())
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_images::noise;
+    use crate::BitDepth;
+
+    #[test]
+    fn written_frame_count_merges_repeats() {
+        let a = noise(8, 6, ColorType::Rgb, BitDepth::Eight, 1);
+        let b = noise(8, 6, ColorType::Rgb, BitDepth::Eight, 2);
+        let frames = [&a, &a, &a, &b, &a]
+            .into_iter()
+            .map(|image| Frame::new(image.clone(), 100))
+            .collect();
+        let animation = Animation::from_frames(frames).unwrap();
+        assert_eq!(animation.written_frame_count().unwrap(), 3);
+
+        let mut encoded = Vec::new();
+        animation.write_to_file(&mut encoded).unwrap();
+        let decoded = Animation::read_from_file(encoded.as_slice()).unwrap();
+        assert_eq!(decoded.frames().len(), 3);
+        assert_eq!(decoded.frames()[0].delay_ms(), 300.0);
+    }
+
+    fn assert_frames_match(decoded: &Animation, animation: &Animation) {
+        assert_eq!(
+            (decoded.width(), decoded.height(), decoded.num_plays),
+            (animation.width(), animation.height(), animation.num_plays)
+        );
+        assert_eq!(decoded.frames().len(), animation.frames().len());
+        for (got, expected) in decoded.frames().iter().zip(animation.frames()) {
+            assert_eq!(
+                (got.x_offset, got.y_offset, got.delay_num, got.delay_den),
+                (
+                    expected.x_offset,
+                    expected.y_offset,
+                    expected.delay_num,
+                    expected.delay_den
+                )
+            );
+            assert_eq!(
+                (got.dispose_op, got.blend_op),
+                (expected.dispose_op, expected.blend_op)
+            );
+            assert_eq!(got.image.color_type(), expected.image.color_type());
+            assert_eq!(got.image.bit_depth(), expected.image.bit_depth());
+            assert_eq!(got.image.palette(), expected.image.palette());
+            assert_eq!(got.image.data(), expected.image.data());
+        }
+    }
+
+    fn sample_animation(color_type: ColorType, bit_depth: BitDepth) -> Animation {
+        let mut animation = Animation::new(16, 12).unwrap();
+        animation.num_plays = 3;
+        let background = noise(16, 12, color_type, bit_depth, 1);
+        animation.add_frame(Frame::new(background, 40)).unwrap();
+        let ops = [
+            (DisposeOp::Background, BlendOp::Source),
+            (DisposeOp::Previous, BlendOp::Over),
+            (DisposeOp::None, BlendOp::Over),
+        ];
+        for (i, (dispose_op, blend_op)) in ops.into_iter().enumerate() {
+            let i = i as u32;
+            animation
+                .add_frame(Frame {
+                    image: noise(5 + i, 4, color_type, bit_depth, 2 + i),
+                    x_offset: 3 * i,
+                    y_offset: 2 + i,
+                    delay_num: 1 + i as u16,
+                    delay_den: 30,
+                    dispose_op,
+                    blend_op,
+                })
+                .unwrap();
+        }
+        animation
+    }
+
+    #[test]
+    fn animation_round_trips() {
+        for (color_type, bit_depth) in [
+            (ColorType::Rgba, BitDepth::Eight),
+            (ColorType::Rgba, BitDepth::Sixteen),
+            (ColorType::Grayscale, BitDepth::Two),
+            (ColorType::Rgb, BitDepth::Eight),
+        ] {
+            let animation = sample_animation(color_type, bit_depth);
+            let mut encoded = Vec::new();
+            animation.write_to_file(&mut encoded).unwrap();
+            let decoded = Animation::read_from_file(encoded.as_slice()).unwrap();
+            assert_frames_match(&decoded, &animation);
+        }
+    }
+
+    #[test]
+    fn indexed_animation_round_trips_with_shared_palette() {
+        let animation = sample_animation(ColorType::Rgba, BitDepth::Eight)
+            .to_indexed()
+            .unwrap();
+        let mut encoded = Vec::new();
+        animation.write_to_file(&mut encoded).unwrap();
+        let decoded = Animation::read_from_file(encoded.as_slice()).unwrap();
+        assert_frames_match(&decoded, &animation);
+        assert_eq!(
+            decoded.composite_frames().unwrap(),
+            animation.composite_frames().unwrap()
+        );
+    }
+
+    #[test]
+    fn plain_png_reads_as_one_frame() {
+        let image = noise(7, 5, ColorType::Rgb, BitDepth::Eight, 3);
+        let mut encoded = Vec::new();
+        image.write_to_file(&mut encoded).unwrap();
+        let animation = Animation::read_from_file(encoded.as_slice()).unwrap();
+        assert_eq!(animation.frames().len(), 1);
+        assert_eq!(animation.frames()[0].image.data(), image.data());
+    }
+}
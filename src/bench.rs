@@ -0,0 +1,213 @@
+//! Tools for comparing encoder settings on synthetic images or your own,
+//! enabled by the `bench` feature.
+
+use std::fmt;
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::color::Color;
+use crate::decoder::Decoder;
+use crate::error::PngError;
+use crate::filter::{FilterStrategy, FilterType};
+use crate::generate::{Easing, Pattern};
+use crate::options::EncodeOptions;
+use crate::{BitDepth, ColorType, PngImage};
+
+/// Encoder settings under a name for reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preset {
+    pub name: String,
+    pub options: EncodeOptions,
+}
+
+impl Preset {
+    pub fn new(name: impl Into<String>, options: EncodeOptions) -> Self {
+        Self {
+            name: name.into(),
+            options,
+        }
+    }
+}
+
+/// Each fixed filter and adaptive filtering at the default compression
+/// level, then adaptive filtering at the fastest and smallest levels.
+pub fn presets() -> Vec<Preset> {
+    let mut presets: Vec<Preset> = FilterType::ALL
+        .iter()
+        .map(|&filter| {
+            Preset::new(
+                format!("{:?}", filter).to_lowercase(),
+                EncodeOptions {
+                    filter: FilterStrategy::Fixed(filter),
+                    ..EncodeOptions::default()
+                },
+            )
+        })
+        .collect();
+    for level in [1, 6, 9] {
+        presets.push(Preset::new(
+            format!("adaptive-{}", level),
+            EncodeOptions {
+                compression_level: level,
+                filter: FilterStrategy::Adaptive,
+                ..EncodeOptions::default()
+            },
+        ));
+    }
+    presets
+}
+
+/// A deterministic set of test images covering content that compresses
+/// differently: smooth gradients, photo-like noise, flat graphics, random
+/// noise, and an alpha channel.
+pub fn sample_images(width: u32, height: u32) -> Result<Vec<(String, PngImage)>, PngError> {
+    let eight = BitDepth::Eight;
+    let images = [
+        (
+            "gradient",
+            Pattern::Gradient {
+                easing: Easing::Linear,
+            },
+            ColorType::Rgb,
+            eight,
+        ),
+        (
+            "gradient-16",
+            Pattern::LinearGradient {
+                from: Color::Rgb(0, 0, 0),
+                to: Color::Rgb(255, 160, 40),
+                vertical: false,
+                easing: Easing::EaseInOut,
+            },
+            ColorType::Rgb,
+            BitDepth::Sixteen,
+        ),
+        (
+            "perlin",
+            Pattern::Perlin {
+                scale: 32.0,
+                octaves: 4,
+                seed: 1,
+            },
+            ColorType::Grayscale,
+            eight,
+        ),
+        ("bars", Pattern::Bars, ColorType::Indexed, eight),
+        (
+            "checkerboard",
+            Pattern::Checkerboard { cell: 8 },
+            ColorType::Grayscale,
+            BitDepth::One,
+        ),
+        ("noise", Pattern::Noise { seed: 1 }, ColorType::Rgba, eight),
+        (
+            "alpha",
+            Pattern::AlphaRamp,
+            ColorType::GrayscaleAlpha,
+            eight,
+        ),
+    ];
+    images
+        .into_iter()
+        .map(|(name, pattern, color_type, bit_depth)| {
+            let image = PngImage::generate(&pattern, width, height, color_type, bit_depth)?;
+            Ok((name.to_string(), image))
+        })
+        .collect()
+}
+
+/// Size and timings from encoding one image with one preset.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchResult {
+    pub image: String,
+    pub preset: String,
+    pub encoded_bytes: u64,
+    /// Raw scanline bytes per compressed byte
+    pub compression_ratio: f64,
+    /// Fastest of the timed runs
+    pub encode_time: Duration,
+    pub decode_time: Duration,
+}
+
+impl fmt::Display for BenchResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<14} {:<12} {:>10} bytes  ratio {:>6.2}  encode {:>9.3}ms  decode {:>9.3}ms",
+            self.image,
+            self.preset,
+            self.encoded_bytes,
+            self.compression_ratio,
+            self.encode_time.as_secs_f64() * 1e3,
+            self.decode_time.as_secs_f64() * 1e3
+        )
+    }
+}
+
+/// Encodes and decodes `image` `iterations` times (at least once) with
+/// `preset`, keeping the fastest time of each.
+pub fn bench_preset(
+    name: &str,
+    image: &PngImage,
+    preset: &Preset,
+    iterations: usize,
+) -> Result<BenchResult, PngError> {
+    let mut encoded = Vec::new();
+    let (report, encode_time) = fastest(iterations, || {
+        encoded.clear();
+        image.write_with_report(&mut encoded, &preset.options)
+    })?;
+    let (_, decode_time) = fastest(iterations, || Decoder::new(encoded.as_slice()).decode())?;
+    Ok(BenchResult {
+        image: name.to_string(),
+        preset: preset.name.clone(),
+        encoded_bytes: report.total_bytes,
+        compression_ratio: report.compression_ratio(),
+        encode_time,
+        decode_time,
+    })
+}
+
+/// Runs [`bench_preset`] for every image with every preset, grouped by
+/// image.
+pub fn compare_presets(
+    images: &[(String, PngImage)],
+    presets: &[Preset],
+    iterations: usize,
+) -> Result<Vec<BenchResult>, PngError> {
+    let mut results = Vec::with_capacity(images.len() * presets.len());
+    for (name, image) in images {
+        for preset in presets {
+            results.push(bench_preset(name, image, preset, iterations)?);
+        }
+    }
+    Ok(results)
+}
+
+/// Loads a PNG file to benchmark alongside or instead of the samples.
+pub fn load_image(path: impl AsRef<std::path::Path>) -> Result<(String, PngImage), PngError> {
+    let path = path.as_ref();
+    let file = io::BufReader::new(std::fs::File::open(path)?);
+    let name = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    Ok((name, Decoder::new(file).decode()?))
+}
+
+/// Runs `f` `iterations` times (at least once), returning its last result
+/// and the shortest time taken.
+pub fn fastest<T, E>(
+    iterations: usize,
+    mut f: impl FnMut() -> Result<T, E>,
+) -> Result<(T, Duration), E> {
+    let mut best = Duration::MAX;
+    let mut result = None;
+    for _ in 0..iterations.max(1) {
+        let start = Instant::now();
+        let value = f()?;
+        best = best.min(start.elapsed());
+        result = Some(value);
+    }
+    Ok((result.expect("at least one iteration"), best))
+}
@@ -0,0 +1,297 @@
+use std::ops::{Deref, DerefMut};
+
+#[cfg(feature = "mmap")]
+use std::fs::File;
+
+#[cfg(feature = "mmap")]
+use memmap2::MmapMut;
+
+use crate::error::PngError;
+#[cfg(feature = "mmap")]
+use crate::image_size;
+use crate::PngImage;
+
+/// Storage for an image's samples: a `Vec`, or with the `mmap` feature an
+/// anonymous temporary file mapped into memory.
+///
+/// Growing a spilled buffer past its file size remaps it, which can fail
+/// like any file I/O, so every method that adds samples returns a
+/// `Result`. Clones are always held in memory; see
+/// [`try_clone`](Self::try_clone).
+pub(crate) enum PixelBuffer {
+    Memory(Vec<u8>),
+    #[cfg(feature = "mmap")]
+    Spilled(Spilled),
+}
+
+#[cfg(feature = "mmap")]
+pub(crate) struct Spilled {
+    file: File,
+    map: MmapMut,
+    len: usize,
+}
+
+#[cfg(feature = "mmap")]
+impl Spilled {
+    fn with_capacity(capacity: usize) -> std::io::Result<Self> {
+        let file = tempfile::tempfile()?;
+        // Mapping an empty file fails on some platforms
+        file.set_len(capacity.max(1) as u64)?;
+        // SAFETY: the file is unnamed and private to this buffer, so nothing
+        // else can resize or write it while it is mapped.
+        let map = unsafe { MmapMut::map_mut(&file)? };
+        Ok(Self { file, map, len: 0 })
+    }
+
+    fn reserve(&mut self, additional: usize) -> Result<(), PngError> {
+        let needed = self
+            .len
+            .checked_add(additional)
+            .ok_or_else(|| std::io::Error::other("spilled pixel buffer size overflows"))?;
+        if needed <= self.map.len() {
+            return Ok(());
+        }
+        let capacity = needed.max(self.map.len().saturating_mul(2));
+        // Unmap first: some platforms can't resize a file while it is mapped.
+        // A failure leaves the buffer unusable, so it is emptied rather than
+        // left pointing at a stand-in map
+        self.map = MmapMut::map_anon(1)?;
+        let len = std::mem::take(&mut self.len);
+        self.file.set_len(capacity as u64)?;
+        // SAFETY: as in `with_capacity`
+        self.map = unsafe { MmapMut::map_mut(&self.file)? };
+        self.len = len;
+        Ok(())
+    }
+
+    fn try_clone(&self) -> Result<Self, PngError> {
+        let mut copy = Spilled::with_capacity(self.map.len())?;
+        copy.map[..self.len].copy_from_slice(&self.map[..self.len]);
+        copy.len = self.len;
+        Ok(copy)
+    }
+}
+
+impl PixelBuffer {
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        PixelBuffer::Memory(Vec::with_capacity(capacity))
+    }
+
+    /// An empty buffer in a temporary file sized for `capacity` bytes,
+    /// without allocating them in memory.
+    #[cfg(feature = "mmap")]
+    pub(crate) fn spilled(capacity: usize) -> Result<Self, PngError> {
+        Ok(PixelBuffer::Spilled(Spilled::with_capacity(capacity)?))
+    }
+
+    pub(crate) fn push(&mut self, value: u8) -> Result<(), PngError> {
+        self.extend_from_slice(&[value])
+    }
+
+    pub(crate) fn extend_from_slice(&mut self, values: &[u8]) -> Result<(), PngError> {
+        match self {
+            PixelBuffer::Memory(data) => data.extend_from_slice(values),
+            #[cfg(feature = "mmap")]
+            PixelBuffer::Spilled(spilled) => {
+                spilled.reserve(values.len())?;
+                spilled.map[spilled.len..spilled.len + values.len()].copy_from_slice(values);
+                spilled.len += values.len();
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn resize(&mut self, len: usize, value: u8) -> Result<(), PngError> {
+        match self {
+            PixelBuffer::Memory(data) => data.resize(len, value),
+            #[cfg(feature = "mmap")]
+            PixelBuffer::Spilled(spilled) => {
+                if len > spilled.len {
+                    spilled.reserve(len - spilled.len)?;
+                    spilled.map[spilled.len..len].fill(value);
+                }
+                spilled.len = len;
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces the contents with `data`, keeping spilled buffers on disk.
+    pub(crate) fn replace(&mut self, data: Vec<u8>) -> Result<(), PngError> {
+        match self {
+            PixelBuffer::Memory(current) => *current = data,
+            #[cfg(feature = "mmap")]
+            PixelBuffer::Spilled(_) => {
+                self.resize(0, 0)?;
+                self.extend_from_slice(&data)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// A copy that stays in a temporary file of its own if this buffer is
+    /// spilled.
+    pub(crate) fn try_clone(&self) -> Result<Self, PngError> {
+        match self {
+            PixelBuffer::Memory(data) => Ok(PixelBuffer::Memory(data.clone())),
+            #[cfg(feature = "mmap")]
+            PixelBuffer::Spilled(spilled) => Ok(PixelBuffer::Spilled(spilled.try_clone()?)),
+        }
+    }
+
+    pub(crate) fn into_vec(self) -> Vec<u8> {
+        match self {
+            PixelBuffer::Memory(data) => data,
+            #[cfg(feature = "mmap")]
+            PixelBuffer::Spilled(spilled) => spilled.map[..spilled.len].to_vec(),
+        }
+    }
+}
+
+impl Deref for PixelBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            PixelBuffer::Memory(data) => data,
+            #[cfg(feature = "mmap")]
+            PixelBuffer::Spilled(spilled) => &spilled.map[..spilled.len],
+        }
+    }
+}
+
+impl DerefMut for PixelBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        match self {
+            PixelBuffer::Memory(data) => data,
+            #[cfg(feature = "mmap")]
+            PixelBuffer::Spilled(spilled) => &mut spilled.map[..spilled.len],
+        }
+    }
+}
+
+impl From<Vec<u8>> for PixelBuffer {
+    fn from(data: Vec<u8>) -> Self {
+        PixelBuffer::Memory(data)
+    }
+}
+
+/// Cloning can't report I/O errors, so clones of spilled buffers are read
+/// into memory; [`PixelBuffer::try_clone`] keeps them on disk.
+impl Clone for PixelBuffer {
+    fn clone(&self) -> Self {
+        PixelBuffer::Memory(self.to_vec())
+    }
+}
+
+impl PartialEq for PixelBuffer {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl PngImage {
+    /// Copies the image like `clone`, except that a copy of an image
+    /// spilled to disk gets a temporary file of its own instead of being
+    /// read into memory, and failing to create it is returned as an error.
+    pub fn try_clone(&self) -> Result<PngImage, PngError> {
+        Ok(PngImage {
+            width: self.width,
+            height: self.height,
+            data: self.data.try_clone()?,
+            color_type: self.color_type,
+            bit_depth: self.bit_depth,
+            palette: self.palette.clone(),
+            chunks: self.chunks.clone(),
+            options: self.options.clone(),
+        })
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl PngImage {
+    /// Moves the pixel data into an anonymous temporary file mapped into
+    /// memory, so large canvases are paged by the OS instead of held in RAM.
+    /// The file is sized for the complete image up front and deleted when
+    /// the image is dropped. See also [`PngImageBuilder::spill_threshold`].
+    ///
+    /// Methods that compute new pixel data, such as conversions, still build
+    /// it in memory before copying it back to the file.
+    ///
+    /// [`PngImageBuilder::spill_threshold`]: crate::PngImageBuilder::spill_threshold
+    pub fn spill_to_disk(&mut self) -> Result<(), PngError> {
+        if self.is_spilled() {
+            return Ok(());
+        }
+        let size = image_size(self.width, self.height, self.bytes_per_pixel())?;
+        let mut spilled = Spilled::with_capacity(size.max(self.data.len()))?;
+        spilled.map[..self.data.len()].copy_from_slice(&self.data);
+        spilled.len = self.data.len();
+        self.data = PixelBuffer::Spilled(spilled);
+        Ok(())
+    }
+
+    /// Whether the pixel data lives in a temporary file.
+    pub fn is_spilled(&self) -> bool {
+        matches!(self.data, PixelBuffer::Spilled(_))
+    }
+}
+
+#[cfg(all(test, feature = "mmap"))]
+mod tests {
+    use super::*;
+    use crate::test_images::noise;
+    use crate::{BitDepth, ColorType};
+
+    #[test]
+    fn spilled_buffers_grow_past_their_file() {
+        let mut buffer = PixelBuffer::Spilled(Spilled::with_capacity(4).unwrap());
+        for i in 0..1000u32 {
+            buffer.extend_from_slice(&i.to_be_bytes()).unwrap();
+        }
+        buffer.resize(4100, 7).unwrap();
+        assert_eq!(buffer.len(), 4100);
+        assert_eq!(&buffer[3996..4000], &999u32.to_be_bytes());
+        assert!(buffer[4000..].iter().all(|&b| b == 7));
+
+        buffer.replace(vec![1, 2, 3]).unwrap();
+        assert!(matches!(buffer, PixelBuffer::Spilled(_)));
+        assert_eq!(&buffer[..], &[1, 2, 3]);
+    }
+
+    #[test]
+    fn builder_spills_large_canvases_without_allocating_them() {
+        // 1 GiB, which the temporary file only takes up as it is written
+        let image = PngImage::builder()
+            .width(16384)
+            .height(16384)
+            .color_type(ColorType::Rgba)
+            .spill_threshold(1 << 20)
+            .build()
+            .unwrap();
+        assert!(image.is_spilled());
+        assert!(image.data().is_empty());
+
+        let small = PngImage::builder()
+            .width(16)
+            .height(16)
+            .color_type(ColorType::Rgba)
+            .spill_threshold(1 << 20)
+            .build()
+            .unwrap();
+        assert!(!small.is_spilled());
+    }
+
+    #[test]
+    fn try_clone_keeps_spilled_images_on_disk() {
+        let mut image = noise(30, 20, ColorType::Rgba, BitDepth::Sixteen, 1);
+        image.spill_to_disk().unwrap();
+
+        let copy = image.try_clone().unwrap();
+        assert!(copy.is_spilled());
+        assert!(copy == image);
+        let clone = image.clone();
+        assert!(!clone.is_spilled());
+        assert!(clone == image);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::buffer::PixelBuffer;
+use crate::dictionary::CompressionDictionary;
+use crate::error::PngError;
+use crate::filter::FilterStrategy;
+use crate::options::{EncodeOptions, Strictness};
+use crate::{BitDepth, ColorType, PngImage};
+
+/// Configures a [`PngImage`] one setting at a time; see
+/// [`PngImage::builder`].
+///
+/// The color type defaults to RGBA and the bit depth to 8. Width and height
+/// must be set.
+#[derive(Debug, Clone)]
+pub struct PngImageBuilder {
+    width: u32,
+    height: u32,
+    color_type: ColorType,
+    bit_depth: BitDepth,
+    palette: Option<Vec<u8>>,
+    options: EncodeOptions,
+    #[cfg(feature = "mmap")]
+    spill_threshold: Option<usize>,
+}
+
+impl PngImage {
+    pub fn builder() -> PngImageBuilder {
+        PngImageBuilder {
+            width: 0,
+            height: 0,
+            color_type: ColorType::Rgba,
+            bit_depth: BitDepth::Eight,
+            palette: None,
+            options: EncodeOptions::default(),
+            #[cfg(feature = "mmap")]
+            spill_threshold: None,
+        }
+    }
+}
+
+impl PngImageBuilder {
+    pub fn width(mut self, width: u32) -> Self {
+        self.width = width;
+        self
+    }
+
+    pub fn height(mut self, height: u32) -> Self {
+        self.height = height;
+        self
+    }
+
+    pub fn color_type(mut self, color_type: ColorType) -> Self {
+        self.color_type = color_type;
+        self
+    }
+
+    pub fn bit_depth(mut self, bit_depth: BitDepth) -> Self {
+        self.bit_depth = bit_depth;
+        self
+    }
+
+    /// RGB triplets, as for [`PngImage::set_palette`].
+    pub fn palette(mut self, palette: &[u8]) -> Self {
+        self.palette = Some(palette.to_vec());
+        self
+    }
+
+    /// zlib level from 0 to 9 used by [`PngImage::write_to_file`].
+    pub fn compression(mut self, level: u8) -> Self {
+        self.options.compression_level = level;
+        self
+    }
+
+    pub fn filter(mut self, filter: FilterStrategy) -> Self {
+        self.options.filter = filter;
+        self
+    }
+
+    pub fn strictness(mut self, strictness: Strictness) -> Self {
+        self.options.strictness = strictness;
+        self
+    }
+
+    /// See [`EncodeOptions::deterministic`].
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.options.deterministic = deterministic;
+        self
+    }
+
+    /// See [`EncodeOptions::optimize_palette`].
+    pub fn optimize_palette(mut self, optimize_palette: bool) -> Self {
+        self.options.optimize_palette = optimize_palette;
+        self
+    }
+
+    /// See [`EncodeOptions::pipelined`].
+    pub fn pipelined(mut self, pipelined: bool) -> Self {
+        self.options.pipelined = pipelined;
+        self
+    }
+
+    /// See [`EncodeOptions::dictionary`].
+    pub fn dictionary(mut self, dictionary: CompressionDictionary) -> Self {
+        self.options.dictionary = Some(dictionary);
+        self
+    }
+
+    /// Replaces every encoding setting at once.
+    pub fn encode_options(mut self, options: EncodeOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Backs the image with a temporary file instead of memory when its
+    /// pixel data would take more than `bytes`; see
+    /// [`PngImage::spill_to_disk`].
+    #[cfg(feature = "mmap")]
+    pub fn spill_threshold(mut self, bytes: usize) -> Self {
+        self.spill_threshold = Some(bytes);
+        self
+    }
+
+    /// Creates an empty image, checking the settings the same way
+    /// [`PngImage::with_bit_depth`] and [`PngImage::set_palette`] do.
+    pub fn build(self) -> Result<PngImage, PngError> {
+        if self.options.compression_level > 9 {
+            return Err(PngError::Compression(format!(
+                "Invalid compression level {}",
+                self.options.compression_level
+            )));
+        }
+
+        // Canvases over the threshold go straight to a temporary file,
+        // never reserving their size in memory
+        #[cfg(feature = "mmap")]
+        let spill_threshold = self.spill_threshold;
+        let buffer = |size: usize| {
+            #[cfg(feature = "mmap")]
+            if spill_threshold.is_some_and(|threshold| size > threshold) {
+                return PixelBuffer::spilled(size);
+            }
+            Ok(PixelBuffer::with_capacity(size))
+        };
+        let mut image = PngImage::with_buffer(
+            self.width,
+            self.height,
+            self.color_type,
+            self.bit_depth,
+            buffer,
+        )?;
+        if let Some(palette) = &self.palette {
+            image.set_palette(palette)?;
+        }
+        image.options = self.options;
+        Ok(image)
+    }
+}
@@ -0,0 +1,135 @@
+use std::io::Write;
+
+use crate::chunks::{Chunk, ChunkReader, ChunkWriter, MAX_CHUNK_LENGTH, PNG_SIGNATURE};
+use crate::error::PngError;
+use crate::options::EncodeOptions;
+use crate::{BitDepth, ColorType, PngImage};
+
+// Flags as found in files from Xcode; readers don't interpret them
+const CGBI_FLAGS: [u8; 4] = [0x50, 0x00, 0x20, 0x02];
+
+impl PngImage {
+    /// Encodes the image in Apple's CgBI variant of PNG, as optimized iOS
+    /// app assets use: a CgBI chunk ahead of IHDR, 8-bit BGR or
+    /// premultiplied BGRA pixels, and image data deflated without a zlib
+    /// wrapper. Other formats are converted to 8-bit RGBA first. Most PNG
+    /// readers other than Apple's can't open the result, and premultiplying
+    /// loses color detail in translucent pixels. A preset dictionary in
+    /// `options` is left out, since no CgBI reader could have it.
+    pub fn write_cgbi<W: Write>(
+        &self,
+        writer: &mut W,
+        options: &EncodeOptions,
+    ) -> Result<(), PngError> {
+        let mut image = self.clone();
+        if image.color_type != ColorType::Rgb {
+            image.convert_to(ColorType::Rgba)?;
+        }
+        image.convert_bit_depth(BitDepth::Eight)?;
+        let channels = image.color_type.channels();
+        for pixel in image.data.chunks_exact_mut(channels) {
+            if channels == 4 {
+                let alpha = pixel[3] as u32;
+                for c in &mut pixel[..3] {
+                    *c = ((*c as u32 * alpha + 127) / 255) as u8;
+                }
+            }
+            pixel.swap(0, 2);
+        }
+
+        let options = EncodeOptions {
+            dictionary: None,
+            ..options.clone()
+        };
+        let mut encoded = Vec::new();
+        image.write_with_options(&mut encoded, &options)?;
+        let chunks: Vec<Chunk> = ChunkReader::new(encoded.as_slice())?.collect::<Result<_, _>>()?;
+        let zlib: Vec<u8> = chunks
+            .iter()
+            .filter(|c| &c.chunk_type == b"IDAT")
+            .flat_map(|c| c.data.iter().copied())
+            .collect();
+        // A zlib stream is a 2-byte header, raw deflate data, and a 4-byte
+        // Adler-32 checksum
+        let deflated = &zlib[2..zlib.len() - 4];
+
+        writer.write_all(&PNG_SIGNATURE)?;
+        ChunkWriter::write_chunk(writer, b"CgBI", &CGBI_FLAGS)?;
+        let mut wrote_data = false;
+        for chunk in &chunks {
+            if &chunk.chunk_type != b"IDAT" {
+                ChunkWriter::write_chunk(writer, &chunk.chunk_type, &chunk.data)?;
+            } else if !wrote_data {
+                for part in deflated.chunks(MAX_CHUNK_LENGTH as usize) {
+                    ChunkWriter::write_chunk(writer, b"IDAT", part)?;
+                }
+                wrote_data = true;
+            }
+        }
+        Ok(())
+    }
+
+    // Turns decoded CgBI pixels back into RGB or straight-alpha RGBA
+    pub(crate) fn normalize_cgbi(&mut self) {
+        if self.bit_depth != BitDepth::Eight
+            || !matches!(self.color_type, ColorType::Rgb | ColorType::Rgba)
+        {
+            return;
+        }
+        let channels = self.color_type.channels();
+        for pixel in self.data.chunks_exact_mut(channels) {
+            pixel.swap(0, 2);
+            if channels == 4 {
+                let alpha = pixel[3] as u32;
+                for c in &mut pixel[..3] {
+                    *c = match alpha {
+                        0 => 0,
+                        _ => ((*c as u32 * 255 + alpha / 2) / alpha).min(255) as u8,
+                    };
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dictionary::CompressionDictionary;
+
+    fn translucent() -> PngImage {
+        let mut image = PngImage::new(4, 3, ColorType::Rgba).unwrap();
+        for i in 0..12u8 {
+            image.add_pixel(&[i * 20, 255 - i * 20, 90, 255]).unwrap();
+        }
+        image
+    }
+
+    #[test]
+    fn cgbi_round_trip() {
+        let image = translucent();
+        let mut encoded = Vec::new();
+        image
+            .write_cgbi(&mut encoded, &EncodeOptions::default())
+            .unwrap();
+        assert_eq!(&encoded[12..16], b"CgBI");
+        let decoded = PngImage::read_from_file(encoded.as_slice()).unwrap();
+        assert_eq!(decoded.data(), image.data());
+    }
+
+    #[test]
+    fn cgbi_ignores_dictionary() {
+        let image = translucent();
+        let mut plain = Vec::new();
+        image
+            .write_cgbi(&mut plain, &EncodeOptions::default())
+            .unwrap();
+        let options = EncodeOptions {
+            dictionary: Some(CompressionDictionary::from_bytes(&[1, 2, 3])),
+            ..EncodeOptions::default()
+        };
+        let mut with_dictionary = Vec::new();
+        image.write_cgbi(&mut with_dictionary, &options).unwrap();
+        assert_eq!(with_dictionary, plain);
+    }
+}
@@ -1,29 +1,580 @@
 use crate::error::PngError;
 use crc::{Crc, CRC_32_ISO_HDLC};
-use std::io::{Seek, SeekFrom, Write};
+use std::fmt;
+use std::io::{self, Cursor, Read, Write};
 
 const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
 
+pub const PNG_SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+/// A recognizable way in which a PNG signature gets mangled in transit,
+/// which the signature's design is meant to expose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureDamage {
+    /// Every LF became CRLF, as in a text-mode transfer to Windows
+    LfToCrLf,
+    /// Every CRLF became LF, as in a text-mode transfer from Windows
+    CrLfToLf,
+    /// The 0x1A end-of-file byte was dropped
+    MissingEof,
+    /// Bytes lost their high bit, as in a 7-bit transfer
+    HighBitStripped,
+}
+
+impl SignatureDamage {
+    // Recognizes the damage from the first eight bytes of a stream
+    fn diagnose(found: &[u8; 8]) -> Option<Self> {
+        match found {
+            [137, 80, 78, 71, 13, 13, 10, 26] => Some(SignatureDamage::LfToCrLf),
+            [137, 80, 78, 71, 10, 26, 10, _] => Some(SignatureDamage::CrLfToLf),
+            [137, 80, 78, 71, 13, 10, 10, _] => Some(SignatureDamage::MissingEof),
+            [9, 80, 78, 71, 13, 10, 26, 10] => Some(SignatureDamage::HighBitStripped),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SignatureDamage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SignatureDamage::LfToCrLf => {
+                "line endings were converted from LF to CRLF by a text-mode transfer"
+            }
+            SignatureDamage::CrLfToLf => {
+                "line endings were converted from CRLF to LF by a text-mode transfer"
+            }
+            SignatureDamage::MissingEof => "the 0x1A byte was removed",
+            SignatureDamage::HighBitStripped => "the high bit of each byte was cleared",
+        })
+    }
+}
+
+// Undoes damage to the stream that can be undone exactly: LF to CRLF
+// conversion, which is reversed throughout, and a dropped 0x1A. Other
+// streams are passed through for ChunkReader to report.
+pub(crate) fn repair_signature<'a, R: Read + 'a>(
+    mut reader: R,
+) -> Result<Box<dyn Read + 'a>, PngError> {
+    let mut found = [0; 8];
+    let read = read_fully(&mut reader, &mut found)?;
+    let start = Cursor::new(found[..read].to_vec());
+    let damage = (read == found.len())
+        .then(|| SignatureDamage::diagnose(&found))
+        .flatten();
+    Ok(match damage {
+        Some(SignatureDamage::LfToCrLf) => Box::new(CrLfReader {
+            inner: start.chain(reader),
+            held_cr: false,
+            buffer: Vec::new(),
+            position: 0,
+        }),
+        Some(SignatureDamage::MissingEof) => {
+            // The eighth byte read already belongs to IHDR
+            let start = [&PNG_SIGNATURE[..], &found[7..]].concat();
+            Box::new(Cursor::new(start).chain(reader))
+        }
+        _ => Box::new(start.chain(reader)),
+    })
+}
+
+// Turns every CRLF back into LF
+struct CrLfReader<R> {
+    inner: R,
+    // A CR at the end of the last read, which may start a CRLF
+    held_cr: bool,
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read> Read for CrLfReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let mut raw = [0; 8192];
+        while self.position == self.buffer.len() {
+            let read = self.inner.read(&mut raw)?;
+            self.buffer.clear();
+            self.position = 0;
+            if read == 0 {
+                if !std::mem::take(&mut self.held_cr) {
+                    return Ok(0);
+                }
+                self.buffer.push(b'\r');
+                break;
+            }
+            for &byte in &raw[..read] {
+                if std::mem::take(&mut self.held_cr) && byte != b'\n' {
+                    self.buffer.push(b'\r');
+                }
+                if byte == b'\r' {
+                    self.held_cr = true;
+                } else {
+                    self.buffer.push(byte);
+                }
+            }
+        }
+        let count = out.len().min(self.buffer.len() - self.position);
+        out[..count].copy_from_slice(&self.buffer[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+// Chunk lengths are limited to 2^31 - 1 by the specification
+pub(crate) const MAX_CHUNK_LENGTH: u32 = 0x7FFF_FFFF;
+
 pub struct ChunkWriter;
 
 impl ChunkWriter {
-    pub fn write_chunk<W: Write + Seek>(
+    pub fn write_chunk<W: Write>(
         writer: &mut W,
         chunk_type: &[u8; 4],
         data: &[u8],
     ) -> Result<(), PngError> {
-        let length = data.len() as u32;
+        let length = u32::try_from(data.len())
+            .ok()
+            .filter(|&length| length <= MAX_CHUNK_LENGTH)
+            .ok_or(PngError::ChunkTooLarge {
+                chunk_type: *chunk_type,
+                length: data.len(),
+            })?;
+        #[cfg(feature = "tracing")]
+        tracing::trace!(
+            chunk_type = %String::from_utf8_lossy(chunk_type),
+            length,
+            "write chunk"
+        );
         writer.write_all(&length.to_be_bytes())?;
 
         writer.write_all(chunk_type)?;
 
         writer.write_all(data)?;
 
-        let crc_data: Vec<u8> = chunk_type.iter().chain(data.iter()).cloned().collect();
-        let crc = CRC32.checksum(&crc_data);
+        let crc = chunk_crc(chunk_type, data);
 
         writer.write_all(&crc.to_be_bytes())?;
 
         Ok(())
     }
 }
+
+pub(crate) fn chunk_crc(chunk_type: &[u8; 4], data: &[u8]) -> u32 {
+    let mut digest = CRC32.digest();
+    digest.update(chunk_type);
+    digest.update(data);
+    digest.finalize()
+}
+
+// Ancillary chunks that refer to palette entries and so must come after PLTE
+pub(crate) fn follows_palette(chunk_type: &[u8; 4]) -> bool {
+    matches!(chunk_type, b"bKGD" | b"hIST" | b"tRNS")
+}
+
+// Ancillary chunks the specification allows at most once per image
+pub(crate) fn single_instance(chunk_type: &[u8; 4]) -> bool {
+    matches!(
+        chunk_type,
+        b"cHRM"
+            | b"gAMA"
+            | b"iCCP"
+            | b"sBIT"
+            | b"sRGB"
+            | b"cICP"
+            | b"mDCV"
+            | b"cLLI"
+            | b"bKGD"
+            | b"hIST"
+            | b"tRNS"
+            | b"eXIf"
+            | b"pHYs"
+            | b"tIME"
+            | b"acTL"
+            | b"oFFs"
+            | b"pCAL"
+            | b"sCAL"
+            | b"sTER"
+    )
+}
+
+pub(crate) fn check_ancillary(chunk_type: &[u8; 4]) -> Result<(), PngError> {
+    if !chunk_type.iter().all(u8::is_ascii_alphabetic) || chunk_type[0].is_ascii_uppercase() {
+        return Err(PngError::InvalidMetadata(format!(
+            "{} is not an ancillary chunk type",
+            String::from_utf8_lossy(chunk_type)
+        )));
+    }
+    Ok(())
+}
+
+/// A single chunk as it appears in a PNG stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    pub chunk_type: [u8; 4],
+    pub data: Vec<u8>,
+    /// The CRC stored in the file, which may not match the data.
+    pub crc: u32,
+    /// Byte offset of the chunk's length field from the start of the stream.
+    pub offset: u64,
+}
+
+impl Chunk {
+    /// Creates a chunk with a correct CRC, not yet placed in a stream.
+    pub fn new(chunk_type: [u8; 4], data: Vec<u8>) -> Self {
+        let crc = chunk_crc(&chunk_type, &data);
+        Self {
+            chunk_type,
+            data,
+            crc,
+            offset: 0,
+        }
+    }
+
+    pub fn type_str(&self) -> String {
+        String::from_utf8_lossy(&self.chunk_type).into_owned()
+    }
+
+    pub fn is_critical(&self) -> bool {
+        self.chunk_type[0].is_ascii_uppercase()
+    }
+
+    /// The CRC computed from the chunk's type and data.
+    pub fn expected_crc(&self) -> u32 {
+        chunk_crc(&self.chunk_type, &self.data)
+    }
+
+    pub fn crc_ok(&self) -> bool {
+        self.expected_crc() == self.crc
+    }
+
+    /// Writes the chunk with its stored CRC, so damaged chunks stay damaged.
+    pub(crate) fn write_stored<W: Write>(&self, writer: &mut W) -> Result<(), PngError> {
+        writer.write_all(&(self.data.len() as u32).to_be_bytes())?;
+        writer.write_all(&self.chunk_type)?;
+        writer.write_all(&self.data)?;
+        writer.write_all(&self.crc.to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Fails with [`PngError::CrcMismatch`] if the stored CRC is wrong.
+    pub fn verify_crc(&self) -> Result<(), PngError> {
+        let computed = self.expected_crc();
+        if computed != self.crc {
+            return Err(PngError::CrcMismatch {
+                chunk_type: self.chunk_type,
+                offset: self.offset,
+                stored: self.crc,
+                computed,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Reads chunks one at a time from a PNG stream, after checking the signature.
+/// Errors say at which byte, and in which chunk, reading failed.
+///
+/// CRCs are not verified here so that tools can inspect damaged files; use
+/// [`Chunk::crc_ok`] to check them.
+pub struct ChunkReader<R: Read> {
+    reader: Position<R>,
+    offset: u64,
+    finished: bool,
+}
+
+// Counts the bytes read, so a failed read can be placed exactly
+struct Position<R> {
+    inner: R,
+    position: u64,
+}
+
+impl<R: Read> Read for Position<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: Read> ChunkReader<R> {
+    pub fn new(reader: R) -> Result<Self, PngError> {
+        let mut reader = Position {
+            inner: reader,
+            position: 0,
+        };
+        let mut signature = [0; 8];
+        reader
+            .read_exact(&mut signature)
+            .map_err(|e| PngError::from(e).at(reader.position, None))?;
+        check_signature(signature)?;
+
+        Ok(Self {
+            reader,
+            offset: PNG_SIGNATURE.len() as u64,
+            finished: false,
+        })
+    }
+
+    /// Returns the next chunk, or `None` once IEND has been read or the
+    /// stream ends cleanly on a chunk boundary.
+    pub fn next_chunk(&mut self) -> Result<Option<Chunk>, PngError> {
+        if self.finished {
+            return Ok(None);
+        }
+        let mut chunk_type = None;
+        self.read_chunk(&mut chunk_type)
+            .map_err(|e| e.at(self.reader.position, chunk_type))
+    }
+
+    // Sets `chunk_type` as soon as it's known, for error context
+    fn read_chunk(&mut self, chunk_type: &mut Option<[u8; 4]>) -> Result<Option<Chunk>, PngError> {
+        let mut length = [0; 4];
+        let read = read_fully(&mut self.reader, &mut length)?;
+        if read == 0 {
+            self.finished = true;
+            return Ok(None);
+        }
+        if read < length.len() {
+            return Err(PngError::Decode("Truncated chunk header".to_string()));
+        }
+
+        let length = u32::from_be_bytes(length);
+        if length > MAX_CHUNK_LENGTH {
+            return Err(PngError::Decode(format!(
+                "Chunk length {} exceeds the maximum",
+                length
+            )));
+        }
+
+        let mut type_bytes = [0; 4];
+        self.reader.read_exact(&mut type_bytes)?;
+        *chunk_type = Some(type_bytes);
+
+        // Read through `take` so a corrupt length can't force a huge allocation
+        let mut data = Vec::new();
+        (&mut self.reader)
+            .take(length as u64)
+            .read_to_end(&mut data)?;
+        if data.len() != length as usize {
+            return Err(PngError::Decode(format!(
+                "Truncated chunk: {} of {} bytes",
+                data.len(),
+                length
+            )));
+        }
+
+        let mut crc = [0; 4];
+        self.reader.read_exact(&mut crc)?;
+
+        let chunk = Chunk {
+            chunk_type: type_bytes,
+            data,
+            crc: u32::from_be_bytes(crc),
+            offset: self.offset,
+        };
+        self.offset += 12 + length as u64;
+
+        if &chunk.chunk_type == b"IEND" {
+            self.finished = true;
+        }
+
+        Ok(Some(chunk))
+    }
+
+    /// Byte offset of the next unread byte in the stream.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader.inner
+    }
+}
+
+impl<R: Read> Iterator for ChunkReader<R> {
+    type Item = Result<Chunk, PngError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_chunk() {
+            Ok(chunk) => chunk.map(Ok),
+            Err(e) => {
+                self.finished = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+fn check_signature(signature: [u8; 8]) -> Result<(), PngError> {
+    if signature != PNG_SIGNATURE {
+        return Err(match SignatureDamage::diagnose(&signature) {
+            Some(damage) => PngError::DamagedSignature {
+                damage,
+                found: signature,
+            },
+            None => PngError::InvalidSignature { found: signature },
+        });
+    }
+    Ok(())
+}
+
+/// A chunk borrowed from a PNG held in memory; see [`ChunkSlices`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkRef<'a> {
+    pub chunk_type: [u8; 4],
+    pub data: &'a [u8],
+    /// The CRC stored in the file, which may not match the data.
+    pub crc: u32,
+    /// Byte offset of the chunk's length field from the start of the stream.
+    pub offset: u64,
+}
+
+impl ChunkRef<'_> {
+    pub fn is_critical(&self) -> bool {
+        self.chunk_type[0].is_ascii_uppercase()
+    }
+
+    /// The CRC computed from the chunk's type and data.
+    pub fn expected_crc(&self) -> u32 {
+        chunk_crc(&self.chunk_type, self.data)
+    }
+
+    /// Fails with [`PngError::CrcMismatch`] if the stored CRC is wrong.
+    pub fn verify_crc(&self) -> Result<(), PngError> {
+        let computed = self.expected_crc();
+        if computed != self.crc {
+            return Err(PngError::CrcMismatch {
+                chunk_type: self.chunk_type,
+                offset: self.offset,
+                stored: self.crc,
+                computed,
+            });
+        }
+        Ok(())
+    }
+
+    /// Copies the data into an owned [`Chunk`].
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk {
+            chunk_type: self.chunk_type,
+            data: self.data.to_vec(),
+            crc: self.crc,
+            offset: self.offset,
+        }
+    }
+}
+
+/// Walks the chunks of a PNG held in memory, such as a memory-mapped file,
+/// like [`ChunkReader`] but handing out slices of the input instead of
+/// copying each chunk's data.
+#[derive(Debug, Clone)]
+pub struct ChunkSlices<'a> {
+    data: &'a [u8],
+    offset: usize,
+    finished: bool,
+}
+
+impl<'a> ChunkSlices<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, PngError> {
+        let Some(signature) = data.first_chunk::<8>() else {
+            return Err(
+                PngError::from(io::Error::from(io::ErrorKind::UnexpectedEof))
+                    .at(data.len() as u64, None),
+            );
+        };
+        check_signature(*signature)?;
+        Ok(Self {
+            data,
+            offset: PNG_SIGNATURE.len(),
+            finished: false,
+        })
+    }
+
+    /// Returns the next chunk, or `None` once IEND has been read or the
+    /// input ends on a chunk boundary.
+    pub fn next_chunk(&mut self) -> Result<Option<ChunkRef<'a>>, PngError> {
+        if self.finished {
+            return Ok(None);
+        }
+        let rest = &self.data[self.offset..];
+        if rest.is_empty() {
+            self.finished = true;
+            return Ok(None);
+        }
+        let offset = self.offset as u64;
+        // Reads can only fail by running out of input
+        let end = self.data.len() as u64;
+
+        let Some((header, rest)) = rest.split_first_chunk::<8>() else {
+            return Err(PngError::Decode("Truncated chunk header".to_string()).at(end, None));
+        };
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        let chunk_type = [header[4], header[5], header[6], header[7]];
+        if length > MAX_CHUNK_LENGTH {
+            return Err(
+                PngError::Decode(format!("Chunk length {} exceeds the maximum", length))
+                    .at(offset + 8, Some(chunk_type)),
+            );
+        }
+        let Some((data, rest)) = rest.split_at_checked(length as usize) else {
+            return Err(PngError::Decode(format!(
+                "Truncated chunk: {} of {} bytes",
+                rest.len(),
+                length
+            ))
+            .at(end, Some(chunk_type)));
+        };
+        let Some(crc) = rest.first_chunk::<4>() else {
+            return Err(
+                PngError::from(io::Error::from(io::ErrorKind::UnexpectedEof))
+                    .at(end, Some(chunk_type)),
+            );
+        };
+
+        self.offset += 12 + length as usize;
+        if &chunk_type == b"IEND" {
+            self.finished = true;
+        }
+        Ok(Some(ChunkRef {
+            chunk_type,
+            data,
+            crc: u32::from_be_bytes(*crc),
+            offset,
+        }))
+    }
+
+    /// Byte offset of the next unread byte in the input.
+    pub fn offset(&self) -> u64 {
+        self.offset as u64
+    }
+
+    /// Whatever follows the last chunk read, such as data after IEND.
+    pub fn remainder(&self) -> &'a [u8] {
+        &self.data[self.offset..]
+    }
+}
+
+impl<'a> Iterator for ChunkSlices<'a> {
+    type Item = Result<ChunkRef<'a>, PngError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_chunk() {
+            Ok(chunk) => chunk.map(Ok),
+            Err(e) => {
+                self.finished = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+// Like `read_exact`, but reports how many bytes were read before EOF
+fn read_fully<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize, PngError> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(filled)
+}
@@ -0,0 +1,70 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::ExitCode;
+
+use png::{Animation, Frame};
+
+use super::convert::read_image;
+use super::{glob, read_input, report, write_output, Args, CliError};
+
+pub fn run_animate(raw: &[String]) -> Result<ExitCode, CliError> {
+    let args = Args::parse(raw, &[], &["--delay", "--loops"])?;
+    let Some((output, inputs)) = args.positionals().split_first() else {
+        return Err(CliError::Usage(
+            "animate requires an output file and frame files".to_string(),
+        ));
+    };
+    let inputs = glob::expand(inputs)?;
+    if inputs.is_empty() {
+        return Err(CliError::Usage("animate requires frame files".to_string()));
+    }
+    let delay = args.parsed::<u16>("--delay")?.unwrap_or(100);
+
+    let frames = inputs
+        .iter()
+        .map(|path| read_image(path).map(|image| Frame::new(image, delay)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut animation = Animation::from_frames(frames)?;
+    animation.num_plays = args.parsed::<u32>("--loops")?.unwrap_or(0);
+
+    write_output(output, |mut w| animation.write_to_file(&mut w))?;
+    report(
+        output,
+        format!("{}: {} frames", output, animation.written_frame_count()?),
+    )?;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+pub fn run_frames(raw: &[String]) -> Result<ExitCode, CliError> {
+    let args = Args::parse(raw, &["--raw"], &[])?;
+    let [input, output_dir] = args.positionals() else {
+        return Err(CliError::Usage(
+            "frames requires an input file and an output directory".to_string(),
+        ));
+    };
+
+    let data = read_input(input)?;
+    let animation =
+        Animation::read_from_file(data.as_slice()).map_err(|e| CliError::file(input, e))?;
+
+    // Raw frames are the stored sub-images; otherwise render each frame as
+    // it would be displayed
+    let images = if args.flag("--raw") {
+        animation.frames().iter().map(|f| f.image.clone()).collect()
+    } else {
+        animation.composite_frames()?
+    };
+
+    let mut out = io::stdout().lock();
+    fs::create_dir_all(output_dir).map_err(|e| CliError::file(output_dir, e))?;
+    for (i, (image, frame)) in images.iter().zip(animation.frames()).enumerate() {
+        let path = Path::new(output_dir).join(format!("frame_{:04}.png", i));
+        let path = path.to_string_lossy();
+        write_output(&path, |mut w| image.write_to_file(&mut w))?;
+        writeln!(out, "{}  delay {:.0} ms", path, frame.delay_ms())?;
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,91 @@
+use super::CliError;
+
+/// Minimal command-line parser: `--flag`, `--option value`, `--option=value`,
+/// and positionals. A lone `-` is a positional and `--` ends option parsing.
+pub struct Args {
+    positionals: Vec<String>,
+    options: Vec<(String, Option<String>)>,
+}
+
+impl Args {
+    /// `flags` take no value, `options` take exactly one. Anything else
+    /// starting with `--` is rejected.
+    pub fn parse(raw: &[String], flags: &[&str], options: &[&str]) -> Result<Self, CliError> {
+        let mut positionals = Vec::new();
+        let mut parsed = Vec::new();
+        let mut iter = raw.iter();
+
+        while let Some(arg) = iter.next() {
+            if arg == "--" {
+                positionals.extend(iter.by_ref().cloned());
+                break;
+            }
+            if !arg.starts_with("--") {
+                positionals.push(arg.clone());
+                continue;
+            }
+
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (arg.as_str(), None),
+            };
+
+            if flags.contains(&name) {
+                if inline_value.is_some() {
+                    return Err(CliError::Usage(format!("{} does not take a value", name)));
+                }
+                parsed.push((name.to_string(), None));
+            } else if options.contains(&name) {
+                let value = match inline_value {
+                    Some(value) => value,
+                    None => iter
+                        .next()
+                        .cloned()
+                        .ok_or_else(|| CliError::Usage(format!("{} requires a value", name)))?,
+                };
+                parsed.push((name.to_string(), Some(value)));
+            } else {
+                return Err(CliError::Usage(format!("Unknown option {}", name)));
+            }
+        }
+
+        Ok(Self {
+            positionals,
+            options: parsed,
+        })
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        self.options.iter().any(|(n, _)| n == name)
+    }
+
+    /// Every value given for `name`, in order.
+    pub fn values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.options
+            .iter()
+            .filter(move |(n, _)| n == name)
+            .filter_map(|(_, v)| v.as_deref())
+    }
+
+    /// The last value given for `name`.
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .rev()
+            .find(|(n, _)| n == name)
+            .and_then(|(_, v)| v.as_deref())
+    }
+
+    pub fn parsed<T: std::str::FromStr>(&self, name: &str) -> Result<Option<T>, CliError> {
+        self.value(name)
+            .map(|v| {
+                v.parse()
+                    .map_err(|_| CliError::Usage(format!("Invalid value for {}: {}", name, v)))
+            })
+            .transpose()
+    }
+
+    pub fn positionals(&self) -> &[String] {
+        &self.positionals
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use png::{Rewriter, TemplateValues, TextChunk, Timestamp};
+
+use super::{glob, write_output, Args, CliError};
+
+/// Options shared by every subcommand that can process files in bulk.
+pub const BATCH_OPTIONS: [&str; 3] = ["--output-dir", "--jobs", "--text"];
+
+/// Text entries from `--text KEY=TEMPLATE`, filled in for each file as
+/// [`TextChunk::from_template`] does.
+pub struct TextTemplates {
+    entries: Vec<(String, String)>,
+    // One time for the whole run, so a batch's files agree
+    timestamp: Timestamp,
+}
+
+impl TextTemplates {
+    pub fn parse(args: &Args) -> Result<Self, CliError> {
+        let templates = Self {
+            entries: args
+                .values("--text")
+                .map(|entry| {
+                    entry
+                        .split_once('=')
+                        .map(|(key, template)| (key.to_string(), template.to_string()))
+                        .ok_or_else(|| {
+                            CliError::Usage(format!("Expected KEY=TEMPLATE, got '{}'", entry))
+                        })
+                })
+                .collect::<Result<_, _>>()?,
+            timestamp: Timestamp::now(),
+        };
+        // Catch bad templates before any file is processed
+        templates.texts("", 0)?;
+        Ok(templates)
+    }
+
+    fn texts(&self, input: &str, index: usize) -> Result<Vec<TextChunk>, CliError> {
+        let values = TemplateValues {
+            file_name: input.to_string(),
+            index,
+            timestamp: self.timestamp,
+        };
+        self.entries
+            .iter()
+            .map(|(key, template)| Ok(TextChunk::from_template(key, template, &values)?))
+            .collect()
+    }
+
+    /// Writes the encoded PNG `data` to `output` with the text entries for
+    /// `input`, the `index`th file, added.
+    pub fn write(
+        &self,
+        input: &str,
+        index: usize,
+        output: &str,
+        data: &[u8],
+    ) -> Result<(), CliError> {
+        if self.entries.is_empty() {
+            return write_output(output, |w| Ok(w.write_all(data)?));
+        }
+        let rewriter = self
+            .texts(input, index)?
+            .into_iter()
+            .fold(Rewriter::new(), Rewriter::set_text);
+        write_output(output, |mut w| rewriter.rewrite(data, &mut w))
+    }
+}
+
+/// Expands `patterns` and runs `job(index, input, output)` for each file on
+/// a pool of worker threads, writing `<output_dir>/<name>.png`. Failures
+/// are reported as they happen and don't stop the remaining files.
+pub fn run(
+    args: &Args,
+    patterns: &[String],
+    output_dir: Option<&str>,
+    job: impl Fn(usize, &str, &str) -> Result<(), CliError> + Sync,
+) -> Result<ExitCode, CliError> {
+    let inputs = glob::expand(patterns)?;
+    if inputs.is_empty() {
+        return Err(CliError::Usage("No input files".to_string()));
+    }
+
+    let outputs = match output_dir {
+        Some(dir) => {
+            fs::create_dir_all(dir).map_err(|e| CliError::file(dir, e))?;
+            output_paths(&inputs, dir)?
+        }
+        None => inputs.clone(),
+    };
+
+    let jobs = match args.parsed::<usize>("--jobs")? {
+        Some(0) => return Err(CliError::Usage("--jobs must be at least 1".to_string())),
+        Some(jobs) => jobs,
+        None => thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+
+    let next = AtomicUsize::new(0);
+    let failures = AtomicUsize::new(0);
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(inputs.len()) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(input) = inputs.get(i) else {
+                    break;
+                };
+                if let Err(e) = job(i, input, &outputs[i]) {
+                    eprintln!("png: {}", e);
+                    failures.fetch_add(1, Ordering::Relaxed);
+                }
+            });
+        }
+    });
+
+    let failures = failures.into_inner();
+    if failures > 0 {
+        eprintln!("png: {} of {} files failed", failures, inputs.len());
+        return Ok(ExitCode::FAILURE);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+// Maps each input to `dir/<stem>.png`, refusing to let two inputs share a name
+fn output_paths(inputs: &[String], dir: &str) -> Result<Vec<String>, CliError> {
+    let mut seen = HashSet::new();
+    inputs
+        .iter()
+        .map(|input| {
+            let stem = Path::new(input)
+                .file_stem()
+                .ok_or_else(|| CliError::Usage(format!("'{}' has no file name", input)))?;
+            // Appended rather than set with `with_extension`, which would
+            // cut dotted stems like `shot.v1` short
+            let mut name = stem.to_os_string();
+            name.push(".png");
+            let output = Path::new(dir).join(name).to_string_lossy().into_owned();
+            if !seen.insert(output.clone()) {
+                return Err(CliError::Usage(format!(
+                    "More than one input would be written to {}",
+                    output
+                )));
+            }
+            Ok(output)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_paths_keep_dotted_stems() {
+        let inputs = ["shots/shot.v1.png".to_string(), "shot.v2.png".to_string()];
+        let outputs = output_paths(&inputs, "out").unwrap();
+        let expected: Vec<String> = ["shot.v1.png", "shot.v2.png"]
+            .iter()
+            .map(|name| Path::new("out").join(name).to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(outputs, expected);
+    }
+
+    #[test]
+    fn output_paths_reject_collisions() {
+        let inputs = ["a/tile.png".to_string(), "b/tile.bmp".to_string()];
+        assert!(output_paths(&inputs, "out").is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use std::io::{self, Write};
+use std::process::ExitCode;
+
+use png::{chunk_manifest, Chunk, ChunkReader};
+
+use super::{read_input, Args, CliError};
+
+const DEFAULT_PREVIEW_BYTES: usize = 32;
+
+pub fn run(raw: &[String]) -> Result<ExitCode, CliError> {
+    let args = Args::parse(raw, &["--hex", "--json"], &["--bytes"])?;
+    let [path] = args.positionals() else {
+        return Err(CliError::Usage("chunks requires one file".to_string()));
+    };
+    let mut out = io::stdout().lock();
+    if args.flag("--json") {
+        let data = read_input(path)?;
+        write!(
+            out,
+            "{}",
+            chunk_manifest(&data).map_err(|e| CliError::file(path, e))?
+        )?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    let preview = match args.parsed::<usize>("--bytes")? {
+        Some(bytes) => Some(bytes),
+        None if args.flag("--hex") => Some(DEFAULT_PREVIEW_BYTES),
+        None => None,
+    };
+
+    let data = read_input(path)?;
+    let reader = ChunkReader::new(data.as_slice()).map_err(|e| CliError::file(path, e))?;
+
+    writeln!(out, "{:>10}  {:>10}  type  crc", "offset", "length")?;
+    let mut bad_crcs = 0;
+    for chunk in reader {
+        // Report what was read so far before giving up on a damaged stream
+        let chunk = chunk.map_err(|e| CliError::file(path, e))?;
+        if !chunk.crc_ok() {
+            bad_crcs += 1;
+        }
+        print_chunk(&mut out, &chunk, preview)?;
+    }
+
+    if bad_crcs > 0 {
+        eprintln!("{}: {} chunk(s) with bad CRCs", path, bad_crcs);
+        return Ok(ExitCode::FAILURE);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn print_chunk(out: &mut impl Write, chunk: &Chunk, preview: Option<usize>) -> io::Result<()> {
+    let status = if chunk.crc_ok() {
+        format!("ok {:08x}", chunk.crc)
+    } else {
+        format!(
+            "BAD {:08x} (expected {:08x})",
+            chunk.crc,
+            chunk.expected_crc()
+        )
+    };
+    writeln!(
+        out,
+        "{:>10}  {:>10}  {}  {}",
+        chunk.offset,
+        chunk.data.len(),
+        chunk.type_str(),
+        status
+    )?;
+
+    if let Some(limit) = preview {
+        let shown = &chunk.data[..chunk.data.len().min(limit)];
+        for (i, line) in shown.chunks(16).enumerate() {
+            let hex: Vec<String> = line.iter().map(|b| format!("{:02x}", b)).collect();
+            let ascii: String = line
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            writeln!(
+                out,
+                "    {:08x}  {:<47}  |{}|",
+                i * 16,
+                hex.join(" "),
+                ascii
+            )?;
+        }
+        if chunk.data.len() > shown.len() {
+            writeln!(out, "    ... {} more bytes", chunk.data.len() - shown.len())?;
+        }
+    }
+    Ok(())
+}
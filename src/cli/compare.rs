@@ -0,0 +1,46 @@
+use std::process::ExitCode;
+
+use png::{compare, diff_image};
+
+use super::convert::read_image;
+use super::{report, write_output, Args, CliError};
+
+pub fn run(raw: &[String]) -> Result<ExitCode, CliError> {
+    let args = Args::parse(raw, &[], &["--diff", "--tolerance"])?;
+    let [expected, actual] = args.positionals() else {
+        return Err(CliError::Usage("compare requires two files".to_string()));
+    };
+    let tolerance = args.parsed::<u16>("--tolerance")?.unwrap_or(0);
+
+    let a = read_image(expected)?;
+    let b = read_image(actual)?;
+    let result = compare(&a, &b, tolerance)?;
+
+    // Keep stdout clean when the diff image is written there
+    let diff_path = args.value("--diff");
+    let out = diff_path.unwrap_or_default();
+    let percent = result.mismatched_pixels as f64 * 100.0 / result.total_pixels as f64;
+    report(out, format!("pixels:     {}", result.total_pixels))?;
+    report(
+        out,
+        format!("mismatched: {} ({:.2}%)", result.mismatched_pixels, percent),
+    )?;
+    report(out, format!("max diff:   {}", result.max_difference))?;
+    if result.psnr.is_infinite() {
+        report(out, "PSNR:       inf")?;
+    } else {
+        report(out, format!("PSNR:       {:.2} dB", result.psnr))?;
+    }
+    report(out, format!("SSIM:       {:.5}", result.ssim))?;
+
+    if let Some(path) = diff_path {
+        let diff = diff_image(&a, &b, tolerance)?;
+        write_output(path, |mut w| diff.write_to_file(&mut w))?;
+    }
+
+    Ok(if result.is_match() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
@@ -0,0 +1,160 @@
+use std::process::ExitCode;
+
+use png::{
+    transcode, BitDepth, EncodeOptions, FilterStrategy, FilterType, PngImage, TranscodeOptions,
+};
+
+use super::batch::{self, TextTemplates, BATCH_OPTIONS};
+use super::meta::parse_strip_policy;
+use super::{parse_color, parse_color_type, read_input, Args, CliError};
+
+pub fn run(raw: &[String]) -> Result<ExitCode, CliError> {
+    let options = [
+        "--color-type",
+        "--bit-depth",
+        "--level",
+        "--filter",
+        "--background",
+        "--strip",
+    ];
+    let args = Args::parse(
+        raw,
+        &["--deterministic", "--optimize-palette"],
+        &[&options[..], &BATCH_OPTIONS].concat(),
+    )?;
+
+    let color_type = args
+        .value("--color-type")
+        .map(parse_color_type)
+        .transpose()?;
+    let bit_depth = args
+        .parsed::<u8>("--bit-depth")?
+        .map(parse_bit_depth)
+        .transpose()?;
+    let background = args.value("--background").map(parse_color).transpose()?;
+    if background.is_some() && color_type.is_none() {
+        return Err(CliError::Usage(
+            "--background requires --color-type".to_string(),
+        ));
+    }
+    let mut encode_options = EncodeOptions::default();
+    if let Some(level) = args.parsed::<u8>("--level")? {
+        if level > 9 {
+            return Err(CliError::Usage(format!(
+                "Invalid compression level {}",
+                level
+            )));
+        }
+        encode_options.compression_level = level;
+    }
+    encode_options.deterministic = args.flag("--deterministic");
+    encode_options.optimize_palette = args.flag("--optimize-palette");
+    if let Some(name) = args.value("--filter") {
+        encode_options.filter = parse_filter(name)?;
+    }
+
+    let options = TranscodeOptions {
+        color_type,
+        background,
+        bit_depth,
+        strip: args
+            .value("--strip")
+            .map(parse_strip_policy)
+            .transpose()?
+            .unwrap_or_default(),
+        encode: encode_options,
+    };
+
+    let templates = TextTemplates::parse(&args)?;
+
+    let convert = |index: usize, input: &str, output: &str| {
+        let bytes = read_input(input)?;
+        let mut encoded = Vec::new();
+        // PNG input keeps its metadata
+        if bytes.starts_with(b"\x89PNG") {
+            transcode(bytes.as_slice(), &mut encoded, &options)
+                .map_err(|e| CliError::file(input, e))?;
+            return templates.write(input, index, output, &encoded);
+        }
+
+        let mut image = decode_image(input, &bytes)?;
+        match (color_type, background) {
+            (Some(color_type), Some(background)) => {
+                image.convert_to_over(color_type, background)?
+            }
+            (Some(color_type), None) => image.convert_to(color_type)?,
+            _ => {}
+        }
+        if let Some(bit_depth) = bit_depth {
+            image.convert_bit_depth(bit_depth)?;
+        }
+        image.write_with_options(&mut encoded, &options.encode)?;
+        templates.write(input, index, output, &encoded)
+    };
+
+    if let Some(dir) = args.value("--output-dir") {
+        return batch::run(
+            &args,
+            args.positionals(),
+            Some(dir),
+            |index, input, output| convert(index, input, output).map_err(|e| e.in_file(input)),
+        );
+    }
+
+    let [input, output] = args.positionals() else {
+        return Err(CliError::Usage(
+            "convert requires an input and an output file, or --output-dir".to_string(),
+        ));
+    };
+    convert(0, input, output)?;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Reads a PNG, PNM, BMP, or QOI file (or stdin for `-`), detected from its
+/// leading bytes.
+pub fn read_image(path: &str) -> Result<PngImage, CliError> {
+    decode_image(path, &read_input(path)?)
+}
+
+fn decode_image(path: &str, bytes: &[u8]) -> Result<PngImage, CliError> {
+    let image = if bytes.starts_with(b"\x89PNG") {
+        PngImage::read_from_file(bytes)
+    } else if bytes.starts_with(b"BM") {
+        PngImage::from_bmp(bytes)
+    } else if bytes.starts_with(b"qoif") {
+        PngImage::from_qoi(bytes)
+    } else if bytes.starts_with(b"P") {
+        PngImage::from_ppm(bytes)
+    } else {
+        return Err(CliError::Usage(format!(
+            "{}: unrecognized image format",
+            path
+        )));
+    };
+    image.map_err(|e| CliError::file(path, e))
+}
+
+pub fn parse_bit_depth(bits: u8) -> Result<BitDepth, CliError> {
+    match bits {
+        1 => Ok(BitDepth::One),
+        2 => Ok(BitDepth::Two),
+        4 => Ok(BitDepth::Four),
+        8 => Ok(BitDepth::Eight),
+        16 => Ok(BitDepth::Sixteen),
+        _ => Err(CliError::Usage(format!("Invalid bit depth {}", bits))),
+    }
+}
+
+fn parse_filter(name: &str) -> Result<FilterStrategy, CliError> {
+    let filter = match name {
+        "none" => FilterType::None,
+        "sub" => FilterType::Sub,
+        "up" => FilterType::Up,
+        "average" => FilterType::Average,
+        "paeth" => FilterType::Paeth,
+        "adaptive" => return Ok(FilterStrategy::Adaptive),
+        _ => return Err(CliError::Usage(format!("Unknown filter '{}'", name))),
+    };
+    Ok(FilterStrategy::Fixed(filter))
+}
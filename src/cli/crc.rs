@@ -0,0 +1,54 @@
+use std::process::ExitCode;
+
+use png::repair_crcs;
+
+use super::{read_input, report, write_output, Args, CliError};
+
+pub fn run(raw: &[String]) -> Result<ExitCode, CliError> {
+    let args = Args::parse(raw, &["--fix"], &[])?;
+    let fix = args.flag("--fix");
+    let (input, output) = match args.positionals() {
+        [input] => (input, input),
+        [input, output] if fix => (input, output),
+        _ => {
+            return Err(CliError::Usage(
+                "crc requires an input file, and takes an output file with --fix".to_string(),
+            ))
+        }
+    };
+
+    let data = read_input(input)?;
+    let mut repaired = Vec::new();
+    let repairs =
+        repair_crcs(data.as_slice(), &mut repaired).map_err(|e| CliError::file(input, e))?;
+    for repair in &repairs {
+        report(
+            output,
+            format!(
+                "{}: {} at offset {} has CRC {:08x}, expected {:08x}",
+                input,
+                String::from_utf8_lossy(&repair.chunk_type),
+                repair.offset,
+                repair.stored,
+                repair.computed
+            ),
+        )?;
+    }
+
+    if !fix {
+        if repairs.is_empty() {
+            report(output, format!("{}: all CRCs ok", input))?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        return Ok(ExitCode::FAILURE);
+    }
+    // Leave an undamaged file alone, but still copy it to a new output
+    if !repairs.is_empty() || output != input {
+        write_output(output, |w| Ok(w.write_all(&repaired)?))?;
+    }
+    report(
+        output,
+        format!("{}: fixed {} CRC(s)", output, repairs.len()),
+    )?;
+    Ok(ExitCode::SUCCESS)
+}
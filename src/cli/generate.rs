@@ -0,0 +1,90 @@
+use std::process::ExitCode;
+
+use png::{BitDepth, ColorType, Easing, Pattern, PngImage};
+
+use super::convert::parse_bit_depth;
+use super::{parse_color_type, write_output, Args, CliError};
+
+pub fn run(raw: &[String]) -> Result<ExitCode, CliError> {
+    let args = Args::parse(
+        raw,
+        &[],
+        &[
+            "--size",
+            "--color-type",
+            "--bit-depth",
+            "--cell",
+            "--seed",
+            "--scale",
+            "--octaves",
+            "--easing",
+        ],
+    )?;
+    let [pattern, output] = args.positionals() else {
+        return Err(CliError::Usage(
+            "generate requires a pattern and an output file".to_string(),
+        ));
+    };
+
+    let seed = args.parsed("--seed")?.unwrap_or(1);
+    let scale = args.parsed("--scale")?.unwrap_or(32.0);
+    let octaves = args.parsed("--octaves")?.unwrap_or(4);
+    let easing = match args.value("--easing") {
+        Some(name) => parse_easing(name)?,
+        None => Easing::Linear,
+    };
+    let pattern = match pattern.as_str() {
+        "gradient" => Pattern::Gradient { easing },
+        "checkerboard" => Pattern::Checkerboard {
+            cell: args.parsed("--cell")?.unwrap_or(8).max(1),
+        },
+        "bars" => Pattern::Bars,
+        "noise" => Pattern::Noise { seed },
+        "value-noise" => Pattern::ValueNoise {
+            scale,
+            octaves,
+            seed,
+        },
+        "perlin" => Pattern::Perlin {
+            scale,
+            octaves,
+            seed,
+        },
+        "alpha-ramp" => Pattern::AlphaRamp,
+        other => return Err(CliError::Usage(format!("Unknown pattern '{}'", other))),
+    };
+    let (width, height) = match args.value("--size") {
+        Some(size) => parse_size(size)?,
+        None => (256, 256),
+    };
+    let color_type = match args.value("--color-type") {
+        Some(name) => parse_color_type(name)?,
+        None => ColorType::Rgba,
+    };
+    let bit_depth = match args.parsed::<u8>("--bit-depth")? {
+        Some(bits) => parse_bit_depth(bits)?,
+        None => BitDepth::Eight,
+    };
+
+    let image = PngImage::generate(&pattern, width, height, color_type, bit_depth)?;
+    write_output(output, |mut w| image.write_to_file(&mut w))?;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Parses `WIDTHxHEIGHT`.
+pub fn parse_size(size: &str) -> Result<(u32, u32), CliError> {
+    size.split_once('x')
+        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+        .ok_or_else(|| CliError::Usage(format!("Invalid size '{}', expected WxH", size)))
+}
+
+fn parse_easing(name: &str) -> Result<Easing, CliError> {
+    match name {
+        "linear" => Ok(Easing::Linear),
+        "ease-in" => Ok(Easing::EaseIn),
+        "ease-out" => Ok(Easing::EaseOut),
+        "ease-in-out" => Ok(Easing::EaseInOut),
+        _ => Err(CliError::Usage(format!("Unknown easing '{}'", name))),
+    }
+}
@@ -0,0 +1,147 @@
+use std::cmp::Ordering;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::CliError;
+
+/// Expands `*`, `?`, and `[...]` wildcards in each argument, sorting each
+/// expansion naturally so `frame_10` follows `frame_9`. Arguments without
+/// wildcards are passed through untouched, even if they don't exist.
+pub fn expand(patterns: &[String]) -> Result<Vec<String>, CliError> {
+    let mut paths = Vec::new();
+    for pattern in patterns {
+        if !has_wildcard(pattern) {
+            paths.push(pattern.clone());
+            continue;
+        }
+
+        let mut matches = expand_pattern(pattern);
+        if matches.is_empty() {
+            return Err(CliError::Usage(format!("No files match '{}'", pattern)));
+        }
+        matches.sort_by(|a, b| natural_cmp(a, b));
+        paths.extend(matches);
+    }
+    Ok(paths)
+}
+
+fn has_wildcard(s: &str) -> bool {
+    s.contains(['*', '?', '['])
+}
+
+fn expand_pattern(pattern: &str) -> Vec<String> {
+    let absolute = pattern.starts_with('/');
+    let mut candidates = vec![if absolute {
+        PathBuf::from("/")
+    } else {
+        PathBuf::new()
+    }];
+
+    for component in pattern.split('/').filter(|c| !c.is_empty()) {
+        let mut next = Vec::new();
+        for base in &candidates {
+            if !has_wildcard(component) {
+                next.push(base.join(component));
+                continue;
+            }
+            let dir = if base.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                base.as_path()
+            };
+            let Ok(entries) = fs::read_dir(dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                // Like shells, only match dotfiles when the pattern asks for them
+                if name.starts_with('.') && !component.starts_with('.') {
+                    continue;
+                }
+                if matches(component.as_bytes(), name.as_bytes()) {
+                    next.push(base.join(name));
+                }
+            }
+        }
+        candidates = next;
+    }
+
+    candidates
+        .into_iter()
+        .filter(|p| p.exists())
+        .map(|p| p.to_string_lossy().into_owned())
+        .collect()
+}
+
+fn matches(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some(b'*') => (0..=name.len()).any(|skip| matches(&pattern[1..], &name[skip..])),
+        Some(b'?') => !name.is_empty() && matches(&pattern[1..], &name[1..]),
+        Some(b'[') => {
+            let Some(end) = pattern
+                .iter()
+                .skip(2)
+                .position(|&b| b == b']')
+                .map(|p| p + 2)
+            else {
+                return name.first() == Some(&b'[') && matches(&pattern[1..], &name[1..]);
+            };
+            let Some(&c) = name.first() else {
+                return false;
+            };
+            let (negated, set) = match pattern[1] {
+                b'!' | b'^' => (true, &pattern[2..end]),
+                _ => (false, &pattern[1..end]),
+            };
+            let mut found = false;
+            let mut i = 0;
+            while i < set.len() {
+                if i + 2 < set.len() && set[i + 1] == b'-' {
+                    found |= (set[i]..=set[i + 2]).contains(&c);
+                    i += 3;
+                } else {
+                    found |= set[i] == c;
+                    i += 1;
+                }
+            }
+            found != negated && matches(&pattern[end + 1..], &name[1..])
+        }
+        Some(&literal) => name.first() == Some(&literal) && matches(&pattern[1..], &name[1..]),
+    }
+}
+
+// Compares runs of digits by numeric value and everything else bytewise
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.as_bytes(), b.as_bytes());
+    loop {
+        match (a.first(), b.first()) {
+            (None, None) => return Ordering::Equal,
+            (None, _) => return Ordering::Less,
+            (_, None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let da = a.iter().take_while(|c| c.is_ascii_digit()).count();
+                let db = b.iter().take_while(|c| c.is_ascii_digit()).count();
+                let (na, nb) = (trim_zeros(&a[..da]), trim_zeros(&b[..db]));
+                let ordering = na.len().cmp(&nb.len()).then_with(|| na.cmp(nb));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a = &a[da..];
+                b = &b[db..];
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(y);
+                }
+                a = &a[1..];
+                b = &b[1..];
+            }
+        }
+    }
+}
+
+fn trim_zeros(digits: &[u8]) -> &[u8] {
+    let zeros = digits.iter().take_while(|&&d| d == b'0').count();
+    &digits[zeros..]
+}
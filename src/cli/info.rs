@@ -0,0 +1,162 @@
+use std::io::{self, Write};
+use std::process::ExitCode;
+
+use png::{PngInfo, TextKind};
+
+use super::{color_type_name, json_string, read_input, Args, CliError};
+
+pub fn run(raw: &[String]) -> Result<ExitCode, CliError> {
+    let args = Args::parse(raw, &["--json"], &[])?;
+    if args.positionals().is_empty() {
+        return Err(CliError::Usage(
+            "info requires at least one file".to_string(),
+        ));
+    }
+
+    let mut infos = Vec::new();
+    for path in args.positionals() {
+        let data = read_input(path)?;
+        let info = PngInfo::read(data.as_slice()).map_err(|e| CliError::file(path, e))?;
+        infos.push((path.as_str(), info));
+    }
+
+    let mut out = io::stdout().lock();
+    if args.flag("--json") {
+        let entries: Vec<String> = infos
+            .iter()
+            .map(|(path, info)| to_json(path, info))
+            .collect();
+        writeln!(out, "[{}]", entries.join(","))?;
+    } else {
+        for (i, (path, info)) in infos.iter().enumerate() {
+            if i > 0 {
+                writeln!(out)?;
+            }
+            print_text(&mut out, path, info)?;
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn print_text(out: &mut impl Write, path: &str, info: &PngInfo) -> io::Result<()> {
+    let header = &info.header;
+    writeln!(out, "{}", path)?;
+    writeln!(out, "  dimensions: {}x{}", header.width, header.height)?;
+    writeln!(out, "  bit depth:  {}", header.bit_depth.bits())?;
+    writeln!(out, "  color type: {}", color_type_name(header.color_type))?;
+    writeln!(
+        out,
+        "  interlace:  {}",
+        if header.interlaced { "adam7" } else { "none" }
+    )?;
+    writeln!(out, "  chunks:")?;
+    for chunk in &info.chunks {
+        writeln!(
+            out,
+            "    {}  {:>10} bytes",
+            String::from_utf8_lossy(&chunk.chunk_type),
+            chunk.length
+        )?;
+    }
+    if !info.text.is_empty() {
+        writeln!(out, "  text:")?;
+        for entry in &info.text {
+            writeln!(out, "    {}: {}", entry.keyword, entry.text)?;
+        }
+    }
+    if let Some(trailing) = &info.trailing {
+        match trailing.format {
+            Some(format) => writeln!(
+                out,
+                "  trailing:   {} bytes after IEND ({})",
+                trailing.length, format
+            )?,
+            None => writeln!(out, "  trailing:   {} bytes after IEND", trailing.length)?,
+        }
+    }
+    if !info.warnings.is_empty() {
+        writeln!(out, "  warnings:")?;
+        for warning in &info.warnings {
+            writeln!(
+                out,
+                "    {} at {}: {}",
+                String::from_utf8_lossy(&warning.chunk_type),
+                warning.offset,
+                warning.message
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn to_json(path: &str, info: &PngInfo) -> String {
+    let header = &info.header;
+    let chunks: Vec<String> = info
+        .chunks
+        .iter()
+        .map(|c| {
+            format!(
+                "{{\"type\":{},\"length\":{},\"offset\":{}}}",
+                json_string(&String::from_utf8_lossy(&c.chunk_type)),
+                c.length,
+                c.offset
+            )
+        })
+        .collect();
+    let text: Vec<String> = info
+        .text
+        .iter()
+        .map(|t| {
+            let kind = match t.kind {
+                TextKind::Plain => "tEXt",
+                TextKind::Compressed => "zTXt",
+                TextKind::International => "iTXt",
+            };
+            format!(
+                "{{\"keyword\":{},\"text\":{},\"chunk\":\"{}\",\"language\":{}}}",
+                json_string(&t.keyword),
+                json_string(&t.text),
+                kind,
+                json_string(&t.language)
+            )
+        })
+        .collect();
+    let trailing = match &info.trailing {
+        Some(trailing) => format!(
+            "{{\"offset\":{},\"length\":{},\"format\":{}}}",
+            trailing.offset,
+            trailing.length,
+            trailing
+                .format
+                .map_or("null".to_string(), |f| json_string(&f.to_string()))
+        ),
+        None => "null".to_string(),
+    };
+    let warnings: Vec<String> = info
+        .warnings
+        .iter()
+        .map(|w| {
+            format!(
+                "{{\"chunk\":{},\"offset\":{},\"message\":{}}}",
+                json_string(&String::from_utf8_lossy(&w.chunk_type)),
+                w.offset,
+                json_string(&w.message)
+            )
+        })
+        .collect();
+
+    format!(
+        "{{\"file\":{},\"width\":{},\"height\":{},\"bit_depth\":{},\"color_type\":\"{}\",\"interlaced\":{},\"chunks\":[{}],\"text\":[{}],\"trailing\":{},\"warnings\":[{}]}}",
+        json_string(path),
+        header.width,
+        header.height,
+        header.bit_depth.bits(),
+        color_type_name(header.color_type),
+        header.interlaced,
+        chunks.join(","),
+        text.join(","),
+        trailing,
+        warnings.join(",")
+    )
+}
@@ -0,0 +1,148 @@
+use std::io::{self, Write};
+use std::process::ExitCode;
+
+use png::{
+    OffsetUnit, PhysicalDimensions, PngInfo, Rewriter, ScaleUnit, StereoLayout, StripPolicy,
+    TextChunk, TextKind, Timestamp,
+};
+
+use super::{read_input, write_output, Args, CliError};
+
+const OPTIONS: [&str; 5] = ["--set", "--remove", "--time", "--dpi", "--strip"];
+const FLAGS: [&str; 2] = ["--remove-time", "--remove-dpi"];
+
+pub fn run(raw: &[String]) -> Result<ExitCode, CliError> {
+    let args = Args::parse(raw, &FLAGS, &OPTIONS)?;
+    let (input, output) = match args.positionals() {
+        [input] => (input, input),
+        [input, output] => (input, output),
+        _ => {
+            return Err(CliError::Usage(
+                "meta requires an input file and an optional output file".to_string(),
+            ))
+        }
+    };
+
+    let data = read_input(input)?;
+    let editing =
+        OPTIONS.iter().any(|o| args.value(o).is_some()) || FLAGS.iter().any(|f| args.flag(f));
+    if !editing {
+        let info = PngInfo::read(data.as_slice()).map_err(|e| CliError::file(input, e))?;
+        print_metadata(&mut io::stdout().lock(), input, &info)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let mut rewriter = Rewriter::new();
+    if let Some(classes) = args.value("--strip") {
+        rewriter = rewriter.strip(parse_strip_policy(classes)?);
+    }
+    for entry in args.values("--set") {
+        let (keyword, text) = entry
+            .split_once('=')
+            .ok_or_else(|| CliError::Usage(format!("Expected KEY=VALUE, got '{}'", entry)))?;
+        rewriter = rewriter.set_text(TextChunk::new(keyword, text));
+    }
+    for keyword in args.values("--remove") {
+        rewriter = rewriter.remove_text(keyword);
+    }
+    let time = match args.value("--time") {
+        Some("now") => Some(Timestamp::now()),
+        Some(value) => Some(value.parse::<Timestamp>()?),
+        None => None,
+    };
+    if let Some(time) = time {
+        rewriter = rewriter.replace(*b"tIME", time.to_bytes().to_vec());
+    } else if args.flag("--remove-time") {
+        rewriter = rewriter.remove(*b"tIME");
+    }
+    if let Some(dpi) = args.parsed::<f64>("--dpi")? {
+        let physical = PhysicalDimensions::from_dpi(dpi);
+        rewriter = rewriter.replace(*b"pHYs", physical.to_bytes().to_vec());
+    } else if args.flag("--remove-dpi") {
+        rewriter = rewriter.remove(*b"pHYs");
+    }
+
+    let mut out = Vec::new();
+    rewriter
+        .rewrite(data.as_slice(), &mut out)
+        .map_err(|e| CliError::file(input, e))?;
+    write_output(output, |w| Ok(w.write_all(&out)?))?;
+    Ok(ExitCode::SUCCESS)
+}
+
+pub fn parse_strip_policy(classes: &str) -> Result<StripPolicy, CliError> {
+    let mut policy = StripPolicy::default();
+    for class in classes.split(',') {
+        match class {
+            "all" => policy = StripPolicy::ALL,
+            "text" => policy.text = true,
+            "time" => policy.time = true,
+            "exif" => policy.exif = true,
+            "private" => policy.private = true,
+            _ => {
+                return Err(CliError::Usage(format!(
+                    "Unknown metadata class '{}'",
+                    class
+                )))
+            }
+        }
+    }
+    Ok(policy)
+}
+
+fn print_metadata(out: &mut impl Write, path: &str, info: &PngInfo) -> io::Result<()> {
+    writeln!(out, "{}", path)?;
+    if let Some(time) = &info.time {
+        writeln!(out, "  time: {}", time)?;
+    }
+    if let Some(physical) = &info.physical {
+        match physical.dpi() {
+            Some((x, _)) if physical.x == physical.y => writeln!(out, "  dpi:  {:.1}", x)?,
+            Some((x, y)) => writeln!(out, "  dpi:  {:.1}x{:.1}", x, y)?,
+            None => writeln!(out, "  aspect: {}:{}", physical.x, physical.y)?,
+        }
+    }
+    if let Some(offset) = &info.offset {
+        let unit = match offset.unit {
+            OffsetUnit::Pixel => "px",
+            OffsetUnit::Micrometer => "um",
+        };
+        writeln!(out, "  offset: {},{} {}", offset.x, offset.y, unit)?;
+    }
+    if let Some(scale) = &info.scale {
+        let unit = match scale.unit {
+            ScaleUnit::Meter => "m",
+            ScaleUnit::Radian => "rad",
+        };
+        writeln!(
+            out,
+            "  pixel size: {}x{} {}",
+            scale.width(),
+            scale.height(),
+            unit
+        )?;
+    }
+    match info.stereo {
+        Some(StereoLayout::CrossFuse) => writeln!(out, "  stereo: cross-fuse")?,
+        Some(StereoLayout::DivergingFuse) => writeln!(out, "  stereo: diverging-fuse")?,
+        None => {}
+    }
+    for entry in &info.text {
+        let kind = match entry.kind {
+            TextKind::Plain => "tEXt",
+            TextKind::Compressed => "zTXt",
+            TextKind::International => "iTXt",
+        };
+        writeln!(out, "  {} {}: {}", kind, entry.keyword, entry.text)?;
+    }
+    for warning in &info.warnings {
+        writeln!(
+            out,
+            "  warning: {} at {}: {}",
+            String::from_utf8_lossy(&warning.chunk_type),
+            warning.offset,
+            warning.message
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,285 @@
+mod animate;
+mod args;
+mod batch;
+mod chunks;
+mod compare;
+mod convert;
+mod crc;
+mod generate;
+mod glob;
+mod info;
+mod meta;
+mod optimize;
+mod trailing;
+mod transform;
+
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::process::ExitCode;
+
+use png::{Color, ColorType, PngError};
+use thiserror::Error;
+
+use args::Args;
+
+#[derive(Error, Debug)]
+pub enum CliError {
+    #[error("{0}")]
+    Usage(String),
+
+    #[error("{path}: {source}")]
+    File { path: String, source: PngError },
+
+    #[error(transparent)]
+    Png(#[from] PngError),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl CliError {
+    fn file(path: &str, source: impl Into<PngError>) -> Self {
+        CliError::File {
+            path: path.to_string(),
+            source: source.into(),
+        }
+    }
+
+    /// Whether this is a write to a closed pipe, as when output is piped
+    /// into `head`, which ends the command cleanly rather than failing.
+    pub fn is_broken_pipe(&self) -> bool {
+        let io_error = match self {
+            CliError::Io(e) => Some(e),
+            CliError::Png(source) | CliError::File { source, .. } => match source.root() {
+                PngError::Io(e) => Some(e),
+                _ => None,
+            },
+            CliError::Usage(_) => None,
+        };
+        io_error.is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe)
+    }
+
+    /// Attaches `path` to a library error that doesn't name its file yet.
+    fn in_file(self, path: &str) -> Self {
+        match self {
+            CliError::Png(source) => CliError::file(path, source),
+            e => e,
+        }
+    }
+}
+
+pub const USAGE: &str = "\
+Usage: png <command> [options]
+
+Any input or output file may be - for stdin or stdout. convert, optimize,
+resize, and crop also take several inputs (or globs) with --output-dir <dir>,
+processed in parallel on --jobs <n> worker threads. They can tag each output
+with --text <key=template> (repeatable), where the template may contain
+{filename}, {stem}, {index} (or zero-padded {index:04}), and {timestamp}.
+
+Commands:
+  info [--json] <file>...    Show header, chunk, and text information
+  convert <input> <output>   Convert PNG/PNM/BMP/QOI to PNG, keeping PNG metadata
+  convert <input>... --output-dir <dir>
+                             Convert many files (or globs) in parallel
+      --color-type <type>      grayscale, grayscale-alpha, rgb, or rgba
+      --bit-depth <bits>       1, 2, 4, 8, or 16
+      --level <0-9>            zlib compression level
+      --filter <filter>        none, sub, up, average, paeth, or adaptive
+      --background <color>     Composite dropped alpha over white, black, or
+                               #rrggbb instead of discarding it
+      --deterministic          Reproducible output: no tIME, stable chunk order
+      --optimize-palette       Drop unused and duplicate palette entries
+      --strip <classes>        Remove metadata, as for meta --strip
+  optimize <input> [<output>]
+                             Losslessly recompress, in place without <output>
+  optimize <input>... (--output-dir <dir> | --in-place)
+                             Optimize many files (or globs) in parallel
+      --strip                  Remove ancillary chunks except tRNS
+      --keep-chunks <list>     Comma-separated chunk types to keep when stripping
+      --level <0-9>            zlib compression level (default 9)
+  chunks <file>              List chunks with offsets, lengths, and CRC status
+      --hex                    Show a hex preview of each chunk's data
+      --bytes <n>              Preview length (default 32, implies --hex)
+      --json                   Print a JSON manifest with decoded values and
+                               chunk data, as png::chunk_manifest writes
+  crc <file>                 Check chunk CRCs; exits 1 if any are wrong
+  crc --fix <input> [<output>]
+                             Correct wrong CRCs without touching chunk data,
+                             in place without <output>
+  trailing <file>            Report data appended after IEND, such as a ZIP
+                             polyglot; exits 1 if there is any
+      --extract <file>         Save the appended data
+      --strip <output>         Write the PNG without it (and exit 0)
+  compare <a> <b>            Compare pixels; exits 1 if the images differ
+      --tolerance <n>          Ignore channel differences up to n
+      --diff <file>            Write an image highlighting differences
+  animate <output> <frame>...
+                             Assemble frames (or a glob of them) into an APNG
+      --delay <ms>             Frame delay (default 100)
+      --loops <n>              Times to play, 0 for forever (default 0)
+  frames <input> <dir>       Write each APNG frame as a PNG
+      --raw                    Write stored sub-frames instead of rendered frames
+  generate <pattern> <output>
+                             Write a test image: gradient, checkerboard, bars,
+                             noise, value-noise, perlin, or alpha-ramp
+      --size <WxH>             Image size (default 256x256)
+      --color-type <type>      Color type (default rgba)
+      --bit-depth <bits>       Bit depth (default 8)
+      --cell <n>               Checkerboard cell size (default 8)
+      --seed <n>               Noise seed (default 1)
+      --scale <px>             Noise feature size (default 32)
+      --octaves <n>            Noise detail levels (default 4)
+      --easing <easing>        Gradient easing: linear, ease-in, ease-out, or
+                               ease-in-out
+  meta <input> [<output>]    Show or edit metadata without re-encoding pixels,
+                             in place without <output>
+      --set <key=value>        Add or replace a text entry (repeatable)
+      --remove <key>           Remove text entries with this keyword (repeatable)
+      --time <now|timestamp>   Set the modification time (YYYY-MM-DDTHH:MM:SS)
+      --remove-time            Remove the modification time
+      --dpi <n>                Set the pixel density
+      --remove-dpi             Remove the pixel density
+      --strip <classes>        Remove text, time, exif, and/or private chunks
+                               (comma-separated), or all of them
+  resize <input> <output>    Scale an image; a missing side keeps the aspect ratio
+      --width <n|p%>           New width in pixels or percent
+      --height <n|p%>          New height in pixels or percent
+      --filter <filter>        nearest, bilinear, bicubic, or lanczos3 (default)
+  crop <input> <output>      Cut out a region; sizes default to the rest of the image
+      --x <n|p%>, --y <n|p%>   Top-left corner (default 0)
+      --width <n|p%>           Region width
+      --height <n|p%>          Region height
+  help                       Show this message";
+
+pub fn run(args: &[String]) -> Result<ExitCode, CliError> {
+    match dispatch(args) {
+        Err(e) if e.is_broken_pipe() => Ok(ExitCode::SUCCESS),
+        result => result,
+    }
+}
+
+fn dispatch(args: &[String]) -> Result<ExitCode, CliError> {
+    let (command, rest) = args
+        .split_first()
+        .ok_or_else(|| CliError::Usage("Missing command".to_string()))?;
+
+    match command.as_str() {
+        "info" => info::run(rest),
+        "convert" => convert::run(rest),
+        "optimize" => optimize::run(rest),
+        "chunks" => chunks::run(rest),
+        "crc" => crc::run(rest),
+        "trailing" => trailing::run(rest),
+        "compare" => compare::run(rest),
+        "animate" => animate::run_animate(rest),
+        "frames" => animate::run_frames(rest),
+        "generate" => generate::run(rest),
+        "meta" => meta::run(rest),
+        "resize" => transform::run_resize(rest),
+        "crop" => transform::run_crop(rest),
+        "help" | "--help" | "-h" => {
+            writeln!(io::stdout().lock(), "{}", USAGE)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        other => Err(CliError::Usage(format!("Unknown command '{}'", other))),
+    }
+}
+
+/// Reads all of `path`, or stdin for `-`.
+pub fn read_input(path: &str) -> Result<Vec<u8>, CliError> {
+    if path == "-" {
+        let mut data = Vec::new();
+        io::stdin()
+            .lock()
+            .read_to_end(&mut data)
+            .map_err(|e| CliError::file(path, e))?;
+        Ok(data)
+    } else {
+        fs::read(path).map_err(|e| CliError::file(path, e))
+    }
+}
+
+/// Runs `write` against `path`, or stdout for `-`, and flushes the result.
+pub fn write_output(
+    path: &str,
+    write: impl FnOnce(&mut dyn Write) -> Result<(), PngError>,
+) -> Result<(), CliError> {
+    let mut writer: Box<dyn Write> = if path == "-" {
+        Box::new(io::stdout().lock())
+    } else {
+        Box::new(BufWriter::new(
+            File::create(path).map_err(|e| CliError::file(path, e))?,
+        ))
+    };
+    write(&mut writer).map_err(|e| CliError::file(path, e))?;
+    writer.flush().map_err(|e| CliError::file(path, e))
+}
+
+/// Prints a status line, on stderr if `output` is stdout so it doesn't mix
+/// with the image data.
+pub fn report(output: &str, message: impl Display) -> Result<(), CliError> {
+    if output == "-" {
+        eprintln!("{}", message);
+    } else {
+        writeln!(io::stdout().lock(), "{}", message)?;
+    }
+    Ok(())
+}
+
+pub fn color_type_name(color_type: ColorType) -> &'static str {
+    match color_type {
+        ColorType::Grayscale => "grayscale",
+        ColorType::Rgb => "rgb",
+        ColorType::GrayscaleAlpha => "grayscale-alpha",
+        ColorType::Rgba => "rgba",
+        ColorType::Indexed => "indexed",
+    }
+}
+
+pub fn parse_color_type(name: &str) -> Result<ColorType, CliError> {
+    match name {
+        "grayscale" => Ok(ColorType::Grayscale),
+        "rgb" => Ok(ColorType::Rgb),
+        "grayscale-alpha" => Ok(ColorType::GrayscaleAlpha),
+        "rgba" => Ok(ColorType::Rgba),
+        "indexed" => Ok(ColorType::Indexed),
+        _ => Err(CliError::Usage(format!("Unknown color type '{}'", name))),
+    }
+}
+
+/// Parses `white`, `black`, or a `#rrggbb` hex color.
+pub fn parse_color(name: &str) -> Result<Color, CliError> {
+    match name {
+        "white" => return Ok(Color::WHITE),
+        "black" => return Ok(Color::BLACK),
+        _ => {}
+    }
+    let invalid = || CliError::Usage(format!("Invalid color '{}'", name));
+    let hex = name.strip_prefix('#').ok_or_else(invalid)?;
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err(invalid());
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+    Ok(Color::Rgb(channel(0)?, channel(2)?, channel(4)?))
+}
+
+/// Quotes and escapes `s` as a JSON string.
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
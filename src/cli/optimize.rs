@@ -0,0 +1,92 @@
+use std::process::ExitCode;
+
+use png::{optimize, OptimizeOptions};
+
+use super::batch::{self, TextTemplates, BATCH_OPTIONS};
+use super::{read_input, report, Args, CliError};
+
+pub fn run(raw: &[String]) -> Result<ExitCode, CliError> {
+    let options = ["--level", "--keep-chunks"];
+    let args = Args::parse(
+        raw,
+        &["--strip", "--in-place"],
+        &[&options[..], &BATCH_OPTIONS].concat(),
+    )?;
+
+    let mut options = OptimizeOptions {
+        strip: args.flag("--strip"),
+        ..OptimizeOptions::default()
+    };
+    if let Some(level) = args.parsed::<u8>("--level")? {
+        if level > 9 {
+            return Err(CliError::Usage(format!(
+                "Invalid compression level {}",
+                level
+            )));
+        }
+        options.compression_level = level;
+    }
+    if let Some(list) = args.value("--keep-chunks") {
+        options.keep_chunks = parse_chunk_list(list)?;
+    }
+
+    let templates = TextTemplates::parse(&args)?;
+
+    let optimize_file = |index: usize, input: &str, output: &str| -> Result<(), CliError> {
+        let data = read_input(input)?;
+        let result = optimize(&data, &options).map_err(|e| CliError::file(input, e))?;
+        templates.write(input, index, output, &result.data)?;
+
+        let saved = result.original_size as f64 - result.optimized_size as f64;
+        report(
+            output,
+            format!(
+                "{}: {} -> {} bytes ({:.1}% smaller)",
+                input,
+                result.original_size,
+                result.optimized_size,
+                saved * 100.0 / result.original_size as f64
+            ),
+        )?;
+        Ok(())
+    };
+
+    // In batch mode every positional is an input, so a shell-expanded pair of
+    // files can't be mistaken for an input and its output
+    let output_dir = args.value("--output-dir");
+    if output_dir.is_some() || args.flag("--in-place") {
+        return batch::run(
+            &args,
+            args.positionals(),
+            output_dir,
+            |index, input, output| {
+                optimize_file(index, input, output).map_err(|e| e.in_file(input))
+            },
+        );
+    }
+
+    match args.positionals() {
+        [input] => optimize_file(0, input, input)?,
+        [input, output] => optimize_file(0, input, output)?,
+        _ => {
+            return Err(CliError::Usage(
+                "optimize requires an input file and an optional output file".to_string(),
+            ))
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Parses a comma-separated list of four-letter chunk types.
+pub fn parse_chunk_list(list: &str) -> Result<Vec<[u8; 4]>, CliError> {
+    list.split(',')
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            <[u8; 4]>::try_from(name.as_bytes())
+                .ok()
+                .filter(|t| t.iter().all(u8::is_ascii_alphabetic))
+                .ok_or_else(|| CliError::Usage(format!("Invalid chunk type '{}'", name)))
+        })
+        .collect()
+}
@@ -0,0 +1,47 @@
+use std::process::ExitCode;
+
+use png::{split_trailing_data, PngInfo};
+
+use super::{read_input, report, write_output, Args, CliError};
+
+pub fn run(raw: &[String]) -> Result<ExitCode, CliError> {
+    let args = Args::parse(raw, &[], &["--extract", "--strip"])?;
+    let [input] = args.positionals() else {
+        return Err(CliError::Usage("trailing requires one file".to_string()));
+    };
+
+    let data = read_input(input)?;
+    let info = PngInfo::read(data.as_slice()).map_err(|e| CliError::file(input, e))?;
+    // Status goes to stderr if either output is stdout
+    let out = ["--extract", "--strip"]
+        .iter()
+        .filter_map(|name| args.value(name))
+        .find(|&path| path == "-")
+        .unwrap_or_default();
+    let Some(trailing) = info.trailing else {
+        report(out, format!("{}: no data after IEND", input))?;
+        return Ok(ExitCode::SUCCESS);
+    };
+    let format = trailing
+        .format
+        .map_or(String::new(), |format| format!(" ({})", format));
+    report(
+        out,
+        format!(
+            "{}: {} bytes after IEND at offset {}{}",
+            input, trailing.length, trailing.offset, format
+        ),
+    )?;
+
+    let mut png = Vec::new();
+    let payload =
+        split_trailing_data(data.as_slice(), &mut png).map_err(|e| CliError::file(input, e))?;
+    if let Some(path) = args.value("--extract") {
+        write_output(path, |w| Ok(w.write_all(&payload)?))?;
+    }
+    if let Some(path) = args.value("--strip") {
+        write_output(path, |w| Ok(w.write_all(&png)?))?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    Ok(ExitCode::FAILURE)
+}
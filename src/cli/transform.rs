@@ -0,0 +1,158 @@
+use std::process::ExitCode;
+
+use png::ResizeFilter;
+
+use super::batch::{self, TextTemplates, BATCH_OPTIONS};
+use super::convert::read_image;
+use super::{Args, CliError};
+
+pub fn run_resize(raw: &[String]) -> Result<ExitCode, CliError> {
+    let options = ["--width", "--height", "--filter"];
+    let args = Args::parse(raw, &[], &[&options[..], &BATCH_OPTIONS].concat())?;
+
+    let width = args.value("--width").map(Length::parse).transpose()?;
+    let height = args.value("--height").map(Length::parse).transpose()?;
+    if width.is_none() && height.is_none() {
+        return Err(CliError::Usage(
+            "resize requires --width, --height, or both".to_string(),
+        ));
+    }
+    let filter = match args.value("--filter") {
+        Some(name) => parse_resize_filter(name)?,
+        None => ResizeFilter::Lanczos3,
+    };
+
+    let templates = TextTemplates::parse(&args)?;
+
+    let resize = |index: usize, input: &str, output: &str| -> Result<(), CliError> {
+        let image = read_image(input)?;
+        let (w, h) = (image.width(), image.height());
+
+        // A missing side keeps the aspect ratio
+        let (new_w, new_h) = match (width, height) {
+            (Some(width), Some(height)) => (width.resolve(w), height.resolve(h)),
+            (Some(width), None) => {
+                let new_w = width.resolve(w);
+                (new_w, scaled(h, new_w, w))
+            }
+            (None, Some(height)) => {
+                let new_h = height.resolve(h);
+                (scaled(w, new_h, h), new_h)
+            }
+            (None, None) => (w, h),
+        };
+
+        let mut encoded = Vec::new();
+        image
+            .resize(new_w, new_h, filter)?
+            .write_to_file(&mut encoded)?;
+        templates.write(input, index, output, &encoded)
+    };
+
+    if let Some(dir) = args.value("--output-dir") {
+        return batch::run(
+            &args,
+            args.positionals(),
+            Some(dir),
+            |index, input, output| resize(index, input, output).map_err(|e| e.in_file(input)),
+        );
+    }
+    let [input, output] = args.positionals() else {
+        return Err(CliError::Usage(
+            "resize requires an input and an output file, or --output-dir".to_string(),
+        ));
+    };
+    resize(0, input, output)?;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+pub fn run_crop(raw: &[String]) -> Result<ExitCode, CliError> {
+    let options = ["--x", "--y", "--width", "--height"];
+    let args = Args::parse(raw, &[], &[&options[..], &BATCH_OPTIONS].concat())?;
+
+    let length = |name: &str| args.value(name).map(Length::parse).transpose();
+    let (x, y) = (length("--x")?, length("--y")?);
+    let (width, height) = (length("--width")?, length("--height")?);
+
+    let templates = TextTemplates::parse(&args)?;
+
+    let crop = |index: usize, input: &str, output: &str| -> Result<(), CliError> {
+        let image = read_image(input)?;
+        let (w, h) = (image.width(), image.height());
+
+        let x = x.map_or(0, |x| x.resolve(w));
+        let y = y.map_or(0, |y| y.resolve(h));
+        // Without a size, keep everything right of and below the corner
+        let width = width.map_or(w.saturating_sub(x), |width| width.resolve(w));
+        let height = height.map_or(h.saturating_sub(y), |height| height.resolve(h));
+
+        let mut encoded = Vec::new();
+        image
+            .crop(x, y, width, height)?
+            .write_to_file(&mut encoded)?;
+        templates.write(input, index, output, &encoded)
+    };
+
+    if let Some(dir) = args.value("--output-dir") {
+        return batch::run(
+            &args,
+            args.positionals(),
+            Some(dir),
+            |index, input, output| crop(index, input, output).map_err(|e| e.in_file(input)),
+        );
+    }
+    let [input, output] = args.positionals() else {
+        return Err(CliError::Usage(
+            "crop requires an input and an output file, or --output-dir".to_string(),
+        ));
+    };
+    crop(0, input, output)?;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// A size or position given in pixels or as a percentage of the image.
+#[derive(Clone, Copy)]
+enum Length {
+    Pixels(u32),
+    Percent(f64),
+}
+
+impl Length {
+    fn parse(value: &str) -> Result<Self, CliError> {
+        let invalid = || CliError::Usage(format!("Invalid length '{}'", value));
+        match value.strip_suffix('%') {
+            Some(percent) => {
+                let percent: f64 = percent.parse().map_err(|_| invalid())?;
+                if !percent.is_finite() || percent < 0.0 {
+                    return Err(invalid());
+                }
+                Ok(Length::Percent(percent))
+            }
+            None => value.parse().map(Length::Pixels).map_err(|_| invalid()),
+        }
+    }
+
+    fn resolve(&self, full: u32) -> u32 {
+        match *self {
+            Length::Pixels(pixels) => pixels,
+            Length::Percent(percent) => (full as f64 * percent / 100.0).round() as u32,
+        }
+    }
+}
+
+// `side` scaled by new/old, never rounding down to zero
+fn scaled(side: u32, new: u32, old: u32) -> u32 {
+    ((side as f64 * new as f64 / old as f64).round() as u32).max(1)
+}
+
+fn parse_resize_filter(name: &str) -> Result<ResizeFilter, CliError> {
+    match name {
+        "nearest" => Ok(ResizeFilter::Nearest),
+        "bilinear" => Ok(ResizeFilter::Bilinear),
+        "bicubic" => Ok(ResizeFilter::Bicubic),
+        "lanczos3" => Ok(ResizeFilter::Lanczos3),
+        _ => Err(CliError::Usage(format!("Unknown resize filter '{}'", name))),
+    }
+}
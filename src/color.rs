@@ -0,0 +1,92 @@
+use crate::error::PngError;
+use crate::{BitDepth, ColorType, PngImage};
+
+/// A single color at 8 bits per channel, converted to an image's color type
+/// and bit depth when used with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Gray(u8),
+    GrayAlpha(u8, u8),
+    Rgb(u8, u8, u8),
+    Rgba(u8, u8, u8, u8),
+    /// A palette index, only valid for indexed images
+    Index(u8),
+}
+
+impl Color {
+    pub const WHITE: Color = Color::Rgb(255, 255, 255);
+    pub const BLACK: Color = Color::Rgb(0, 0, 0);
+    pub const TRANSPARENT: Color = Color::Rgba(0, 0, 0, 0);
+
+    /// The color as RGBA, or `None` for a palette index.
+    pub fn to_rgba(self) -> Option<(u8, u8, u8, u8)> {
+        match self {
+            Color::Gray(v) => Some((v, v, v, 255)),
+            Color::GrayAlpha(v, a) => Some((v, v, v, a)),
+            Color::Rgb(r, g, b) => Some((r, g, b, 255)),
+            Color::Rgba(r, g, b, a) => Some((r, g, b, a)),
+            Color::Index(_) => None,
+        }
+    }
+}
+
+impl PngImage {
+    /// Appends one pixel, converting `color` to the image's format; see
+    /// [`PngImage::color_components`].
+    pub fn add_color(&mut self, color: Color) -> Result<(), PngError> {
+        let components = self.color_components(color)?;
+        self.add_pixel(&components)
+    }
+
+    /// The bytes `add_pixel` expects for `color` in this image's format.
+    /// Gray and color convert into each other with Rec. 601 luma weights and
+    /// alpha is dropped for color types without it. Indexed images take
+    /// `Color::Index` or a color that exactly matches a palette entry.
+    pub fn color_components(&self, color: Color) -> Result<Vec<u8>, PngError> {
+        if self.color_type == ColorType::Indexed {
+            let index = match color {
+                Color::Index(index) => index,
+                _ => {
+                    let rgba = color.to_rgba();
+                    let entries = self.palette().unwrap_or_default();
+                    let index = entries
+                        .iter()
+                        .position(|&entry| Some(entry) == rgba)
+                        .ok_or_else(|| {
+                            PngError::InvalidPalette(format!("{:?} is not in the palette", color))
+                        })?;
+                    index as u8
+                }
+            };
+            if index as u16 > self.bit_depth.max_value() {
+                return Err(PngError::SampleOutOfRange {
+                    value: index as u16,
+                    bit_depth: self.bit_depth.bits(),
+                });
+            }
+            return Ok(vec![index]);
+        }
+
+        let (r, g, b, a) = color.to_rgba().ok_or(PngError::ColorTypeError)?;
+        // Rec. 601 luma weights
+        let gray = ((r as u32 * 299 + g as u32 * 587 + b as u32 * 114 + 500) / 1000) as u8;
+        let samples: &[u8] = match self.color_type {
+            ColorType::Grayscale => &[gray],
+            ColorType::GrayscaleAlpha => &[gray, a],
+            ColorType::Rgb => &[r, g, b],
+            _ => &[r, g, b, a],
+        };
+
+        let max = self.bit_depth.max_value() as u32;
+        let mut components = Vec::with_capacity(samples.len() * 2);
+        for &sample in samples {
+            match self.bit_depth {
+                BitDepth::Sixteen => {
+                    components.extend_from_slice(&(sample as u16 * 257).to_be_bytes())
+                }
+                _ => components.push(((sample as u32 * max + 127) / 255) as u8),
+            }
+        }
+        Ok(components)
+    }
+}
@@ -0,0 +1,244 @@
+use crc::{Crc, CRC_64_XZ};
+
+use crate::color::Color;
+use crate::error::PngError;
+use crate::montage::montage;
+use crate::{BitDepth, ColorType, PngImage};
+
+// Gap between the panels of a side-by-side diff, and around them
+const PANEL_PADDING: u32 = 4;
+const PANEL_BACKGROUND: Color = Color::Rgb(128, 128, 128);
+
+const CRC64: Crc<u64> = Crc::<u64>::new(&CRC_64_XZ);
+
+/// The result of comparing two images pixel by pixel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Comparison {
+    pub total_pixels: usize,
+    /// Pixels where any channel differs by more than the tolerance
+    pub mismatched_pixels: usize,
+    /// Largest per-channel difference, in the samples' own range
+    pub max_difference: u16,
+    /// Peak signal-to-noise ratio in dB; infinite for identical images
+    pub psnr: f64,
+    /// Mean structural similarity of the luma channels, 1.0 when identical
+    pub ssim: f64,
+}
+
+impl Comparison {
+    pub fn is_match(&self) -> bool {
+        self.mismatched_pixels == 0
+    }
+}
+
+// Both images as RGBA at a shared depth, one u16 per channel
+struct Normalized {
+    width: usize,
+    height: usize,
+    max: f64,
+    samples: Vec<u16>,
+}
+
+fn normalize(image: &PngImage, sixteen: bool) -> Result<Normalized, PngError> {
+    let mut image = image.clone();
+    image.convert_to(ColorType::Rgba)?;
+    image.convert_bit_depth(if sixteen {
+        BitDepth::Sixteen
+    } else {
+        BitDepth::Eight
+    })?;
+
+    let samples = if sixteen {
+        image
+            .data
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect()
+    } else {
+        image.data.iter().map(|&b| b as u16).collect()
+    };
+
+    Ok(Normalized {
+        width: image.width as usize,
+        height: image.height as usize,
+        max: image.bit_depth.max_value() as f64,
+        samples,
+    })
+}
+
+fn normalize_pair(a: &PngImage, b: &PngImage) -> Result<(Normalized, Normalized), PngError> {
+    if (a.width, a.height) != (b.width, b.height) {
+        return Err(PngError::ImageSizeMismatch {
+            expected: (a.width, a.height),
+            actual: (b.width, b.height),
+        });
+    }
+
+    // Compare at 16 bits if either side has the extra precision
+    let sixteen = a.bit_depth == BitDepth::Sixteen || b.bit_depth == BitDepth::Sixteen;
+    Ok((normalize(a, sixteen)?, normalize(b, sixteen)?))
+}
+
+impl PngImage {
+    /// A 64-bit fingerprint of what the image looks like, for finding
+    /// duplicates stored differently. It depends only on the size and the
+    /// pixels as 16-bit RGBA, so it's the same whatever the compression,
+    /// filters, metadata, color type, or bit depth, and invisible colors
+    /// under zero alpha are ignored. The value is stable across platforms
+    /// and versions of this crate, but it's a CRC, not a cryptographic hash,
+    /// so files can be crafted to collide.
+    pub fn content_hash(&self) -> Result<u64, PngError> {
+        let normalized = normalize(self, true)?;
+        let mut digest = CRC64.digest();
+        digest.update(&(normalized.width as u32).to_be_bytes());
+        digest.update(&(normalized.height as u32).to_be_bytes());
+        let mut bytes = Vec::with_capacity(normalized.width * 8);
+        for row in normalized.samples.chunks_exact(normalized.width.max(1) * 4) {
+            bytes.clear();
+            for pixel in row.chunks_exact(4) {
+                let pixel = if pixel[3] == 0 { &[0; 4] } else { pixel };
+                bytes.extend(pixel.iter().flat_map(|sample| sample.to_be_bytes()));
+            }
+            digest.update(&bytes);
+        }
+        Ok(digest.finalize())
+    }
+}
+
+/// Compares two images of the same size, regardless of color type or bit
+/// depth. Channel differences up to `tolerance` don't count as mismatches.
+pub fn compare(a: &PngImage, b: &PngImage, tolerance: u16) -> Result<Comparison, PngError> {
+    let (a, b) = normalize_pair(a, b)?;
+
+    let mut mismatched_pixels = 0;
+    let mut max_difference = 0;
+    let mut squared_error = 0.0;
+    for (pa, pb) in a.samples.chunks_exact(4).zip(b.samples.chunks_exact(4)) {
+        let mut mismatch = false;
+        for (&sa, &sb) in pa.iter().zip(pb) {
+            let difference = sa.abs_diff(sb);
+            max_difference = max_difference.max(difference);
+            mismatch |= difference > tolerance;
+            squared_error += (difference as f64).powi(2);
+        }
+        if mismatch {
+            mismatched_pixels += 1;
+        }
+    }
+
+    let mse = squared_error / a.samples.len() as f64;
+    let psnr = if mse == 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * (a.max * a.max / mse).log10()
+    };
+
+    Ok(Comparison {
+        total_pixels: a.samples.len() / 4,
+        mismatched_pixels,
+        max_difference,
+        psnr,
+        ssim: ssim(&a, &b),
+    })
+}
+
+// Luma premultiplied by alpha, scaled to 0..1, so transparent differences
+// still register
+fn luma(image: &Normalized) -> Vec<f64> {
+    image
+        .samples
+        .chunks_exact(4)
+        .map(|p| {
+            let y = 0.299 * p[0] as f64 + 0.587 * p[1] as f64 + 0.114 * p[2] as f64;
+            y * p[3] as f64 / (image.max * image.max)
+        })
+        .collect()
+}
+
+const SSIM_WINDOW: usize = 8;
+const SSIM_STEP: usize = 4;
+
+fn ssim(a: &Normalized, b: &Normalized) -> f64 {
+    let (la, lb) = (luma(a), luma(b));
+    let (width, height) = (a.width, a.height);
+    let window_w = SSIM_WINDOW.min(width);
+    let window_h = SSIM_WINDOW.min(height);
+    let c1 = 0.01f64.powi(2);
+    let c2 = 0.03f64.powi(2);
+
+    let mut positions_x: Vec<usize> = (0..=width - window_w).step_by(SSIM_STEP).collect();
+    let mut positions_y: Vec<usize> = (0..=height - window_h).step_by(SSIM_STEP).collect();
+    // Always cover the right and bottom edges
+    if positions_x.last() != Some(&(width - window_w)) {
+        positions_x.push(width - window_w);
+    }
+    if positions_y.last() != Some(&(height - window_h)) {
+        positions_y.push(height - window_h);
+    }
+
+    let n = (window_w * window_h) as f64;
+    let mut total = 0.0;
+    let mut windows = 0;
+    for &y0 in &positions_y {
+        for &x0 in &positions_x {
+            let (mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) =
+                (0.0, 0.0, 0.0, 0.0, 0.0);
+            for y in y0..y0 + window_h {
+                for x in x0..x0 + window_w {
+                    let (va, vb) = (la[y * width + x], lb[y * width + x]);
+                    sum_a += va;
+                    sum_b += vb;
+                    sum_aa += va * va;
+                    sum_bb += vb * vb;
+                    sum_ab += va * vb;
+                }
+            }
+            let (mean_a, mean_b) = (sum_a / n, sum_b / n);
+            let var_a = sum_aa / n - mean_a * mean_a;
+            let var_b = sum_bb / n - mean_b * mean_b;
+            let covariance = sum_ab / n - mean_a * mean_b;
+
+            total += ((2.0 * mean_a * mean_b + c1) * (2.0 * covariance + c2))
+                / ((mean_a * mean_a + mean_b * mean_b + c1) * (var_a + var_b + c2));
+            windows += 1;
+        }
+    }
+    total / windows as f64
+}
+
+/// Renders an 8-bit RGBA image that shows `a` faded to gray where the images
+/// match and solid red wherever they differ by more than `tolerance`.
+pub fn diff_image(a: &PngImage, b: &PngImage, tolerance: u16) -> Result<PngImage, PngError> {
+    let (na, nb) = normalize_pair(a, b)?;
+    let scale = na.max / 255.0;
+
+    let mut diff = PngImage::new(a.width, a.height, ColorType::Rgba)?;
+    for (pa, pb) in na.samples.chunks_exact(4).zip(nb.samples.chunks_exact(4)) {
+        let differs = pa
+            .iter()
+            .zip(pb)
+            .any(|(&sa, &sb)| sa.abs_diff(sb) > tolerance);
+        if differs {
+            diff.data.extend_from_slice(&[255, 0, 0, 255])?;
+        } else {
+            let y = (0.299 * pa[0] as f64 + 0.587 * pa[1] as f64 + 0.114 * pa[2] as f64) / scale;
+            // Fade toward white so the red highlights stand out
+            let faded = (192.0 + y / 4.0) as u8;
+            diff.data.extend_from_slice(&[faded, faded, faded, 255])?;
+        }
+    }
+    Ok(diff)
+}
+
+/// Renders `a`, `b`, and their [`diff_image`] side by side in one 8-bit
+/// RGBA image, separated by a gray border, for attaching to bug reports or
+/// CI results. Transparent areas of `a` and `b` show the border color.
+pub fn side_by_side_diff(a: &PngImage, b: &PngImage, tolerance: u16) -> Result<PngImage, PngError> {
+    let diff = diff_image(a, b, tolerance)?;
+    montage(
+        &[a.clone(), b.clone(), diff],
+        3,
+        PANEL_PADDING,
+        PANEL_BACKGROUND,
+    )
+}
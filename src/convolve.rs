@@ -0,0 +1,189 @@
+use crate::error::PngError;
+use crate::{ColorType, PngImage};
+
+/// How [`PngImage::convolve`] samples pixels beyond the image's edges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EdgeMode {
+    /// Repeats the nearest edge pixel
+    #[default]
+    Clamp,
+    /// Reflects the image about its edge, without repeating the edge pixel
+    Mirror,
+    /// Takes pixels from the opposite edge
+    Wrap,
+    /// Treats outside pixels as zero
+    Zero,
+}
+
+impl EdgeMode {
+    // Maps a coordinate to one inside 0..len, or `None` for zero
+    fn resolve(self, position: isize, len: isize) -> Option<usize> {
+        if (0..len).contains(&position) {
+            return Some(position as usize);
+        }
+        let position = match self {
+            EdgeMode::Clamp => position.clamp(0, len - 1),
+            EdgeMode::Wrap => position.rem_euclid(len),
+            EdgeMode::Mirror if len == 1 => 0,
+            EdgeMode::Mirror => {
+                let period = 2 * (len - 1);
+                let p = position.rem_euclid(period);
+                if p < len {
+                    p
+                } else {
+                    period - p
+                }
+            }
+            EdgeMode::Zero => return None,
+        };
+        Some(position as usize)
+    }
+}
+
+/// Weights for [`PngImage::convolve`], in rows from the top left, with an
+/// odd width and height so the kernel has a center.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Kernel {
+    width: usize,
+    height: usize,
+    weights: Vec<f64>,
+    bias: f64,
+}
+
+impl Kernel {
+    pub fn new(width: usize, height: usize, weights: Vec<f64>) -> Result<Self, PngError> {
+        if width.is_multiple_of(2) || height.is_multiple_of(2) {
+            return Err(PngError::InvalidKernel(format!(
+                "{}x{} kernel has no center",
+                width, height
+            )));
+        }
+        if weights.len() != width * height {
+            return Err(PngError::InvalidKernel(format!(
+                "{}x{} kernel needs {} weights, got {}",
+                width,
+                height,
+                width * height,
+                weights.len()
+            )));
+        }
+        Ok(Self {
+            width,
+            height,
+            weights,
+            bias: 0.0,
+        })
+    }
+
+    pub fn from_3x3(weights: [[f64; 3]; 3]) -> Self {
+        Self {
+            width: 3,
+            height: 3,
+            weights: weights.concat(),
+            bias: 0.0,
+        }
+    }
+
+    /// Adds `bias`, as a fraction of the maximum sample value, to every
+    /// result; kernels whose weights sum to zero usually want 0.5.
+    pub fn with_bias(mut self, bias: f64) -> Self {
+        self.bias = bias;
+        self
+    }
+
+    /// Scales the weights to sum to 1, if they don't sum to 0.
+    pub fn normalized(mut self) -> Self {
+        let total: f64 = self.weights.iter().sum();
+        if total.abs() > f64::EPSILON {
+            for w in &mut self.weights {
+                *w /= total;
+            }
+        }
+        self
+    }
+
+    pub fn box_blur(radius: usize) -> Self {
+        let size = radius * 2 + 1;
+        Self::new(size, size, vec![1.0; size * size])
+            .expect("odd size")
+            .normalized()
+    }
+
+    pub fn sharpen() -> Self {
+        Self::from_3x3([[0.0, -1.0, 0.0], [-1.0, 5.0, -1.0], [0.0, -1.0, 0.0]])
+    }
+
+    pub fn emboss() -> Self {
+        Self::from_3x3([[-2.0, -1.0, 0.0], [-1.0, 1.0, 1.0], [0.0, 1.0, 2.0]])
+    }
+
+    /// Laplacian edge detection, with a bias of 0.5 so flat areas are
+    /// mid-gray rather than clipped at black.
+    pub fn edge_detect() -> Self {
+        Self::from_3x3([[0.0, -1.0, 0.0], [-1.0, 4.0, -1.0], [0.0, -1.0, 0.0]]).with_bias(0.5)
+    }
+}
+
+impl PngImage {
+    /// Replaces each color sample with the weighted sum of its neighborhood
+    /// under `kernel`, on samples scaled to 0..=1 and clamped back. Alpha is
+    /// unchanged and indexed images are expanded to RGB or RGBA first.
+    pub fn convolve(&mut self, kernel: &Kernel, edges: EdgeMode) -> Result<(), PngError> {
+        self.check_complete()?;
+        if self.color_type == ColorType::Indexed {
+            self.expand_palette()?;
+        }
+        let original = self.samples();
+        let mut convolved = self.convolve_samples(&original, kernel, edges);
+        if matches!(self.color_type, ColorType::GrayscaleAlpha | ColorType::Rgba) {
+            let channels = self.color_type.channels();
+            for (pixel, source) in convolved
+                .chunks_exact_mut(channels)
+                .zip(original.chunks_exact(channels))
+            {
+                pixel[channels - 1] = source[channels - 1];
+            }
+        }
+        self.store_samples(&convolved)?;
+        Ok(())
+    }
+
+    /// [`PngImage::convolve`] with a 3x3 kernel.
+    pub fn convolve_3x3(&mut self, kernel: [[f64; 3]; 3], edges: EdgeMode) -> Result<(), PngError> {
+        self.convolve(&Kernel::from_3x3(kernel), edges)
+    }
+
+    // Convolves every channel of `samples`, laid out as from `samples`,
+    // without clamping the results
+    pub(crate) fn convolve_samples(
+        &self,
+        samples: &[f64],
+        kernel: &Kernel,
+        edges: EdgeMode,
+    ) -> Vec<f64> {
+        let (width, height) = (self.width as isize, self.height as isize);
+        let channels = self.color_type.channels();
+        let (half_x, half_y) = ((kernel.width / 2) as isize, (kernel.height / 2) as isize);
+
+        let mut out = Vec::with_capacity(samples.len());
+        for y in 0..height {
+            for x in 0..width {
+                for c in 0..channels {
+                    let mut sum = kernel.bias;
+                    for (k, &w) in kernel.weights.iter().enumerate() {
+                        let kx = (k % kernel.width) as isize - half_x;
+                        let ky = (k / kernel.width) as isize - half_y;
+                        let (Some(sx), Some(sy)) =
+                            (edges.resolve(x + kx, width), edges.resolve(y + ky, height))
+                        else {
+                            continue;
+                        };
+                        sum += w * samples[(sy * width as usize + sx) * channels + c];
+                    }
+                    out.push(sum);
+                }
+            }
+        }
+        out
+    }
+}
@@ -0,0 +1,1375 @@
+use flate2::read::{DeflateDecoder, ZlibDecoder};
+use std::borrow::Cow;
+use std::io::Read;
+
+use crate::chunks::{repair_signature, Chunk, ChunkReader, ChunkRef, ChunkSlices, PNG_SIGNATURE};
+use crate::dictionary::{required_dictionary, CompressionDictionary, PrimedReader};
+use crate::error::PngError;
+use crate::filter::{unfilter_row, FilterType};
+use crate::icc::{ColorProfile, RenderingIntent};
+use crate::info::ImageHeader;
+use crate::limits::{DecodeLimits, LimitCounter};
+use crate::orientation::read_orientation;
+use crate::progress::{Phase, ProgressHook};
+use crate::text::{is_text_chunk, TextChunk};
+use crate::{image_size, BitDepth, ColorType, PngImage};
+
+// Adam7 passes as (x start, y start, x step, y step)
+pub(crate) const ADAM7_PASSES: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+// Width and height of the block each pixel of a pass stands for in a
+// progressive preview
+const ADAM7_BLOCKS: [(usize, usize); 7] = [(8, 8), (4, 8), (4, 4), (2, 4), (2, 2), (1, 2), (1, 1)];
+
+/// A callback given a preview after each pass of an interlaced image
+type PassCallback = Box<dyn FnMut(u8, &PngImage) + Send>;
+
+// Given the raw image data after each pass
+type PassData<'a> = &'a mut dyn FnMut(u8, &[u8]);
+
+/// One scanline as stored in the stream, before unfiltering.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilteredScanline {
+    /// Adam7 pass from 1 to 7, or 0 for non-interlaced images
+    pub pass: u8,
+    /// The filter byte, which may not be a valid filter type
+    pub filter: u8,
+    /// Filtered, still-packed sample bytes
+    pub data: Vec<u8>,
+}
+
+impl FilteredScanline {
+    pub fn filter_type(&self) -> Option<FilterType> {
+        FilterType::from_u8(self.filter)
+    }
+}
+
+/// The decompressed image data of a PNG, split into scanlines but not
+/// unfiltered; see [`Decoder::decode_filtered`].
+#[derive(Debug, Clone)]
+pub struct FilteredImage {
+    pub header: ImageHeader,
+    /// In stream order, pass by pass for interlaced images
+    pub scanlines: Vec<FilteredScanline>,
+}
+
+// The critical chunks of a stream
+struct Stream<'a> {
+    header: ImageHeader,
+    header_offset: u64,
+    palette: Option<Chunk>,
+    transparency: Option<Chunk>,
+    exif: Option<Chunk>,
+    gamma: Option<Chunk>,
+    chromaticities: Option<Chunk>,
+    icc_profile: Option<Chunk>,
+    srgb: bool,
+    /// IDAT payloads, borrowed from the input when it's a slice
+    compressed: Vec<Cow<'a, [u8]>>,
+    /// Offset of the first IDAT chunk, where data errors are reported
+    data_offset: u64,
+    /// Apple's CgBI variant, with raw deflate data and premultiplied BGRA
+    cgbi: bool,
+}
+
+/// How [`Decoder::reduce_16_bit`] maps 16-bit samples to 8 bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepthMapping {
+    /// Keep the high byte, the cheapest option, darkening by up to one step
+    Truncate,
+    /// Round to the nearest 8-bit value
+    Round,
+    /// Re-encode color samples from the gamma in a gAMA chunk to the sRGB
+    /// curve, so that images stored with another gamma (linear 16-bit data
+    /// in particular) preview correctly on sRGB displays. Without gAMA, or
+    /// with an sRGB chunk, this is the same as `Round`. Alpha is always
+    /// rounded.
+    Srgb,
+}
+
+/// How much [`Decoder::scale`] shrinks an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DecodeScale {
+    #[default]
+    Full,
+    Half,
+    Quarter,
+    Eighth,
+}
+
+impl DecodeScale {
+    pub fn factor(self) -> u32 {
+        match self {
+            DecodeScale::Full => 1,
+            DecodeScale::Half => 2,
+            DecodeScale::Quarter => 4,
+            DecodeScale::Eighth => 8,
+        }
+    }
+
+    // The last Adam7 pass with pixels on the scaled grid; the passes up to
+    // it hold all of them
+    fn last_pass(self) -> u8 {
+        match self {
+            DecodeScale::Full => 7,
+            DecodeScale::Half => 5,
+            DecodeScale::Quarter => 3,
+            DecodeScale::Eighth => 1,
+        }
+    }
+}
+
+/// Reads a PNG stream into a `PngImage`, keeping its color type and bit
+/// depth unless asked to expand palettes or transparency. Apple's CgBI
+/// variant is accepted too, and its pixels are converted back to standard
+/// RGB or straight-alpha RGBA.
+///
+/// Errors about the file's contents carry the byte offset, and usually the
+/// chunk, where the problem was found; see [`PngError::offset`].
+pub struct Decoder<R: Read> {
+    reader: R,
+    progress: ProgressHook,
+    on_pass: Option<PassCallback>,
+    depth_mapping: Option<DepthMapping>,
+    convert_to_srgb: bool,
+    rendering_intent: RenderingIntent,
+    black_point_compensation: bool,
+    expand_palette: bool,
+    expand_transparency: bool,
+    auto_orient: bool,
+    repair_signature: bool,
+    limits: DecodeLimits,
+    scale: DecodeScale,
+    region: Option<(u32, u32, u32, u32)>,
+    dictionary: Option<CompressionDictionary>,
+}
+
+impl<R: Read> Decoder<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            progress: ProgressHook::new(),
+            on_pass: None,
+            depth_mapping: None,
+            convert_to_srgb: false,
+            rendering_intent: RenderingIntent::default(),
+            black_point_compensation: false,
+            expand_palette: false,
+            expand_transparency: false,
+            auto_orient: false,
+            repair_signature: false,
+            limits: DecodeLimits::default(),
+            scale: DecodeScale::Full,
+            region: None,
+            dictionary: None,
+        }
+    }
+
+    /// Reports progress through unfiltering and stops if the hook's token is
+    /// cancelled.
+    pub fn progress(mut self, progress: ProgressHook) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Calls `callback` with the pass number and a full-size preview after
+    /// each Adam7 pass of an interlaced image, for progressive display.
+    /// Pixels not decoded yet repeat the nearest decoded pixel above and to
+    /// their left. Previews keep the file's format, before any palette or
+    /// transparency expansion; non-interlaced images never call this.
+    pub fn on_pass(mut self, callback: impl FnMut(u8, &PngImage) + Send + 'static) -> Self {
+        self.on_pass = Some(Box::new(callback));
+        self
+    }
+
+    /// Decodes indexed images to 8-bit RGB, or RGBA if the palette has
+    /// transparency, instead of keeping the indices and palette.
+    pub fn expand_palette(mut self, expand: bool) -> Self {
+        self.expand_palette = expand;
+        self
+    }
+
+    /// Turns the single transparent color that a tRNS chunk can mark in
+    /// grayscale and RGB images into an alpha channel. Without this the
+    /// chunk is dropped and those pixels decode as opaque.
+    pub fn expand_transparency(mut self, expand: bool) -> Self {
+        self.expand_transparency = expand;
+        self
+    }
+
+    /// Reduces 16-bit images to 8 bits per sample as they are decoded,
+    /// for previews and thumbnails. Lower bit depths are left alone.
+    pub fn reduce_16_bit(mut self, mapping: DepthMapping) -> Self {
+        self.depth_mapping = Some(mapping);
+        self
+    }
+
+    /// Converts pixels described by an iCCP chunk, or by gAMA and cHRM, to
+    /// sRGB, so they look the same as untagged images. ICC profiles are
+    /// applied if they are the matrix/TRC or gray TRC kind that displays and
+    /// cameras use; images with other profiles, or an sRGB chunk, are left
+    /// as they are. Indexed images have their palette converted, and
+    /// sub-byte grayscale images become 8-bit.
+    pub fn convert_to_srgb(mut self, convert: bool) -> Self {
+        self.convert_to_srgb = convert;
+        self
+    }
+
+    /// Sets how [`convert_to_srgb`](Self::convert_to_srgb) handles the
+    /// image's white point and colors outside sRGB. Defaults to
+    /// [`RenderingIntent::RelativeColorimetric`].
+    pub fn rendering_intent(mut self, intent: RenderingIntent) -> Self {
+        self.rendering_intent = intent;
+        self
+    }
+
+    /// Makes [`convert_to_srgb`](Self::convert_to_srgb) map the profile's
+    /// black to sRGB black, so that shadows of profiles with a raised black
+    /// aren't washed out. Off by default.
+    pub fn black_point_compensation(mut self, compensate: bool) -> Self {
+        self.black_point_compensation = compensate;
+        self
+    }
+
+    /// Rotates or flips the decoded pixels as the eXIf Orientation tag says,
+    /// so they display upright without it.
+    pub fn auto_orient(mut self, auto_orient: bool) -> Self {
+        self.auto_orient = auto_orient;
+        self
+    }
+
+    /// Undoes signature damage that can be undone exactly: a dropped 0x1A
+    /// byte, or LF to CRLF conversion, which is reversed throughout the
+    /// stream. Chunk CRCs still have to match afterwards. Without this, or
+    /// for other damage, decoding fails with
+    /// [`PngError::DamagedSignature`] saying what happened.
+    pub fn repair_signature(mut self, repair: bool) -> Self {
+        self.repair_signature = repair;
+        self
+    }
+
+    /// Caps the ancillary chunks and text the file may contain, failing
+    /// with [`PngError::LimitExceeded`] past them. Defaults to
+    /// [`DecodeLimits::default`].
+    pub fn limits(mut self, limits: DecodeLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Decodes only every 2nd, 4th, or 8th pixel of every 2nd, 4th, or 8th
+    /// row, for thumbnails. Sizes round up, so a 100x100 image decodes to
+    /// 13x13 at [`DecodeScale::Eighth`]. Pixels are sampled, not averaged,
+    /// so fine detail can alias. Interlaced images are quickest, as reading
+    /// stops after the Adam7 passes holding those pixels (only the first
+    /// for an eighth); other images still have every row unfiltered.
+    /// [`on_pass`](Self::on_pass) isn't called when scaling.
+    pub fn scale(mut self, scale: DecodeScale) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Decodes only the `width` x `height` rectangle at (`x`, `y`), for
+    /// viewing tiles of images too large to decode whole. Rows below it
+    /// aren't read at all unless the image is interlaced, though rows above
+    /// it still have to be unfiltered. Combined with
+    /// [`scale`](Self::scale), the rectangle is what gets scaled. The
+    /// rectangle is in the file's own orientation, before
+    /// [`auto_orient`](Self::auto_orient). Decoding fails with
+    /// [`PngError::RegionOutOfBounds`] if it doesn't fit in the image, and
+    /// [`on_pass`](Self::on_pass) isn't called.
+    pub fn region(mut self, x: u32, y: u32, width: u32, height: u32) -> Self {
+        self.region = Some((x, y, width, height));
+        self
+    }
+
+    /// Supplies the preset dictionary that image data written with
+    /// [`EncodeOptions::dictionary`](crate::EncodeOptions::dictionary) was
+    /// compressed against. Data that needs a dictionary other than this one
+    /// fails to decode.
+    pub fn dictionary(mut self, dictionary: CompressionDictionary) -> Self {
+        self.dictionary = Some(dictionary);
+        self
+    }
+
+    pub fn decode(mut self) -> Result<PngImage, PngError> {
+        let stream = self.read_stream()?;
+        self.decode_stream(stream)
+    }
+
+    fn decode_stream(mut self, stream: Stream<'_>) -> Result<PngImage, PngError> {
+        let mut progress = std::mem::take(&mut self.progress);
+        let Stream {
+            header,
+            header_offset,
+            palette,
+            mut transparency,
+            exif,
+            mut gamma,
+            chromaticities,
+            icc_profile,
+            srgb,
+            compressed,
+            data_offset,
+            cgbi,
+        } = stream;
+        let in_data = |e: PngError| e.at(data_offset, Some(*b"IDAT"));
+
+        let window = match self.region {
+            Some((x, y, width, height)) => {
+                let fits = |start: u32, len: u32, limit: u32| {
+                    start.checked_add(len).is_some_and(|end| end <= limit)
+                };
+                if !fits(x, width, header.width) || !fits(y, height, header.height) {
+                    return Err(PngError::RegionOutOfBounds {
+                        region: (x, y, width, height),
+                        dimensions: (header.width, header.height),
+                    });
+                }
+                (x, y, width, height)
+            }
+            None => (0, 0, header.width, header.height),
+        };
+        let partial = self.region.is_some() || self.scale != DecodeScale::Full;
+        let factor = self.scale.factor();
+        let mut image = PngImage::with_bit_depth(
+            window.2.div_ceil(factor),
+            window.3.div_ceil(factor),
+            header.color_type,
+            header.bit_depth,
+        )
+        .map_err(|e| e.at(header_offset, Some(*b"IHDR")))?;
+        if let Some(palette) = palette {
+            if image.color_type == ColorType::Indexed {
+                image
+                    .set_palette(&palette.data)
+                    .map_err(|e| e.at(palette.offset, Some(*b"PLTE")))?;
+                // Palette alpha is kept so it survives expansion and
+                // re-encoding; other tRNS forms depend on the bit depth
+                image.chunks.extend(transparency.take());
+            }
+        } else if image.color_type == ColorType::Indexed {
+            return Err(PngError::MissingChunk {
+                chunk_type: *b"PLTE",
+            });
+        }
+
+        let mut preview = self
+            .on_pass
+            .take()
+            .filter(|_| !partial)
+            .map(|mut callback| {
+                let mut preview = image.clone();
+                move |pass: u8, data: &[u8]| {
+                    preview.data = data.to_vec().into();
+                    if cgbi {
+                        preview.normalize_cgbi();
+                    }
+                    callback(pass, &preview);
+                }
+            });
+        let on_pass = preview.as_mut().map(|f| f as PassData);
+        let raw = inflater(&compressed, cgbi, self.dictionary.as_ref()).map_err(in_data)?;
+        image.data = if partial {
+            decode_window(&header, raw, &mut progress, window, self.scale)
+        } else {
+            unfilter_image_data(&header, raw, &mut progress, on_pass)
+        }
+        .map_err(in_data)?
+        .into();
+        if cgbi {
+            image.normalize_cgbi();
+        }
+        match image.color_type {
+            ColorType::Indexed if self.expand_palette => image.expand_palette().map_err(in_data)?,
+            ColorType::Grayscale | ColorType::Rgb if self.expand_transparency => {
+                if let Some(transparency) = transparency {
+                    expand_transparency(&mut image, &transparency.data)
+                        .map_err(|e| e.at(transparency.offset, Some(*b"tRNS")))?;
+                }
+            }
+            _ => {}
+        }
+        if self.convert_to_srgb && !srgb {
+            // An embedded profile overrides gAMA and cHRM
+            let profile = match &icc_profile {
+                Some(chunk) => ColorProfile::from_iccp(&chunk.data),
+                None => ColorProfile::from_chunks(
+                    gamma.as_ref().map(|c| c.data.as_slice()),
+                    chromaticities.as_ref().map(|c| c.data.as_slice()),
+                ),
+            };
+            if let Some(profile) = profile {
+                profile.convert_to_srgb(
+                    &mut image,
+                    self.rendering_intent,
+                    self.black_point_compensation,
+                );
+                gamma = None;
+            }
+        }
+        if let Some(mapping) = self.depth_mapping {
+            // sRGB takes precedence over gAMA, which is then only a fallback
+            let gamma = gamma
+                .filter(|_| !srgb)
+                .and_then(|c| <[u8; 4]>::try_from(c.data.as_slice()).ok())
+                .map(u32::from_be_bytes)
+                .filter(|&g| g > 0);
+            reduce_to_8_bit(&mut image, mapping, gamma);
+        }
+        if self.auto_orient {
+            if let Some(orientation) = exif.and_then(|c| read_orientation(&c.data)) {
+                image.apply_orientation(orientation)?;
+            }
+        }
+        Ok(image)
+    }
+
+    /// Inflates the image data without unfiltering it, for inspecting the
+    /// filters an encoder chose or re-using them. Chunks are validated as
+    /// for [`Decoder::decode`].
+    pub fn decode_filtered(mut self) -> Result<FilteredImage, PngError> {
+        let Stream {
+            header,
+            compressed,
+            data_offset,
+            cgbi,
+            ..
+        } = self.read_stream()?;
+        let in_data = |e: PngError| e.at(data_offset, Some(*b"IDAT"));
+        let mut raw = Vec::new();
+        inflater(&compressed, cgbi, self.dictionary.as_ref())
+            .and_then(|mut inflater| inflater.read_to_end(&mut raw).map_err(invalid_data))
+            .map_err(in_data)?;
+
+        let passes: Vec<(u8, usize, usize)> = if header.interlaced {
+            adam7_passes(&header)
+                .map(|(pass, _, width, height)| (pass, width, height))
+                .collect()
+        } else {
+            vec![(0, header.width as usize, header.height as usize)]
+        };
+
+        let mut scanlines = Vec::new();
+        let mut rest = raw.as_slice();
+        for (pass, width, height) in passes {
+            let row_length = width
+                .checked_mul(bits_per_pixel(&header))
+                .ok_or(PngError::ImageTooLarge {
+                    width: header.width,
+                    height: header.height,
+                })
+                .map_err(in_data)?
+                .div_ceil(8);
+            for _ in 0..height {
+                let Some((line, next)) = rest.split_at_checked(row_length + 1) else {
+                    return Err(in_data(PngError::Decode(
+                        "Image data is truncated".to_string(),
+                    )));
+                };
+                scanlines.push(FilteredScanline {
+                    pass,
+                    filter: line[0],
+                    data: line[1..].to_vec(),
+                });
+                rest = next;
+            }
+        }
+
+        Ok(FilteredImage { header, scanlines })
+    }
+
+    fn read_stream(&mut self) -> Result<Stream<'static>, PngError> {
+        let reader: Box<dyn Read + '_> = if self.repair_signature {
+            repair_signature(&mut self.reader)?
+        } else {
+            Box::new(&mut self.reader)
+        };
+        let mut chunks = ChunkReader::new(reader)?;
+        let mut stream = StreamBuilder::new(self.limits);
+        while let Some(chunk) = chunks.next_chunk()? {
+            chunk.verify_crc()?;
+            stream.add(chunk.into())?;
+        }
+        stream.finish(chunks.offset())
+    }
+}
+
+impl Decoder<&[u8]> {
+    /// Decodes like [`Decoder::decode`], but reads chunks in place instead
+    /// of copying them out of the input first, so that only the decoded
+    /// pixels are allocated. Suits memory-mapped files; see
+    /// `PngImage::read_from_path_mmap` with the `mmap` feature.
+    pub fn decode_borrowed(self) -> Result<PngImage, PngError> {
+        let data = self.reader;
+        // Repairs rewrite the stream, so they need the copying path
+        if self.repair_signature && !data.starts_with(&PNG_SIGNATURE) {
+            return self.decode();
+        }
+        let mut chunks = ChunkSlices::new(data)?;
+        let mut stream = StreamBuilder::new(self.limits);
+        while let Some(chunk) = chunks.next_chunk()? {
+            chunk.verify_crc()?;
+            stream.add(chunk.into())?;
+        }
+        let stream = stream.finish(chunks.offset())?;
+        self.decode_stream(stream)
+    }
+}
+
+// A chunk whose data may be borrowed from the input
+struct StreamChunk<'a> {
+    chunk_type: [u8; 4],
+    data: Cow<'a, [u8]>,
+    crc: u32,
+    offset: u64,
+}
+
+impl StreamChunk<'_> {
+    fn into_chunk(self) -> Chunk {
+        Chunk {
+            chunk_type: self.chunk_type,
+            data: self.data.into_owned(),
+            crc: self.crc,
+            offset: self.offset,
+        }
+    }
+}
+
+impl From<Chunk> for StreamChunk<'_> {
+    fn from(chunk: Chunk) -> Self {
+        Self {
+            chunk_type: chunk.chunk_type,
+            data: Cow::Owned(chunk.data),
+            crc: chunk.crc,
+            offset: chunk.offset,
+        }
+    }
+}
+
+impl<'a> From<ChunkRef<'a>> for StreamChunk<'a> {
+    fn from(chunk: ChunkRef<'a>) -> Self {
+        Self {
+            chunk_type: chunk.chunk_type,
+            data: Cow::Borrowed(chunk.data),
+            crc: chunk.crc,
+            offset: chunk.offset,
+        }
+    }
+}
+
+// Collects the chunks the decoder needs, checking their order as they come
+struct StreamBuilder<'a> {
+    header: Option<ImageHeader>,
+    header_offset: u64,
+    palette: Option<Chunk>,
+    transparency: Option<Chunk>,
+    exif: Option<Chunk>,
+    gamma: Option<Chunk>,
+    chromaticities: Option<Chunk>,
+    icc_profile: Option<Chunk>,
+    srgb: bool,
+    compressed: Vec<Cow<'a, [u8]>>,
+    data_offset: Option<u64>,
+    seen_iend: bool,
+    cgbi: bool,
+    limits: LimitCounter,
+}
+
+impl<'a> StreamBuilder<'a> {
+    fn new(limits: DecodeLimits) -> Self {
+        Self {
+            header: None,
+            header_offset: 0,
+            palette: None,
+            transparency: None,
+            exif: None,
+            gamma: None,
+            chromaticities: None,
+            icc_profile: None,
+            srgb: false,
+            compressed: Vec::new(),
+            data_offset: None,
+            seen_iend: false,
+            cgbi: false,
+            limits: LimitCounter::new(limits),
+        }
+    }
+
+    // Takes the next chunk, whose CRC has been checked
+    fn add(&mut self, chunk: StreamChunk<'a>) -> Result<(), PngError> {
+        self.limits.count_chunk(chunk.chunk_type, chunk.offset)?;
+        if is_text_chunk(&chunk.chunk_type) {
+            // Compressed text counts as it would decompress, inflating no
+            // further than the limit; text the decoder can't parse, which it
+            // would otherwise skip, counts as stored
+            let length = TextChunk::parse_capped(
+                &chunk.chunk_type,
+                &chunk.data,
+                self.limits.text_remaining().saturating_add(1),
+            )
+            .map_or(chunk.data.len(), |parsed| parsed.text.len());
+            self.limits
+                .count_text(chunk.chunk_type, chunk.offset, length)?;
+        }
+
+        // Apple's variant puts its marker ahead of IHDR
+        if self.header.is_none() && !self.cgbi && &chunk.chunk_type == b"CgBI" {
+            self.cgbi = true;
+            return Ok(());
+        }
+
+        if self.header.is_none() && &chunk.chunk_type != b"IHDR" {
+            return Err(PngError::UnexpectedChunk {
+                chunk_type: chunk.chunk_type,
+                offset: chunk.offset,
+                reason: "IHDR must be the first chunk",
+            });
+        }
+
+        match &chunk.chunk_type {
+            b"IHDR" => {
+                if self.header.is_some() {
+                    return Err(PngError::UnexpectedChunk {
+                        chunk_type: chunk.chunk_type,
+                        offset: chunk.offset,
+                        reason: "duplicate IHDR",
+                    });
+                }
+                let header = ImageHeader::parse(&chunk.data)
+                    .and_then(|header| {
+                        let limits = self.limits.limits();
+                        limits.check_dimensions(header.width, header.height)?;
+                        Ok(header)
+                    })
+                    .map_err(|e| e.at(chunk.offset, Some(chunk.chunk_type)))?;
+                self.header = Some(header);
+                self.header_offset = chunk.offset;
+            }
+            b"PLTE" => self.palette = Some(chunk.into_chunk()),
+            b"tRNS" => self.transparency = Some(chunk.into_chunk()),
+            b"eXIf" => self.exif = Some(chunk.into_chunk()),
+            b"gAMA" => self.gamma = Some(chunk.into_chunk()),
+            b"cHRM" => self.chromaticities = Some(chunk.into_chunk()),
+            b"iCCP" => self.icc_profile = Some(chunk.into_chunk()),
+            b"sRGB" => self.srgb = true,
+            b"IDAT" => {
+                self.data_offset.get_or_insert(chunk.offset);
+                self.compressed.push(chunk.data);
+            }
+            b"IEND" => self.seen_iend = true,
+            _ if chunk.chunk_type[0].is_ascii_uppercase() => {
+                return Err(PngError::UnexpectedChunk {
+                    chunk_type: chunk.chunk_type,
+                    offset: chunk.offset,
+                    reason: "unknown critical chunk",
+                });
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // Checks that nothing required is missing, with `end` the offset just
+    // past the last chunk
+    fn finish(self, end: u64) -> Result<Stream<'a>, PngError> {
+        // Missing chunks are reported at the end of the stream
+        let missing = |chunk_type: &[u8; 4]| {
+            PngError::MissingChunk {
+                chunk_type: *chunk_type,
+            }
+            .at(end, None)
+        };
+        let header = self.header.ok_or_else(|| missing(b"IHDR"))?;
+        if !self.seen_iend {
+            return Err(missing(b"IEND"));
+        }
+        let Some(data_offset) = self
+            .data_offset
+            .filter(|_| self.compressed.iter().any(|piece| !piece.is_empty()))
+        else {
+            return Err(missing(b"IDAT"));
+        };
+
+        Ok(Stream {
+            header,
+            header_offset: self.header_offset,
+            palette: self.palette,
+            transparency: self.transparency,
+            exif: self.exif,
+            gamma: self.gamma,
+            chromaticities: self.chromaticities,
+            icc_profile: self.icc_profile,
+            srgb: self.srgb,
+            compressed: self.compressed,
+            data_offset,
+            cgbi: self.cgbi,
+        })
+    }
+}
+
+/// Inflates, unfilters, and deinterlaces the concatenated IDAT (or fdAT)
+/// payload of an image described by `header`.
+pub(crate) fn decompress_image_data(
+    header: &ImageHeader,
+    compressed: &[u8],
+    progress: &mut ProgressHook,
+) -> Result<Vec<u8>, PngError> {
+    unfilter_image_data(header, ZlibDecoder::new(compressed), progress, None)
+}
+
+// Unfilters and deinterlaces image data as it is inflated, a row at a time,
+// so nothing but the output and a couple of rows is held in memory
+fn unfilter_image_data<R: Read>(
+    header: &ImageHeader,
+    mut raw: R,
+    progress: &mut ProgressHook,
+    on_pass: Option<PassData>,
+) -> Result<Vec<u8>, PngError> {
+    let data = if header.interlaced {
+        let rows = adam7_passes(header).map(|(.., height)| height).sum();
+        progress.begin(Phase::Decoding, rows)?;
+        decode_interlaced(header, &mut raw, progress, on_pass)?
+    } else {
+        progress.begin(Phase::Decoding, header.height as usize)?;
+        let (width, height) = (header.width as usize, header.height as usize);
+        let pixel_size = header.color_type.channels() * header.bit_depth.bytes_per_sample();
+        let mut data = Vec::with_capacity(image_size(header.width, header.height, pixel_size)?);
+        decode_pass(header, &mut raw, width, height, progress, |_, samples| {
+            data.extend_from_slice(samples)
+        })?;
+        data
+    };
+    // Read to the end so the zlib checksum is verified
+    std::io::copy(&mut raw, &mut std::io::sink()).map_err(invalid_data)?;
+    Ok(data)
+}
+
+// Pass number (from 1), origin, spacing, and size of each non-empty pass
+fn adam7_passes(
+    header: &ImageHeader,
+) -> impl Iterator<Item = (u8, (usize, usize, usize, usize), usize, usize)> {
+    let (width, height) = (header.width as usize, header.height as usize);
+    (1..)
+        .zip(ADAM7_PASSES)
+        .map(move |(pass, (x0, y0, dx, dy))| {
+            let pass_width = (width + dx - x0 - 1) / dx;
+            let pass_height = (height + dy - y0 - 1) / dy;
+            (pass, (x0, y0, dx, dy), pass_width, pass_height)
+        })
+        .filter(|&(.., pass_width, pass_height)| pass_width > 0 && pass_height > 0)
+}
+
+// Reads IDAT payloads back to back, as the one zlib stream they make up
+struct ImageData<'s, 'a> {
+    pieces: &'s [Cow<'a, [u8]>],
+    position: usize,
+}
+
+impl<'s, 'a> ImageData<'s, 'a> {
+    fn new(pieces: &'s [Cow<'a, [u8]>]) -> Self {
+        Self {
+            pieces,
+            position: 0,
+        }
+    }
+}
+
+impl Read for ImageData<'_, '_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while let Some(piece) = self.pieces.first() {
+            let rest = &piece[self.position..];
+            if rest.is_empty() {
+                self.pieces = &self.pieces[1..];
+                self.position = 0;
+                continue;
+            }
+            let n = rest.len().min(buf.len());
+            buf[..n].copy_from_slice(&rest[..n]);
+            self.position += n;
+            return Ok(n);
+        }
+        Ok(0)
+    }
+}
+
+// Inflates image data: a zlib stream, possibly compressed against a preset
+// dictionary, or for CgBI a bare deflate stream without header or checksum
+fn inflater<'s>(
+    compressed: &'s [Cow<'_, [u8]>],
+    cgbi: bool,
+    dictionary: Option<&CompressionDictionary>,
+) -> Result<Box<dyn Read + 's>, PngError> {
+    if cgbi {
+        return Ok(Box::new(DeflateDecoder::new(ImageData::new(compressed))));
+    }
+    // The header, and the dictionary ID if there is one
+    let mut header = Vec::with_capacity(6);
+    ImageData::new(compressed)
+        .take(6)
+        .read_to_end(&mut header)
+        .map_err(invalid_data)?;
+    let Some(id) = required_dictionary(&header) else {
+        return Ok(Box::new(ZlibDecoder::new(ImageData::new(compressed))));
+    };
+    match dictionary {
+        Some(dictionary) if dictionary.id() == id => {
+            let mut rest = ImageData::new(compressed);
+            rest.read_exact(&mut [0; 6]).map_err(invalid_data)?;
+            Ok(Box::new(PrimedReader::new(dictionary, rest)))
+        }
+        _ => Err(PngError::Decode(format!(
+            "Image data needs preset dictionary {:08x}",
+            id
+        ))),
+    }
+}
+
+fn invalid_data(e: std::io::Error) -> PngError {
+    PngError::Decode(format!("Invalid compressed image data: {}", e))
+}
+
+impl PngImage {
+    /// Decodes a PNG stream; see [`Decoder`].
+    pub fn read_from_file<R: Read>(reader: R) -> Result<Self, PngError> {
+        Decoder::new(reader).decode()
+    }
+}
+
+fn bits_per_pixel(header: &ImageHeader) -> usize {
+    header.color_type.channels() * header.bit_depth.bits() as usize
+}
+
+/// Reads, unfilters, and unpacks one (sub-)image of `width` x `height`
+/// pixels from `raw`, handing each row's samples to `row` along with its
+/// index.
+fn decode_pass<R: Read>(
+    header: &ImageHeader,
+    raw: &mut R,
+    width: usize,
+    height: usize,
+    progress: &mut ProgressHook,
+    mut row: impl FnMut(usize, &[u8]),
+) -> Result<(), PngError> {
+    let bits_per_pixel = bits_per_pixel(header);
+    let row_length = width
+        .checked_mul(bits_per_pixel)
+        .ok_or(PngError::ImageTooLarge {
+            width: header.width,
+            height: header.height,
+        })?
+        .div_ceil(8);
+    let bpp = (bits_per_pixel / 8).max(1);
+    let samples_per_row = width * header.color_type.channels();
+    let bits = header.bit_depth.bits() as usize;
+
+    let mut line = vec![0; row_length + 1];
+    let mut prev = vec![0; row_length];
+    let mut samples = vec![0; samples_per_row * header.bit_depth.bytes_per_sample()];
+    for y in 0..height {
+        raw.read_exact(&mut line).map_err(|e| match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => PngError::Decode(format!(
+                "Image data is truncated at row {} of a {}x{} pass",
+                y, width, height
+            )),
+            _ => invalid_data(e),
+        })?;
+        let filter = line[0];
+        let current = &mut line[1..];
+        unfilter_row(filter, current, &prev, bpp)?;
+
+        if bits >= 8 {
+            samples.copy_from_slice(current);
+        } else {
+            let mask = (1u8 << bits) - 1;
+            for (i, sample) in samples.iter_mut().enumerate() {
+                let bit = i * bits;
+                *sample = (current[bit / 8] >> (8 - bits - bit % 8)) & mask;
+            }
+        }
+        prev.copy_from_slice(current);
+        row(y, &samples);
+        progress.advance()?;
+    }
+    Ok(())
+}
+
+// Scatters each pass straight into the output as its rows are read, so
+// peak memory stays at the image plus a row, however large the passes.
+// For previews each pixel also fills its block; those pixels belong to
+// later passes, which overwrite them.
+fn decode_interlaced<R: Read>(
+    header: &ImageHeader,
+    raw: &mut R,
+    progress: &mut ProgressHook,
+    mut on_pass: Option<PassData>,
+) -> Result<Vec<u8>, PngError> {
+    let (width, height) = (header.width as usize, header.height as usize);
+    let pixel_size = header.color_type.channels() * header.bit_depth.bytes_per_sample();
+    let mut data = vec![0; image_size(header.width, header.height, pixel_size)?];
+
+    for (pass, (x0, y0, dx, dy), pass_width, pass_height) in adam7_passes(header) {
+        let (block_width, block_height) = match on_pass {
+            Some(_) => ADAM7_BLOCKS[pass as usize - 1],
+            None => (1, 1),
+        };
+        decode_pass(header, raw, pass_width, pass_height, progress, |py, row| {
+            let y = y0 + py * dy;
+            for (px, pixel) in row.chunks_exact(pixel_size).enumerate() {
+                let x = x0 + px * dx;
+                let columns = block_width.min(width - x);
+                for by in y..(y + block_height).min(height) {
+                    let start = (by * width + x) * pixel_size;
+                    for dst in
+                        data[start..start + columns * pixel_size].chunks_exact_mut(pixel_size)
+                    {
+                        dst.copy_from_slice(pixel);
+                    }
+                }
+            }
+        })?;
+        if let Some(callback) = on_pass.as_mut() {
+            callback(pass, &data);
+        }
+    }
+
+    Ok(data)
+}
+
+// Keeps every `factor`th pixel of every `factor`th row of `window`, given
+// as (x, y, width, height). Reading stops once nothing more would be kept:
+// after the window's last row for non-interlaced images, and for
+// interlaced ones after the passes holding the sampled pixels, if the
+// window is aligned to them. The rest of the data, and its checksum, then
+// go unread.
+fn decode_window<R: Read>(
+    header: &ImageHeader,
+    mut raw: R,
+    progress: &mut ProgressHook,
+    window: (u32, u32, u32, u32),
+    scale: DecodeScale,
+) -> Result<Vec<u8>, PngError> {
+    let factor = scale.factor() as usize;
+    let (left, top) = (window.0 as usize, window.1 as usize);
+    let (window_width, window_height) = (window.2 as usize, window.3 as usize);
+    let width = window_width.div_ceil(factor);
+    let height = window_height.div_ceil(factor);
+    let pixel_size = header.color_type.channels() * header.bit_depth.bytes_per_sample();
+    let mut data = vec![0; image_size(width as u32, height as u32, pixel_size)?];
+    let mut keep = |x: usize, y: usize, pixel: &[u8]| {
+        let (Some(dx), Some(dy)) = (x.checked_sub(left), y.checked_sub(top)) else {
+            return;
+        };
+        if dx < window_width
+            && dy < window_height
+            && dx.is_multiple_of(factor)
+            && dy.is_multiple_of(factor)
+        {
+            let start = ((dy / factor) * width + dx / factor) * pixel_size;
+            data[start..start + pixel_size].copy_from_slice(pixel);
+        }
+    };
+
+    if header.interlaced {
+        let last_pass = if left.is_multiple_of(factor) && top.is_multiple_of(factor) {
+            scale.last_pass()
+        } else {
+            7
+        };
+        let passes: Vec<_> = adam7_passes(header)
+            .take_while(|&(pass, ..)| pass <= last_pass)
+            .collect();
+        progress.begin(
+            Phase::Decoding,
+            passes.iter().map(|&(.., height)| height).sum(),
+        )?;
+        for (_, (x0, y0, dx, dy), pass_width, pass_height) in passes {
+            decode_pass(
+                header,
+                &mut raw,
+                pass_width,
+                pass_height,
+                progress,
+                |py, row| {
+                    for (px, pixel) in row.chunks_exact(pixel_size).enumerate() {
+                        keep(x0 + px * dx, y0 + py * dy, pixel);
+                    }
+                },
+            )?;
+        }
+    } else {
+        let bottom = top + window_height;
+        progress.begin(Phase::Decoding, bottom)?;
+        let full_width = header.width as usize;
+        decode_pass(header, &mut raw, full_width, bottom, progress, |y, row| {
+            if y >= top {
+                for (x, pixel) in row
+                    .chunks_exact(pixel_size)
+                    .enumerate()
+                    .skip(left)
+                    .step_by(factor)
+                {
+                    keep(x, y, pixel);
+                }
+            }
+        })?;
+        if bottom == header.height as usize {
+            std::io::copy(&mut raw, &mut std::io::sink()).map_err(invalid_data)?;
+        }
+    }
+    Ok(data)
+}
+
+// Converts a 16-bit image to 8 bits. `gamma` is the gAMA chunk's value: the
+// encoding exponent times 100000.
+fn reduce_to_8_bit(image: &mut PngImage, mapping: DepthMapping, gamma: Option<u32>) {
+    if image.bit_depth != BitDepth::Sixteen {
+        return;
+    }
+    let round = |v: u16| ((v as u32 * 255 + 32767) / 65535) as u8;
+    let table: Vec<u8> = match (mapping, gamma) {
+        (DepthMapping::Truncate, _) => (0..=u16::MAX).map(|v| (v >> 8) as u8).collect(),
+        (DepthMapping::Srgb, Some(gamma)) => {
+            let decode = 100_000.0 / gamma as f64;
+            (0..=u16::MAX)
+                .map(|v| {
+                    let linear = (v as f64 / 65535.0).powf(decode);
+                    let encoded = if linear <= 0.003_130_8 {
+                        linear * 12.92
+                    } else {
+                        1.055 * linear.powf(1.0 / 2.4) - 0.055
+                    };
+                    (encoded * 255.0).round().clamp(0.0, 255.0) as u8
+                })
+                .collect()
+        }
+        _ => (0..=u16::MAX).map(round).collect(),
+    };
+
+    let channels = image.color_type.channels();
+    let has_alpha = matches!(
+        image.color_type,
+        ColorType::GrayscaleAlpha | ColorType::Rgba
+    );
+    let data: Vec<u8> = image
+        .data
+        .chunks_exact(2)
+        .enumerate()
+        .map(|(i, pair)| {
+            let value = u16::from_be_bytes([pair[0], pair[1]]);
+            if has_alpha && i % channels == channels - 1 && mapping != DepthMapping::Truncate {
+                round(value)
+            } else {
+                table[value as usize]
+            }
+        })
+        .collect();
+    image.data = data.into();
+    image.bit_depth = BitDepth::Eight;
+}
+
+// Adds an alpha channel that is zero wherever a pixel matches the tRNS key
+// color and opaque elsewhere. Malformed keys are ignored.
+pub(crate) fn expand_transparency(image: &mut PngImage, key: &[u8]) -> Result<(), PngError> {
+    let channels = image.color_type.channels();
+    if key.len() != channels * 2 {
+        return Ok(());
+    }
+    let key: Vec<u16> = key
+        .chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect();
+    let sample_size = image.bit_depth.bytes_per_sample();
+    let transparent: Vec<bool> = image
+        .data
+        .chunks_exact(channels * sample_size)
+        .map(|pixel| {
+            pixel
+                .chunks_exact(sample_size)
+                .zip(&key)
+                .all(|(sample, &key)| match sample {
+                    [hi, lo] => u16::from_be_bytes([*hi, *lo]) == key,
+                    _ => sample[0] as u16 == key,
+                })
+        })
+        .collect();
+
+    let color_type = if image.color_type == ColorType::Grayscale {
+        ColorType::GrayscaleAlpha
+    } else {
+        ColorType::Rgba
+    };
+    image.convert_to(color_type)?;
+
+    let sample_size = image.bit_depth.bytes_per_sample();
+    let pixel_size = (channels + 1) * sample_size;
+    for (pixel, transparent) in image.data.chunks_exact_mut(pixel_size).zip(transparent) {
+        if transparent {
+            pixel[pixel_size - sample_size..].fill(0);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunks::ChunkWriter;
+    use crate::test_images::{formats, interlaced_png, noise};
+    use crate::text::TextKind;
+
+    // Sizes with partial Adam7 blocks, and a single pixel, which leaves
+    // most passes empty
+    const SIZES: [(u32, u32); 3] = [(13, 11), (1, 1), (8, 3)];
+
+    fn assert_same(decoded: &PngImage, image: &PngImage) {
+        assert_eq!(
+            (decoded.width(), decoded.height()),
+            (image.width(), image.height())
+        );
+        assert_eq!(decoded.color_type(), image.color_type());
+        assert_eq!(decoded.bit_depth(), image.bit_depth());
+        assert_eq!(decoded.palette(), image.palette());
+        assert_eq!(decoded.data(), image.data());
+    }
+
+    #[test]
+    fn round_trips_every_format() {
+        for (color_type, bit_depth) in formats() {
+            for (width, height) in SIZES {
+                let image = noise(width, height, color_type, bit_depth, width + height);
+                let mut encoded = Vec::new();
+                image.write_to_file(&mut encoded).unwrap();
+                assert_same(&Decoder::new(encoded.as_slice()).decode().unwrap(), &image);
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips_every_format_interlaced() {
+        for (color_type, bit_depth) in formats() {
+            for (width, height) in SIZES {
+                let image = noise(width, height, color_type, bit_depth, width * height);
+                let encoded = interlaced_png(&image);
+                let decoded = Decoder::new(encoded.as_slice()).decode().unwrap();
+                assert_same(&decoded, &image);
+            }
+        }
+    }
+
+    #[test]
+    fn reports_each_interlaced_pass() {
+        let image = noise(13, 11, ColorType::Rgb, BitDepth::Eight, 2);
+        let encoded = interlaced_png(&image);
+        let passes = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = std::sync::Arc::clone(&passes);
+        let decoded = Decoder::new(encoded.as_slice())
+            .on_pass(move |pass, preview| {
+                assert_eq!((preview.width(), preview.height()), (13, 11));
+                seen.lock().unwrap().push(pass);
+            })
+            .decode()
+            .unwrap();
+        assert_same(&decoded, &image);
+        assert_eq!(*passes.lock().unwrap(), [1, 2, 3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn text_limit_counts_decompressed_text() {
+        let mut image = noise(4, 4, ColorType::Rgb, BitDepth::Eight, 1);
+        let mut text = TextChunk::new("Comment", &"a".repeat(1 << 20));
+        text.kind = TextKind::Compressed;
+        image.add_text(&text).unwrap();
+        let mut encoded = Vec::new();
+        image.write_to_file(&mut encoded).unwrap();
+        assert!(encoded.len() < 4096);
+
+        let limits = DecodeLimits {
+            max_text_bytes: 10_000,
+            ..DecodeLimits::default()
+        };
+        let error = Decoder::new(encoded.as_slice())
+            .limits(limits)
+            .decode()
+            .unwrap_err();
+        assert!(matches!(error.root(), PngError::LimitExceeded(_)));
+
+        let limits = DecodeLimits {
+            max_text_bytes: 1 << 20,
+            ..DecodeLimits::default()
+        };
+        let decoded = Decoder::new(encoded.as_slice())
+            .limits(limits)
+            .decode()
+            .unwrap();
+        assert_eq!(decoded.data(), image.data());
+    }
+
+    #[test]
+    fn decodes_images_wider_than_32767() {
+        let image = noise(40_000, 2, ColorType::Grayscale, BitDepth::Eight, 3);
+        let mut encoded = Vec::new();
+        image.write_to_file(&mut encoded).unwrap();
+        let decoded = Decoder::new(encoded.as_slice()).decode().unwrap();
+        assert_eq!(decoded.width(), 40_000);
+        assert_eq!(decoded.data(), image.data());
+    }
+
+    #[test]
+    fn pixel_limit_rejects_huge_headers_before_allocating() {
+        let mut header = Vec::new();
+        header.extend_from_slice(&100_000u32.to_be_bytes());
+        header.extend_from_slice(&100_000u32.to_be_bytes());
+        header.extend_from_slice(&[8, 6, 0, 0, 0]);
+        let mut encoded = PNG_SIGNATURE.to_vec();
+        ChunkWriter::write_chunk(&mut encoded, b"IHDR", &header).unwrap();
+        ChunkWriter::write_chunk(&mut encoded, b"IEND", &[]).unwrap();
+
+        let error = Decoder::new(encoded.as_slice()).decode().unwrap_err();
+        assert!(matches!(error.root(), PngError::LimitExceeded(_)));
+        let limits = DecodeLimits {
+            max_pixels: 1000,
+            ..DecodeLimits::default()
+        };
+        let image = noise(40, 30, ColorType::Rgb, BitDepth::Eight, 3);
+        let mut encoded = Vec::new();
+        image.write_to_file(&mut encoded).unwrap();
+        let error = Decoder::new(encoded.as_slice())
+            .limits(limits)
+            .decode()
+            .unwrap_err();
+        assert!(matches!(error.root(), PngError::LimitExceeded(_)));
+    }
+
+    #[test]
+    fn borrowed_decode_matches_copying_decode() {
+        for (color_type, bit_depth) in formats() {
+            let image = noise(13, 11, color_type, bit_depth, 4);
+            let mut encoded = Vec::new();
+            image.write_to_file(&mut encoded).unwrap();
+            for encoded in [encoded, interlaced_png(&image)] {
+                let decoded = Decoder::new(encoded.as_slice()).decode_borrowed().unwrap();
+                assert_same(&decoded, &image);
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_input_fails() {
+        let image = noise(13, 11, ColorType::Rgba, BitDepth::Eight, 5);
+        let mut encoded = Vec::new();
+        image.write_to_file(&mut encoded).unwrap();
+        for encoded in [encoded, interlaced_png(&image)] {
+            for length in 0..encoded.len() {
+                let truncated = &encoded[..length];
+                assert!(Decoder::new(truncated).decode().is_err(), "{}", length);
+                assert!(Decoder::new(truncated).decode_borrowed().is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn bad_crc_fails() {
+        let image = noise(13, 11, ColorType::Rgb, BitDepth::Eight, 6);
+        let mut encoded = Vec::new();
+        image.write_to_file(&mut encoded).unwrap();
+        // One byte into each chunk's data or CRC: IHDR, IDAT, and IEND's CRC
+        for position in [20, 50, encoded.len() - 2] {
+            let mut damaged = encoded.clone();
+            damaged[position] ^= 0x10;
+            let error = Decoder::new(damaged.as_slice()).decode().unwrap_err();
+            assert!(matches!(error.root(), PngError::CrcMismatch { .. }));
+            let error = Decoder::new(damaged.as_slice())
+                .decode_borrowed()
+                .unwrap_err();
+            assert!(matches!(error.root(), PngError::CrcMismatch { .. }));
+        }
+    }
+
+    // Every `factor`th pixel of every `factor`th row, as a scaled decode
+    // samples them
+    fn sampled(image: &PngImage, factor: u32) -> PngImage {
+        let mut sampled = image.crop(0, 0, image.width(), image.height()).unwrap();
+        sampled.width = image.width().div_ceil(factor);
+        sampled.height = image.height().div_ceil(factor);
+        let bpp = image.bytes_per_pixel();
+        let mut data = Vec::new();
+        for y in (0..image.height() as usize).step_by(factor as usize) {
+            for x in (0..image.width() as usize).step_by(factor as usize) {
+                let start = (y * image.width() as usize + x) * bpp;
+                data.extend_from_slice(&image.data()[start..start + bpp]);
+            }
+        }
+        sampled.data.replace(data).unwrap();
+        sampled
+    }
+
+    #[test]
+    fn scaled_decode_samples_every_format() {
+        let scales = [DecodeScale::Half, DecodeScale::Quarter, DecodeScale::Eighth];
+        for (color_type, bit_depth) in formats() {
+            for (width, height) in [(21, 19), (1, 1), (9, 2)] {
+                let image = noise(width, height, color_type, bit_depth, 7);
+                let mut encoded = Vec::new();
+                image.write_to_file(&mut encoded).unwrap();
+                for encoded in [&encoded, &interlaced_png(&image)] {
+                    for scale in scales {
+                        let decoded = Decoder::new(encoded.as_slice())
+                            .scale(scale)
+                            .decode()
+                            .unwrap();
+                        assert_same(&decoded, &sampled(&image, scale.factor()));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn region_decode_matches_crop() {
+        let regions = [(0, 0, 21, 19), (3, 5, 7, 4), (20, 18, 1, 1), (0, 9, 21, 1)];
+        for (color_type, bit_depth) in formats() {
+            let image = noise(21, 19, color_type, bit_depth, 8);
+            let mut encoded = Vec::new();
+            image.write_to_file(&mut encoded).unwrap();
+            for encoded in [&encoded, &interlaced_png(&image)] {
+                for (x, y, width, height) in regions {
+                    let decoded = Decoder::new(encoded.as_slice())
+                        .region(x, y, width, height)
+                        .decode()
+                        .unwrap();
+                    let expected = image.crop(x, y, width, height).unwrap();
+                    assert_same(&decoded, &expected);
+
+                    let decoded = Decoder::new(encoded.as_slice())
+                        .region(x, y, width, height)
+                        .scale(DecodeScale::Half)
+                        .decode()
+                        .unwrap();
+                    assert_same(&decoded, &sampled(&expected, 2));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn region_out_of_bounds_fails() {
+        let image = noise(21, 19, ColorType::Rgb, BitDepth::Eight, 9);
+        let mut encoded = Vec::new();
+        image.write_to_file(&mut encoded).unwrap();
+        for (x, y, width, height) in [(0, 0, 22, 1), (20, 0, 2, 1), (0, u32::MAX, 1, 2)] {
+            let error = Decoder::new(encoded.as_slice())
+                .region(x, y, width, height)
+                .decode()
+                .unwrap_err();
+            assert!(matches!(error.root(), PngError::RegionOutOfBounds { .. }));
+        }
+    }
+}
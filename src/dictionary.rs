@@ -0,0 +1,436 @@
+use std::fmt;
+use std::io::{self, BufReader, Cursor, Read, Write};
+
+use flate2::bufread::DeflateDecoder;
+use flate2::write::{DeflateEncoder, ZlibEncoder};
+use flate2::Compression;
+
+use crate::error::PngError;
+use crate::filter::FilterStrategy;
+use crate::PngImage;
+
+// Deflate only refers back this far, so only the end of a longer
+// dictionary could ever be used
+const WINDOW_SIZE: usize = 32 << 10;
+
+// The preset dictionary flag in a zlib header's second byte
+const FDICT: u8 = 0x20;
+
+// The most a stored deflate block can hold
+const STORED_BLOCK_SIZE: usize = u16::MAX as usize;
+
+/// A preset dictionary for the zlib stream holding image data, set through
+/// [`EncodeOptions::dictionary`](crate::EncodeOptions::dictionary) and
+/// [`Decoder::dictionary`](crate::Decoder::dictionary). Images that share
+/// a lot with the dictionary, such as neighboring tiles of a map, compress
+/// much better, since the compressor can refer back into it from the first
+/// row.
+///
+/// The PNG specification forbids preset dictionaries, so files written
+/// with one can only be read by this crate's decoder, given the same
+/// dictionary. They suit private stores, not files to hand out.
+#[derive(Clone, PartialEq, Eq)]
+pub struct CompressionDictionary {
+    bytes: Vec<u8>,
+}
+
+impl CompressionDictionary {
+    /// Uses the last 32 KiB of `bytes`, all that deflate can refer to.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            bytes: bytes[bytes.len().saturating_sub(WINDOW_SIZE)..].to_vec(),
+        }
+    }
+
+    /// Builds a dictionary from `image`'s scanlines filtered with `filter`,
+    /// which is what the compressor sees; images encoded against it should
+    /// have the same format and use the same filter strategy.
+    pub fn from_image(image: &PngImage, filter: FilterStrategy) -> Result<Self, PngError> {
+        image.check_complete()?;
+        let filtered = image.filter_scanlines(filter, &mut Default::default())?;
+        Ok(Self::from_bytes(&filtered))
+    }
+
+    /// zlib's identifier for the dictionary, the Adler-32 checksum of its
+    /// bytes, which streams compressed with it record.
+    pub fn id(&self) -> u32 {
+        let mut checksum = Adler32::new();
+        checksum.update(&self.bytes);
+        checksum.value()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl fmt::Debug for CompressionDictionary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompressionDictionary")
+            .field("id", &format_args!("{:08x}", self.id()))
+            .field("len", &self.bytes.len())
+            .finish()
+    }
+}
+
+// zlib-compresses image data, against a preset dictionary if there is one.
+// flate2's Rust backend can't set one, so the dictionary is compressed
+// first and its output dropped; the encoder can still refer back into it.
+pub(crate) enum ZlibWriter {
+    Plain(ZlibEncoder<Vec<u8>>),
+    // Level 0, written as stored blocks directly
+    Stored {
+        stream: Vec<u8>,
+        pending: Vec<u8>,
+        checksum: Adler32,
+    },
+    // The output starts with the zlib header and dictionary ID, in place
+    // of the blocks that hold the dictionary itself
+    Primed {
+        encoder: DeflateEncoder<Vec<u8>>,
+        checksum: Adler32,
+    },
+}
+
+impl ZlibWriter {
+    pub(crate) fn new(level: u8, dictionary: Option<&CompressionDictionary>) -> io::Result<Self> {
+        let compression = Compression::new(level as u32);
+        let Some(dictionary) = dictionary else {
+            if level == 0 {
+                return Ok(ZlibWriter::Stored {
+                    stream: zlib_header(level, false).to_vec(),
+                    pending: Vec::with_capacity(STORED_BLOCK_SIZE),
+                    checksum: Adler32::new(),
+                });
+            }
+            return Ok(ZlibWriter::Plain(ZlibEncoder::new(Vec::new(), compression)));
+        };
+        let mut encoder = DeflateEncoder::new(Vec::new(), compression);
+        encoder.write_all(&dictionary.bytes)?;
+        // A sync flush ends the dictionary's blocks on a byte boundary
+        encoder.flush()?;
+        let output = encoder.get_mut();
+        output.clear();
+        output.extend_from_slice(&zlib_header(level, true));
+        output.extend_from_slice(&dictionary.id().to_be_bytes());
+        Ok(ZlibWriter::Primed {
+            encoder,
+            checksum: Adler32::new(),
+        })
+    }
+
+    pub(crate) fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        match self {
+            ZlibWriter::Plain(encoder) => encoder.write_all(data),
+            ZlibWriter::Stored {
+                stream,
+                pending,
+                checksum,
+            } => {
+                checksum.update(data);
+                let mut data = data;
+                while !data.is_empty() {
+                    let take = (STORED_BLOCK_SIZE - pending.len()).min(data.len());
+                    pending.extend_from_slice(&data[..take]);
+                    data = &data[take..];
+                    if pending.len() == STORED_BLOCK_SIZE {
+                        write_stored_block(stream, pending, false);
+                        pending.clear();
+                    }
+                }
+                Ok(())
+            }
+            ZlibWriter::Primed { encoder, checksum } => {
+                checksum.update(data);
+                encoder.write_all(data)
+            }
+        }
+    }
+
+    pub(crate) fn finish(self) -> io::Result<Vec<u8>> {
+        match self {
+            ZlibWriter::Plain(encoder) => encoder.finish(),
+            ZlibWriter::Stored {
+                mut stream,
+                pending,
+                checksum,
+            } => {
+                // The final block may be empty
+                write_stored_block(&mut stream, &pending, true);
+                stream.extend_from_slice(&checksum.value().to_be_bytes());
+                Ok(stream)
+            }
+            ZlibWriter::Primed { encoder, checksum } => {
+                let mut stream = encoder.finish()?;
+                stream.extend_from_slice(&checksum.value().to_be_bytes());
+                Ok(stream)
+            }
+        }
+    }
+
+    /// Takes the compressed output ready so far, which `finish` then leaves
+    /// out, so a stream can be written as it compresses.
+    pub(crate) fn take_output(&mut self) -> Vec<u8> {
+        match self {
+            ZlibWriter::Plain(encoder) => std::mem::take(encoder.get_mut()),
+            ZlibWriter::Stored { stream, .. } => std::mem::take(stream),
+            ZlibWriter::Primed { encoder, .. } => std::mem::take(encoder.get_mut()),
+        }
+    }
+}
+
+// Deflate with a 32 KiB window, zlib's hint of the level used, the preset
+// dictionary flag, and the check bits that make the pair a multiple of 31
+pub(crate) fn zlib_header(level: u8, dictionary: bool) -> [u8; 2] {
+    let method = 0x78;
+    let level_hint = match level {
+        0..=1 => 0,
+        2..=5 => 1,
+        6 => 2,
+        _ => 3,
+    };
+    let flags = (level_hint << 6) | if dictionary { FDICT } else { 0 };
+    let check = (31 - (u16::from_be_bytes([method, flags]) % 31) as u8) % 31;
+    [method, flags + check]
+}
+
+// A deflate block holding `data` as it is, which must be at most
+// `STORED_BLOCK_SIZE` bytes: a 3-bit header padded to a byte, since stored
+// blocks here always start on one, then the length and its complement
+fn write_stored_block(stream: &mut Vec<u8>, data: &[u8], last: bool) {
+    stream.push(last as u8);
+    stream.extend_from_slice(&(data.len() as u16).to_le_bytes());
+    stream.extend_from_slice(&(!(data.len() as u16)).to_le_bytes());
+    stream.extend_from_slice(data);
+}
+
+// The preset dictionary ID a zlib stream needs, if it needs one, from its
+// first six bytes
+pub(crate) fn required_dictionary(header: &[u8]) -> Option<u32> {
+    match header {
+        [_, flags, id @ ..] if flags & FDICT != 0 => {
+            Some(u32::from_be_bytes(id.get(..4)?.try_into().ok()?))
+        }
+        _ => None,
+    }
+}
+
+type Primed<R> = io::Chain<Cursor<Vec<u8>>, R>;
+
+// Inflates the rest of a zlib stream compressed against `dictionary`,
+// from just past its header and dictionary ID. The dictionary is fed to
+// the inflater first as stored blocks, putting it in the window, and its
+// output dropped.
+pub(crate) struct PrimedReader<R: Read> {
+    inflater: DeflateDecoder<BufReader<Primed<R>>>,
+    skip: usize,
+    checksum: Adler32,
+    done: bool,
+}
+
+impl<R: Read> PrimedReader<R> {
+    pub(crate) fn new(dictionary: &CompressionDictionary, rest: R) -> Self {
+        let mut stored = Vec::with_capacity(dictionary.bytes.len() + 5);
+        for block in dictionary.bytes.chunks(STORED_BLOCK_SIZE) {
+            write_stored_block(&mut stored, block, false);
+        }
+        Self {
+            inflater: DeflateDecoder::new(BufReader::new(Cursor::new(stored).chain(rest))),
+            skip: dictionary.bytes.len(),
+            checksum: Adler32::new(),
+            done: false,
+        }
+    }
+}
+
+impl<R: Read> Read for PrimedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let mut read = self.inflater.read(buf)?;
+            if read == 0 {
+                if !self.done && !buf.is_empty() {
+                    // The checksum follows the deflate data
+                    let mut stored = [0; 4];
+                    self.inflater.get_mut().read_exact(&mut stored)?;
+                    if u32::from_be_bytes(stored) != self.checksum.value() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "checksum mismatch",
+                        ));
+                    }
+                    self.done = true;
+                }
+                return Ok(0);
+            }
+            if self.skip > 0 {
+                let skipped = self.skip.min(read);
+                self.skip -= skipped;
+                buf.copy_within(skipped..read, 0);
+                read -= skipped;
+                if read == 0 {
+                    continue;
+                }
+            }
+            self.checksum.update(&buf[..read]);
+            return Ok(read);
+        }
+    }
+}
+
+// The Adler-32 checksum zlib uses
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Adler32 {
+    a: u32,
+    b: u32,
+}
+
+impl Adler32 {
+    pub(crate) fn new() -> Self {
+        Self { a: 1, b: 0 }
+    }
+
+    // Carries on from a checksum's value
+    pub(crate) fn from_value(value: u32) -> Self {
+        Self {
+            a: value & 0xFFFF,
+            b: value >> 16,
+        }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        const MODULUS: u32 = 65521;
+        // The most bytes that can be summed before `b` could overflow
+        for chunk in data.chunks(5552) {
+            for &byte in chunk {
+                self.a += byte as u32;
+                self.b += self.a;
+            }
+            self.a %= MODULUS;
+            self.b %= MODULUS;
+        }
+    }
+
+    pub(crate) fn value(&self) -> u32 {
+        (self.b << 16) | self.a
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::Decoder;
+    use crate::filter::FilterType;
+    use crate::options::EncodeOptions;
+    use crate::test_images::noise;
+    use crate::{BitDepth, ColorType};
+
+    // A tile and a neighbor that differs from it in a few pixels
+    fn tiles() -> (PngImage, PngImage) {
+        let reference = noise(64, 32, ColorType::Rgb, BitDepth::Eight, 11);
+        let mut neighbor = reference.clone();
+        for i in (0..neighbor.data().len()).step_by(997) {
+            neighbor.data_mut()[i] ^= 0x55;
+        }
+        (reference, neighbor)
+    }
+
+    fn encode(image: &PngImage, level: u8, dictionary: Option<&CompressionDictionary>) -> Vec<u8> {
+        let options = EncodeOptions {
+            compression_level: level,
+            filter: FilterStrategy::Fixed(FilterType::None),
+            dictionary: dictionary.cloned(),
+            ..EncodeOptions::default()
+        };
+        let mut encoded = Vec::new();
+        image.write_with_options(&mut encoded, &options).unwrap();
+        encoded
+    }
+
+    #[test]
+    fn dictionary_round_trips_at_every_level() {
+        let (reference, neighbor) = tiles();
+        let filter = FilterStrategy::Fixed(FilterType::None);
+        let dictionary = CompressionDictionary::from_image(&reference, filter).unwrap();
+        for level in [0, 1, 6, 9] {
+            let encoded = encode(&neighbor, level, Some(&dictionary));
+            let decoded = Decoder::new(encoded.as_slice())
+                .dictionary(dictionary.clone())
+                .decode()
+                .unwrap();
+            assert_eq!(decoded.data(), neighbor.data(), "level {}", level);
+            let decoded = Decoder::new(encoded.as_slice())
+                .dictionary(dictionary.clone())
+                .decode_borrowed()
+                .unwrap();
+            assert_eq!(decoded.data(), neighbor.data(), "level {}", level);
+
+            if level > 0 {
+                let plain = encode(&neighbor, level, None);
+                assert!(encoded.len() * 4 < plain.len(), "level {}", level);
+            }
+        }
+    }
+
+    #[test]
+    fn dictionary_is_required_to_decode() {
+        let (reference, neighbor) = tiles();
+        let filter = FilterStrategy::Fixed(FilterType::None);
+        let dictionary = CompressionDictionary::from_image(&reference, filter).unwrap();
+        let other = CompressionDictionary::from_bytes(b"some other dictionary");
+        for level in [0, 1, 6, 9] {
+            let encoded = encode(&neighbor, level, Some(&dictionary));
+            assert!(Decoder::new(encoded.as_slice()).decode().is_err());
+            assert!(Decoder::new(encoded.as_slice())
+                .dictionary(other.clone())
+                .decode()
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn zlib_headers_are_valid() {
+        for level in 0..=9 {
+            for dictionary in [false, true] {
+                let header = zlib_header(level, dictionary);
+                assert_eq!(u16::from_be_bytes(header) % 31, 0);
+                assert_eq!(header[1] & FDICT != 0, dictionary);
+            }
+        }
+    }
+
+    #[test]
+    fn stored_stream_round_trips() {
+        for length in [0, 1, STORED_BLOCK_SIZE, STORED_BLOCK_SIZE + 1, 200_000] {
+            let data: Vec<u8> = (0..length).map(|i| (i * 7 % 251) as u8).collect();
+            let mut writer = ZlibWriter::new(0, None).unwrap();
+            // Uneven writes that straddle block boundaries
+            for piece in data.chunks(40_000) {
+                writer.write_all(piece).unwrap();
+            }
+            let stream = writer.finish().unwrap();
+
+            let blocks = length / STORED_BLOCK_SIZE + 1;
+            assert_eq!(stream.len(), 2 + blocks * 5 + length + 4);
+            let mut inflated = Vec::new();
+            flate2::read::ZlibDecoder::new(stream.as_slice())
+                .read_to_end(&mut inflated)
+                .unwrap();
+            assert_eq!(inflated, data);
+        }
+    }
+
+    #[test]
+    fn uncompressed_images_round_trip() {
+        for (width, height) in [(1, 1), (200, 150), (300, 300)] {
+            let image = noise(width, height, ColorType::Rgba, BitDepth::Eight, 12);
+            let mut encoded = Vec::new();
+            image
+                .write_with_options(&mut encoded, &EncodeOptions::uncompressed())
+                .unwrap();
+            let raw = (width as usize * 4 + 1) * height as usize;
+            assert!(encoded.len() > raw && encoded.len() as u64 <= image.encoded_size_bound());
+            let decoded = Decoder::new(encoded.as_slice()).decode().unwrap();
+            assert_eq!(decoded.data(), image.data());
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use crate::color::Color;
+use crate::error::PngError;
+use crate::{image_size, PngImage};
+
+impl PngImage {
+    /// Sets every pixel to `color`, completing the image.
+    pub fn fill(&mut self, color: Color) -> Result<(), PngError> {
+        let pixel = self.color_components(color)?;
+        let pixels = image_size(self.width, self.height, 1)?;
+        self.data.replace(pixel.repeat(pixels))?;
+        Ok(())
+    }
+
+    /// Sets the `width`x`height` region whose top-left corner is at (`x`,
+    /// `y`) to `color`. Pixels not added yet are filled with zero bytes
+    /// first, so the image is complete afterwards.
+    pub fn fill_rect(
+        &mut self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        color: Color,
+    ) -> Result<(), PngError> {
+        self.check_region(x, y, width, height)?;
+        let pixel = self.color_components(color)?;
+        self.complete()?;
+
+        let row = pixel.repeat(width as usize);
+        let stride = self.width as usize * pixel.len();
+        for line in y as usize..(y + height) as usize {
+            let start = line * stride + x as usize * pixel.len();
+            self.data[start..start + row.len()].copy_from_slice(&row);
+        }
+        Ok(())
+    }
+
+    /// Copies `src` over this image with its top-left corner at (`x`, `y`),
+    /// replacing pixels rather than blending. Parts of `src` that fall
+    /// outside this image are clipped. Both images must have the same color
+    /// type and bit depth; palette indices are copied unchanged. As with
+    /// `fill_rect`, missing pixels of this image are zero-filled first.
+    pub fn blit(&mut self, src: &PngImage, x: i32, y: i32) -> Result<(), PngError> {
+        if src.color_type != self.color_type || src.bit_depth != self.bit_depth {
+            return Err(PngError::ColorTypeError);
+        }
+        src.check_complete()?;
+        self.complete()?;
+
+        // Overlap in destination coordinates
+        let left = (x as i64).max(0);
+        let top = (y as i64).max(0);
+        let right = (x as i64 + src.width as i64).min(self.width as i64);
+        let bottom = (y as i64 + src.height as i64).min(self.height as i64);
+        if left >= right || top >= bottom {
+            return Ok(());
+        }
+
+        let bpp = self.bytes_per_pixel();
+        let len = (right - left) as usize * bpp;
+        for dst_y in top..bottom {
+            let src_y = (dst_y - y as i64) as usize;
+            let src_start = (src_y * src.width as usize + (left - x as i64) as usize) * bpp;
+            let dst_start = (dst_y as usize * self.width as usize + left as usize) * bpp;
+            self.data[dst_start..dst_start + len]
+                .copy_from_slice(&src.data[src_start..src_start + len]);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_region(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> Result<(), PngError> {
+        let fits = |start: u32, len: u32, limit: u32| {
+            start.checked_add(len).is_some_and(|end| end <= limit)
+        };
+        if !fits(x, width, self.width) || !fits(y, height, self.height) {
+            return Err(PngError::RegionOutOfBounds {
+                region: (x, y, width, height),
+                dimensions: (self.width, self.height),
+            });
+        }
+        Ok(())
+    }
+
+    // Fails unless every pixel has been added
+    pub(crate) fn check_complete(&self) -> Result<(), PngError> {
+        let pixels = self.width as usize * self.height as usize;
+        if self.data.len() != pixels * self.bytes_per_pixel() {
+            return Err(PngError::PixelCountMismatch {
+                expected: pixels,
+                actual: self.data.len() / self.bytes_per_pixel(),
+                dimensions: (self.width, self.height),
+            });
+        }
+        Ok(())
+    }
+
+    // Pads missing pixels with zero bytes
+    pub(crate) fn complete(&mut self) -> Result<(), PngError> {
+        let size = self.width as usize * self.height as usize * self.bytes_per_pixel();
+        self.data.resize(size, 0)
+    }
+}
@@ -1,9 +1,33 @@
 use std::io;
 use thiserror::Error;
 
+use crate::chunks::SignatureDamage;
 use crate::ColorType;
 
+/// Broad classes of [`PngError`], for callers that only need to know what
+/// kind of thing went wrong.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Reading or writing the underlying stream failed
+    Io,
+    /// The input file is malformed or uses something unsupported
+    InvalidData,
+    /// An argument or the image being built is invalid
+    InvalidInput,
+    /// zlib compression failed
+    Compression,
+    /// The image is too large to hold in memory
+    TooLarge,
+    /// Stopped through a [`CancelToken`](crate::CancelToken)
+    Cancelled,
+    /// The encoder wrote something that doesn't decode back to the image,
+    /// which is a bug in this crate
+    Internal,
+}
+
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum PngError {
     #[error("Invalid image dimensions: {0}x{1}")]
     InvalidDimensions(u32, u32),
@@ -34,8 +58,148 @@ pub enum PngError {
     #[error("Invalid palette: {0}")]
     InvalidPalette(String),
 
-    #[error("Invalid palette index: {0}")]
-    InvalidPaletteEntry(u8),
+    #[error("Palette index {index} at ({x}, {y}) is out of range for a {palette_entries}-entry palette ({count} pixel(s) affected)")]
+    InvalidPaletteEntry {
+        /// The first offending index, in pixel order
+        index: u8,
+        x: u32,
+        y: u32,
+        /// Offset of that pixel in the image's sample data
+        offset: usize,
+        /// Number of pixels with out-of-range indices
+        count: usize,
+        palette_entries: usize,
+    },
+
+    #[error("Decoding error: {0}")]
+    Decode(String),
+
+    #[error("Invalid PNG signature")]
+    InvalidSignature { found: [u8; 8] },
+
+    /// The signature was mangled in a way that usually damages the rest of
+    /// the file too; see [`Decoder::repair_signature`](crate::Decoder::repair_signature).
+    #[error("Damaged PNG signature: {damage}")]
+    DamagedSignature {
+        damage: SignatureDamage,
+        found: [u8; 8],
+    },
+
+    #[error("CRC mismatch in {} chunk at offset {offset}: stored {stored:08x}, computed {computed:08x}", String::from_utf8_lossy(.chunk_type))]
+    CrcMismatch {
+        chunk_type: [u8; 4],
+        /// Offset of the chunk's length field
+        offset: u64,
+        stored: u32,
+        computed: u32,
+    },
+
+    #[error("Unexpected {} chunk at offset {offset}: {reason}", String::from_utf8_lossy(.chunk_type))]
+    UnexpectedChunk {
+        chunk_type: [u8; 4],
+        offset: u64,
+        reason: &'static str,
+    },
+
+    #[error("Missing {} chunk", String::from_utf8_lossy(.chunk_type))]
+    MissingChunk { chunk_type: [u8; 4] },
+
+    #[error("Invalid IHDR: {0}")]
+    InvalidHeader(String),
+
+    #[error("Import error: {0}")]
+    Import(String),
+
+    #[error("Bit depth {bit_depth} is not valid for {color_type:?}")]
+    InvalidBitDepth {
+        bit_depth: u8,
+        color_type: ColorType,
+    },
+
+    #[error("Invalid animation: {0}")]
+    Animation(String),
+
+    #[error("Image sizes differ: {}x{} vs {}x{}", .expected.0, .expected.1, .actual.0, .actual.1)]
+    ImageSizeMismatch {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+
+    #[error("Invalid metadata: {0}")]
+    InvalidMetadata(String),
+
+    #[error("Region {}x{} at ({}, {}) is outside the {}x{} image", .region.2, .region.3, .region.0, .region.1, .dimensions.0, .dimensions.1)]
+    RegionOutOfBounds {
+        /// x, y, width, height
+        region: (u32, u32, u32, u32),
+        dimensions: (u32, u32),
+    },
+
+    #[error("Image of {width}x{height} pixels is too large for this platform")]
+    ImageTooLarge { width: u32, height: u32 },
+
+    #[error("{} chunk of {length} bytes exceeds the maximum chunk length", String::from_utf8_lossy(.chunk_type))]
+    ChunkTooLarge { chunk_type: [u8; 4], length: usize },
+
+    #[error("Rejected in strict mode: {0}")]
+    StrictViolation(String),
+
+    /// A file went past one of the caps in
+    /// [`DecodeLimits`](crate::DecodeLimits)
+    #[error("Decode limit exceeded: {0}")]
+    LimitExceeded(String),
+
+    #[error("Sample value {value} does not fit in {bit_depth} bits")]
+    SampleOutOfRange { value: u16, bit_depth: u8 },
+
+    #[error("Row is {actual} bytes, expected {expected}")]
+    RowLengthMismatch { expected: usize, actual: usize },
+
+    #[error("Stride of {stride} bytes is shorter than a {row_length}-byte row")]
+    InvalidStride { stride: usize, row_length: usize },
+
+    #[error("Invalid stream checkpoint: {0}")]
+    InvalidCheckpoint(String),
+
+    #[error("Unsupported encode option: {0}")]
+    UnsupportedOption(String),
+
+    #[error("Operation cancelled")]
+    Cancelled,
+
+    #[error("Buffer of {actual} bytes is too small, expected at least {expected}")]
+    BufferTooSmall { expected: usize, actual: usize },
+
+    #[error("Invalid template '{0}'")]
+    InvalidTemplate(String),
+
+    #[error("Invalid kernel: {0}")]
+    InvalidKernel(String),
+
+    #[error("Invalid chunk manifest: {0}")]
+    InvalidManifest(String),
+
+    #[error("Can't encode QR code: {0}")]
+    QrCode(String),
+
+    #[error("Invalid multipart boundary '{0}'")]
+    InvalidBoundary(String),
+
+    #[error("Invalid nine-patch: {0}")]
+    InvalidNinePatch(String),
+
+    #[error("Round trip check failed: {0}")]
+    RoundTripMismatch(String),
+
+    /// An error while reading a stream, with where in it things went wrong
+    #[error("{source} (at byte {offset}{})", .chunk_type.map(|t| format!(", in {} chunk", String::from_utf8_lossy(&t))).unwrap_or_default())]
+    AtOffset {
+        /// Offset from the start of the stream, signature included
+        offset: u64,
+        /// The chunk being processed, if any
+        chunk_type: Option<[u8; 4]>,
+        source: Box<PngError>,
+    },
 }
 
 impl From<flate2::CompressError> for PngError {
@@ -43,3 +207,82 @@ impl From<flate2::CompressError> for PngError {
         PngError::Compression(e.to_string())
     }
 }
+
+impl PngError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            PngError::AtOffset { source, .. } => source.kind(),
+            PngError::Io(_) => ErrorKind::Io,
+            PngError::Compression(_) => ErrorKind::Compression,
+            PngError::ImageTooLarge { .. } | PngError::LimitExceeded(_) => ErrorKind::TooLarge,
+            PngError::Cancelled => ErrorKind::Cancelled,
+            PngError::RoundTripMismatch(_) => ErrorKind::Internal,
+            PngError::Decode(_)
+            | PngError::Import(_)
+            | PngError::InvalidSignature { .. }
+            | PngError::DamagedSignature { .. }
+            | PngError::CrcMismatch { .. }
+            | PngError::UnexpectedChunk { .. }
+            | PngError::MissingChunk { .. }
+            | PngError::InvalidHeader(_) => ErrorKind::InvalidData,
+            PngError::InvalidDimensions(..)
+            | PngError::ColorTypeError
+            | PngError::ComponentCountMismatch { .. }
+            | PngError::PixelCountMismatch { .. }
+            | PngError::InvalidPalette(_)
+            | PngError::InvalidPaletteEntry { .. }
+            | PngError::InvalidBitDepth { .. }
+            | PngError::Animation(_)
+            | PngError::ImageSizeMismatch { .. }
+            | PngError::InvalidMetadata(_)
+            | PngError::RegionOutOfBounds { .. }
+            | PngError::InvalidCheckpoint(_)
+            | PngError::UnsupportedOption(_)
+            | PngError::ChunkTooLarge { .. }
+            | PngError::StrictViolation(_)
+            | PngError::SampleOutOfRange { .. }
+            | PngError::RowLengthMismatch { .. }
+            | PngError::InvalidStride { .. }
+            | PngError::BufferTooSmall { .. }
+            | PngError::InvalidTemplate(_)
+            | PngError::InvalidKernel(_)
+            | PngError::InvalidManifest(_)
+            | PngError::InvalidBoundary(_)
+            | PngError::InvalidNinePatch(_)
+            | PngError::QrCode(_) => ErrorKind::InvalidInput,
+        }
+    }
+
+    /// The byte offset in the stream where the error was found, if known.
+    pub fn offset(&self) -> Option<u64> {
+        match self {
+            PngError::AtOffset { offset, .. }
+            | PngError::CrcMismatch { offset, .. }
+            | PngError::UnexpectedChunk { offset, .. } => Some(*offset),
+            _ => None,
+        }
+    }
+
+    /// The error without any [`PngError::AtOffset`] context.
+    pub fn root(&self) -> &PngError {
+        match self {
+            PngError::AtOffset { source, .. } => source.root(),
+            e => e,
+        }
+    }
+
+    // Adds where in the stream the error happened, unless it already says
+    pub(crate) fn at(self, offset: u64, chunk_type: Option<[u8; 4]>) -> Self {
+        match self {
+            PngError::AtOffset { .. }
+            | PngError::CrcMismatch { .. }
+            | PngError::UnexpectedChunk { .. }
+            | PngError::Cancelled => self,
+            source => PngError::AtOffset {
+                offset,
+                chunk_type,
+                source: Box::new(source),
+            },
+        }
+    }
+}
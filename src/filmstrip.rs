@@ -0,0 +1,133 @@
+use crate::apng::Animation;
+use crate::color::Color;
+use crate::error::PngError;
+use crate::montage::to_cell;
+use crate::transform::ResizeFilter;
+use crate::{ColorType, PngImage};
+
+/// Which frames a filmstrip shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameSampling {
+    All,
+    /// Every `n`th frame, starting with the first
+    Every(usize),
+    /// This many frames, spread evenly through an animation's running time,
+    /// or through the list given to [`filmstrip`]
+    Count(usize),
+}
+
+/// Settings for [`filmstrip`] and [`Animation::filmstrip`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilmstripOptions {
+    pub sampling: FrameSampling,
+    /// Scale frames to this height, keeping their aspect ratio
+    pub frame_height: Option<u32>,
+    /// Pixels between neighboring frames
+    pub spacing: u32,
+    /// Fills the spacing and the area around frames shorter than the
+    /// strip. Frames are composited over it if it's opaque, and copied as
+    /// they are otherwise.
+    pub background: Color,
+}
+
+impl Default for FilmstripOptions {
+    fn default() -> Self {
+        Self {
+            sampling: FrameSampling::All,
+            frame_height: None,
+            spacing: 0,
+            background: Color::TRANSPARENT,
+        }
+    }
+}
+
+impl Animation {
+    /// Renders sampled frames side by side into one 8-bit RGBA image, as a
+    /// preview of the animation. Frames are composited onto the canvas
+    /// first, so each shows what a viewer would at that point.
+    pub fn filmstrip(&self, options: &FilmstripOptions) -> Result<PngImage, PngError> {
+        let mut starts = Vec::with_capacity(self.frames().len());
+        let mut elapsed = 0.0;
+        for frame in self.frames() {
+            starts.push(elapsed);
+            elapsed += frame.delay_ms();
+        }
+        let indices = sample(
+            self.frames().len(),
+            options.sampling,
+            Some((&starts, elapsed)),
+        );
+
+        let rendered = self.composite_frames()?;
+        let frames: Vec<PngImage> = indices.into_iter().map(|i| rendered[i].clone()).collect();
+        lay_out(&frames, options)
+    }
+}
+
+/// Lays images side by side into one 8-bit RGBA image, as
+/// [`Animation::filmstrip`] does for an animation's frames. Images of
+/// different heights are centered vertically.
+pub fn filmstrip(frames: &[PngImage], options: &FilmstripOptions) -> Result<PngImage, PngError> {
+    let indices = sample(frames.len(), options.sampling, None);
+    let frames: Vec<PngImage> = indices.into_iter().map(|i| frames[i].clone()).collect();
+    lay_out(&frames, options)
+}
+
+// Indices of the frames to show. With start times, `Count` samples the
+// frame on screen at evenly spaced moments instead of evenly spaced indices.
+fn sample(len: usize, sampling: FrameSampling, timing: Option<(&[f64], f64)>) -> Vec<usize> {
+    match sampling {
+        FrameSampling::All => (0..len).collect(),
+        FrameSampling::Every(n) => (0..len).step_by(n.max(1)).collect(),
+        FrameSampling::Count(n) if n >= len => (0..len).collect(),
+        FrameSampling::Count(n) => {
+            let mut indices: Vec<usize> = match timing {
+                Some((starts, total)) if total > 0.0 => (0..n)
+                    .map(|k| {
+                        let time = total * k as f64 / n as f64;
+                        starts.partition_point(|&start| start <= time) - 1
+                    })
+                    .collect(),
+                _ => (0..n).map(|k| k * len / n).collect(),
+            };
+            // Long frames can cover several sample points
+            indices.dedup();
+            indices
+        }
+    }
+}
+
+fn lay_out(frames: &[PngImage], options: &FilmstripOptions) -> Result<PngImage, PngError> {
+    if frames.is_empty() {
+        return Err(PngError::Animation("filmstrip has no frames".to_string()));
+    }
+    let mut cells = Vec::with_capacity(frames.len());
+    for frame in frames {
+        let scaled = match options.frame_height {
+            Some(height) if height != frame.height => {
+                let width = (frame.width as u64 * height as u64 / frame.height.max(1) as u64)
+                    .clamp(1, u32::MAX as u64) as u32;
+                frame.resize(width, height, ResizeFilter::Lanczos3)?
+            }
+            _ => frame.clone(),
+        };
+        cells.push(to_cell(&scaled, options.background)?);
+    }
+
+    let height = cells.iter().map(|c| c.height).max().unwrap_or(0);
+    let width = cells.iter().map(|c| c.width as u64).sum::<u64>()
+        + options.spacing as u64 * (cells.len() as u64 - 1);
+    let width = u32::try_from(width).map_err(|_| PngError::ImageTooLarge {
+        width: u32::MAX,
+        height,
+    })?;
+
+    let mut strip = PngImage::new(width, height, ColorType::Rgba)?;
+    strip.fill(options.background)?;
+    let mut x = 0;
+    for cell in &cells {
+        strip.blit(cell, x as i32, ((height - cell.height) / 2) as i32)?;
+        x += cell.width + options.spacing;
+    }
+    Ok(strip)
+}
@@ -0,0 +1,287 @@
+use crate::error::PngError;
+use crate::progress::{Phase, ProgressHook};
+
+/// The five scanline filters defined by PNG filter method 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum FilterType {
+    None = 0,
+    Sub = 1,
+    Up = 2,
+    Average = 3,
+    Paeth = 4,
+}
+
+impl FilterType {
+    pub const ALL: [FilterType; 5] = [
+        FilterType::None,
+        FilterType::Sub,
+        FilterType::Up,
+        FilterType::Average,
+        FilterType::Paeth,
+    ];
+
+    pub(crate) fn from_u8(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(FilterType::None),
+            1 => Some(FilterType::Sub),
+            2 => Some(FilterType::Up),
+            3 => Some(FilterType::Average),
+            4 => Some(FilterType::Paeth),
+            _ => None,
+        }
+    }
+}
+
+/// How the encoder picks a filter for each scanline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum FilterStrategy {
+    /// Use the same filter for every row
+    Fixed(FilterType),
+    /// Try every filter per row and keep the one with the smallest sum of
+    /// absolute values, the heuristic recommended by the specification
+    Adaptive,
+}
+
+impl Default for FilterStrategy {
+    fn default() -> Self {
+        FilterStrategy::Fixed(FilterType::Sub)
+    }
+}
+
+fn paeth_predictor(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Filters `row` against `prev` (all zeros for the first row) into `out`.
+/// `bpp` is the number of bytes per complete pixel, rounded up to 1.
+pub(crate) fn filter_row(
+    filter: FilterType,
+    row: &[u8],
+    prev: &[u8],
+    bpp: usize,
+    out: &mut Vec<u8>,
+) {
+    out.push(filter as u8);
+    match bpp {
+        1 => filter_bytes::<1>(filter, row, prev, out),
+        2 => filter_bytes::<2>(filter, row, prev, out),
+        3 => filter_bytes::<3>(filter, row, prev, out),
+        4 => filter_bytes::<4>(filter, row, prev, out),
+        6 => filter_bytes::<6>(filter, row, prev, out),
+        8 => filter_bytes::<8>(filter, row, prev, out),
+        _ => filter_bytes_with(filter, row, prev, bpp, out),
+    }
+}
+
+// A copy of the filter loops for each pixel size, so the offsets are
+// constants and the loops can be vectorized
+fn filter_bytes<const BPP: usize>(filter: FilterType, row: &[u8], prev: &[u8], out: &mut Vec<u8>) {
+    filter_bytes_with(filter, row, prev, BPP, out)
+}
+
+// The first pixel has no left neighbor, so it's handled before the loop
+// rather than checked for on every byte
+#[inline(always)]
+fn filter_bytes_with(filter: FilterType, row: &[u8], prev: &[u8], bpp: usize, out: &mut Vec<u8>) {
+    let first = bpp.min(row.len());
+    let (left, right) = (&row[..row.len() - first], &row[first..]);
+    let (up_left, up) = (&prev[..row.len() - first], &prev[first..row.len()]);
+    match filter {
+        FilterType::None => out.extend_from_slice(row),
+        FilterType::Sub => {
+            out.extend_from_slice(&row[..first]);
+            out.extend(right.iter().zip(left).map(|(&x, &a)| x.wrapping_sub(a)));
+        }
+        FilterType::Up => out.extend(row.iter().zip(prev).map(|(&x, &b)| x.wrapping_sub(b))),
+        FilterType::Average => {
+            out.extend(
+                row[..first]
+                    .iter()
+                    .zip(prev)
+                    .map(|(&x, &b)| x.wrapping_sub(b / 2)),
+            );
+            out.extend(
+                right
+                    .iter()
+                    .zip(left)
+                    .zip(up)
+                    .map(|((&x, &a), &b)| x.wrapping_sub(((a as u16 + b as u16) / 2) as u8)),
+            );
+        }
+        FilterType::Paeth => {
+            // With no left neighbors the predictor is always the byte above
+            out.extend(
+                row[..first]
+                    .iter()
+                    .zip(prev)
+                    .map(|(&x, &b)| x.wrapping_sub(b)),
+            );
+            out.extend(
+                right
+                    .iter()
+                    .zip(left)
+                    .zip(up)
+                    .zip(up_left)
+                    .map(|(((&x, &a), &b), &c)| x.wrapping_sub(paeth_predictor(a, b, c))),
+            );
+        }
+    }
+}
+
+/// Filters every row of `rows` (each `row_len` bytes) according to `strategy`.
+pub(crate) fn filter_rows(
+    strategy: FilterStrategy,
+    rows: &[u8],
+    row_len: usize,
+    bpp: usize,
+    progress: &mut ProgressHook,
+) -> Result<Vec<u8>, PngError> {
+    progress.begin(Phase::Filtering, rows.len() / row_len.max(1))?;
+    let mut filtered = Vec::with_capacity(rows.len() + rows.len() / row_len.max(1));
+    let mut filter = RowFilter::new(strategy, row_len, bpp);
+    #[cfg(feature = "tracing")]
+    let _span =
+        tracing::debug_span!("filter", ?strategy, rows = rows.len() / row_len.max(1)).entered();
+
+    for row in rows.chunks_exact(row_len) {
+        filter.filter(row, &mut filtered);
+        progress.advance()?;
+    }
+    Ok(filtered)
+}
+
+/// Filters rows one after another, remembering each as the previous row
+/// for the next.
+pub(crate) struct RowFilter {
+    strategy: FilterStrategy,
+    bpp: usize,
+    prev: Vec<u8>,
+    candidate: Vec<u8>,
+}
+
+impl RowFilter {
+    pub(crate) fn new(strategy: FilterStrategy, row_len: usize, bpp: usize) -> Self {
+        Self {
+            strategy,
+            bpp,
+            prev: vec![0; row_len],
+            candidate: Vec::with_capacity(row_len + 1),
+        }
+    }
+
+    // Carries on after `prev`, the last row filtered before
+    pub(crate) fn resume(strategy: FilterStrategy, prev: Vec<u8>, bpp: usize) -> Self {
+        Self {
+            strategy,
+            bpp,
+            candidate: Vec::with_capacity(prev.len() + 1),
+            prev,
+        }
+    }
+
+    pub(crate) fn previous(&self) -> &[u8] {
+        &self.prev
+    }
+
+    /// Appends the filter byte and filtered `row` to `out`.
+    pub(crate) fn filter(&mut self, row: &[u8], out: &mut Vec<u8>) {
+        match self.strategy {
+            FilterStrategy::Fixed(filter) => filter_row(filter, row, &self.prev, self.bpp, out),
+            FilterStrategy::Adaptive => {
+                let mut best: Option<(u64, Vec<u8>)> = None;
+                for filter in FilterType::ALL {
+                    self.candidate.clear();
+                    filter_row(filter, row, &self.prev, self.bpp, &mut self.candidate);
+                    // Treat bytes as signed so small negative residuals score low
+                    let score: u64 = self.candidate[1..]
+                        .iter()
+                        .map(|&b| (b as i8).unsigned_abs() as u64)
+                        .sum();
+                    if best.as_ref().is_none_or(|(s, _)| score < *s) {
+                        best = Some((score, self.candidate.clone()));
+                    }
+                }
+                if let Some((_, bytes)) = best {
+                    #[cfg(feature = "tracing")]
+                    tracing::trace!(filter = bytes[0], "filter chosen");
+                    out.extend_from_slice(&bytes);
+                }
+            }
+        }
+        self.prev.copy_from_slice(row);
+    }
+}
+
+/// Reverses filtering in place. `row` excludes the filter byte.
+pub(crate) fn unfilter_row(
+    filter: u8,
+    row: &mut [u8],
+    prev: &[u8],
+    bpp: usize,
+) -> Result<(), PngError> {
+    let filter = FilterType::from_u8(filter)
+        .ok_or_else(|| PngError::Decode(format!("Invalid filter type {}", filter)))?;
+
+    match bpp {
+        1 => unfilter_bytes::<1>(filter, row, prev),
+        2 => unfilter_bytes::<2>(filter, row, prev),
+        3 => unfilter_bytes::<3>(filter, row, prev),
+        4 => unfilter_bytes::<4>(filter, row, prev),
+        6 => unfilter_bytes::<6>(filter, row, prev),
+        8 => unfilter_bytes::<8>(filter, row, prev),
+        _ => unfilter_bytes_with(filter, row, prev, bpp),
+    }
+    Ok(())
+}
+
+fn unfilter_bytes<const BPP: usize>(filter: FilterType, row: &mut [u8], prev: &[u8]) {
+    unfilter_bytes_with(filter, row, prev, BPP)
+}
+
+#[inline(always)]
+fn unfilter_bytes_with(filter: FilterType, row: &mut [u8], prev: &[u8], bpp: usize) {
+    let first = bpp.min(row.len());
+    let prev = &prev[..row.len()];
+    match filter {
+        FilterType::None => {}
+        FilterType::Sub => {
+            for i in first..row.len() {
+                row[i] = row[i].wrapping_add(row[i - bpp]);
+            }
+        }
+        FilterType::Up => {
+            for (x, &b) in row.iter_mut().zip(prev) {
+                *x = x.wrapping_add(b);
+            }
+        }
+        FilterType::Average => {
+            for i in 0..first {
+                row[i] = row[i].wrapping_add(prev[i] / 2);
+            }
+            for i in first..row.len() {
+                row[i] = row[i].wrapping_add(((row[i - bpp] as u16 + prev[i] as u16) / 2) as u8);
+            }
+        }
+        FilterType::Paeth => {
+            for i in 0..first {
+                row[i] = row[i].wrapping_add(prev[i]);
+            }
+            for i in first..row.len() {
+                let predicted = paeth_predictor(row[i - bpp], prev[i], prev[i - bpp]);
+                row[i] = row[i].wrapping_add(predicted);
+            }
+        }
+    }
+}
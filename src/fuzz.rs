@@ -0,0 +1,171 @@
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+use crate::decoder::Decoder;
+use crate::error::PngError;
+use crate::options::{EncodeOptions, Strictness};
+use crate::pixel::Rgba16;
+use crate::{BitDepth, ColorType, PngImage};
+
+// Keeps generated images small enough for fuzzers to run quickly
+const MAX_DIMENSION: u32 = 64;
+
+impl<'a> Arbitrary<'a> for EncodeOptions {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self {
+            compression_level: u.int_in_range(0..=9)?,
+            filter: u.arbitrary()?,
+            strictness: u.arbitrary()?,
+            deterministic: u.arbitrary()?,
+            optimize_palette: u.arbitrary()?,
+            pipelined: u.arbitrary()?,
+            dictionary: None,
+        })
+    }
+}
+
+/// A complete, valid image of up to 64x64 pixels in any format. Indexed
+/// images get a palette covering every index they use.
+impl<'a> Arbitrary<'a> for PngImage {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let color_type: ColorType = u.arbitrary()?;
+        let depths: Vec<BitDepth> = [
+            BitDepth::One,
+            BitDepth::Two,
+            BitDepth::Four,
+            BitDepth::Eight,
+            BitDepth::Sixteen,
+        ]
+        .into_iter()
+        .filter(|&d| color_type.allows_bit_depth(d))
+        .collect();
+        let bit_depth = *u.choose(&depths)?;
+        let width = u.int_in_range(1..=MAX_DIMENSION)?;
+        let height = u.int_in_range(1..=MAX_DIMENSION)?;
+        let mut image = PngImage::with_bit_depth(width, height, color_type, bit_depth)
+            .map_err(|_| arbitrary::Error::IncorrectFormat)?;
+
+        let samples = (width * height) as usize * color_type.channels();
+        let mut data = Vec::with_capacity(samples * bit_depth.bytes_per_sample());
+        if color_type == ColorType::Indexed {
+            let max_entries = 256.min(1 << bit_depth.bits());
+            let entries = u.int_in_range(1..=max_entries)?;
+            let palette: Vec<u8> = (0..entries * 3)
+                .map(|_| u.arbitrary())
+                .collect::<Result<_>>()?;
+            image
+                .set_palette(&palette)
+                .map_err(|_| arbitrary::Error::IncorrectFormat)?;
+            for _ in 0..samples {
+                data.push((u.arbitrary::<u8>()? as usize % entries) as u8);
+            }
+        } else if bit_depth == BitDepth::Sixteen {
+            for _ in 0..samples {
+                data.extend_from_slice(&u.arbitrary::<u16>()?.to_be_bytes());
+            }
+        } else {
+            let max = bit_depth.max_value() as u8;
+            for _ in 0..samples {
+                data.push(u.arbitrary::<u8>()? & max);
+            }
+        }
+        image.data = data.into();
+        Ok(image)
+    }
+}
+
+/// Bytes for [`Decoder`] along with its settings. The bytes are usually an
+/// encoded arbitrary image, possibly with some bytes overwritten, so that
+/// fuzzing reaches past the signature and header checks; otherwise they are
+/// taken as they come.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecoderInput {
+    pub bytes: Vec<u8>,
+    pub expand_palette: bool,
+    pub expand_transparency: bool,
+    pub auto_orient: bool,
+}
+
+impl DecoderInput {
+    pub fn decode(&self) -> Result<PngImage, PngError> {
+        Decoder::new(self.bytes.as_slice())
+            .expand_palette(self.expand_palette)
+            .expand_transparency(self.expand_transparency)
+            .auto_orient(self.auto_orient)
+            .decode()
+    }
+}
+
+impl<'a> Arbitrary<'a> for DecoderInput {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let expand_palette = u.arbitrary()?;
+        let expand_transparency = u.arbitrary()?;
+        let auto_orient = u.arbitrary()?;
+        let bytes = if u.ratio(1u8, 4)? {
+            u.arbitrary()?
+        } else {
+            let image: PngImage = u.arbitrary()?;
+            let options = EncodeOptions {
+                strictness: Strictness::Permissive,
+                ..u.arbitrary()?
+            };
+            let mut bytes = Vec::new();
+            image
+                .write_with_options(&mut bytes, &options)
+                .map_err(|_| arbitrary::Error::IncorrectFormat)?;
+            let corruptions = u.int_in_range(0..=4)?;
+            for _ in 0..corruptions {
+                let index = u.choose_index(bytes.len())?;
+                bytes[index] = u.arbitrary()?;
+            }
+            bytes
+        };
+        Ok(Self {
+            bytes,
+            expand_palette,
+            expand_transparency,
+            auto_orient,
+        })
+    }
+}
+
+/// An image and the options to encode it with, for checking that decoding
+/// gives the image back.
+#[derive(Debug, Clone, PartialEq, Eq, Arbitrary)]
+pub struct RoundTrip {
+    pub image: PngImage,
+    pub options: EncodeOptions,
+}
+
+impl RoundTrip {
+    /// Encodes the image and decodes the result. Strict checks are turned
+    /// off, since arbitrary images don't describe their color space.
+    pub fn run(&self) -> Result<PngImage, PngError> {
+        let options = EncodeOptions {
+            strictness: Strictness::Permissive,
+            ..self.options.clone()
+        };
+        let mut bytes = Vec::new();
+        self.image.write_with_options(&mut bytes, &options)?;
+        Decoder::new(bytes.as_slice()).decode()
+    }
+
+    /// Panics unless [`RoundTrip::run`] succeeds and gives back the same
+    /// image. With `optimize_palette` only the colors have to match, since
+    /// the palette may be reordered.
+    pub fn check(&self) {
+        let decoded = self.run().expect("round trip failed");
+        if self.options.optimize_palette && self.image.color_type == ColorType::Indexed {
+            assert_eq!(
+                (decoded.width, decoded.height),
+                (self.image.width, self.image.height)
+            );
+            assert_eq!(
+                decoded.pixels::<Rgba16>().expect("decoded pixels"),
+                self.image.pixels::<Rgba16>().expect("original pixels"),
+                "round trip changed colors"
+            );
+        } else {
+            assert_eq!(decoded, self.image, "round trip changed the image");
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use crate::color::Color;
+use crate::error::PngError;
+use crate::{BitDepth, ColorType, PngImage};
+
+/// Color bars in the usual SMPTE order
+const BARS: [[f64; 3]; 7] = [
+    [1.0, 1.0, 1.0],
+    [1.0, 1.0, 0.0],
+    [0.0, 1.0, 1.0],
+    [0.0, 1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0],
+];
+
+/// How a gradient moves between its ends, as a function from 0..=1 onto
+/// 0..=1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Easing {
+    #[default]
+    Linear,
+    /// Quadratic, starting slowly
+    EaseIn,
+    /// Quadratic, ending slowly
+    EaseOut,
+    /// Smoothstep, slow at both ends
+    EaseInOut,
+}
+
+impl Easing {
+    pub fn apply(self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+/// A procedural image for [`PngImage::generate`]. Noise patterns are
+/// deterministic for a given seed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pattern {
+    /// Red increasing downward and green increasing to the right
+    Gradient { easing: Easing },
+    /// Blends from `from` at the left (or top) edge to `to` at the right
+    /// (or bottom) edge
+    LinearGradient {
+        from: Color,
+        to: Color,
+        vertical: bool,
+        easing: Easing,
+    },
+    /// Black and white squares `cell` pixels wide
+    Checkerboard { cell: u32 },
+    /// Vertical SMPTE-style color bars
+    Bars,
+    /// Independent random values in every sample, including alpha
+    Noise { seed: u64 },
+    /// Gray value noise with lattice cells `scale` pixels wide, summed over
+    /// `octaves` of halving size
+    ValueNoise { scale: f64, octaves: u32, seed: u64 },
+    /// Gray Perlin gradient noise, with `scale` and `octaves` as for
+    /// `ValueNoise`
+    Perlin { scale: f64, octaves: u32, seed: u64 },
+    /// Gray falling from white at the top, alpha rising to the right
+    AlphaRamp,
+}
+
+impl PngImage {
+    /// Renders `pattern` into a new image. Indexed images get a 3-3-2 RGB
+    /// palette at 8 bits and a gray ramp below that.
+    pub fn generate(
+        pattern: &Pattern,
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+        bit_depth: BitDepth,
+    ) -> Result<PngImage, PngError> {
+        let mut img = PngImage::with_bit_depth(width, height, color_type, bit_depth)?;
+        if color_type == ColorType::Indexed {
+            img.set_palette(&rgb332_palette(bit_depth))?;
+        }
+
+        let rgba = |color: Color| {
+            color
+                .to_rgba()
+                .map(|(r, g, b, a)| [r, g, b, a].map(|v| v as f64 / 255.0))
+                .ok_or(PngError::ColorTypeError)
+        };
+        let ends = match pattern {
+            Pattern::LinearGradient { from, to, .. } => Some((rgba(*from)?, rgba(*to)?)),
+            _ => None,
+        };
+
+        let mut rng = match pattern {
+            Pattern::Noise { seed } => *seed | 1,
+            _ => 1,
+        };
+        let fx = |x: u32| x as f64 / (width.max(2) - 1) as f64;
+        let fy = |y: u32| y as f64 / (height.max(2) - 1) as f64;
+
+        let mut components = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                let rgba = match *pattern {
+                    Pattern::Gradient { easing } => {
+                        [easing.apply(fy(y)), easing.apply(fx(x)), 0.0, 1.0]
+                    }
+                    Pattern::LinearGradient {
+                        vertical, easing, ..
+                    } => {
+                        let (from, to) = ends.unwrap_or_default();
+                        let t = easing.apply(if vertical { fy(y) } else { fx(x) });
+                        [0, 1, 2, 3].map(|c| from[c] + (to[c] - from[c]) * t)
+                    }
+                    Pattern::Checkerboard { cell } => {
+                        let cell = cell.max(1);
+                        let v = ((x / cell + y / cell) % 2) as f64;
+                        [v, v, v, 1.0]
+                    }
+                    Pattern::Bars => {
+                        let [r, g, b] = BARS[(x as usize * BARS.len()) / width as usize];
+                        [r, g, b, 1.0]
+                    }
+                    Pattern::Noise { .. } => {
+                        let mut next = || {
+                            // xorshift64
+                            rng ^= rng << 13;
+                            rng ^= rng >> 7;
+                            rng ^= rng << 17;
+                            (rng >> 11) as f64 / (1u64 << 53) as f64
+                        };
+                        [next(), next(), next(), next()]
+                    }
+                    Pattern::ValueNoise {
+                        scale,
+                        octaves,
+                        seed,
+                    } => {
+                        let v = fractal(x, y, scale, octaves, |x, y| value_noise(x, y, seed));
+                        [v, v, v, 1.0]
+                    }
+                    Pattern::Perlin {
+                        scale,
+                        octaves,
+                        seed,
+                    } => {
+                        // Perlin noise stays within about ±0.7
+                        let v = fractal(x, y, scale, octaves, |x, y| perlin(x, y, seed));
+                        let v = 0.5 + v / std::f64::consts::SQRT_2;
+                        [v, v, v, 1.0]
+                    }
+                    Pattern::AlphaRamp => {
+                        let v = 1.0 - fy(y);
+                        [v, v, v, fx(x)]
+                    }
+                };
+
+                components.clear();
+                encode_pixel(rgba, color_type, bit_depth, &mut components);
+                img.add_pixel(&components)?;
+            }
+        }
+        Ok(img)
+    }
+}
+
+// Sums octaves of `noise` at halving amplitude and doubling frequency,
+// normalized back to the range of a single octave
+fn fractal(x: u32, y: u32, scale: f64, octaves: u32, noise: impl Fn(f64, f64) -> f64) -> f64 {
+    let (mut total, mut weight) = (0.0, 0.0);
+    let (mut frequency, mut amplitude) = (1.0 / scale.max(f64::MIN_POSITIVE), 1.0);
+    for _ in 0..octaves.max(1) {
+        total += amplitude * noise(x as f64 * frequency, y as f64 * frequency);
+        weight += amplitude;
+        frequency *= 2.0;
+        amplitude /= 2.0;
+    }
+    total / weight
+}
+
+// A random value in 0..1 for each lattice point, interpolated smoothly
+fn value_noise(x: f64, y: f64, seed: u64) -> f64 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (tx, ty) = (fade(x - x0), fade(y - y0));
+    let corner = |dx: f64, dy: f64| {
+        let h = lattice_hash(x0 + dx, y0 + dy, seed);
+        (h >> 11) as f64 / (1u64 << 53) as f64
+    };
+    let top = lerp(corner(0.0, 0.0), corner(1.0, 0.0), tx);
+    let bottom = lerp(corner(0.0, 1.0), corner(1.0, 1.0), tx);
+    lerp(top, bottom, ty)
+}
+
+// Classic gradient noise: a random unit gradient at each lattice point,
+// dotted with the offset to it and interpolated
+fn perlin(x: f64, y: f64, seed: u64) -> f64 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let corner = |dx: f64, dy: f64| {
+        let h = lattice_hash(x0 + dx, y0 + dy, seed);
+        let angle = (h >> 11) as f64 / (1u64 << 53) as f64 * std::f64::consts::TAU;
+        angle.cos() * (fx - dx) + angle.sin() * (fy - dy)
+    };
+    let (tx, ty) = (fade(fx), fade(fy));
+    let top = lerp(corner(0.0, 0.0), corner(1.0, 0.0), tx);
+    let bottom = lerp(corner(0.0, 1.0), corner(1.0, 1.0), tx);
+    lerp(top, bottom, ty)
+}
+
+// splitmix64 over the lattice coordinates and seed
+fn lattice_hash(x: f64, y: f64, seed: u64) -> u64 {
+    let mut h = seed
+        ^ (x as i64 as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (y as i64 as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+    h = (h ^ (h >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    h ^ (h >> 31)
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+fn encode_pixel(rgba: [f64; 4], color_type: ColorType, bit_depth: BitDepth, out: &mut Vec<u8>) {
+    let [r, g, b, a] = rgba;
+    let gray = 0.299 * r + 0.587 * g + 0.114 * b;
+    let channels: &[f64] = match color_type {
+        ColorType::Grayscale => &[gray],
+        ColorType::GrayscaleAlpha => &[gray, a],
+        ColorType::Rgb => &[r, g, b],
+        ColorType::Rgba => &[r, g, b, a],
+        ColorType::Indexed => {
+            out.push(rgb332_index(r, g, b, bit_depth));
+            return;
+        }
+    };
+
+    let max = ((1u32 << bit_depth.bits()) - 1) as f64;
+    for &v in channels {
+        let sample = (v.clamp(0.0, 1.0) * max).round() as u16;
+        if bit_depth == BitDepth::Sixteen {
+            out.extend_from_slice(&sample.to_be_bytes());
+        } else {
+            out.push(sample as u8);
+        }
+    }
+}
+
+// At 8 bits, a 3-3-2 RGB palette; at lower depths, a gray ramp
+fn rgb332_palette(bit_depth: BitDepth) -> Vec<u8> {
+    if bit_depth == BitDepth::Eight {
+        (0..=255u8)
+            .flat_map(|i| {
+                let r = (i >> 5) as u32 * 255 / 7;
+                let g = ((i >> 2) & 7) as u32 * 255 / 7;
+                let b = (i & 3) as u32 * 255 / 3;
+                [r as u8, g as u8, b as u8]
+            })
+            .collect()
+    } else {
+        let entries = 1u32 << bit_depth.bits();
+        (0..entries)
+            .flat_map(|i| {
+                let v = (i * 255 / (entries - 1)) as u8;
+                [v, v, v]
+            })
+            .collect()
+    }
+}
+
+fn rgb332_index(r: f64, g: f64, b: f64, bit_depth: BitDepth) -> u8 {
+    if bit_depth == BitDepth::Eight {
+        let r = (r.clamp(0.0, 1.0) * 7.0).round() as u8;
+        let g = (g.clamp(0.0, 1.0) * 7.0).round() as u8;
+        let b = (b.clamp(0.0, 1.0) * 3.0).round() as u8;
+        (r << 5) | (g << 2) | b
+    } else {
+        let max = ((1u32 << bit_depth.bits()) - 1) as f64;
+        let gray = 0.299 * r + 0.587 * g + 0.114 * b;
+        (gray.clamp(0.0, 1.0) * max).round() as u8
+    }
+}
@@ -0,0 +1,68 @@
+use std::io::Read;
+
+use ::gif::{ColorOutput, DecodeOptions, DecodingError, DisposalMethod, Repeat};
+
+use crate::apng::{Animation, BlendOp, DisposeOp, Frame};
+use crate::error::PngError;
+use crate::{ColorType, PngImage};
+
+fn gif_error(e: DecodingError) -> PngError {
+    PngError::Import(format!("Invalid GIF: {}", e))
+}
+
+impl Animation {
+    /// Converts a GIF, animated or not, into an APNG with the same canvas,
+    /// frame positions, delays, loop count, and disposal. Frames are 8-bit
+    /// RGBA drawn over the canvas, so GIF transparency carries over;
+    /// [`Animation::to_indexed`] brings them back to a shared palette. A
+    /// first frame smaller than the canvas is padded with transparent
+    /// pixels, since an APNG's first frame has to cover it.
+    pub fn from_gif<R: Read>(reader: R) -> Result<Self, PngError> {
+        let mut options = DecodeOptions::new();
+        options.set_color_output(ColorOutput::RGBA);
+        let mut decoder = options.read_info(reader).map_err(gif_error)?;
+        let (width, height) = (decoder.width() as u32, decoder.height() as u32);
+        let mut animation = Animation::new(width, height)?;
+
+        while let Some(gif_frame) = decoder.read_next_frame().map_err(gif_error)? {
+            let (x, y) = (gif_frame.left as u32, gif_frame.top as u32);
+            let (frame_width, frame_height) = (gif_frame.width as u32, gif_frame.height as u32);
+            let mut image = PngImage::new(frame_width, frame_height, ColorType::Rgba)?;
+            image.data.replace(gif_frame.buffer.to_vec())?;
+
+            let covers_canvas = (x, y, frame_width, frame_height) == (0, 0, width, height);
+            let (image, x_offset, y_offset) = if animation.frames().is_empty() && !covers_canvas {
+                let mut canvas = PngImage::new(width, height, ColorType::Rgba)?;
+                canvas.blit(&image, x as i32, y as i32)?;
+                (canvas, 0, 0)
+            } else {
+                (image, x, y)
+            };
+
+            animation.add_frame(Frame {
+                image,
+                x_offset,
+                y_offset,
+                // GIF delays are in hundredths of a second
+                delay_num: gif_frame.delay,
+                delay_den: 100,
+                dispose_op: match gif_frame.dispose {
+                    DisposalMethod::Any | DisposalMethod::Keep => DisposeOp::None,
+                    DisposalMethod::Background => DisposeOp::Background,
+                    DisposalMethod::Previous => DisposeOp::Previous,
+                },
+                blend_op: BlendOp::Over,
+            })?;
+        }
+        if animation.frames().is_empty() {
+            return Err(PngError::Import("GIF has no frames".to_string()));
+        }
+
+        // GIF counts repetitions after the first play
+        animation.num_plays = match decoder.repeat() {
+            Repeat::Infinite => 0,
+            Repeat::Finite(repetitions) => repetitions as u32 + 1,
+        };
+        Ok(animation)
+    }
+}
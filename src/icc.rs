@@ -0,0 +1,528 @@
+use std::io::Read;
+
+use flate2::read::ZlibDecoder;
+
+use crate::{BitDepth, ColorType, PngImage};
+
+type Matrix = [[f64; 3]; 3];
+
+// The ICC profile connection space white, which profile colorants use
+const D50: [f64; 3] = [0.9642, 1.0, 0.8249];
+
+// sRGB's white point
+const D65: [f64; 3] = [0.9505, 1.0, 1.0890];
+
+// sRGB's linear RGB to XYZ, adapted to D50 as in the ICC sRGB profile
+const SRGB_TO_D50: Matrix = [
+    [0.436_074_7, 0.385_064_9, 0.143_080_4],
+    [0.222_504_5, 0.716_878_6, 0.060_616_9],
+    [0.013_932_2, 0.097_104_5, 0.714_173_3],
+];
+
+// Cone response space for Bradford chromatic adaptation
+const BRADFORD: Matrix = [
+    [0.8951, 0.2664, -0.1614],
+    [-0.7502, 1.7135, 0.0367],
+    [0.0389, -0.0685, 1.0296],
+];
+
+/// How colors outside sRGB, or a different white point, are handled when
+/// converting to sRGB, as in an ICC rendering intent.
+///
+/// The matrix/TRC profiles this crate applies hold a single transform, so,
+/// as the ICC specification has it, perceptual and saturation rendering
+/// give the same result as relative colorimetric.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RenderingIntent {
+    Perceptual,
+    /// Maps the image's white to sRGB's white, and clips other colors
+    /// sRGB can't show.
+    #[default]
+    RelativeColorimetric,
+    Saturation,
+    /// Keeps colors as measured, so an image with a warmer white than sRGB
+    /// looks warmer, as in print proofs of the paper color. Black point
+    /// compensation doesn't apply.
+    AbsoluteColorimetric,
+}
+
+/// A transfer curve from encoded samples (0 to 1) to linear light.
+#[derive(Debug, Clone, PartialEq)]
+enum Curve {
+    Gamma(f64),
+    Table(Vec<u16>),
+    /// ICC parametric curve: `(a x + b)^g + e` from `d` up, `c x + f` below
+    Parametric {
+        g: f64,
+        a: f64,
+        b: f64,
+        c: f64,
+        d: f64,
+        e: f64,
+        f: f64,
+    },
+}
+
+impl Curve {
+    fn eval(&self, x: f64) -> f64 {
+        match self {
+            Curve::Gamma(g) => x.powf(*g),
+            Curve::Table(table) => {
+                let pos = x * (table.len() - 1) as f64;
+                let i = (pos.floor() as usize).min(table.len() - 1);
+                let next = (i + 1).min(table.len() - 1);
+                let t = pos - i as f64;
+                (table[i] as f64 * (1.0 - t) + table[next] as f64 * t) / 65535.0
+            }
+            Curve::Parametric {
+                g,
+                a,
+                b,
+                c,
+                d,
+                e,
+                f,
+            } => {
+                if x >= *d {
+                    (a * x + b).max(0.0).powf(*g) + e
+                } else {
+                    c * x + f
+                }
+            }
+        }
+    }
+}
+
+/// How a PNG's pixels are encoded, from its iCCP chunk or its gAMA and
+/// cHRM chunks, as far as it takes to convert them to sRGB.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ColorProfile {
+    /// One curve for every channel, or one per RGB channel
+    curves: Vec<Curve>,
+    /// Linear RGB to D50 XYZ; `None` for grayscale or sRGB's primaries
+    to_xyz: Option<Matrix>,
+    /// The measured XYZ of the image's white, which `to_xyz` maps to D50
+    white: [f64; 3],
+}
+
+impl ColorProfile {
+    /// Reads an iCCP chunk, as long as its profile is the matrix/TRC or
+    /// gray TRC kind that display profiles use. Other profiles, such as
+    /// lookup-table ones, give `None`.
+    pub(crate) fn from_iccp(chunk: &[u8]) -> Option<Self> {
+        let name_end = chunk.iter().position(|&b| b == 0)?;
+        // Keyword, separator, and compression method (always zlib)
+        let compressed = chunk.get(name_end + 2..)?;
+        let mut icc = Vec::new();
+        ZlibDecoder::new(compressed).read_to_end(&mut icc).ok()?;
+        Self::from_icc(&icc)
+    }
+
+    fn from_icc(icc: &[u8]) -> Option<Self> {
+        if icc.get(36..40)? != b"acsp" || icc.get(20..24)? != b"XYZ " {
+            return None;
+        }
+        let count = be_u32(icc, 128)? as usize;
+        let tag = |signature: &[u8; 4]| -> Option<&[u8]> {
+            (0..count.min(1024)).find_map(|i| {
+                let entry = 132 + i * 12;
+                if icc.get(entry..entry + 4)? != signature {
+                    return None;
+                }
+                let offset = be_u32(icc, entry + 4)? as usize;
+                let size = be_u32(icc, entry + 8)? as usize;
+                icc.get(offset..offset.checked_add(size)?)
+            })
+        };
+
+        // Version 4 profiles store the adaptation to D50, and version 2
+        // ones the measured white
+        let white = match (tag(b"chad").and_then(parse_matrix), tag(b"wtpt")) {
+            (Some(chad), _) => apply(&invert(&chad), D50),
+            (None, Some(wtpt)) => parse_xyz(wtpt).filter(|xyz| xyz[1] > 0.0)?,
+            (None, None) => D50,
+        };
+
+        match icc.get(16..20)? {
+            b"GRAY" => Some(Self {
+                curves: vec![parse_curve(tag(b"kTRC")?)?],
+                to_xyz: None,
+                white,
+            }),
+            b"RGB " => {
+                let curves = [b"rTRC", b"gTRC", b"bTRC"]
+                    .iter()
+                    .map(|sig| parse_curve(tag(sig)?))
+                    .collect::<Option<Vec<_>>>()?;
+                let columns = [b"rXYZ", b"gXYZ", b"bXYZ"]
+                    .iter()
+                    .map(|sig| parse_xyz(tag(sig)?))
+                    .collect::<Option<Vec<_>>>()?;
+                let mut to_xyz = [[0.0; 3]; 3];
+                for (column, xyz) in columns.iter().enumerate() {
+                    for (row, value) in xyz.iter().enumerate() {
+                        to_xyz[row][column] = *value;
+                    }
+                }
+                Some(Self {
+                    curves,
+                    to_xyz: Some(to_xyz),
+                    white,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Builds a profile from gAMA and cHRM chunk data. Without gAMA the
+    /// samples are taken to use the sRGB curve, and without cHRM the sRGB
+    /// primaries. Returns `None` when that describes sRGB itself.
+    pub(crate) fn from_chunks(gamma: Option<&[u8]>, chromaticities: Option<&[u8]>) -> Option<Self> {
+        let gamma = gamma
+            .and_then(|data| <[u8; 4]>::try_from(data).ok())
+            .map(u32::from_be_bytes)
+            .filter(|&g| g > 0);
+        let chromaticities = chromaticities.and_then(parse_chromaticities);
+        if gamma.is_none() && chromaticities.is_none() {
+            return None;
+        }
+
+        let curve = match gamma {
+            // gAMA stores the encoding exponent times 100000
+            Some(gamma) => Curve::Gamma(100_000.0 / gamma as f64),
+            None => Curve::Parametric {
+                g: 2.4,
+                a: 1.0 / 1.055,
+                b: 0.055 / 1.055,
+                c: 1.0 / 12.92,
+                d: 0.040_45,
+                e: 0.0,
+                f: 0.0,
+            },
+        };
+        Some(Self {
+            curves: vec![curve],
+            to_xyz: chromaticities.map(|(to_xyz, _)| to_xyz),
+            white: chromaticities.map_or(D65, |(_, white)| white),
+        })
+    }
+
+    /// Converts the image's pixels, or its palette, to sRGB. Alpha is left
+    /// alone, and sub-byte grayscale images become 8-bit. Grayscale images
+    /// only use the transfer curve, and are left as they are with an RGB
+    /// ICC profile. Black point compensation maps the darkest color the
+    /// profile can encode to sRGB black.
+    pub(crate) fn convert_to_srgb(
+        &self,
+        image: &mut PngImage,
+        intent: RenderingIntent,
+        black_point_compensation: bool,
+    ) {
+        let gray = matches!(
+            image.color_type,
+            ColorType::Grayscale | ColorType::GrayscaleAlpha
+        );
+        if gray && self.curves.len() != 1 {
+            return;
+        }
+        let transform = self.transform(intent, black_point_compensation);
+
+        if image.color_type == ColorType::Indexed {
+            if let Some(palette) = &mut image.palette {
+                for rgb in palette.chunks_exact_mut(3) {
+                    let encoded = [rgb[0], rgb[1], rgb[2]].map(|v| v as f64 / 255.0);
+                    let linear = transform.apply(self.decode(encoded));
+                    for (sample, value) in rgb.iter_mut().zip(linear) {
+                        *sample = (encode_srgb(value) * 255.0).round() as u8;
+                    }
+                }
+            }
+            return;
+        }
+        if image.bit_depth.bits() < 8 {
+            // Converted values rarely land on the few levels a low bit
+            // depth has
+            if image.convert_bit_depth(BitDepth::Eight).is_err() {
+                return;
+            }
+        }
+
+        let sixteen = image.bit_depth == BitDepth::Sixteen;
+        let max = image.bit_depth.max_value() as f64;
+        let sample_size = image.bit_depth.bytes_per_sample();
+        let channels = image.color_type.channels();
+        let color_channels = if gray { 1 } else { 3 };
+        // Decoding tables for every possible sample value
+        let tables: Vec<Vec<f64>> = (0..color_channels)
+            .map(|ch| {
+                let curve = &self.curves[ch.min(self.curves.len() - 1)];
+                (0..=max as u32)
+                    .map(|v| curve.eval(v as f64 / max).clamp(0.0, 1.0))
+                    .collect()
+            })
+            .collect();
+        let read = |bytes: &[u8]| -> usize {
+            match bytes {
+                [hi, lo] => u16::from_be_bytes([*hi, *lo]) as usize,
+                _ => bytes[0] as usize,
+            }
+        };
+
+        for pixel in image.data.chunks_exact_mut(channels * sample_size) {
+            let mut linear = [0.0; 3];
+            for (ch, value) in linear.iter_mut().enumerate() {
+                let ch = ch.min(color_channels - 1);
+                *value = tables[ch][read(&pixel[ch * sample_size..(ch + 1) * sample_size])];
+            }
+            let linear = transform.apply(linear);
+            for ch in 0..color_channels {
+                let value = (encode_srgb(linear[ch]) * max).round() as u16;
+                let sample = &mut pixel[ch * sample_size..(ch + 1) * sample_size];
+                if sixteen {
+                    sample.copy_from_slice(&value.to_be_bytes());
+                } else {
+                    sample[0] = value as u8;
+                }
+            }
+        }
+    }
+
+    // Encoded samples to linear light in the image's own primaries
+    fn decode(&self, encoded: [f64; 3]) -> [f64; 3] {
+        let mut linear = [0.0; 3];
+        for (ch, value) in linear.iter_mut().enumerate() {
+            *value = self.curves[ch.min(self.curves.len() - 1)]
+                .eval(encoded[ch])
+                .clamp(0.0, 1.0);
+        }
+        linear
+    }
+
+    fn transform(&self, intent: RenderingIntent, black_point_compensation: bool) -> Transform {
+        let matrix = self.to_xyz.map(|to_xyz| match intent {
+            RenderingIntent::AbsoluteColorimetric => {
+                // Undo the adaptation to D50 on both sides
+                let source = multiply(&adaptation(D50, self.white), &to_xyz);
+                let srgb = multiply(&adaptation(D50, D65), &SRGB_TO_D50);
+                multiply(&invert(&srgb), &source)
+            }
+            _ => multiply(&invert(&SRGB_TO_D50), &to_xyz),
+        });
+        let mut transform = Transform {
+            matrix,
+            black: [0.0; 3],
+        };
+        if black_point_compensation && intent != RenderingIntent::AbsoluteColorimetric {
+            let black = transform.apply(self.decode([0.0; 3]));
+            if black.iter().all(|&b| b < 1.0) {
+                transform.black = black;
+            }
+        }
+        transform
+    }
+}
+
+// Linear light in a profile's primaries to linear sRGB
+struct Transform {
+    matrix: Option<Matrix>,
+    /// Linear sRGB of the profile's black, to be mapped to 0
+    black: [f64; 3],
+}
+
+impl Transform {
+    fn apply(&self, linear: [f64; 3]) -> [f64; 3] {
+        let linear = match &self.matrix {
+            Some(matrix) => apply(matrix, linear),
+            None => linear,
+        };
+        [0, 1, 2].map(|ch| {
+            let black = self.black[ch];
+            ((linear[ch] - black) / (1.0 - black)).clamp(0.0, 1.0)
+        })
+    }
+}
+
+fn encode_srgb(linear: f64) -> f64 {
+    if linear <= 0.003_130_8 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn be_u32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+// An ICC s15Fixed16Number
+fn fixed(data: &[u8], at: usize) -> Option<f64> {
+    Some(be_u32(data, at)? as i32 as f64 / 65536.0)
+}
+
+fn parse_xyz(tag: &[u8]) -> Option<[f64; 3]> {
+    if tag.get(0..4)? != b"XYZ " {
+        return None;
+    }
+    Some([fixed(tag, 8)?, fixed(tag, 12)?, fixed(tag, 16)?])
+}
+
+// An sf32 tag holding a 3x3 matrix
+fn parse_matrix(tag: &[u8]) -> Option<Matrix> {
+    if tag.get(0..4)? != b"sf32" {
+        return None;
+    }
+    let mut matrix = [[0.0; 3]; 3];
+    for (i, value) in matrix.iter_mut().flatten().enumerate() {
+        *value = fixed(tag, 8 + i * 4)?;
+    }
+    Some(matrix)
+}
+
+fn parse_curve(tag: &[u8]) -> Option<Curve> {
+    match tag.get(0..4)? {
+        b"curv" => {
+            let count = be_u32(tag, 8)? as usize;
+            match count {
+                0 => Some(Curve::Gamma(1.0)),
+                // A u8Fixed8Number exponent
+                1 => Some(Curve::Gamma(
+                    u16::from_be_bytes(tag.get(12..14)?.try_into().ok()?) as f64 / 256.0,
+                )),
+                _ => {
+                    let table = tag
+                        .get(12..12 + count.checked_mul(2)?)?
+                        .chunks_exact(2)
+                        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                        .collect();
+                    Some(Curve::Table(table))
+                }
+            }
+        }
+        b"para" => {
+            let function = u16::from_be_bytes(tag.get(8..10)?.try_into().ok()?);
+            let count = *[1, 3, 4, 5, 7].get(function as usize)?;
+            let params = (0..count)
+                .map(|i| fixed(tag, 12 + i * 4))
+                .collect::<Option<Vec<_>>>()?;
+            let p = |i: usize| params.get(i).copied().unwrap_or(0.0);
+            let (g, a, b) = (p(0), p(1), p(2));
+            // The shorter forms are special cases of the 7-parameter one
+            let curve = match function {
+                0 => Curve::Gamma(g),
+                1 => Curve::Parametric {
+                    g,
+                    a,
+                    b,
+                    c: 0.0,
+                    d: -b / a,
+                    e: 0.0,
+                    f: 0.0,
+                },
+                2 => Curve::Parametric {
+                    g,
+                    a,
+                    b,
+                    c: 0.0,
+                    d: -b / a,
+                    e: p(3),
+                    f: p(3),
+                },
+                3 => Curve::Parametric {
+                    g,
+                    a,
+                    b,
+                    c: p(3),
+                    d: p(4),
+                    e: 0.0,
+                    f: 0.0,
+                },
+                4 => Curve::Parametric {
+                    g,
+                    a,
+                    b,
+                    c: p(3),
+                    d: p(4),
+                    e: p(5),
+                    f: p(6),
+                },
+                _ => return None,
+            };
+            Some(curve)
+        }
+        _ => None,
+    }
+}
+
+// Linear RGB to D50 XYZ from a cHRM chunk's white point and primaries,
+// along with the white point's XYZ
+fn parse_chromaticities(data: &[u8]) -> Option<(Matrix, [f64; 3])> {
+    let values: Vec<f64> = (0..8)
+        .map(|i| be_u32(data, i * 4).map(|v| v as f64 / 100_000.0))
+        .collect::<Option<_>>()?;
+    if data.len() != 32 || values.iter().skip(1).step_by(2).any(|&y| y == 0.0) {
+        return None;
+    }
+    let xyz = |x: f64, y: f64| [x / y, 1.0, (1.0 - x - y) / y];
+    let white = xyz(values[0], values[1]);
+    let primaries = [
+        xyz(values[2], values[3]),
+        xyz(values[4], values[5]),
+        xyz(values[6], values[7]),
+    ];
+
+    // Scale each primary so that full RGB gives the white point
+    let mut matrix = [[0.0; 3]; 3];
+    for (column, primary) in primaries.iter().enumerate() {
+        for (row, value) in primary.iter().enumerate() {
+            matrix[row][column] = *value;
+        }
+    }
+    let scale = apply(&invert(&matrix), white);
+    for row in matrix.iter_mut() {
+        for (value, s) in row.iter_mut().zip(scale) {
+            *value *= s;
+        }
+    }
+    Some((multiply(&adaptation(white, D50), &matrix), white))
+}
+
+// Bradford transform from XYZ under `from` white to XYZ under `to` white
+fn adaptation(from: [f64; 3], to: [f64; 3]) -> Matrix {
+    let from = apply(&BRADFORD, from);
+    let to = apply(&BRADFORD, to);
+    let mut scale = [[0.0; 3]; 3];
+    for i in 0..3 {
+        scale[i][i] = to[i] / from[i];
+    }
+    multiply(&invert(&BRADFORD), &multiply(&scale, &BRADFORD))
+}
+
+fn apply(m: &Matrix, v: [f64; 3]) -> [f64; 3] {
+    [0, 1, 2].map(|row| m[row][0] * v[0] + m[row][1] * v[1] + m[row][2] * v[2])
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut out = [[0.0; 3]; 3];
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    out
+}
+
+fn invert(m: &Matrix) -> Matrix {
+    let cofactor = |r: usize, c: usize| {
+        let (r0, r1) = ((r + 1) % 3, (r + 2) % 3);
+        let (c0, c1) = ((c + 1) % 3, (c + 2) % 3);
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    };
+    let det: f64 = (0..3).map(|c| m[0][c] * cofactor(0, c)).sum();
+    let mut out = [[0.0; 3]; 3];
+    for (r, row) in out.iter_mut().enumerate() {
+        for (c, value) in row.iter_mut().enumerate() {
+            *value = cofactor(c, r) / det;
+        }
+    }
+    out
+}
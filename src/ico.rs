@@ -0,0 +1,116 @@
+use std::io::Write;
+
+use crate::error::PngError;
+use crate::transform::ResizeFilter;
+use crate::{BitDepth, ColorType, PngImage};
+
+/// Sizes browsers and Windows look for in a favicon.ico.
+pub const FAVICON_SIZES: [u32; 3] = [16, 32, 48];
+
+/// Writes a Windows ICO file holding `image` scaled to each of `sizes`
+/// (square, from 1 to 256 pixels) with Lanczos3, each entry stored as an
+/// 8-bit RGBA PNG. Non-square images are stretched to fit.
+pub fn write_ico<W: Write>(
+    image: &PngImage,
+    sizes: &[u32],
+    writer: &mut W,
+) -> Result<(), PngError> {
+    if sizes.is_empty() || sizes.len() > u16::MAX as usize {
+        return Err(PngError::InvalidDimensions(0, 0));
+    }
+    let entries = sizes
+        .iter()
+        .map(|&size| {
+            if !(1..=256).contains(&size) {
+                return Err(PngError::InvalidDimensions(size, size));
+            }
+            let mut icon = image.resize(size, size, ResizeFilter::Lanczos3)?;
+            icon.convert_to(ColorType::Rgba)?;
+            icon.convert_bit_depth(BitDepth::Eight)?;
+            let mut encoded = Vec::new();
+            icon.write_to_file(&mut encoded)?;
+            Ok((size, encoded))
+        })
+        .collect::<Result<Vec<_>, PngError>>()?;
+
+    // ICONDIR: reserved, type 1 (icon), entry count
+    writer.write_all(&[0, 0, 1, 0])?;
+    writer.write_all(&(entries.len() as u16).to_le_bytes())?;
+
+    // One 16-byte ICONDIRENTRY per image, then the images themselves
+    let mut offset = 6 + 16 * entries.len() as u32;
+    for (size, encoded) in &entries {
+        // 256 is stored as 0
+        let dimension = *size as u8;
+        writer.write_all(&[dimension, dimension, 0, 0])?;
+        // Color planes and bits per pixel
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&32u16.to_le_bytes())?;
+        writer.write_all(&(encoded.len() as u32).to_le_bytes())?;
+        writer.write_all(&offset.to_le_bytes())?;
+        offset += encoded.len() as u32;
+    }
+    for (_, encoded) in &entries {
+        writer.write_all(encoded)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::Decoder;
+    use crate::test_images::noise;
+
+    fn u16_at(bytes: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes([bytes[at], bytes[at + 1]])
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn directory_entries_point_at_each_png() {
+        let image = noise(64, 40, ColorType::Rgb, BitDepth::Eight, 5);
+        let sizes = [16, 32, 48, 256];
+        let mut ico = Vec::new();
+        write_ico(&image, &sizes, &mut ico).unwrap();
+
+        assert_eq!(&ico[..4], &[0, 0, 1, 0]);
+        assert_eq!(u16_at(&ico, 4), 4);
+        let mut expected_offset = 6 + 16 * 4;
+        for (i, &size) in sizes.iter().enumerate() {
+            let entry = &ico[6 + 16 * i..6 + 16 * (i + 1)];
+            let stored = if size == 256 { 0 } else { size as u8 };
+            assert_eq!(&entry[..4], &[stored, stored, 0, 0]);
+            assert_eq!(u16_at(entry, 4), 1);
+            assert_eq!(u16_at(entry, 6), 32);
+            let length = u32_at(entry, 8) as usize;
+            let offset = u32_at(entry, 12) as usize;
+            assert_eq!(offset, expected_offset);
+            expected_offset += length;
+
+            let icon = Decoder::new(&ico[offset..offset + length])
+                .decode()
+                .unwrap();
+            assert_eq!((icon.width(), icon.height()), (size, size));
+            assert_eq!(icon.color_type(), ColorType::Rgba);
+            assert_eq!(icon.bit_depth(), BitDepth::Eight);
+        }
+        assert_eq!(expected_offset, ico.len());
+    }
+
+    #[test]
+    fn rejects_sizes_icons_cant_hold() {
+        let image = noise(8, 8, ColorType::Rgba, BitDepth::Eight, 6);
+        for sizes in [&[][..], &[0], &[16, 257]] {
+            let mut ico = Vec::new();
+            assert!(matches!(
+                write_ico(&image, sizes, &mut ico),
+                Err(PngError::InvalidDimensions(..))
+            ));
+            assert!(ico.is_empty());
+        }
+    }
+}
@@ -0,0 +1,313 @@
+use std::io::Read;
+
+use crate::buffer::PixelBuffer;
+use crate::error::PngError;
+use crate::{image_size, BitDepth, ColorType, PngImage};
+
+fn import_error(message: impl Into<String>) -> PngError {
+    PngError::Import(message.into())
+}
+
+impl PngImage {
+    /// Reads a binary (P5/P6) or ASCII (P2/P3) PGM/PPM file. A maximum value
+    /// above 255 produces a 16-bit image.
+    pub fn from_ppm<R: Read>(mut reader: R) -> Result<Self, PngError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+
+        let mut pos = 0;
+        let magic = next_token(&bytes, &mut pos).ok_or_else(|| import_error("Empty PNM file"))?;
+        let (color_type, ascii) = match magic {
+            b"P2" => (ColorType::Grayscale, true),
+            b"P3" => (ColorType::Rgb, true),
+            b"P5" => (ColorType::Grayscale, false),
+            b"P6" => (ColorType::Rgb, false),
+            _ => return Err(import_error("Unsupported PNM format")),
+        };
+
+        let mut header = [0u32; 3];
+        for value in header.iter_mut() {
+            *value = next_token(&bytes, &mut pos)
+                .and_then(parse_decimal)
+                .ok_or_else(|| import_error("Invalid PNM header"))?;
+        }
+        let [width, height, max_value] = header;
+        if max_value == 0 || max_value > 65535 {
+            return Err(import_error(format!(
+                "Invalid PNM maximum value {}",
+                max_value
+            )));
+        }
+
+        let bit_depth = if max_value > 255 {
+            BitDepth::Sixteen
+        } else {
+            BitDepth::Eight
+        };
+        let mut image = PngImage::with_bit_depth(width, height, color_type, bit_depth)?;
+        let sample_count = image_size(width, height, color_type.channels())?;
+
+        let samples: Vec<u32> = if ascii {
+            let mut samples = Vec::with_capacity(sample_count);
+            for _ in 0..sample_count {
+                let value = next_token(&bytes, &mut pos)
+                    .and_then(parse_decimal)
+                    .ok_or_else(|| import_error("Truncated PNM sample data"))?;
+                samples.push(value);
+            }
+            samples
+        } else {
+            // Exactly one whitespace byte separates the header from the data
+            let start = pos + 1;
+            let sample_size = bit_depth.bytes_per_sample();
+            // `with_bit_depth` already checked that this size fits
+            let data = bytes
+                .get(start..start + sample_count * sample_size)
+                .ok_or_else(|| import_error("Truncated PNM sample data"))?;
+            if sample_size == 2 {
+                data.chunks_exact(2)
+                    .map(|pair| u16::from_be_bytes([pair[0], pair[1]]) as u32)
+                    .collect()
+            } else {
+                data.iter().map(|&b| b as u32).collect()
+            }
+        };
+
+        // Rescale to the full range of the output depth
+        let target_max = bit_depth.max_value() as u32;
+        image.data = PixelBuffer::with_capacity(sample_count * bit_depth.bytes_per_sample());
+        for sample in samples {
+            let value = (sample.min(max_value) * target_max + max_value / 2) / max_value;
+            match bit_depth {
+                BitDepth::Sixteen => image
+                    .data
+                    .extend_from_slice(&(value as u16).to_be_bytes())?,
+                _ => image.data.push(value as u8)?,
+            }
+        }
+
+        Ok(image)
+    }
+
+    /// Reads an uncompressed Windows BMP with 8-bit paletted, 24-bit, or
+    /// 32-bit pixels. 32-bit files become RGBA unless every alpha byte is 0,
+    /// which writers commonly use to mean "no alpha".
+    pub fn from_bmp<R: Read>(mut reader: R) -> Result<Self, PngError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+
+        if bytes.len() < 54 || &bytes[0..2] != b"BM" {
+            return Err(import_error("Not a BMP file"));
+        }
+        let u16_at = |at: usize| u16::from_le_bytes([bytes[at], bytes[at + 1]]);
+        let u32_at = |at: usize| {
+            u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+        };
+
+        let data_offset = u32_at(10) as usize;
+        let header_size = u32_at(14) as usize;
+        if header_size < 40 {
+            return Err(import_error("Unsupported BMP header version"));
+        }
+        let width = u32_at(18) as i32;
+        let raw_height = u32_at(22) as i32;
+        let bits = u16_at(28);
+        let compression = u32_at(30);
+        let colors_used = u32_at(46) as usize;
+
+        // BI_RGB, or BI_BITFIELDS with the usual BGRA masks for 32-bit data
+        if compression != 0 && !(compression == 3 && bits == 32) {
+            return Err(import_error("Compressed BMP files are not supported"));
+        }
+        if width <= 0 || raw_height == 0 {
+            return Err(import_error("Invalid BMP dimensions"));
+        }
+        let top_down = raw_height < 0;
+        let width = width as u32;
+        let height = raw_height.unsigned_abs();
+
+        let color_type = match bits {
+            8 => ColorType::Indexed,
+            24 => ColorType::Rgb,
+            32 => ColorType::Rgba,
+            _ => return Err(import_error(format!("Unsupported BMP bit count {}", bits))),
+        };
+        let mut image = PngImage::new(width, height, color_type)?;
+
+        if bits == 8 {
+            let count = if colors_used == 0 {
+                256
+            } else {
+                colors_used.min(256)
+            };
+            let table_start = 14 + header_size;
+            let table = bytes
+                .get(table_start..table_start + count * 4)
+                .ok_or_else(|| import_error("Truncated BMP color table"))?;
+            let palette: Vec<u8> = table
+                .chunks_exact(4)
+                .flat_map(|bgra| [bgra[2], bgra[1], bgra[0]])
+                .collect();
+            image.set_palette(&palette)?;
+        }
+
+        let bytes_per_pixel = bits as usize / 8;
+        let stride = (width as usize * bytes_per_pixel).div_ceil(4) * 4;
+        let pixel_bytes = stride
+            .checked_mul(height as usize)
+            .ok_or(PngError::ImageTooLarge { width, height })?;
+        let pixels = data_offset
+            .checked_add(pixel_bytes)
+            .and_then(|end| bytes.get(data_offset..end))
+            .ok_or_else(|| import_error("Truncated BMP pixel data"))?;
+
+        image.data = PixelBuffer::with_capacity(image_size(width, height, color_type.channels())?);
+        for y in 0..height as usize {
+            let row = if top_down { y } else { height as usize - 1 - y };
+            let row = &pixels[row * stride..row * stride + width as usize * bytes_per_pixel];
+            for pixel in row.chunks_exact(bytes_per_pixel) {
+                match bits {
+                    8 => image.data.push(pixel[0])?,
+                    24 => image
+                        .data
+                        .extend_from_slice(&[pixel[2], pixel[1], pixel[0]])?,
+                    _ => image
+                        .data
+                        .extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]])?,
+                }
+            }
+        }
+
+        if bits == 32 && image.data.chunks_exact(4).all(|p| p[3] == 0) {
+            let data = image
+                .data
+                .chunks_exact(4)
+                .flat_map(|p| [p[0], p[1], p[2]])
+                .collect();
+            image.data.replace(data)?;
+            image.color_type = ColorType::Rgb;
+        }
+
+        Ok(image)
+    }
+
+    /// Reads a QOI ("Quite OK Image") file.
+    pub fn from_qoi<R: Read>(mut reader: R) -> Result<Self, PngError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+
+        if bytes.len() < 14 || &bytes[0..4] != b"qoif" {
+            return Err(import_error("Not a QOI file"));
+        }
+        let width = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        let height = u32::from_be_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]);
+        let channels = bytes[12];
+        let color_type = match channels {
+            3 => ColorType::Rgb,
+            4 => ColorType::Rgba,
+            _ => {
+                return Err(import_error(format!(
+                    "Invalid QOI channel count {}",
+                    channels
+                )))
+            }
+        };
+
+        let mut image = PngImage::new(width, height, color_type)?;
+        let pixel_count = image_size(width, height, 1)?;
+        let mut pixels = Vec::with_capacity(image_size(width, height, channels as usize)?);
+
+        let mut index = [[0u8; 4]; 64];
+        let mut px = [0u8, 0, 0, 255];
+        let mut pos = 14;
+        let mut decoded = 0;
+
+        let next_byte = |pos: &mut usize| -> Result<u8, PngError> {
+            let byte = *bytes
+                .get(*pos)
+                .ok_or_else(|| import_error("Truncated QOI data"))?;
+            *pos += 1;
+            Ok(byte)
+        };
+
+        while decoded < pixel_count {
+            let tag = next_byte(&mut pos)?;
+            let mut run = 1;
+            match tag {
+                0xFE => {
+                    px[0] = next_byte(&mut pos)?;
+                    px[1] = next_byte(&mut pos)?;
+                    px[2] = next_byte(&mut pos)?;
+                }
+                0xFF => {
+                    for channel in px.iter_mut() {
+                        *channel = next_byte(&mut pos)?;
+                    }
+                }
+                _ => match tag >> 6 {
+                    // QOI_OP_INDEX
+                    0 => px = index[(tag & 0x3F) as usize],
+                    // QOI_OP_DIFF
+                    1 => {
+                        px[0] = px[0].wrapping_add((tag >> 4) & 3).wrapping_sub(2);
+                        px[1] = px[1].wrapping_add((tag >> 2) & 3).wrapping_sub(2);
+                        px[2] = px[2].wrapping_add(tag & 3).wrapping_sub(2);
+                    }
+                    // QOI_OP_LUMA
+                    2 => {
+                        let dg = (tag & 0x3F).wrapping_sub(32);
+                        let next = next_byte(&mut pos)?;
+                        let dr_dg = (next >> 4).wrapping_sub(8);
+                        let db_dg = (next & 0x0F).wrapping_sub(8);
+                        px[0] = px[0].wrapping_add(dg).wrapping_add(dr_dg);
+                        px[1] = px[1].wrapping_add(dg);
+                        px[2] = px[2].wrapping_add(dg).wrapping_add(db_dg);
+                    }
+                    // QOI_OP_RUN
+                    _ => run = (tag & 0x3F) as usize + 1,
+                },
+            }
+
+            let hash = (px[0] as usize * 3
+                + px[1] as usize * 5
+                + px[2] as usize * 7
+                + px[3] as usize * 11)
+                % 64;
+            index[hash] = px;
+
+            for _ in 0..run.min(pixel_count - decoded) {
+                pixels.extend_from_slice(&px[..channels as usize]);
+            }
+            decoded += run;
+        }
+
+        image.data = pixels.into();
+        Ok(image)
+    }
+}
+
+// Returns the next whitespace-separated token, skipping `#` comments
+fn next_token<'a>(bytes: &'a [u8], pos: &mut usize) -> Option<&'a [u8]> {
+    loop {
+        while *pos < bytes.len() && bytes[*pos].is_ascii_whitespace() {
+            *pos += 1;
+        }
+        if *pos < bytes.len() && bytes[*pos] == b'#' {
+            while *pos < bytes.len() && bytes[*pos] != b'\n' {
+                *pos += 1;
+            }
+            continue;
+        }
+        break;
+    }
+
+    let start = *pos;
+    while *pos < bytes.len() && !bytes[*pos].is_ascii_whitespace() {
+        *pos += 1;
+    }
+    (*pos > start).then(|| &bytes[start..*pos])
+}
+
+fn parse_decimal(token: &[u8]) -> Option<u32> {
+    std::str::from_utf8(token).ok()?.parse().ok()
+}
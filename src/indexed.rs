@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use crate::error::PngError;
+use crate::{image_size, ColorType, PngImage};
+
+impl PngImage {
+    /// Creates an 8-bit indexed image from 8-bit RGBA samples, with one
+    /// palette entry per distinct color in order of first appearance. Alpha
+    /// goes into a tRNS chunk as with [`PngImage::set_palette_rgba`]. Fails
+    /// with [`PngError::InvalidPalette`] if there are more than 256 colors.
+    pub fn indexed_from_rgba(width: u32, height: u32, rgba: &[u8]) -> Result<Self, PngError> {
+        let mut image = Self::new(width, height, ColorType::Indexed)?;
+        let expected = image_size(width, height, 4)?;
+        if rgba.len() != expected {
+            return Err(PngError::PixelCountMismatch {
+                expected: expected / 4,
+                actual: rgba.len() / 4,
+                dimensions: (width, height),
+            });
+        }
+
+        let mut palette = Vec::new();
+        let mut lookup = HashMap::new();
+        let mut indices = Vec::with_capacity(expected / 4);
+        for pixel in rgba.chunks_exact(4) {
+            let color = (pixel[0], pixel[1], pixel[2], pixel[3]);
+            let index = match lookup.get(&color) {
+                Some(&index) => index,
+                None => {
+                    if palette.len() == 256 {
+                        return Err(PngError::InvalidPalette(
+                            "Image has more than 256 distinct colors".to_string(),
+                        ));
+                    }
+                    palette.push(color);
+                    let index = (palette.len() - 1) as u8;
+                    lookup.insert(color, index);
+                    index
+                }
+            };
+            indices.push(index);
+        }
+
+        image.set_palette_rgba(&palette)?;
+        image.data.replace(indices)?;
+        Ok(image)
+    }
+
+    /// Rewrites the palette of an indexed image to hold only the entries its
+    /// pixels use, merging duplicates and placing translucent entries first
+    /// so the tRNS chunk is as short as possible. Indices are remapped to
+    /// match, a bKGD index is remapped (and kept in the palette), and hIST
+    /// is removed since its counts no longer line up.
+    pub fn optimize_palette(&mut self) -> Result<(), PngError> {
+        if self.color_type != ColorType::Indexed {
+            return Err(PngError::ColorTypeError);
+        }
+        let entries = self.palette().ok_or_else(|| {
+            PngError::InvalidPalette("Palette required for indexed color".to_string())
+        })?;
+        self.validate_palette_indices()?;
+
+        let background = self
+            .chunks
+            .iter()
+            .find(|c| &c.chunk_type == b"bKGD")
+            .and_then(|c| c.data.first().copied())
+            .filter(|&index| (index as usize) < entries.len());
+        let mut used = [false; 256];
+        for &index in self.data.iter().chain(background.as_ref()) {
+            used[index as usize] = true;
+        }
+
+        // Translucent entries first, each group in the original order
+        let (translucent, opaque): (Vec<usize>, Vec<usize>) = (0..entries.len())
+            .filter(|&i| used[i])
+            .partition(|&i| entries[i].3 != 255);
+        let mut palette = Vec::new();
+        let mut lookup = HashMap::new();
+        let mut remap = [0u8; 256];
+        for i in translucent.into_iter().chain(opaque) {
+            remap[i] = *lookup.entry(entries[i]).or_insert_with(|| {
+                palette.push(entries[i]);
+                (palette.len() - 1) as u8
+            });
+        }
+
+        for index in self.data.iter_mut() {
+            *index = remap[*index as usize];
+        }
+        self.chunks.retain(|c| &c.chunk_type != b"hIST");
+        if let Some(index) = background {
+            if let Some(chunk) = self.chunks.iter_mut().find(|c| &c.chunk_type == b"bKGD") {
+                chunk.data = vec![remap[index as usize]];
+            }
+        }
+        self.set_palette_rgba(&palette)
+    }
+}
+
+/// Chooses up to `max_colors` RGBA colors to stand for `histogram`, keeping
+/// every color when there are few enough and splitting the color space by
+/// median cut otherwise.
+pub(crate) fn median_cut(histogram: &HashMap<[u8; 4], u64>, max_colors: usize) -> Vec<[u8; 4]> {
+    let colors: Vec<([u8; 4], u64)> = histogram.iter().map(|(&c, &n)| (c, n)).collect();
+    if colors.len() <= max_colors {
+        return colors.into_iter().map(|(c, _)| c).collect();
+    }
+
+    // Channel with the widest range in a box, and that range
+    let widest = |colors: &[([u8; 4], u64)]| -> (usize, u8) {
+        (0..4)
+            .map(|ch| {
+                let (lo, hi) = colors
+                    .iter()
+                    .fold((255, 0), |(lo, hi), (c, _)| (c[ch].min(lo), c[ch].max(hi)));
+                (ch, hi - lo)
+            })
+            .max_by_key(|&(_, range)| range)
+            .unwrap_or((0, 0))
+    };
+
+    let mut boxes = vec![colors];
+    while boxes.len() < max_colors {
+        let Some((i, channel)) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .map(|(i, b)| (i, widest(b)))
+            .max_by_key(|&(_, (_, range))| range)
+            .map(|(i, (channel, _))| (i, channel))
+        else {
+            break;
+        };
+        let mut colors = boxes.swap_remove(i);
+        colors.sort_unstable_by_key(|(c, _)| c[channel]);
+        // Split where half the pixels fall on each side
+        let total: u64 = colors.iter().map(|(_, n)| n).sum();
+        let mut seen = 0;
+        let split = colors
+            .iter()
+            .position(|(_, n)| {
+                seen += n;
+                seen * 2 >= total
+            })
+            .map_or(1, |i| (i + 1).clamp(1, colors.len() - 1));
+        let upper = colors.split_off(split);
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+
+    // Each box is represented by its pixel-weighted mean
+    boxes
+        .iter()
+        .map(|colors| {
+            let total: u64 = colors.iter().map(|(_, n)| n).sum();
+            let mut mean = [0u8; 4];
+            for (ch, value) in mean.iter_mut().enumerate() {
+                let sum: u64 = colors.iter().map(|(c, n)| c[ch] as u64 * n).sum();
+                *value = ((sum + total / 2) / total) as u8;
+            }
+            mean
+        })
+        .collect()
+}
+
+/// The index of the entry in `palette` closest to `color`.
+pub(crate) fn nearest_entry(palette: &[[u8; 4]], color: [u8; 4]) -> u8 {
+    let distance = |entry: &[u8; 4]| -> u32 {
+        entry
+            .iter()
+            .zip(color)
+            .map(|(&a, b)| (a as i32 - b as i32).pow(2) as u32)
+            .sum()
+    };
+    (0..palette.len())
+        .min_by_key(|&i| distance(&palette[i]))
+        .unwrap_or(0) as u8
+}
@@ -0,0 +1,369 @@
+use std::fmt;
+use std::io::Read;
+
+use crate::chunks::ChunkReader;
+use crate::error::PngError;
+use crate::limits::{DecodeLimits, LimitCounter};
+use crate::metadata::{ImageOffset, PhysicalDimensions, PhysicalScale, StereoLayout, Timestamp};
+use crate::text::{is_text_chunk, TextChunk};
+use crate::{BitDepth, ColorType};
+
+/// The fields of an IHDR chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageHeader {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: BitDepth,
+    pub color_type: ColorType,
+    pub interlaced: bool,
+}
+
+impl ImageHeader {
+    pub fn parse(data: &[u8]) -> Result<Self, PngError> {
+        if data.len() != 13 {
+            return Err(PngError::InvalidHeader(format!(
+                "must be 13 bytes, got {}",
+                data.len()
+            )));
+        }
+
+        let width = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+        let height = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+        if width == 0 || height == 0 || width > 0x7FFF_FFFF || height > 0x7FFF_FFFF {
+            return Err(PngError::InvalidHeader(format!(
+                "dimensions {}x{} are out of range",
+                width, height
+            )));
+        }
+
+        let bit_depth = BitDepth::from_u8(data[8])
+            .ok_or_else(|| PngError::InvalidHeader(format!("bit depth {}", data[8])))?;
+        let color_type = ColorType::from_header_code(data[9])
+            .ok_or_else(|| PngError::InvalidHeader(format!("color type {}", data[9])))?;
+        if !color_type.allows_bit_depth(bit_depth) {
+            return Err(PngError::InvalidHeader(format!(
+                "bit depth {} is not allowed for {:?}",
+                data[8], color_type
+            )));
+        }
+
+        if data[10] != 0 {
+            return Err(PngError::InvalidHeader(format!(
+                "unknown compression method {}",
+                data[10]
+            )));
+        }
+        if data[11] != 0 {
+            return Err(PngError::InvalidHeader(format!(
+                "unknown filter method {}",
+                data[11]
+            )));
+        }
+        let interlaced = match data[12] {
+            0 => false,
+            1 => true,
+            method => {
+                return Err(PngError::InvalidHeader(format!(
+                    "unknown interlace method {}",
+                    method
+                )))
+            }
+        };
+
+        Ok(Self {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            interlaced,
+        })
+    }
+}
+
+/// Summary of one chunk in a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkSummary {
+    pub chunk_type: [u8; 4],
+    pub length: u32,
+    pub offset: u64,
+}
+
+/// A metadata chunk [`PngInfo::read`] couldn't parse and left out, such as
+/// a tIME chunk with a month of 13.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkWarning {
+    pub chunk_type: [u8; 4],
+    pub offset: u64,
+    pub message: String,
+}
+
+/// A known file format found in data appended to a PNG.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrailingFormat {
+    /// A ZIP archive (or JAR, DOCX, APK, and so on), making the file a
+    /// PNG/ZIP polyglot that archive tools open too
+    Zip,
+    Pdf,
+    Png,
+    Jpeg,
+    Gif,
+    Gzip,
+    SevenZip,
+    Rar,
+    /// A Windows or ELF executable
+    Executable,
+}
+
+impl TrailingFormat {
+    // Recognizes a format from the first bytes after IEND
+    fn sniff(start: &[u8]) -> Option<Self> {
+        const MAGIC: [(&[u8], TrailingFormat); 10] = [
+            (b"PK\x03\x04", TrailingFormat::Zip),
+            (b"%PDF", TrailingFormat::Pdf),
+            (b"\x89PNG\r\n\x1a\n", TrailingFormat::Png),
+            (b"\xff\xd8\xff", TrailingFormat::Jpeg),
+            (b"GIF8", TrailingFormat::Gif),
+            (b"\x1f\x8b", TrailingFormat::Gzip),
+            (b"7z\xbc\xaf\x27\x1c", TrailingFormat::SevenZip),
+            (b"Rar!\x1a\x07", TrailingFormat::Rar),
+            (b"MZ", TrailingFormat::Executable),
+            (b"\x7fELF", TrailingFormat::Executable),
+        ];
+        MAGIC
+            .iter()
+            .find(|(magic, _)| start.starts_with(magic))
+            .map(|&(_, format)| format)
+    }
+}
+
+impl fmt::Display for TrailingFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TrailingFormat::Zip => "ZIP archive",
+            TrailingFormat::Pdf => "PDF document",
+            TrailingFormat::Png => "PNG image",
+            TrailingFormat::Jpeg => "JPEG image",
+            TrailingFormat::Gif => "GIF image",
+            TrailingFormat::Gzip => "gzip data",
+            TrailingFormat::SevenZip => "7z archive",
+            TrailingFormat::Rar => "RAR archive",
+            TrailingFormat::Executable => "executable",
+        })
+    }
+}
+
+/// Bytes found after the IEND chunk, which viewers ignore but which can
+/// smuggle a second file past upload checks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrailingData {
+    /// Byte offset just past IEND
+    pub offset: u64,
+    pub length: u64,
+    /// What the bytes look like, if recognized. A ZIP archive is also
+    /// recognized from its end record, as archive tools find it, even when
+    /// something else comes first.
+    pub format: Option<TrailingFormat>,
+}
+
+// A ZIP archive's end-of-central-directory record, which sits within the
+// last 64 KiB + 22 bytes of the archive
+const ZIP_END_MAGIC: &[u8] = b"PK\x05\x06";
+const ZIP_END_WINDOW: usize = 65_557;
+
+// Reads everything after IEND, keeping only the start and a window at the
+// end so that huge payloads aren't held in memory
+fn scan_trailing<R: Read>(mut reader: R, offset: u64) -> Result<Option<TrailingData>, PngError> {
+    let mut start = Vec::new();
+    let mut tail = Vec::new();
+    let mut length = 0u64;
+    let mut buffer = [0; 8192];
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let bytes = &buffer[..read];
+        length += read as u64;
+        if start.len() < 16 {
+            start.extend_from_slice(&bytes[..bytes.len().min(16 - start.len())]);
+        }
+        tail.extend_from_slice(bytes);
+        if tail.len() > 2 * ZIP_END_WINDOW {
+            tail.drain(..tail.len() - ZIP_END_WINDOW);
+        }
+    }
+    if length == 0 {
+        return Ok(None);
+    }
+
+    let tail = &tail[tail.len().saturating_sub(ZIP_END_WINDOW)..];
+    let zip_end = tail
+        .windows(ZIP_END_MAGIC.len())
+        .any(|w| w == ZIP_END_MAGIC);
+    let format = match TrailingFormat::sniff(&start) {
+        None if zip_end => Some(TrailingFormat::Zip),
+        format => format,
+    };
+    Ok(Some(TrailingData {
+        offset,
+        length,
+        format,
+    }))
+}
+
+/// Structural information about a PNG file, gathered without decoding pixels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PngInfo {
+    pub header: ImageHeader,
+    pub chunks: Vec<ChunkSummary>,
+    pub text: Vec<TextChunk>,
+    pub time: Option<Timestamp>,
+    pub physical: Option<PhysicalDimensions>,
+    pub offset: Option<ImageOffset>,
+    pub scale: Option<PhysicalScale>,
+    pub stereo: Option<StereoLayout>,
+    /// Anything after IEND
+    pub trailing: Option<TrailingData>,
+    /// Text and metadata chunks that couldn't be parsed, which are left
+    /// out of the fields above rather than failing the read
+    pub warnings: Vec<ChunkWarning>,
+}
+
+impl PngInfo {
+    /// Reads the file's structure with [`DecodeLimits::default`].
+    pub fn read<R: Read>(reader: R) -> Result<Self, PngError> {
+        Self::read_with_limits(reader, DecodeLimits::default())
+    }
+
+    /// Reads the file's structure, failing with
+    /// [`PngError::LimitExceeded`] if it has more ancillary chunks or text
+    /// than `limits` allow. A malformed text or metadata chunk is recorded
+    /// in [`warnings`](Self::warnings) and skipped.
+    pub fn read_with_limits<R: Read>(reader: R, limits: DecodeLimits) -> Result<Self, PngError> {
+        let mut chunks = ChunkReader::new(reader)?;
+
+        let first = chunks.next_chunk()?.ok_or(PngError::MissingChunk {
+            chunk_type: *b"IHDR",
+        })?;
+        if &first.chunk_type != b"IHDR" {
+            return Err(PngError::UnexpectedChunk {
+                chunk_type: first.chunk_type,
+                offset: first.offset,
+                reason: "IHDR must be the first chunk",
+            });
+        }
+        let header = ImageHeader::parse(&first.data)?;
+
+        let mut summaries = vec![ChunkSummary {
+            chunk_type: first.chunk_type,
+            length: first.data.len() as u32,
+            offset: first.offset,
+        }];
+        let mut text = Vec::new();
+        let mut time = None;
+        let mut physical = None;
+        let mut offset = None;
+        let mut scale = None;
+        let mut stereo = None;
+        let mut warnings = Vec::new();
+        let mut limits = LimitCounter::new(limits);
+
+        while let Some(chunk) = chunks.next_chunk()? {
+            limits.count_chunk(chunk.chunk_type, chunk.offset)?;
+            let parsed = match &chunk.chunk_type {
+                t if is_text_chunk(t) => {
+                    let parsed = TextChunk::parse_capped(
+                        t,
+                        &chunk.data,
+                        limits.text_remaining().saturating_add(1),
+                    );
+                    if let Ok(parsed) = &parsed {
+                        limits.count_text(chunk.chunk_type, chunk.offset, parsed.text.len())?;
+                    }
+                    parsed.map(|parsed| text.push(parsed))
+                }
+                b"tIME" => Timestamp::parse(&chunk.data).map(|v| time = Some(v)),
+                b"pHYs" => PhysicalDimensions::parse(&chunk.data).map(|v| physical = Some(v)),
+                b"oFFs" => ImageOffset::parse(&chunk.data).map(|v| offset = Some(v)),
+                b"sCAL" => PhysicalScale::parse(&chunk.data).map(|v| scale = Some(v)),
+                b"sTER" => StereoLayout::parse(&chunk.data).map(|v| stereo = Some(v)),
+                _ => Ok(()),
+            };
+            if let Err(e) = parsed {
+                warnings.push(ChunkWarning {
+                    chunk_type: chunk.chunk_type,
+                    offset: chunk.offset,
+                    message: e.to_string(),
+                });
+            }
+            summaries.push(ChunkSummary {
+                chunk_type: chunk.chunk_type,
+                length: chunk.data.len() as u32,
+                offset: chunk.offset,
+            });
+        }
+        let end = chunks.offset();
+        let trailing = scan_trailing(chunks.into_inner(), end)?;
+
+        Ok(Self {
+            header,
+            chunks: summaries,
+            text,
+            time,
+            physical,
+            offset,
+            scale,
+            stereo,
+            trailing,
+            warnings,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_images::noise;
+    use crate::{BitDepth, ColorType};
+
+    #[test]
+    fn malformed_metadata_becomes_a_warning() {
+        let mut image = noise(4, 4, ColorType::Rgb, BitDepth::Eight, 1);
+        // Month 0, and a pHYs two bytes short
+        image
+            .add_chunk(*b"tIME", vec![7, 232, 0, 1, 0, 0, 0])
+            .unwrap();
+        image.add_chunk(*b"pHYs", vec![0; 7]).unwrap();
+        image
+            .add_text(&TextChunk::new("Title", "still read"))
+            .unwrap();
+        let mut encoded = Vec::new();
+        image.write_to_file(&mut encoded).unwrap();
+
+        let info = PngInfo::read(encoded.as_slice()).unwrap();
+        assert_eq!(info.time, None);
+        assert_eq!(info.physical, None);
+        assert_eq!(info.text[0].text, "still read");
+        let types: Vec<_> = info.warnings.iter().map(|w| &w.chunk_type).collect();
+        assert_eq!(types, [b"tIME", b"pHYs"]);
+    }
+
+    #[test]
+    fn limits_still_fail_the_read() {
+        let mut image = noise(4, 4, ColorType::Rgb, BitDepth::Eight, 1);
+        image
+            .add_text(&TextChunk::new("Comment", &"a".repeat(100)))
+            .unwrap();
+        let mut encoded = Vec::new();
+        image.write_to_file(&mut encoded).unwrap();
+
+        let limits = DecodeLimits {
+            max_text_bytes: 10,
+            ..DecodeLimits::default()
+        };
+        let error = PngInfo::read_with_limits(encoded.as_slice(), limits).unwrap_err();
+        assert!(matches!(error.root(), PngError::LimitExceeded(_)));
+    }
+}
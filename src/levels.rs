@@ -0,0 +1,163 @@
+use crate::color::Color;
+use crate::error::PngError;
+use crate::{BitDepth, ColorType, PngImage};
+
+impl PngImage {
+    /// Stretches each color channel separately so that its darkest samples
+    /// become black and its brightest white, after ignoring `low_clip` and
+    /// `high_clip` percent of the samples at either end (each clamped to
+    /// 0..=50). Alpha is left alone. This also corrects color casts; see
+    /// [`PngImage::auto_contrast`] for a stretch that keeps hues.
+    pub fn auto_levels(&mut self, low_clip: f64, high_clip: f64) -> Result<(), PngError> {
+        self.stretch_levels(true, low_clip, high_clip)
+    }
+
+    /// Like [`PngImage::auto_levels`], but finds one black and white point
+    /// for all color channels together.
+    pub fn auto_contrast(&mut self, low_clip: f64, high_clip: f64) -> Result<(), PngError> {
+        self.stretch_levels(false, low_clip, high_clip)
+    }
+
+    /// A 1-bit grayscale copy in which pixels whose 8-bit gray value is at
+    /// least `level` are white and the rest black. Alpha is composited over
+    /// white first.
+    pub fn threshold(&self, level: u8) -> Result<PngImage, PngError> {
+        let gray = self.gray8()?;
+        let mut binary =
+            PngImage::with_bit_depth(self.width, self.height, ColorType::Grayscale, BitDepth::One)?;
+        binary
+            .data
+            .replace(gray.data.iter().map(|&v| (v >= level) as u8).collect())?;
+        Ok(binary)
+    }
+
+    /// Like [`PngImage::threshold`], with the level chosen by Otsu's method
+    /// to best separate the image's gray values into two groups.
+    pub fn threshold_otsu(&self) -> Result<PngImage, PngError> {
+        let gray = self.gray8()?;
+        let mut histogram = [0u64; 256];
+        for &v in gray.data.iter() {
+            histogram[v as usize] += 1;
+        }
+        self.threshold(otsu_level(&histogram))
+    }
+
+    // A complete 8-bit grayscale copy for thresholding
+    fn gray8(&self) -> Result<PngImage, PngError> {
+        self.check_complete()?;
+        let mut gray = self.clone();
+        gray.convert_to_over(ColorType::Grayscale, Color::WHITE)?;
+        gray.convert_bit_depth(BitDepth::Eight)?;
+        Ok(gray)
+    }
+
+    fn stretch_levels(
+        &mut self,
+        per_channel: bool,
+        low_clip: f64,
+        high_clip: f64,
+    ) -> Result<(), PngError> {
+        if self.color_type == ColorType::Indexed {
+            return Err(PngError::ColorTypeError);
+        }
+        let colors = match self.color_type {
+            ColorType::Grayscale | ColorType::GrayscaleAlpha => 1,
+            _ => 3,
+        };
+        let channels = self.color_type.channels();
+        let sixteen = self.bit_depth == BitDepth::Sixteen;
+        let sample_size = self.bit_depth.bytes_per_sample();
+        let max = self.bit_depth.max_value() as usize;
+        let read = |sample: &[u8]| match sample {
+            [hi, lo] => u16::from_be_bytes([*hi, *lo]) as usize,
+            _ => sample[0] as usize,
+        };
+
+        let mut histograms = vec![vec![0u64; max + 1]; if per_channel { colors } else { 1 }];
+        for pixel in self.data.chunks_exact(channels * sample_size) {
+            for (c, sample) in pixel.chunks_exact(sample_size).take(colors).enumerate() {
+                let histogram = if per_channel { c } else { 0 };
+                histograms[histogram][read(sample)] += 1;
+            }
+        }
+
+        let low_clip = low_clip.clamp(0.0, 50.0) / 100.0;
+        let high_clip = high_clip.clamp(0.0, 50.0) / 100.0;
+        let maps: Vec<Option<(usize, usize)>> = histograms
+            .iter()
+            .map(|histogram| levels(histogram, low_clip, high_clip))
+            .collect();
+
+        for pixel in self.data.chunks_exact_mut(channels * sample_size) {
+            for (c, sample) in pixel.chunks_exact_mut(sample_size).take(colors).enumerate() {
+                let Some((black, white)) = maps[if per_channel { c } else { 0 }] else {
+                    continue;
+                };
+                let value = read(sample).clamp(black, white) - black;
+                let value = ((value * max + (white - black) / 2) / (white - black)) as u16;
+                if sixteen {
+                    sample.copy_from_slice(&value.to_be_bytes());
+                } else {
+                    sample[0] = value as u8;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// The black and white points after clipping the given fractions of samples,
+// or `None` if they meet and there is nothing to stretch
+fn levels(histogram: &[u64], low_clip: f64, high_clip: f64) -> Option<(usize, usize)> {
+    let total: u64 = histogram.iter().sum();
+    let black = clip_point(histogram, total as f64 * low_clip, 0..histogram.len())?;
+    let white = clip_point(
+        histogram,
+        total as f64 * high_clip,
+        (0..histogram.len()).rev(),
+    )?;
+    (white > black).then_some((black, white))
+}
+
+// The first value in `order` at which more than `limit` samples are covered
+fn clip_point(
+    histogram: &[u64],
+    limit: f64,
+    mut order: impl Iterator<Item = usize>,
+) -> Option<usize> {
+    let mut seen = 0;
+    order.find(|&v| {
+        seen += histogram[v];
+        seen as f64 > limit
+    })
+}
+
+// The lowest level of the upper group under Otsu's method, which maximizes
+// the variance between the groups below and at or above it
+fn otsu_level(histogram: &[u64; 256]) -> u8 {
+    let total: u64 = histogram.iter().sum();
+    let sum: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(v, &n)| v as f64 * n as f64)
+        .sum();
+
+    let (mut below, mut below_sum) = (0u64, 0.0);
+    let (mut best, mut best_variance) = (0, -1.0);
+    for level in 1..256 {
+        below += histogram[level - 1];
+        below_sum += (level - 1) as f64 * histogram[level - 1] as f64;
+        let above = total - below;
+        if below == 0 || above == 0 {
+            continue;
+        }
+        let mean_below = below_sum / below as f64;
+        let mean_above = (sum - below_sum) / above as f64;
+        let variance = below as f64 * above as f64 * (mean_below - mean_above).powi(2);
+        if variance > best_variance {
+            best = level;
+            best_variance = variance;
+        }
+    }
+    best as u8
+}
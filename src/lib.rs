@@ -1,13 +1,136 @@
+mod alpha;
+mod apng;
+#[cfg(feature = "bench")]
+pub mod bench;
+mod buffer;
+mod builder;
+mod cgbi;
 mod chunks;
+mod color;
+mod compare;
+mod convolve;
+mod decoder;
+mod dictionary;
+mod draw;
 mod error;
+mod filmstrip;
+mod filter;
+#[cfg(feature = "arbitrary")]
+pub mod fuzz;
+mod generate;
+#[cfg(feature = "gif")]
+mod gif;
+mod icc;
+mod ico;
+mod import;
+mod indexed;
+mod info;
+mod levels;
+mod limits;
+mod manifest;
+mod metadata;
+#[cfg(feature = "mmap")]
+mod mmap;
+mod montage;
+mod multipart;
+mod optimize;
+mod options;
+mod orientation;
+mod phash;
+mod pixel;
+mod plot;
+mod pool;
+mod progress;
+#[cfg(feature = "qr")]
+mod qr;
+mod report;
+mod rewrite;
+mod samples;
+mod sharpen;
+mod signature;
+mod stream;
+#[cfg(test)]
+mod test_images;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod text;
+mod throttle;
+mod transcode;
+mod transform;
+mod tune;
+mod verify;
+mod video;
+mod watermark;
+#[cfg(feature = "http")]
+mod web;
 
-use chunks::ChunkWriter;
-pub use error::PngError;
-use flate2::write::ZlibEncoder;
-use flate2::Compression;
-use std::io::{Seek, Write};
+pub use apng::{Animation, BlendOp, DisposeOp, Frame};
+use buffer::PixelBuffer;
+pub use builder::PngImageBuilder;
+use chunks::{
+    check_ancillary, follows_palette, single_instance, ChunkWriter, MAX_CHUNK_LENGTH, PNG_SIGNATURE,
+};
+pub use chunks::{Chunk, ChunkReader, ChunkRef, ChunkSlices, SignatureDamage};
+pub use color::Color;
+pub use compare::{compare, diff_image, side_by_side_diff, Comparison};
+pub use convolve::{EdgeMode, Kernel};
+pub use decoder::{DecodeScale, Decoder, DepthMapping, FilteredImage, FilteredScanline};
+pub use dictionary::CompressionDictionary;
+use dictionary::ZlibWriter;
+pub use error::{ErrorKind, PngError};
+pub use filmstrip::{filmstrip, FilmstripOptions, FrameSampling};
+pub use filter::{FilterStrategy, FilterType};
+pub use generate::{Easing, Pattern};
+pub use icc::RenderingIntent;
+pub use ico::{write_ico, FAVICON_SIZES};
+pub use info::{ChunkSummary, ChunkWarning, ImageHeader, PngInfo, TrailingData, TrailingFormat};
+pub use limits::DecodeLimits;
+pub use manifest::{chunk_manifest, image_from_manifest};
+pub use metadata::{
+    ImageOffset, OffsetUnit, PhysicalDimensions, PhysicalScale, PixelUnit, ScaleUnit, StereoLayout,
+    Timestamp,
+};
+pub use montage::montage;
+pub use multipart::MultipartWriter;
+pub use optimize::{optimize, OptimizeOptions, OptimizeResult};
+pub use options::{EncodeOptions, Strictness};
+pub use orientation::Orientation;
+pub use phash::ImageHash;
+pub use pixel::{
+    FromPixel, IntoPixel, Luma16, Luma8, LumaA16, LumaA8, Pixel, Rgb16, Rgb8, Rgba16, Rgba8,
+};
+pub use plot::{Plot, SeriesKind};
+pub use pool::{
+    decode_many, decode_many_with_options, DecodeManyOptions, EncodeHandle, EncoderPool,
+};
+pub use progress::{CancelToken, Phase, Progress, ProgressHook};
+#[cfg(feature = "qr")]
+pub use qr::QrErrorCorrection;
+pub use report::{AnimationSize, EncodeReport};
+pub use rewrite::{
+    repair_crcs, split_trailing_data, strip_chunks, CrcRepair, Rewriter, StripPolicy,
+};
+pub use signature::{add_signature, verify_signature};
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Instant;
+pub use stream::{StreamCheckpoint, StreamWriter};
+use text::{is_text_chunk, REGISTERED_KEYWORDS};
+pub use text::{TemplateValues, TextChunk, TextKind};
+pub use throttle::ThrottledWriter;
+pub use transcode::{transcode, TranscodeOptions};
+pub use transform::{Insets, ResizeFilter};
+pub use tune::{TuneOptions, TunePreset};
+pub use video::YuvLayout;
+pub use watermark::WatermarkPosition;
+#[cfg(feature = "http")]
+pub use web::PngBody;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum ColorType {
     Grayscale,
     Rgb,
@@ -17,6 +140,25 @@ pub enum ColorType {
 }
 
 impl ColorType {
+    fn from_header_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(ColorType::Grayscale),
+            2 => Some(ColorType::Rgb),
+            3 => Some(ColorType::Indexed),
+            4 => Some(ColorType::GrayscaleAlpha),
+            6 => Some(ColorType::Rgba),
+            _ => None,
+        }
+    }
+
+    fn allows_bit_depth(&self, bit_depth: BitDepth) -> bool {
+        match self {
+            ColorType::Grayscale => true,
+            ColorType::Indexed => bit_depth != BitDepth::Sixteen,
+            _ => matches!(bit_depth, BitDepth::Eight | BitDepth::Sixteen),
+        }
+    }
+
     fn png_header_code(&self) -> u8 {
         match self {
             ColorType::Grayscale => 0,
@@ -27,7 +169,7 @@ impl ColorType {
         }
     }
 
-    fn bytes_per_pixel(&self) -> usize {
+    fn channels(&self) -> usize {
         match self {
             ColorType::Grayscale => 1,
             ColorType::Rgb => 3,
@@ -37,51 +179,340 @@ impl ColorType {
         }
     }
 
-    fn validate_components(&self, components: &[u8]) -> Result<(), PngError> {
-        let expected = self.bytes_per_pixel();
+    fn validate_components(&self, components: &[u8], bit_depth: BitDepth) -> Result<(), PngError> {
+        let expected = self.channels() * bit_depth.bytes_per_sample();
         if components.len() != expected {
-            Err(PngError::ComponentCountMismatch {
+            return Err(PngError::ComponentCountMismatch {
                 expected,
                 actual: components.len(),
                 color_type: *self,
-            })
+            });
+        }
+
+        if bit_depth.bits() < 8 {
+            if let Some(&value) = components
+                .iter()
+                .find(|&&c| c as u16 > bit_depth.max_value())
+            {
+                return Err(PngError::SampleOutOfRange {
+                    value: value as u16,
+                    bit_depth: bit_depth.bits(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitDepth {
+    One = 1,
+    Two = 2,
+    Four = 4,
+    Eight = 8,
+    Sixteen = 16,
+}
+
+impl BitDepth {
+    fn from_u8(bits: u8) -> Option<Self> {
+        match bits {
+            1 => Some(BitDepth::One),
+            2 => Some(BitDepth::Two),
+            4 => Some(BitDepth::Four),
+            8 => Some(BitDepth::Eight),
+            16 => Some(BitDepth::Sixteen),
+            _ => None,
+        }
+    }
+
+    pub fn bits(&self) -> u8 {
+        *self as u8
+    }
+
+    /// Bytes used to store one sample in `PngImage` data. Depths below 8
+    /// still take a whole byte each; they are packed only when encoding.
+    fn bytes_per_sample(&self) -> usize {
+        if *self == BitDepth::Sixteen {
+            2
         } else {
-            Ok(())
+            1
         }
     }
+
+    fn max_value(&self) -> u16 {
+        ((1u32 << self.bits()) - 1) as u16
+    }
+}
+
+// Packs a row of `bits`-bit samples, held one per byte, into `packed`,
+// which must start zeroed
+pub(crate) fn pack_samples(samples: &[u8], bits: usize, packed: &mut [u8]) {
+    for (i, &sample) in samples.iter().enumerate() {
+        let bit = i * bits;
+        packed[bit / 8] |= sample << (8 - bits - bit % 8);
+    }
 }
 
+/// Bytes needed for `width` x `height` pixels of `bytes_per_pixel` each,
+/// failing instead of overflowing `usize` or passing the largest buffer a
+/// `Vec` can hold.
+pub(crate) fn image_size(
+    width: u32,
+    height: u32,
+    bytes_per_pixel: usize,
+) -> Result<usize, PngError> {
+    (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(bytes_per_pixel))
+        .filter(|&size| size <= isize::MAX as usize)
+        .ok_or(PngError::ImageTooLarge { width, height })
+}
+
+#[derive(Clone)]
 pub struct PngImage {
     width: u32,
     height: u32,
-    data: Vec<u8>,
+    data: PixelBuffer,
     color_type: ColorType,
+    bit_depth: BitDepth,
     palette: Option<Vec<u8>>,
+    /// Ancillary chunks written along with the image
+    chunks: Vec<Chunk>,
+    /// Used by `write_to_file`
+    options: EncodeOptions,
+}
+
+/// Images are equal when their format, palette, samples and ancillary chunk
+/// contents match; encoding options and where chunks were read from are
+/// ignored.
+impl PartialEq for PngImage {
+    fn eq(&self, other: &Self) -> bool {
+        self.width == other.width
+            && self.height == other.height
+            && self.color_type == other.color_type
+            && self.bit_depth == other.bit_depth
+            && self.palette == other.palette
+            && self.data == other.data
+            && self.chunks.len() == other.chunks.len()
+            && self
+                .chunks
+                .iter()
+                .zip(&other.chunks)
+                .all(|(a, b)| a.chunk_type == b.chunk_type && a.data == b.data)
+    }
+}
+
+impl Eq for PngImage {}
+
+/// Summarizes the image instead of dumping its samples.
+impl fmt::Debug for PngImage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("PngImage");
+        debug
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("color_type", &self.color_type)
+            .field("bit_depth", &self.bit_depth.bits())
+            .field("data_len", &self.data.len());
+        if let Some(palette) = &self.palette {
+            debug.field("palette_entries", &(palette.len() / 3));
+        }
+        let chunks: Vec<String> = self
+            .chunks
+            .iter()
+            .map(|c| String::from_utf8_lossy(&c.chunk_type).into_owned())
+            .collect();
+        debug.field("chunks", &chunks).finish()
+    }
+}
+
+// What a preset dictionary can add to the zlib stream: its 4-byte ID, and
+// a stored block header
+const DICTIONARY_OVERHEAD: u64 = 4 + 5;
+
+// Rows handed from the filtering thread to the compressing thread at a
+// time are about this many bytes, with this many batches in flight
+const PIPELINE_BATCH_BYTES: usize = 64 << 10;
+const PIPELINE_DEPTH: usize = 4;
+
+// Filters `rows` of `row_length` bytes on this thread while another
+// compresses the batches already filtered, returning the filtered
+// scanlines and their zlib stream
+fn filter_and_compress_pipelined(
+    rows: &[u8],
+    row_length: usize,
+    bpp: usize,
+    options: &EncodeOptions,
+    progress: &mut ProgressHook,
+) -> Result<(Vec<u8>, Vec<u8>), PngError> {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!(
+        "filter_and_compress",
+        level = options.compression_level,
+        raw_bytes = rows.len()
+    )
+    .entered();
+    let (level, dictionary) = (options.compression_level, options.dictionary.as_ref());
+    let row_count = rows.len() / row_length.max(1);
+    let batch_rows = (PIPELINE_BATCH_BYTES / (row_length + 1)).max(1);
+    progress.begin(Phase::Filtering, row_count)?;
+
+    thread::scope(|scope| {
+        let (batches, received) = mpsc::sync_channel::<Vec<u8>>(PIPELINE_DEPTH);
+        let compressor = scope.spawn(move || -> Result<(Vec<u8>, Vec<u8>), PngError> {
+            let mut encoder = ZlibWriter::new(level, dictionary)?;
+            let mut filtered = Vec::with_capacity(rows.len() + row_count);
+            for batch in received {
+                encoder.write_all(&batch)?;
+                filtered.extend_from_slice(&batch);
+            }
+            Ok((filtered, encoder.finish()?))
+        });
+
+        // Returning early drops the sender, which lets the compressor finish
+        let mut filter = filter::RowFilter::new(options.filter, row_length, bpp);
+        for rows in rows.chunks(row_length * batch_rows) {
+            let mut batch = Vec::with_capacity(rows.len() + batch_rows);
+            for row in rows.chunks_exact(row_length) {
+                filter.filter(row, &mut batch);
+                progress.advance()?;
+            }
+            if batches.send(batch).is_err() {
+                // The compressor failed, and says why below
+                break;
+            }
+        }
+        drop(batches);
+        compressor
+            .join()
+            .unwrap_or_else(|_| Err(PngError::Io(io::Error::other("compressor thread panicked"))))
+    })
+}
+
+// Compresses filtered scanlines of `row_length` bytes plus the filter byte
+fn compress(
+    filtered: &[u8],
+    row_length: usize,
+    options: &EncodeOptions,
+    progress: &mut ProgressHook,
+) -> Result<Vec<u8>, PngError> {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!(
+        "compress",
+        level = options.compression_level,
+        raw_bytes = filtered.len()
+    )
+    .entered();
+    let mut encoder = ZlibWriter::new(options.compression_level, options.dictionary.as_ref())?;
+    let lines = filtered.chunks(row_length + 1);
+    progress.begin(Phase::Compressing, lines.len())?;
+    for line in lines {
+        encoder.write_all(line)?;
+        progress.advance()?;
+    }
+    let compressed = encoder.finish()?;
+    #[cfg(feature = "tracing")]
+    tracing::debug!(compressed_bytes = compressed.len(), "compressed");
+    Ok(compressed)
 }
 
 impl PngImage {
     pub fn new(width: u32, height: u32, color_type: ColorType) -> Result<Self, PngError> {
-        if width == 0 || height == 0 || width > 0x7FFF || height > 0x7FFF {
+        Self::with_bit_depth(width, height, color_type, BitDepth::Eight)
+    }
+
+    /// Creates an image with a bit depth other than 8. At 16 bits each sample
+    /// is passed to `add_pixel` as two big-endian bytes; below 8 bits each
+    /// sample is one byte holding a value in `0..2^bit_depth`.
+    pub fn with_bit_depth(
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+        bit_depth: BitDepth,
+    ) -> Result<Self, PngError> {
+        Self::with_buffer(width, height, color_type, bit_depth, |size| {
+            Ok(PixelBuffer::with_capacity(size))
+        })
+    }
+
+    // Checks the format, then stores the pixels in what `buffer` makes for
+    // the image's size in bytes
+    fn with_buffer(
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+        bit_depth: BitDepth,
+        buffer: impl FnOnce(usize) -> Result<PixelBuffer, PngError>,
+    ) -> Result<Self, PngError> {
+        if width == 0 || height == 0 || width > 0x7FFF_FFFF || height > 0x7FFF_FFFF {
             return Err(PngError::InvalidDimensions(width, height));
         }
+        if !color_type.allows_bit_depth(bit_depth) {
+            return Err(PngError::InvalidBitDepth {
+                bit_depth: bit_depth.bits(),
+                color_type,
+            });
+        }
 
+        let bytes_per_pixel = color_type.channels() * bit_depth.bytes_per_sample();
         Ok(Self {
             width,
             height,
-            data: Vec::with_capacity(
-                (width as usize) * (height as usize) * color_type.bytes_per_pixel(),
-            ),
+            data: buffer(image_size(width, height, bytes_per_pixel)?)?,
             color_type,
+            bit_depth,
             palette: None,
+            chunks: Vec::new(),
+            options: EncodeOptions::default(),
         })
     }
 
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn color_type(&self) -> ColorType {
+        self.color_type
+    }
+
+    pub fn bit_depth(&self) -> BitDepth {
+        self.bit_depth
+    }
+
+    /// The samples added so far, row by row with no padding. Samples below 8
+    /// bits take one byte each and 16-bit samples are big-endian pairs, the
+    /// same layout `add_pixel` accepts.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Mutable access to the samples; see [`PngImage::data`]. Values must
+    /// stay below `2^bit_depth`; palette indices are checked when the image
+    /// is written.
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// Consumes the image, returning its samples.
+    pub fn into_raw(self) -> Vec<u8> {
+        self.data.into_vec()
+    }
+
+    fn bytes_per_pixel(&self) -> usize {
+        self.color_type.channels() * self.bit_depth.bytes_per_sample()
+    }
+
     pub fn add_pixel(&mut self, components: &[u8]) -> Result<(), PngError> {
-        self.color_type.validate_components(components)?;
+        self.color_type
+            .validate_components(components, self.bit_depth)?;
 
         // Check pixel count
-        let max_pixels = (self.width * self.height) as usize;
-        let current_pixels = self.data.len() / self.color_type.bytes_per_pixel();
+        let max_pixels = image_size(self.width, self.height, 1)?;
+        let current_pixels = self.data.len() / self.bytes_per_pixel();
         if current_pixels >= max_pixels {
             return Err(PngError::PixelCountMismatch {
                 expected: max_pixels,
@@ -90,18 +521,92 @@ impl PngImage {
             });
         }
 
-        self.data.extend_from_slice(components);
+        self.data.extend_from_slice(components)?;
+        Ok(())
+    }
+
+    /// Replaces row `y` with `row`, laid out as for `add_pixel`. Rows not
+    /// added yet are zero-filled first, so rows can be set in any order.
+    pub fn set_row(&mut self, y: u32, row: &[u8]) -> Result<(), PngError> {
+        self.check_region(0, y, self.width, 1)?;
+        let row_length = self.width as usize * self.bytes_per_pixel();
+        if row.len() != row_length {
+            return Err(PngError::RowLengthMismatch {
+                expected: row_length,
+                actual: row.len(),
+            });
+        }
+        self.check_samples(row)?;
+
+        self.complete()?;
+        let start = y as usize * row_length;
+        self.data[start..start + row_length].copy_from_slice(row);
         Ok(())
     }
 
+    /// Replaces every pixel from a buffer whose rows start `stride` bytes
+    /// apart, ignoring any padding after each row. The last row needs no
+    /// padding.
+    pub fn copy_from_slice_with_stride(
+        &mut self,
+        src: &[u8],
+        stride: usize,
+    ) -> Result<(), PngError> {
+        let row_length = self.width as usize * self.bytes_per_pixel();
+        if stride < row_length {
+            return Err(PngError::InvalidStride { stride, row_length });
+        }
+        let expected = (self.height as usize - 1)
+            .checked_mul(stride)
+            .and_then(|n| n.checked_add(row_length))
+            .ok_or(PngError::ImageTooLarge {
+                width: self.width,
+                height: self.height,
+            })?;
+        if src.len() < expected {
+            return Err(PngError::BufferTooSmall {
+                expected,
+                actual: src.len(),
+            });
+        }
+
+        let mut data =
+            Vec::with_capacity(image_size(self.width, self.height, self.bytes_per_pixel())?);
+        for row in src.chunks(stride).take(self.height as usize) {
+            let row = &row[..row_length];
+            self.check_samples(row)?;
+            data.extend_from_slice(row);
+        }
+        self.data.replace(data)?;
+        Ok(())
+    }
+
+    // Rejects sub-8-bit samples that don't fit the bit depth, which packing
+    // would otherwise spill into their neighbors
+    fn check_samples(&self, samples: &[u8]) -> Result<(), PngError> {
+        if self.bit_depth.bits() >= 8 {
+            return Ok(());
+        }
+        match samples
+            .iter()
+            .find(|&&v| v as u16 > self.bit_depth.max_value())
+        {
+            Some(&value) => Err(PngError::SampleOutOfRange {
+                value: value as u16,
+                bit_depth: self.bit_depth.bits(),
+            }),
+            None => Ok(()),
+        }
+    }
+
     fn generate_ihdr(&self) -> Vec<u8> {
         let mut data = Vec::with_capacity(13);
 
         data.extend_from_slice(&self.width.to_be_bytes());
         data.extend_from_slice(&self.height.to_be_bytes());
 
-        // Bit depth (8 bits per sample)
-        data.push(8);
+        // Bit depth (bits per sample, or per palette index)
+        data.push(self.bit_depth.bits());
 
         // Color type
         data.push(self.color_type.png_header_code());
@@ -116,26 +621,173 @@ impl PngImage {
         data
     }
 
-    fn filter_scanlines(&self) -> Vec<u8> {
-        let bytes_per_pixel = self.color_type.bytes_per_pixel();
-        let row_length = self.width as usize * bytes_per_pixel;
-        let mut filtered = Vec::with_capacity(self.data.len() + self.height as usize);
+    // Length in bytes of one encoded scanline, excluding the filter byte
+    fn packed_row_length(&self) -> usize {
+        let bits =
+            self.width as usize * self.color_type.channels() * self.bit_depth.bits() as usize;
+        bits.div_ceil(8)
+    }
+
+    // Packs sub-byte samples into the layout PNG expects; other depths are
+    // already stored exactly as they are encoded
+    fn packed_rows(&self) -> Cow<'_, [u8]> {
+        let bits = self.bit_depth.bits() as usize;
+        if bits >= 8 {
+            return Cow::Borrowed(&self.data);
+        }
 
-        for row in self.data.chunks_exact(row_length) {
-            filtered.push(1);
+        let samples_per_row = self.width as usize * self.color_type.channels();
+        let row_length = self.packed_row_length();
+        let mut packed = Vec::with_capacity(row_length * self.height as usize);
+        for row in self.data.chunks_exact(samples_per_row) {
+            let start = packed.len();
+            packed.resize(start + row_length, 0);
+            pack_samples(row, bits, &mut packed[start..]);
+        }
+        Cow::Owned(packed)
+    }
 
-            let mut prev = vec![0; bytes_per_pixel];
-            for (i, &byte) in row.iter().enumerate() {
-                let channel = i % bytes_per_pixel;
-                let filtered_byte = byte.wrapping_sub(prev[channel]);
-                filtered.push(filtered_byte);
-                prev[channel] = byte;
-            }
+    fn filter_scanlines(
+        &self,
+        strategy: FilterStrategy,
+        progress: &mut ProgressHook,
+    ) -> Result<Vec<u8>, PngError> {
+        // Filters operate on whole bytes, so sub-byte pixels use a distance of 1
+        let bytes_per_pixel = self.bytes_per_pixel().max(1);
+        let rows = self.packed_rows();
+        filter::filter_rows(
+            strategy,
+            &rows,
+            self.packed_row_length(),
+            bytes_per_pixel,
+            progress,
+        )
+    }
+
+    // Filtered scanlines, and the same compressed, ready for IDAT (or fdAT)
+    fn filter_and_compress(
+        &self,
+        options: &EncodeOptions,
+        progress: &mut ProgressHook,
+    ) -> Result<(Vec<u8>, Vec<u8>), PngError> {
+        let row_length = self.packed_row_length();
+        if options.pipelined {
+            // Filters operate on whole bytes, so sub-byte pixels use a distance of 1
+            let bytes_per_pixel = self.bytes_per_pixel().max(1);
+            let rows = self.packed_rows();
+            return filter_and_compress_pipelined(
+                &rows,
+                row_length,
+                bytes_per_pixel,
+                options,
+                progress,
+            );
         }
-        filtered
+        let filtered = self.filter_scanlines(options.filter, progress)?;
+        let compressed = compress(&filtered, row_length, options, progress)?;
+        Ok((filtered, compressed))
+    }
+
+    // Filtered and zlib-compressed scanlines, ready for IDAT (or fdAT)
+    fn compress_image_data(&self, options: &EncodeOptions) -> Result<Vec<u8>, PngError> {
+        let (_, compressed) = self.filter_and_compress(options, &mut ProgressHook::new())?;
+        Ok(compressed)
     }
 
-    pub fn write_to_file<W: Write + Seek>(&self, writer: &mut W) -> Result<(), PngError> {
+    /// An upper bound on the size of the encoded stream with any options,
+    /// a preset dictionary included, computed without encoding, for
+    /// preallocating output.
+    pub fn encoded_size_bound(&self) -> u64 {
+        // Length, type, and CRC around each chunk's data
+        const OVERHEAD: u64 = 12;
+        let raw = (self.packed_row_length() as u64 + 1) * self.height as u64;
+        // zlib's compressBound, which covers falling back to stored blocks.
+        // miniz's fast levels only fall back within a 32 KiB window, so
+        // incompressible data can also grow by its Huffman tables, up to
+        // about 0.5%; and a preset dictionary adds its ID and the stored
+        // block that ends the primed part of the stream.
+        let compressed =
+            raw + (raw >> 7) + (raw >> 12) + (raw >> 14) + (raw >> 25) + 13 + DICTIONARY_OVERHEAD;
+        // Streamed output splits image data into smaller chunks
+        let idat_chunks = compressed / stream::IDAT_SIZE as u64 + 1;
+
+        let ancillary: u64 = self
+            .chunks
+            .iter()
+            .map(|c| OVERHEAD + c.data.len() as u64)
+            .sum();
+        let palette = self
+            .palette
+            .as_ref()
+            .map_or(0, |p| OVERHEAD + p.len() as u64);
+        PNG_SIGNATURE.len() as u64
+            + (OVERHEAD + 13)
+            + ancillary
+            + palette
+            + compressed
+            + idat_chunks * OVERHEAD
+            + OVERHEAD
+    }
+
+    /// Encodes the image with the options it was built with (the defaults
+    /// unless set through [`PngImage::builder`]).
+    pub fn write_to_file<W: Write>(&self, writer: &mut W) -> Result<(), PngError> {
+        self.write_with_options(writer, &self.options)
+    }
+
+    pub fn write_with_options<W: Write>(
+        &self,
+        writer: &mut W,
+        options: &EncodeOptions,
+    ) -> Result<(), PngError> {
+        self.write_with_report(writer, options).map(|_| ())
+    }
+
+    /// Encodes the image like [`PngImage::write_with_options`] and reports
+    /// the chunks written, the filters chosen, and how well the image data
+    /// compressed.
+    pub fn write_with_report<W: Write>(
+        &self,
+        writer: &mut W,
+        options: &EncodeOptions,
+    ) -> Result<EncodeReport, PngError> {
+        self.write_with_progress(writer, options, &mut ProgressHook::new())
+    }
+
+    /// Like [`PngImage::write_with_report`], reporting progress through
+    /// filtering and compression and stopping if `progress` is cancelled.
+    /// Nothing is written until both are done, except for images spilled to
+    /// disk, which are written as they compress, reporting every row as
+    /// filtering.
+    pub fn write_with_progress<W: Write>(
+        &self,
+        writer: &mut W,
+        options: &EncodeOptions,
+        progress: &mut ProgressHook,
+    ) -> Result<EncodeReport, PngError> {
+        self.encode(writer, options, progress, false)
+    }
+
+    // Encodes as `write_with_progress` does. `streamed` writes the image
+    // data as it compresses, as spilled images always are, rather than
+    // holding it whole until the end
+    pub(crate) fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+        options: &EncodeOptions,
+        progress: &mut ProgressHook,
+        streamed: bool,
+    ) -> Result<EncodeReport, PngError> {
+        let start = Instant::now();
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!(
+            "encode",
+            width = self.width,
+            height = self.height,
+            color_type = ?self.color_type,
+            bit_depth = self.bit_depth.bits()
+        )
+        .entered();
         if self.color_type == ColorType::Indexed {
             if self.palette.is_none() {
                 return Err(PngError::InvalidPalette(
@@ -143,27 +795,239 @@ impl PngImage {
                 ));
             }
             self.validate_palette_indices()?;
+
+            if options.optimize_palette {
+                let mut image = self.try_clone()?;
+                image.optimize_palette()?;
+                let options = EncodeOptions {
+                    optimize_palette: false,
+                    ..options.clone()
+                };
+                return image.encode(writer, &options, progress, streamed);
+            }
+        } else {
+            self.check_samples(&self.data)?;
+        }
+
+        if options.compression_level > 9 {
+            return Err(PngError::Compression(format!(
+                "Invalid compression level {}",
+                options.compression_level
+            )));
+        }
+
+        if options.strictness == Strictness::Strict {
+            if options.dictionary.is_some() {
+                return Err(PngError::StrictViolation(
+                    "PNG doesn't allow preset dictionaries".to_string(),
+                ));
+            }
+            self.check_strict()?;
+            // Encode in memory and prove the result decodes back to the same
+            // pixels before writing any of it
+            let options = EncodeOptions {
+                strictness: Strictness::Permissive,
+                ..options.clone()
+            };
+            let mut encoded = Vec::new();
+            let report = self.write_with_progress(&mut encoded, &options, progress)?;
+            self.verify_encoding(&encoded)?;
+            writer.write_all(&encoded)?;
+            return Ok(report);
+        }
+        #[cfg(feature = "mmap")]
+        let streamed = streamed || self.is_spilled();
+        if streamed {
+            return self.write_streamed(writer, options, progress);
         }
+        let row_length = self.packed_row_length();
+        let (filtered, compressed) = self.filter_and_compress(options, progress)?;
 
         // Write PNG signature
-        writer.write_all(&[137, 80, 78, 71, 13, 10, 26, 10])?;
+        writer.write_all(&PNG_SIGNATURE)?;
+
+        let mut summaries = Vec::new();
+        let mut offset = PNG_SIGNATURE.len() as u64;
+        let mut write = |chunk_type: &[u8; 4], data: &[u8]| -> Result<(), PngError> {
+            ChunkWriter::write_chunk(writer, chunk_type, data)?;
+            summaries.push(ChunkSummary {
+                chunk_type: *chunk_type,
+                length: data.len() as u32,
+                offset,
+            });
+            // Length, type, and CRC around the data
+            offset += 12 + data.len() as u64;
+            Ok(())
+        };
+
+        for (chunk_type, data) in self.chunks_before_data(options) {
+            write(&chunk_type, &data)?;
+        }
+
+        // Image data, split if it won't fit in one chunk
+        for part in compressed.chunks(MAX_CHUNK_LENGTH as usize) {
+            write(b"IDAT", part)?;
+        }
+        write(b"IEND", &[])?;
+
+        let mut filters = [0; 5];
+        for line in filtered.chunks(row_length + 1) {
+            filters[line[0] as usize] += 1;
+        }
+        Ok(EncodeReport {
+            chunks: summaries,
+            filters,
+            raw_bytes: filtered.len(),
+            compressed_bytes: compressed.len(),
+            total_bytes: offset,
+            elapsed: start.elapsed(),
+        })
+    }
 
-        // Write IHDR chunk
-        let ihdr_data = self.generate_ihdr();
-        ChunkWriter::write_chunk(writer, b"IHDR", &ihdr_data)?;
+    // IHDR, then the chunks that go before the image data, in order.
+    // Permissive mode drops what can't be stored rather than failing.
+    pub(crate) fn chunks_before_data(
+        &self,
+        options: &EncodeOptions,
+    ) -> Vec<([u8; 4], Cow<'_, [u8]>)> {
+        let mut chunks: Vec<&Chunk> = self
+            .chunks
+            .iter()
+            .filter(|c| c.data.len() <= MAX_CHUNK_LENGTH as usize)
+            .filter(|c| !(options.deterministic && &c.chunk_type == b"tIME"))
+            .collect();
+        if options.deterministic {
+            chunks.sort_by_key(|c| c.chunk_type);
+        }
+        // These refer to palette entries, so they must follow PLTE
+        let (after_palette, before_palette): (Vec<&Chunk>, Vec<&Chunk>) = chunks
+            .into_iter()
+            .partition(|c| follows_palette(&c.chunk_type));
 
+        let mut ordered = vec![(*b"IHDR", Cow::Owned(self.generate_ihdr()))];
+        for chunk in before_palette {
+            ordered.push((chunk.chunk_type, Cow::Borrowed(&chunk.data[..])));
+        }
         if let Some(palette) = &self.palette {
-            ChunkWriter::write_chunk(writer, b"PLTE", palette)?;
+            ordered.push((*b"PLTE", Cow::Borrowed(&palette[..])));
+        }
+        for chunk in after_palette {
+            ordered.push((chunk.chunk_type, Cow::Borrowed(&chunk.data[..])));
         }
+        ordered
+    }
+
+    /// Adds an ancillary chunk to be written with the image. Chunks that
+    /// must follow the palette (bKGD, hIST, tRNS) are placed after PLTE and
+    /// everything else before it.
+    ///
+    /// Fails rather than queue a chunk that would make the file invalid: a
+    /// second copy of a chunk allowed only once, sRGB alongside iCCP, or a
+    /// text entry reusing a keyword.
+    pub fn add_chunk(&mut self, chunk_type: [u8; 4], data: Vec<u8>) -> Result<(), PngError> {
+        check_ancillary(&chunk_type)?;
+        self.check_queued(&chunk_type, &data)?;
+        self.chunks.push(Chunk::new(chunk_type, data));
+        Ok(())
+    }
 
-        // Process image data
-        let filtered = self.filter_scanlines();
-        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
-        encoder.write_all(&filtered)?;
-        let compressed = encoder.finish()?;
-        ChunkWriter::write_chunk(writer, b"IDAT", &compressed)?;
-        ChunkWriter::write_chunk(writer, b"IEND", &[])?;
+    fn check_queued(&self, chunk_type: &[u8; 4], data: &[u8]) -> Result<(), PngError> {
+        let name = String::from_utf8_lossy(chunk_type);
+        if is_text_chunk(chunk_type) {
+            let keyword = TextChunk::parse(chunk_type, data)?.keyword;
+            let queued = self
+                .chunks
+                .iter()
+                .filter(|c| is_text_chunk(&c.chunk_type))
+                .filter_map(|c| TextChunk::parse(&c.chunk_type, &c.data).ok());
+            for text in queued {
+                if text.keyword == keyword {
+                    return Err(PngError::InvalidMetadata(format!(
+                        "text keyword '{}' is already used",
+                        keyword
+                    )));
+                }
+            }
+        } else if single_instance(chunk_type)
+            && self.chunks.iter().any(|c| &c.chunk_type == chunk_type)
+        {
+            return Err(PngError::InvalidMetadata(format!(
+                "{} chunk is already present",
+                name
+            )));
+        }
 
+        let conflict: Option<&[u8; 4]> = match chunk_type {
+            b"sRGB" => Some(b"iCCP"),
+            b"iCCP" => Some(b"sRGB"),
+            _ => None,
+        };
+        if let Some(other) = conflict.filter(|t| self.chunks.iter().any(|c| &c.chunk_type == *t)) {
+            return Err(PngError::InvalidMetadata(format!(
+                "{} chunk conflicts with {}",
+                name,
+                String::from_utf8_lossy(other)
+            )));
+        }
+        Ok(())
+    }
+
+    /// Marks the image as a side-by-side stereo pair, replacing any earlier
+    /// sTER chunk.
+    pub fn set_stereo(&mut self, layout: StereoLayout) {
+        self.chunks.retain(|c| &c.chunk_type != b"sTER");
+        self.chunks
+            .push(Chunk::new(*b"sTER", layout.to_bytes().to_vec()));
+    }
+
+    /// Adds a tEXt, zTXt, or iTXt entry.
+    pub fn add_text(&mut self, text: &TextChunk) -> Result<(), PngError> {
+        let data = text.encode()?;
+        self.add_chunk(text.chunk_type(), data)
+    }
+
+    fn check_strict(&self) -> Result<(), PngError> {
+        let mut seen = Vec::new();
+        for chunk in &self.chunks {
+            let name = chunk.type_str();
+            if chunk.data.len() > MAX_CHUNK_LENGTH as usize {
+                return Err(PngError::StrictViolation(format!(
+                    "{} chunk is too long to store",
+                    name
+                )));
+            }
+            if is_text_chunk(&chunk.chunk_type) {
+                let text = TextChunk::parse(&chunk.chunk_type, &chunk.data)?;
+                if !REGISTERED_KEYWORDS.contains(&text.keyword.as_str()) {
+                    return Err(PngError::StrictViolation(format!(
+                        "unregistered text keyword '{}'",
+                        text.keyword
+                    )));
+                }
+            } else if chunk.chunk_type != *b"sPLT" {
+                if seen.contains(&chunk.chunk_type) {
+                    return Err(PngError::StrictViolation(format!(
+                        "more than one {} chunk",
+                        name
+                    )));
+                }
+                seen.push(chunk.chunk_type);
+            }
+        }
+
+        if seen.contains(b"sRGB") && seen.contains(b"iCCP") {
+            return Err(PngError::StrictViolation(
+                "sRGB and iCCP chunks both describe the color space".to_string(),
+            ));
+        }
+        if !seen
+            .iter()
+            .any(|t| matches!(t, b"sRGB" | b"gAMA" | b"iCCP"))
+        {
+            return Err(PngError::StrictViolation(
+                "no sRGB, gAMA, or iCCP chunk describes the color space".to_string(),
+            ));
+        }
         Ok(())
     }
 
@@ -172,7 +1036,7 @@ impl PngImage {
             return Err(PngError::ColorTypeError);
         }
 
-        if palette.len() % 3 != 0 {
+        if !palette.len().is_multiple_of(3) {
             return Err(PngError::InvalidPalette(
                 "Palette must contain RGB triplets".to_string(),
             ));
@@ -188,15 +1052,362 @@ impl PngImage {
         Ok(())
     }
 
-    fn validate_palette_indices(&self) -> Result<(), PngError> {
-        if let Some(palette) = &self.palette {
-            let max_index = (palette.len() / 3).saturating_sub(1);
-            for &index in &self.data {
-                if index as usize > max_index {
-                    return Err(PngError::InvalidPaletteEntry(index));
+    /// Sets an opaque palette, removing any palette transparency.
+    pub fn set_palette_rgb(&mut self, entries: &[(u8, u8, u8)]) -> Result<(), PngError> {
+        let palette: Vec<u8> = entries.iter().flat_map(|&(r, g, b)| [r, g, b]).collect();
+        self.set_palette(&palette)?;
+        self.chunks.retain(|c| &c.chunk_type != b"tRNS");
+        Ok(())
+    }
+
+    /// Sets a palette with per-entry alpha, stored in a tRNS chunk unless
+    /// every entry is opaque.
+    pub fn set_palette_rgba(&mut self, entries: &[(u8, u8, u8, u8)]) -> Result<(), PngError> {
+        let rgb: Vec<(u8, u8, u8)> = entries.iter().map(|&(r, g, b, _)| (r, g, b)).collect();
+        self.set_palette_rgb(&rgb)?;
+
+        // Entries past the end of tRNS are opaque, so trailing ones are omitted
+        let mut alpha: Vec<u8> = entries.iter().map(|&(_, _, _, a)| a).collect();
+        while alpha.last() == Some(&255) {
+            alpha.pop();
+        }
+        if !alpha.is_empty() {
+            self.chunks.push(Chunk::new(*b"tRNS", alpha));
+        }
+        Ok(())
+    }
+
+    /// The palette entries with their alpha, or `None` if no palette is set.
+    pub fn palette(&self) -> Option<Vec<(u8, u8, u8, u8)>> {
+        let palette = self.palette.as_ref()?;
+        let alpha = self.palette_alpha();
+        Some(
+            palette
+                .chunks_exact(3)
+                .enumerate()
+                .map(|(i, rgb)| (rgb[0], rgb[1], rgb[2], alpha.get(i).copied().unwrap_or(255)))
+                .collect(),
+        )
+    }
+
+    // Alpha for the leading palette entries, from the tRNS chunk if any
+    fn palette_alpha(&self) -> &[u8] {
+        self.chunks
+            .iter()
+            .find(|c| &c.chunk_type == b"tRNS")
+            .map_or(&[], |c| c.data.as_slice())
+    }
+
+    pub(crate) fn validate_palette_indices(&self) -> Result<(), PngError> {
+        // Indices are stored a byte each whatever the bit depth, so one too
+        // large to pack would pass a 256-entry palette check
+        self.check_samples(&self.data)?;
+        match &self.palette {
+            Some(palette) => self.check_indices(palette.len() / 3),
+            None => Ok(()),
+        }
+    }
+
+    // Reports the first index that doesn't fit a palette of `entries`
+    // colors, along with how many pixels are out of range
+    fn check_indices(&self, entries: usize) -> Result<(), PngError> {
+        let mut bad = self
+            .data
+            .iter()
+            .enumerate()
+            .filter(|&(_, &index)| index as usize >= entries);
+        let Some((offset, &index)) = bad.next() else {
+            return Ok(());
+        };
+
+        let width = self.width as usize;
+        Err(PngError::InvalidPaletteEntry {
+            index,
+            x: (offset % width) as u32,
+            y: (offset / width) as u32,
+            offset,
+            count: 1 + bad.count(),
+            palette_entries: entries,
+        })
+    }
+
+    /// Rescales every sample to `bit_depth`. Indexed images keep their
+    /// indices unchanged, so reducing their depth fails if any index doesn't
+    /// fit.
+    pub fn convert_bit_depth(&mut self, bit_depth: BitDepth) -> Result<(), PngError> {
+        if !self.color_type.allows_bit_depth(bit_depth) {
+            return Err(PngError::InvalidBitDepth {
+                bit_depth: bit_depth.bits(),
+                color_type: self.color_type,
+            });
+        }
+        if bit_depth == self.bit_depth {
+            return Ok(());
+        }
+
+        let from = self.bit_depth;
+        let samples: Vec<u16> = match from {
+            BitDepth::Sixteen => self
+                .data
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect(),
+            _ => self.data.iter().map(|&b| b as u16).collect(),
+        };
+
+        let to_max = bit_depth.max_value() as u32;
+        let from_max = from.max_value() as u32;
+        let mut data = Vec::with_capacity(samples.len() * bit_depth.bytes_per_sample());
+        for sample in samples {
+            let value = if self.color_type == ColorType::Indexed {
+                if sample as u32 > to_max {
+                    return Err(PngError::SampleOutOfRange {
+                        value: sample,
+                        bit_depth: bit_depth.bits(),
+                    });
                 }
+                sample
+            } else {
+                // Round to the nearest representable value
+                ((sample as u32 * to_max + from_max / 2) / from_max) as u16
+            };
+            match bit_depth {
+                BitDepth::Sixteen => data.extend_from_slice(&value.to_be_bytes()),
+                _ => data.push(value as u8),
             }
         }
+
+        self.data.replace(data)?;
+        self.bit_depth = bit_depth;
         Ok(())
     }
+
+    /// Changes the color type, expanding or dropping channels as needed.
+    /// Indexed images are expanded through their palette, to RGBA if it has
+    /// transparent entries and RGB otherwise; converting to
+    /// `Indexed` is not supported. Dropped alpha is discarded, not composited;
+    /// see [`PngImage::convert_to_over`].
+    pub fn convert_to(&mut self, color_type: ColorType) -> Result<(), PngError> {
+        self.convert(color_type, None)
+    }
+
+    /// Like [`PngImage::convert_to`], but when converting to a color type
+    /// without alpha each pixel is composited over `background` instead of
+    /// having its alpha discarded. The background's own alpha is ignored.
+    pub fn convert_to_over(
+        &mut self,
+        color_type: ColorType,
+        background: Color,
+    ) -> Result<(), PngError> {
+        let (r, g, b, _) = background.to_rgba().ok_or(PngError::ColorTypeError)?;
+        self.convert(color_type, Some([r, g, b]))
+    }
+
+    fn convert(
+        &mut self,
+        color_type: ColorType,
+        background: Option<[u8; 3]>,
+    ) -> Result<(), PngError> {
+        if color_type == self.color_type {
+            return Ok(());
+        }
+        if color_type == ColorType::Indexed {
+            return Err(PngError::ColorTypeError);
+        }
+
+        if self.color_type == ColorType::Indexed {
+            self.expand_palette()?;
+        }
+        if self.bit_depth.bits() < 8 && color_type != ColorType::Grayscale {
+            self.convert_bit_depth(BitDepth::Eight)?;
+        }
+        if color_type == self.color_type {
+            return Ok(());
+        }
+
+        let max = self.bit_depth.max_value();
+        let sixteen = self.bit_depth == BitDepth::Sixteen;
+        let sample_size = self.bit_depth.bytes_per_sample();
+        let from = self.color_type;
+        // Composite only when alpha is actually being dropped
+        let background = background
+            .filter(|_| {
+                matches!(from, ColorType::GrayscaleAlpha | ColorType::Rgba)
+                    && matches!(color_type, ColorType::Grayscale | ColorType::Rgb)
+            })
+            .map(|rgb| rgb.map(|v| (v as u32 * max as u32 + 127) / 255));
+        let read = |bytes: &[u8], i: usize| -> u16 {
+            if sixteen {
+                u16::from_be_bytes([bytes[i * 2], bytes[i * 2 + 1]])
+            } else {
+                bytes[i] as u16
+            }
+        };
+
+        if self.bit_depth == BitDepth::Eight && background.is_none() {
+            let data = convert_channels(&self.data, from.channels(), color_type.channels());
+            self.data.replace(data)?;
+            self.color_type = color_type;
+            return Ok(());
+        }
+
+        let mut data =
+            Vec::with_capacity(self.data.len() / from.channels() * color_type.channels());
+        for pixel in self.data.chunks_exact(from.channels() * sample_size) {
+            let (r, g, b, a) = match from {
+                ColorType::Grayscale => {
+                    let v = read(pixel, 0);
+                    (v, v, v, max)
+                }
+                ColorType::GrayscaleAlpha => {
+                    let v = read(pixel, 0);
+                    (v, v, v, read(pixel, 1))
+                }
+                ColorType::Rgb => (read(pixel, 0), read(pixel, 1), read(pixel, 2), max),
+                _ => (
+                    read(pixel, 0),
+                    read(pixel, 1),
+                    read(pixel, 2),
+                    read(pixel, 3),
+                ),
+            };
+            let (r, g, b) = match background {
+                Some([br, bg, bb]) => {
+                    let (a, max) = (a as u32, max as u32);
+                    let over = |c: u16, back: u32| {
+                        ((c as u32 * a + back * (max - a) + max / 2) / max) as u16
+                    };
+                    (over(r, br), over(g, bg), over(b, bb))
+                }
+                None => (r, g, b),
+            };
+            // Rec. 601 luma weights
+            let gray = || ((r as u32 * 299 + g as u32 * 587 + b as u32 * 114 + 500) / 1000) as u16;
+            let samples: &[u16] = match color_type {
+                ColorType::Grayscale => &[gray()],
+                ColorType::GrayscaleAlpha => &[gray(), a],
+                ColorType::Rgb => &[r, g, b],
+                _ => &[r, g, b, a],
+            };
+            for &sample in samples {
+                if sixteen {
+                    data.extend_from_slice(&sample.to_be_bytes());
+                } else {
+                    data.push(sample as u8);
+                }
+            }
+        }
+
+        self.data.replace(data)?;
+        self.color_type = color_type;
+        Ok(())
+    }
+
+    // Replaces palette indices with their RGB entries at 8 bits per sample,
+    // or RGBA entries if the palette has transparency
+    pub(crate) fn expand_palette(&mut self) -> Result<(), PngError> {
+        let Some(palette) = &self.palette else {
+            return Err(PngError::InvalidPalette(
+                "Palette required for indexed color".to_string(),
+            ));
+        };
+        self.check_indices(palette.len() / 3)?;
+
+        let alpha = self.palette_alpha();
+        let color_type = if alpha.is_empty() {
+            ColorType::Rgb
+        } else {
+            ColorType::Rgba
+        };
+        let mut data = Vec::with_capacity(self.data.len() * color_type.channels());
+        for &index in self.data.iter() {
+            let start = index as usize * 3;
+            data.extend_from_slice(&palette[start..start + 3]);
+            if color_type == ColorType::Rgba {
+                data.push(alpha.get(index as usize).copied().unwrap_or(255));
+            }
+        }
+
+        self.palette = None;
+        self.chunks.retain(|c| &c.chunk_type != b"tRNS");
+        self.data.replace(data)?;
+        self.color_type = color_type;
+        self.bit_depth = BitDepth::Eight;
+        Ok(())
+    }
+}
+
+// 8-bit conversion between channel counts without compositing, with a copy
+// of the loop for each pair so the compiler can unroll the pixel accesses
+fn convert_channels(data: &[u8], from: usize, to: usize) -> Vec<u8> {
+    match (from, to) {
+        (1, 2) => convert_pixels::<1, 2>(data),
+        (1, 3) => convert_pixels::<1, 3>(data),
+        (1, 4) => convert_pixels::<1, 4>(data),
+        (2, 1) => convert_pixels::<2, 1>(data),
+        (2, 3) => convert_pixels::<2, 3>(data),
+        (2, 4) => convert_pixels::<2, 4>(data),
+        (3, 1) => convert_pixels::<3, 1>(data),
+        (3, 2) => convert_pixels::<3, 2>(data),
+        (3, 4) => convert_pixels::<3, 4>(data),
+        (4, 1) => convert_pixels::<4, 1>(data),
+        (4, 2) => convert_pixels::<4, 2>(data),
+        (4, 3) => convert_pixels::<4, 3>(data),
+        _ => data.to_vec(),
+    }
+}
+
+fn convert_pixels<const FROM: usize, const TO: usize>(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() / FROM * TO);
+    for pixel in data.chunks_exact(FROM) {
+        let (r, g, b, a) = match *pixel {
+            [v] => (v, v, v, u8::MAX),
+            [v, a] => (v, v, v, a),
+            [r, g, b] => (r, g, b, u8::MAX),
+            [r, g, b, a, ..] => (r, g, b, a),
+            _ => unreachable!(),
+        };
+        // Rec. 601 luma weights, as in convert_to
+        let gray = || ((r as u32 * 299 + g as u32 * 587 + b as u32 * 114 + 500) / 1000) as u8;
+        match TO {
+            1 => out.push(gray()),
+            2 => out.extend_from_slice(&[gray(), a]),
+            3 => out.extend_from_slice(&[r, g, b]),
+            _ => out.extend_from_slice(&[r, g, b, a]),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_images::noise;
+
+    #[test]
+    fn encoded_size_bound_holds_for_incompressible_data() {
+        let dictionary = CompressionDictionary::from_bytes(&[7; 40000]);
+        for (width, height) in [(1, 1), (1, 300), (40, 30), (300, 200)] {
+            let image = noise(width, height, ColorType::Rgba, BitDepth::Eight, 7);
+            for level in 0..=9 {
+                for dictionary in [None, Some(dictionary.clone())] {
+                    let options = EncodeOptions {
+                        compression_level: level,
+                        dictionary,
+                        ..EncodeOptions::default()
+                    };
+                    let mut encoded = Vec::new();
+                    image.write_with_options(&mut encoded, &options).unwrap();
+                    assert!(encoded.len() as u64 <= image.encoded_size_bound());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn image_size_fails_instead_of_overflowing() {
+        let error = PngImage::new(0x7FFF_FFFF, 0x7FFF_FFFF, ColorType::Rgba).unwrap_err();
+        assert!(matches!(error, PngError::ImageTooLarge { .. }));
+        assert!(PngImage::new(0x8000_0000, 1, ColorType::Rgb).is_err());
+    }
 }
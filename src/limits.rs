@@ -0,0 +1,111 @@
+use crate::error::PngError;
+
+/// Caps on what a file can make [`Decoder`](crate::Decoder) and
+/// [`PngInfo::read`](crate::PngInfo::read) work through, so that files
+/// claiming enormous dimensions, with huge numbers of small chunks, or with
+/// text that decompresses to gigabytes, fail quickly instead of tying up
+/// the reader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeLimits {
+    /// Width times height of the image, checked against IHDR before any
+    /// pixels are allocated. [`PngInfo`](crate::PngInfo) allocates none, so
+    /// it doesn't check this.
+    pub max_pixels: u64,
+    /// Ancillary chunks of any type, including APNG frame chunks
+    pub max_ancillary_chunks: usize,
+    /// Total size of the text in tEXt, zTXt, and iTXt chunks, counted after
+    /// decompression. Compressed text is inflated no further than the limit,
+    /// so a small chunk can't expand to gigabytes on the way.
+    pub max_text_bytes: usize,
+}
+
+impl DecodeLimits {
+    pub const UNLIMITED: Self = Self {
+        max_pixels: u64::MAX,
+        max_ancillary_chunks: usize::MAX,
+        max_text_bytes: usize::MAX,
+    };
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self {
+            // Enough for a 32768x32768 image
+            max_pixels: 1 << 30,
+            max_ancillary_chunks: 100_000,
+            max_text_bytes: 16 << 20,
+        }
+    }
+}
+
+impl DecodeLimits {
+    pub(crate) fn check_dimensions(&self, width: u32, height: u32) -> Result<(), PngError> {
+        let pixels = width as u64 * height as u64;
+        if pixels > self.max_pixels {
+            return Err(PngError::LimitExceeded(format!(
+                "{}x{} is more than {} pixels",
+                width, height, self.max_pixels
+            )));
+        }
+        Ok(())
+    }
+}
+
+// Running totals checked against a set of limits as chunks are read
+pub(crate) struct LimitCounter {
+    limits: DecodeLimits,
+    ancillary_chunks: usize,
+    text_bytes: usize,
+}
+
+impl LimitCounter {
+    pub(crate) fn new(limits: DecodeLimits) -> Self {
+        Self {
+            limits,
+            ancillary_chunks: 0,
+            text_bytes: 0,
+        }
+    }
+
+    pub(crate) fn count_chunk(&mut self, chunk_type: [u8; 4], offset: u64) -> Result<(), PngError> {
+        if chunk_type[0].is_ascii_uppercase() {
+            return Ok(());
+        }
+        self.ancillary_chunks += 1;
+        if self.ancillary_chunks > self.limits.max_ancillary_chunks {
+            return Err(PngError::LimitExceeded(format!(
+                "more than {} ancillary chunks",
+                self.limits.max_ancillary_chunks
+            ))
+            .at(offset, Some(chunk_type)));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn count_text(
+        &mut self,
+        chunk_type: [u8; 4],
+        offset: u64,
+        length: usize,
+    ) -> Result<(), PngError> {
+        self.text_bytes = self.text_bytes.saturating_add(length);
+        if self.text_bytes > self.limits.max_text_bytes {
+            return Err(PngError::LimitExceeded(format!(
+                "more than {} bytes of text",
+                self.limits.max_text_bytes
+            ))
+            .at(offset, Some(chunk_type)));
+        }
+        Ok(())
+    }
+
+    // How much more text fits; decompressing one byte past this is enough
+    // to know the limit is exceeded
+    pub(crate) fn text_remaining(&self) -> usize {
+        self.limits.max_text_bytes - self.text_bytes
+    }
+
+    pub(crate) fn limits(&self) -> &DecodeLimits {
+        &self.limits
+    }
+}
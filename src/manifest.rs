@@ -0,0 +1,605 @@
+use std::fmt::Write as _;
+
+use crate::chunks::{Chunk, ChunkReader};
+use crate::error::PngError;
+use crate::info::ImageHeader;
+use crate::metadata::{PhysicalDimensions, PixelUnit, Timestamp};
+use crate::text::{is_text_chunk, TextChunk, TextKind};
+use crate::{ColorType, PngImage};
+
+/// Describes every chunk of a PNG stream as JSON, for debugging and for
+/// writing test fixtures by hand. Each chunk gets its type, offset, length,
+/// CRC, and whether the CRC is correct; all but the image data also get
+/// their bytes in hex. IHDR, PLTE, text, tIME, pHYs, gAMA, and sRGB chunks
+/// get their values under `decoded` as well. Chunks that don't parse are
+/// listed without `decoded`.
+///
+/// ```text
+/// {
+///   "chunks": [
+///     {"type": "IHDR", "offset": 8, "length": 13, "crc": "0a1b2c3d", "crc_ok": true,
+///      "decoded": {"width": 16, ...}, "data": "00000010..."},
+///     ...
+///   ]
+/// }
+/// ```
+pub fn chunk_manifest(png: &[u8]) -> Result<String, PngError> {
+    let mut entries = Vec::new();
+    for chunk in ChunkReader::new(png)? {
+        let chunk = chunk?;
+        let mut entry = format!(
+            "{{\"type\": {}, \"offset\": {}, \"length\": {}, \"crc\": \"{:08x}\", \"crc_ok\": {}",
+            json_string(&chunk.type_str()),
+            chunk.offset,
+            chunk.data.len(),
+            chunk.crc,
+            chunk.crc_ok()
+        );
+        if let Some(decoded) = decode(&chunk) {
+            let _ = write!(entry, ", \"decoded\": {}", decoded);
+        }
+        if !matches!(&chunk.chunk_type, b"IDAT" | b"fdAT") {
+            let _ = write!(entry, ", \"data\": \"{}\"", hex(&chunk.data));
+        }
+        entry.push('}');
+        entries.push(entry);
+    }
+    Ok(format!(
+        "{{\n  \"chunks\": [\n    {}\n  ]\n}}\n",
+        entries.join(",\n    ")
+    ))
+}
+
+/// Builds an image from a manifest in the form [`chunk_manifest`] writes
+/// and pixel data laid out as for [`PngImage::data`]. The header comes from
+/// the IHDR entry and the palette from PLTE. Each ancillary chunk is taken
+/// from its `data` if present, or else built from `decoded` for the types
+/// [`chunk_manifest`] decodes. Offsets, lengths, and CRCs are ignored, as
+/// are IDAT, IEND, and animation chunks; interlacing isn't carried over.
+pub fn image_from_manifest(manifest: &str, pixels: &[u8]) -> Result<PngImage, PngError> {
+    let root = Json::parse(manifest)?;
+    let entries = root
+        .get("chunks")
+        .and_then(Json::as_array)
+        .ok_or_else(|| invalid("missing \"chunks\" array"))?;
+
+    let mut header = None;
+    let mut palette = None;
+    let mut chunks = Vec::new();
+    for entry in entries {
+        let name = entry
+            .get("type")
+            .and_then(Json::as_str)
+            .ok_or_else(|| invalid("chunk without a \"type\""))?;
+        let chunk_type: [u8; 4] = name
+            .as_bytes()
+            .try_into()
+            .ok()
+            .filter(|t: &[u8; 4]| t.iter().all(u8::is_ascii_alphabetic))
+            .ok_or_else(|| invalid(format!("invalid chunk type {:?}", name)))?;
+        let data = match entry.get("data").and_then(Json::as_str) {
+            Some(hex) => Some(unhex(hex).ok_or_else(|| invalid(format!("bad hex in {}", name)))?),
+            None => None,
+        };
+        let decoded = entry.get("decoded");
+
+        match &chunk_type {
+            b"IHDR" => {
+                header = Some(match (&data, decoded) {
+                    (Some(data), _) => ImageHeader::parse(data)?,
+                    (None, Some(decoded)) => header_from_json(decoded)?,
+                    (None, None) => return Err(invalid("IHDR has no data or decoded values")),
+                });
+            }
+            b"PLTE" => palette = Some(data.ok_or_else(|| invalid("PLTE has no data"))?),
+            b"IDAT" | b"IEND" | b"CgBI" | b"acTL" | b"fcTL" | b"fdAT" => {}
+            _ if chunk_type[0].is_ascii_uppercase() => {
+                return Err(invalid(format!("unsupported critical chunk {}", name)));
+            }
+            _ => {
+                let data = match (data, decoded) {
+                    (Some(data), _) => data,
+                    (None, Some(decoded)) => encode(&chunk_type, decoded)?,
+                    (None, None) => {
+                        return Err(invalid(format!("{} has no data or decoded values", name)))
+                    }
+                };
+                chunks.push(Chunk::new(chunk_type, data));
+            }
+        }
+    }
+
+    let header = header.ok_or(PngError::MissingChunk {
+        chunk_type: *b"IHDR",
+    })?;
+    let mut image = PngImage::with_bit_depth(
+        header.width,
+        header.height,
+        header.color_type,
+        header.bit_depth,
+    )?;
+    if let Some(palette) = palette {
+        image.set_palette(&palette)?;
+    }
+    for chunk in chunks {
+        image.add_chunk(chunk.chunk_type, chunk.data)?;
+    }
+    let row_length = image.width as usize * image.bytes_per_pixel();
+    image.copy_from_slice_with_stride(pixels, row_length)?;
+    Ok(image)
+}
+
+fn invalid(message: impl Into<String>) -> PngError {
+    PngError::InvalidManifest(message.into())
+}
+
+fn color_type_name(color_type: ColorType) -> &'static str {
+    match color_type {
+        ColorType::Grayscale => "grayscale",
+        ColorType::Rgb => "rgb",
+        ColorType::GrayscaleAlpha => "grayscale-alpha",
+        ColorType::Rgba => "rgba",
+        ColorType::Indexed => "indexed",
+    }
+}
+
+// The chunk's values as a JSON object, for the types that have any
+fn decode(chunk: &Chunk) -> Option<String> {
+    let data = &chunk.data;
+    match &chunk.chunk_type {
+        b"IHDR" => {
+            let header = ImageHeader::parse(data).ok()?;
+            Some(format!(
+                "{{\"width\": {}, \"height\": {}, \"bit_depth\": {}, \"color_type\": \"{}\", \"interlaced\": {}}}",
+                header.width,
+                header.height,
+                header.bit_depth.bits(),
+                color_type_name(header.color_type),
+                header.interlaced
+            ))
+        }
+        b"PLTE" => Some(format!("{{\"entries\": {}}}", data.len() / 3)),
+        t if is_text_chunk(t) => {
+            let text = TextChunk::parse(t, data).ok()?;
+            let mut out = format!(
+                "{{\"keyword\": {}, \"text\": {}",
+                json_string(&text.keyword),
+                json_string(&text.text)
+            );
+            if text.kind == TextKind::International {
+                let _ = write!(
+                    out,
+                    ", \"language\": {}, \"translated_keyword\": {}",
+                    json_string(&text.language),
+                    json_string(&text.translated_keyword)
+                );
+            }
+            out.push('}');
+            Some(out)
+        }
+        b"tIME" => {
+            let time = Timestamp::parse(data).ok()?;
+            Some(format!("{{\"time\": \"{}\"}}", time))
+        }
+        b"pHYs" => {
+            let physical = PhysicalDimensions::parse(data).ok()?;
+            let unit = match physical.unit {
+                PixelUnit::Unknown => "unknown",
+                PixelUnit::Meter => "meter",
+            };
+            Some(format!(
+                "{{\"x\": {}, \"y\": {}, \"unit\": \"{}\"}}",
+                physical.x, physical.y, unit
+            ))
+        }
+        b"gAMA" => {
+            let bytes: [u8; 4] = data.as_slice().try_into().ok()?;
+            let gamma = u32::from_be_bytes(bytes) as f64 / 100_000.0;
+            Some(format!("{{\"gamma\": {}}}", gamma))
+        }
+        b"sRGB" if data.len() == 1 => Some(format!("{{\"intent\": {}}}", data[0])),
+        _ => None,
+    }
+}
+
+// The reverse of `decode`, for ancillary chunks
+fn encode(chunk_type: &[u8; 4], decoded: &Json) -> Result<Vec<u8>, PngError> {
+    let name = String::from_utf8_lossy(chunk_type);
+    let string = |key: &str| decoded.get(key).and_then(Json::as_str);
+    let number = |key: &str| {
+        decoded
+            .get(key)
+            .and_then(Json::as_f64)
+            .ok_or_else(|| invalid(format!("{} needs a numeric \"{}\"", name, key)))
+    };
+    let integer = |key: &str| {
+        let value = number(key)?;
+        if value.fract() == 0.0 && (0.0..=u32::MAX as f64).contains(&value) {
+            Ok(value as u32)
+        } else {
+            Err(invalid(format!("{} \"{}\" is out of range", name, key)))
+        }
+    };
+
+    match chunk_type {
+        t if is_text_chunk(t) => {
+            let kind = match t {
+                b"tEXt" => TextKind::Plain,
+                b"zTXt" => TextKind::Compressed,
+                _ => TextKind::International,
+            };
+            let text = TextChunk {
+                keyword: string("keyword")
+                    .ok_or_else(|| invalid(format!("{} needs a \"keyword\"", name)))?
+                    .to_string(),
+                text: string("text").unwrap_or_default().to_string(),
+                kind,
+                language: string("language").unwrap_or_default().to_string(),
+                translated_keyword: string("translated_keyword").unwrap_or_default().to_string(),
+            };
+            text.encode()
+        }
+        b"tIME" => {
+            let time: Timestamp = string("time")
+                .ok_or_else(|| invalid("tIME needs a \"time\""))?
+                .parse()?;
+            Ok(time.to_bytes().to_vec())
+        }
+        b"pHYs" => {
+            let unit = match string("unit") {
+                Some("meter") => PixelUnit::Meter,
+                Some("unknown") | None => PixelUnit::Unknown,
+                Some(unit) => return Err(invalid(format!("unknown pHYs unit {:?}", unit))),
+            };
+            let physical = PhysicalDimensions {
+                x: integer("x")?,
+                y: integer("y")?,
+                unit,
+            };
+            Ok(physical.to_bytes().to_vec())
+        }
+        b"gAMA" => {
+            let gamma = (number("gamma")? * 100_000.0).round();
+            if !(0.0..=u32::MAX as f64).contains(&gamma) {
+                return Err(invalid("gAMA \"gamma\" is out of range"));
+            }
+            Ok((gamma as u32).to_be_bytes().to_vec())
+        }
+        b"sRGB" => {
+            let intent = integer("intent")?;
+            u8::try_from(intent)
+                .map(|intent| vec![intent])
+                .map_err(|_| invalid("sRGB \"intent\" is out of range"))
+        }
+        _ => Err(invalid(format!(
+            "{} can't be built from decoded values",
+            name
+        ))),
+    }
+}
+
+fn header_from_json(decoded: &Json) -> Result<ImageHeader, PngError> {
+    let number = |key: &str| {
+        decoded
+            .get(key)
+            .and_then(Json::as_f64)
+            .filter(|v| v.fract() == 0.0 && (0.0..=u32::MAX as f64).contains(v))
+            .map(|v| v as u32)
+            .ok_or_else(|| invalid(format!("IHDR needs a whole number \"{}\"", key)))
+    };
+    let color_type = match decoded.get("color_type").and_then(Json::as_str) {
+        Some("grayscale") => 0,
+        Some("rgb") => 2,
+        Some("indexed") => 3,
+        Some("grayscale-alpha") => 4,
+        Some("rgba") => 6,
+        other => return Err(invalid(format!("IHDR has unknown color type {:?}", other))),
+    };
+    let bit_depth = u8::try_from(number("bit_depth")?).unwrap_or(0);
+    let interlaced = decoded
+        .get("interlaced")
+        .and_then(Json::as_bool)
+        .unwrap_or(false);
+
+    // Go through the chunk bytes so the usual header checks apply
+    let mut data = Vec::with_capacity(13);
+    data.extend_from_slice(&number("width")?.to_be_bytes());
+    data.extend_from_slice(&number("height")?.to_be_bytes());
+    data.extend_from_slice(&[bit_depth, color_type, 0, 0, interlaced as u8]);
+    ImageHeader::parse(&data)
+}
+
+fn hex(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len() * 2);
+    for byte in data {
+        let _ = write!(out, "{:02x}", byte);
+    }
+    out
+}
+
+fn unhex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// Just enough JSON to read manifests back
+#[derive(Debug, Clone, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn parse(text: &str) -> Result<Self, PngError> {
+        let mut parser = Parser {
+            text: text.as_bytes(),
+            pos: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos != parser.text.len() {
+            return Err(parser.error("trailing characters"));
+        }
+        Ok(value)
+    }
+
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> PngError {
+        invalid(format!("{} at byte {}", message, self.pos))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .text
+            .get(self.pos)
+            .is_some_and(|b| b.is_ascii_whitespace())
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), PngError> {
+        self.skip_whitespace();
+        if self.text.get(self.pos) == Some(&byte) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", byte as char)))
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> Result<Json, PngError> {
+        if self.text[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            Ok(value)
+        } else {
+            Err(self.error("unexpected character"))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, PngError> {
+        self.skip_whitespace();
+        match self.text.get(self.pos) {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, PngError> {
+        self.expect(b'{')?;
+        let mut fields = Vec::new();
+        self.skip_whitespace();
+        if self.text.get(self.pos) == Some(&b'}') {
+            self.pos += 1;
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(b':')?;
+            fields.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.text.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Json::Object(fields));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, PngError> {
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.text.get(self.pos) == Some(&b']') {
+            self.pos += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.text.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, PngError> {
+        let start = self.pos;
+        while self
+            .text
+            .get(self.pos)
+            .is_some_and(|b| matches!(b, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9'))
+        {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.text[start..self.pos])
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .map(Json::Number)
+            .ok_or_else(|| self.error("invalid number"))
+    }
+
+    fn string(&mut self) -> Result<String, PngError> {
+        self.expect(b'"')?;
+        let mut out = String::new();
+        loop {
+            // Copy runs of plain characters at once, keeping UTF-8 intact
+            let start = self.pos;
+            while self
+                .text
+                .get(self.pos)
+                .is_some_and(|&b| b != b'"' && b != b'\\')
+            {
+                self.pos += 1;
+            }
+            out.push_str(
+                std::str::from_utf8(&self.text[start..self.pos])
+                    .map_err(|_| self.error("invalid UTF-8"))?,
+            );
+            match self.text.get(self.pos) {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(out);
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let escape = *self
+                        .text
+                        .get(self.pos)
+                        .ok_or_else(|| self.error("unexpected end"))?;
+                    self.pos += 1;
+                    match escape {
+                        b'"' => out.push('"'),
+                        b'\\' => out.push('\\'),
+                        b'/' => out.push('/'),
+                        b'b' => out.push('\u{8}'),
+                        b'f' => out.push('\u{c}'),
+                        b'n' => out.push('\n'),
+                        b'r' => out.push('\r'),
+                        b't' => out.push('\t'),
+                        b'u' => out.push(self.unicode_escape()?),
+                        _ => return Err(self.error("invalid escape")),
+                    }
+                }
+                _ => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    // The code point of a \u escape whose "\u" has been consumed, combining
+    // surrogate pairs
+    fn unicode_escape(&mut self) -> Result<char, PngError> {
+        let first = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&first) {
+            if !self.text[self.pos..].starts_with(b"\\u") {
+                return Err(self.error("unpaired surrogate"));
+            }
+            self.pos += 2;
+            let second = self.hex4()?;
+            if !(0xDC00..0xE000).contains(&second) {
+                return Err(self.error("unpaired surrogate"));
+            }
+            0x10000 + ((first - 0xD800) << 10) + (second - 0xDC00)
+        } else {
+            first
+        };
+        char::from_u32(code).ok_or_else(|| self.error("invalid \\u escape"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, PngError> {
+        let digits = self
+            .text
+            .get(self.pos..self.pos + 4)
+            .and_then(|d| std::str::from_utf8(d).ok())
+            .and_then(|d| u32::from_str_radix(d, 16).ok())
+            .ok_or_else(|| self.error("invalid \\u escape"))?;
+        self.pos += 4;
+        Ok(digits)
+    }
+}
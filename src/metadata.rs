@@ -0,0 +1,371 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::PngError;
+
+/// The last-modification time stored in a tIME chunk, always in UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timestamp {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    /// Up to 60 to allow for leap seconds
+    pub second: u8,
+}
+
+impl Timestamp {
+    pub fn new(
+        year: u16,
+        month: u8,
+        day: u8,
+        hour: u8,
+        minute: u8,
+        second: u8,
+    ) -> Result<Self, PngError> {
+        if !(1..=12).contains(&month)
+            || !(1..=31).contains(&day)
+            || hour > 23
+            || minute > 59
+            || second > 60
+        {
+            return Err(PngError::InvalidMetadata(format!(
+                "Invalid time {:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+                year, month, day, hour, minute, second
+            )));
+        }
+        Ok(Self {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        })
+    }
+
+    /// The current system time.
+    pub fn now() -> Self {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let (days, time) = (seconds / 86_400, seconds % 86_400);
+
+        // Civil-from-days conversion over 400-year eras
+        let z = days as i64 + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + i64::from(month <= 2);
+
+        Self {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (time / 3600) as u8,
+            minute: (time / 60 % 60) as u8,
+            second: (time % 60) as u8,
+        }
+    }
+
+    /// Parses the data of a tIME chunk.
+    pub fn parse(data: &[u8]) -> Result<Self, PngError> {
+        let data: [u8; 7] = data
+            .try_into()
+            .map_err(|_| PngError::Decode(format!("tIME must be 7 bytes, got {}", data.len())))?;
+        Self::new(
+            u16::from_be_bytes([data[0], data[1]]),
+            data[2],
+            data[3],
+            data[4],
+            data[5],
+            data[6],
+        )
+    }
+
+    pub fn to_bytes(&self) -> [u8; 7] {
+        let [y0, y1] = self.year.to_be_bytes();
+        [
+            y0,
+            y1,
+            self.month,
+            self.day,
+            self.hour,
+            self.minute,
+            self.second,
+        ]
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+/// Parses `YYYY-MM-DDTHH:MM:SS`, with an optional trailing `Z` and a space
+/// allowed in place of the `T`.
+impl FromStr for Timestamp {
+    type Err = PngError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || PngError::InvalidMetadata(format!("Invalid timestamp '{}'", s));
+
+        let s = s.strip_suffix('Z').unwrap_or(s);
+        let (date, time) = s.split_once(['T', ' ']).ok_or_else(invalid)?;
+        let date: Vec<&str> = date.split('-').collect();
+        let time: Vec<&str> = time.split(':').collect();
+        let ([year, month, day], [hour, minute, second]) = (date.as_slice(), time.as_slice())
+        else {
+            return Err(invalid());
+        };
+
+        let field = |v: &str| v.parse::<u8>().map_err(|_| invalid());
+        Self::new(
+            year.parse().map_err(|_| invalid())?,
+            field(month)?,
+            field(day)?,
+            field(hour)?,
+            field(minute)?,
+            field(second)?,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelUnit {
+    /// Only the aspect ratio is meaningful
+    Unknown,
+    Meter,
+}
+
+/// Pixel density from a pHYs chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhysicalDimensions {
+    pub x: u32,
+    pub y: u32,
+    pub unit: PixelUnit,
+}
+
+const METERS_PER_INCH: f64 = 0.0254;
+
+impl PhysicalDimensions {
+    /// Square pixels at `dpi` dots per inch.
+    pub fn from_dpi(dpi: f64) -> Self {
+        let per_meter = (dpi / METERS_PER_INCH).round() as u32;
+        Self {
+            x: per_meter,
+            y: per_meter,
+            unit: PixelUnit::Meter,
+        }
+    }
+
+    /// Horizontal and vertical dots per inch, if the unit is known.
+    pub fn dpi(&self) -> Option<(f64, f64)> {
+        match self.unit {
+            PixelUnit::Meter => Some((
+                self.x as f64 * METERS_PER_INCH,
+                self.y as f64 * METERS_PER_INCH,
+            )),
+            PixelUnit::Unknown => None,
+        }
+    }
+
+    /// Parses the data of a pHYs chunk.
+    pub fn parse(data: &[u8]) -> Result<Self, PngError> {
+        let data: [u8; 9] = data
+            .try_into()
+            .map_err(|_| PngError::Decode(format!("pHYs must be 9 bytes, got {}", data.len())))?;
+        let unit = match data[8] {
+            0 => PixelUnit::Unknown,
+            1 => PixelUnit::Meter,
+            unit => return Err(PngError::Decode(format!("Unknown pHYs unit {}", unit))),
+        };
+        Ok(Self {
+            x: u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+            y: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            unit,
+        })
+    }
+
+    pub fn to_bytes(&self) -> [u8; 9] {
+        let mut bytes = [0; 9];
+        bytes[..4].copy_from_slice(&self.x.to_be_bytes());
+        bytes[4..8].copy_from_slice(&self.y.to_be_bytes());
+        bytes[8] = match self.unit {
+            PixelUnit::Unknown => 0,
+            PixelUnit::Meter => 1,
+        };
+        bytes
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OffsetUnit {
+    Pixel,
+    Micrometer,
+}
+
+/// Image position on a page or larger canvas, from an oFFs chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageOffset {
+    pub x: i32,
+    pub y: i32,
+    pub unit: OffsetUnit,
+}
+
+impl ImageOffset {
+    /// Parses the data of an oFFs chunk.
+    pub fn parse(data: &[u8]) -> Result<Self, PngError> {
+        let data: [u8; 9] = data
+            .try_into()
+            .map_err(|_| PngError::Decode(format!("oFFs must be 9 bytes, got {}", data.len())))?;
+        let unit = match data[8] {
+            0 => OffsetUnit::Pixel,
+            1 => OffsetUnit::Micrometer,
+            unit => return Err(PngError::Decode(format!("Unknown oFFs unit {}", unit))),
+        };
+        Ok(Self {
+            x: i32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+            y: i32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            unit,
+        })
+    }
+
+    pub fn to_bytes(&self) -> [u8; 9] {
+        let mut bytes = [0; 9];
+        bytes[..4].copy_from_slice(&self.x.to_be_bytes());
+        bytes[4..8].copy_from_slice(&self.y.to_be_bytes());
+        bytes[8] = match self.unit {
+            OffsetUnit::Pixel => 0,
+            OffsetUnit::Micrometer => 1,
+        };
+        bytes
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScaleUnit {
+    Meter,
+    Radian,
+}
+
+/// The physical size of one pixel, from an sCAL chunk.
+///
+/// The sizes are kept as the decimal strings the chunk stores, so a file
+/// read and written again is unchanged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PhysicalScale {
+    pub unit: ScaleUnit,
+    width: String,
+    height: String,
+}
+
+impl PhysicalScale {
+    /// Pixels `width` by `height` units in size; both must be positive.
+    pub fn new(width: f64, height: f64, unit: ScaleUnit) -> Result<Self, PngError> {
+        for value in [width, height] {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(PngError::InvalidMetadata(format!(
+                    "Invalid pixel size {}",
+                    value
+                )));
+            }
+        }
+        Ok(Self {
+            unit,
+            width: width.to_string(),
+            height: height.to_string(),
+        })
+    }
+
+    pub fn width(&self) -> f64 {
+        self.width.parse().unwrap_or_default()
+    }
+
+    pub fn height(&self) -> f64 {
+        self.height.parse().unwrap_or_default()
+    }
+
+    /// Parses the data of an sCAL chunk.
+    pub fn parse(data: &[u8]) -> Result<Self, PngError> {
+        let invalid = || PngError::Decode("Invalid sCAL chunk".to_string());
+        let (&unit, sizes) = data.split_first().ok_or_else(invalid)?;
+        let unit = match unit {
+            1 => ScaleUnit::Meter,
+            2 => ScaleUnit::Radian,
+            unit => return Err(PngError::Decode(format!("Unknown sCAL unit {}", unit))),
+        };
+        let sizes = std::str::from_utf8(sizes).map_err(|_| invalid())?;
+        let (width, height) = sizes.split_once('\0').ok_or_else(invalid)?;
+
+        // The chunk allows only plain decimal numbers with an optional
+        // exponent, which Rust's float parser also accepts
+        let positive = |s: &str| {
+            s.bytes()
+                .all(|b| b.is_ascii_digit() || matches!(b, b'.' | b'e' | b'E' | b'+' | b'-'))
+                && s.parse::<f64>().is_ok_and(|v| v > 0.0)
+        };
+        if !positive(width) || !positive(height) {
+            return Err(invalid());
+        }
+        Ok(Self {
+            unit,
+            width: width.to_string(),
+            height: height.to_string(),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![match self.unit {
+            ScaleUnit::Meter => 1,
+            ScaleUnit::Radian => 2,
+        }];
+        bytes.extend_from_slice(self.width.as_bytes());
+        bytes.push(0);
+        bytes.extend_from_slice(self.height.as_bytes());
+        bytes
+    }
+}
+
+/// How the two halves of a side-by-side stereo pair are viewed, from an
+/// sTER chunk. In both layouts the images sit next to each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StereoLayout {
+    /// The right-eye image is on the left
+    CrossFuse,
+    /// The left-eye image is on the left
+    DivergingFuse,
+}
+
+impl StereoLayout {
+    /// Parses the data of an sTER chunk.
+    pub fn parse(data: &[u8]) -> Result<Self, PngError> {
+        match data {
+            [0] => Ok(StereoLayout::CrossFuse),
+            [1] => Ok(StereoLayout::DivergingFuse),
+            [mode] => Err(PngError::Decode(format!("Unknown sTER mode {}", mode))),
+            _ => Err(PngError::Decode(format!(
+                "sTER must be 1 byte, got {}",
+                data.len()
+            ))),
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 1] {
+        match self {
+            StereoLayout::CrossFuse => [0],
+            StereoLayout::DivergingFuse => [1],
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use std::fs::{File, OpenOptions};
+use std::io::Cursor;
+use std::path::Path;
+
+use memmap2::{Mmap, MmapMut};
+
+use crate::decoder::Decoder;
+use crate::error::PngError;
+use crate::options::EncodeOptions;
+use crate::progress::ProgressHook;
+use crate::PngImage;
+
+impl PngImage {
+    /// Encodes the image straight into a memory-mapped file at `path`,
+    /// returning the number of bytes written. The file is first extended to
+    /// [`PngImage::encoded_size_bound`], which most filesystems allocate
+    /// sparsely, then compressed into a row at a time, and truncated to the
+    /// real size afterwards. If encoding fails, the file is removed.
+    pub fn write_to_path_mmap<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: &EncodeOptions,
+    ) -> Result<u64, PngError> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        let result = self.write_mapped(&file, options);
+        if result.is_err() {
+            // Rather than leave a bound-sized file of zeros behind
+            drop(file);
+            let _ = std::fs::remove_file(path);
+        }
+        result
+    }
+
+    fn write_mapped(&self, file: &File, options: &EncodeOptions) -> Result<u64, PngError> {
+        file.set_len(self.encoded_size_bound())?;
+
+        // SAFETY: the file was just created and truncated through this
+        // handle, and the map is dropped before the file is resized again;
+        // other processes modifying it meanwhile is outside our control, as
+        // with any mapped file.
+        let mut map = unsafe { MmapMut::map_mut(file)? };
+        let mut cursor = Cursor::new(&mut map[..]);
+        self.encode(&mut cursor, options, &mut ProgressHook::new(), true)?;
+        let written = cursor.position();
+        map.flush()?;
+        drop(map);
+
+        file.set_len(written)?;
+        Ok(written)
+    }
+
+    /// Decodes the file at `path` through a read-only memory map, with
+    /// [`Decoder::decode_borrowed`], so its chunks are never copied into
+    /// memory of their own.
+    pub fn read_from_path_mmap<P: AsRef<Path>>(path: P) -> Result<Self, PngError> {
+        let file = File::open(path)?;
+        // SAFETY: the map is only read, and dropped before returning; the
+        // file being truncated or modified by another process meanwhile is
+        // outside our control, as with any mapped file.
+        let map = unsafe { Mmap::map(&file)? };
+        Decoder::new(&map[..]).decode_borrowed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::Decoder;
+    use crate::dictionary::CompressionDictionary;
+    use crate::test_images::noise;
+    use crate::{BitDepth, ColorType};
+
+    #[test]
+    fn mmap_output_fits_bound_with_dictionary() {
+        let dictionary = CompressionDictionary::from_bytes(&[7; 40000]);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.png");
+        for (width, height) in [(1, 1), (40, 30)] {
+            let image = noise(width, height, ColorType::Rgba, BitDepth::Eight, 1);
+            for level in [0, 9] {
+                let options = EncodeOptions {
+                    compression_level: level,
+                    dictionary: Some(dictionary.clone()),
+                    ..EncodeOptions::default()
+                };
+                let written = image.write_to_path_mmap(&path, &options).unwrap();
+                let bytes = std::fs::read(&path).unwrap();
+                assert_eq!(bytes.len() as u64, written);
+                let decoded = Decoder::new(bytes.as_slice())
+                    .dictionary(dictionary.clone())
+                    .decode()
+                    .unwrap();
+                assert_eq!(decoded.data(), image.data());
+            }
+        }
+    }
+
+    #[test]
+    fn mmap_output_streams_large_images_within_bound() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.png");
+        let image = noise(600, 500, ColorType::Rgba, BitDepth::Sixteen, 2);
+        for level in [0, 1] {
+            let options = EncodeOptions {
+                compression_level: level,
+                ..EncodeOptions::default()
+            };
+            let written = image.write_to_path_mmap(&path, &options).unwrap();
+            assert!(written <= image.encoded_size_bound());
+            let decoded = PngImage::read_from_path_mmap(&path).unwrap();
+            assert_eq!(decoded.data(), image.data());
+        }
+    }
+
+    #[test]
+    fn failed_writes_leave_no_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.png");
+        // Indexed, with no palette to write
+        let image = PngImage::new(64, 64, ColorType::Indexed).unwrap();
+        assert!(image
+            .write_to_path_mmap(&path, &EncodeOptions::default())
+            .is_err());
+        assert!(!path.exists());
+    }
+}
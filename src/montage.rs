@@ -0,0 +1,63 @@
+use crate::color::Color;
+use crate::error::PngError;
+use crate::{BitDepth, ColorType, PngImage};
+
+/// Lays `images` out in a grid, `columns` wide and filled row by row, as an
+/// 8-bit RGBA contact sheet. Every cell is the size of the widest and the
+/// tallest image, with smaller images centered in theirs. `padding` pixels
+/// of `background` separate the cells and surround the grid; images are
+/// composited over `background` if it's opaque, and copied as they are
+/// otherwise.
+pub fn montage(
+    images: &[PngImage],
+    columns: u32,
+    padding: u32,
+    background: Color,
+) -> Result<PngImage, PngError> {
+    if images.is_empty() || columns == 0 {
+        return Err(PngError::InvalidDimensions(columns, 0));
+    }
+    let cells = images
+        .iter()
+        .map(|image| to_cell(image, background))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let cell_width = cells.iter().map(|c| c.width).max().unwrap_or(0) as u64;
+    let cell_height = cells.iter().map(|c| c.height).max().unwrap_or(0) as u64;
+    let columns = (columns as u64).min(cells.len() as u64);
+    let rows = (cells.len() as u64).div_ceil(columns);
+    let extent = |count: u64, cell: u64| count * (cell + padding as u64) + padding as u64;
+    let (width, height) = (extent(columns, cell_width), extent(rows, cell_height));
+    let too_large = || PngError::ImageTooLarge {
+        width: width.min(u32::MAX as u64) as u32,
+        height: height.min(u32::MAX as u64) as u32,
+    };
+    let width = u32::try_from(width).map_err(|_| too_large())?;
+    let height = u32::try_from(height).map_err(|_| too_large())?;
+
+    let mut sheet = PngImage::new(width, height, ColorType::Rgba)?;
+    sheet.fill(background)?;
+    for (i, cell) in cells.iter().enumerate() {
+        let (column, row) = (i as u64 % columns, i as u64 / columns);
+        let x = padding as u64 + column * (cell_width + padding as u64);
+        let y = padding as u64 + row * (cell_height + padding as u64);
+        sheet.blit(
+            cell,
+            (x + (cell_width - cell.width as u64) / 2) as i32,
+            (y + (cell_height - cell.height as u64) / 2) as i32,
+        )?;
+    }
+    Ok(sheet)
+}
+
+// An image as 8-bit RGBA, composited over the background if that's opaque
+pub(crate) fn to_cell(image: &PngImage, background: Color) -> Result<PngImage, PngError> {
+    let (_, _, _, alpha) = background.to_rgba().ok_or(PngError::ColorTypeError)?;
+    let mut cell = image.clone();
+    if alpha == 255 {
+        cell.flatten(background)?;
+    }
+    cell.convert_to(ColorType::Rgba)?;
+    cell.convert_bit_depth(BitDepth::Eight)?;
+    Ok(cell)
+}
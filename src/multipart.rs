@@ -0,0 +1,175 @@
+use std::io::Write;
+
+use crate::error::PngError;
+use crate::options::EncodeOptions;
+use crate::PngImage;
+
+const DEFAULT_BOUNDARY: &str = "png-frame-9f3a61c2";
+
+/// Writes a sequence of PNGs as a `multipart/x-mixed-replace` body, the
+/// MJPEG-style stream browsers show as live video in an `<img>` tag, for
+/// camera previews and the like. Send [`content_type`](Self::content_type)
+/// as the response's Content-Type, then write frames as they come; each is
+/// flushed through so clients see it straight away.
+#[derive(Debug)]
+pub struct MultipartWriter<W: Write> {
+    inner: W,
+    boundary: String,
+}
+
+impl<W: Write> MultipartWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            boundary: DEFAULT_BOUNDARY.to_string(),
+        }
+    }
+
+    /// Uses `boundary` to separate frames. It must be 1 to 70 characters
+    /// that RFC 2046 allows, and not end in a space.
+    pub fn with_boundary(mut self, boundary: &str) -> Result<Self, PngError> {
+        let allowed = |c: char| c.is_ascii_alphanumeric() || "'()+_,-./:=? ".contains(c);
+        if boundary.is_empty()
+            || boundary.len() > 70
+            || !boundary.chars().all(allowed)
+            || boundary.ends_with(' ')
+        {
+            return Err(PngError::InvalidBoundary(boundary.to_string()));
+        }
+        self.boundary = boundary.to_string();
+        Ok(self)
+    }
+
+    /// The Content-Type header value for the stream.
+    pub fn content_type(&self) -> String {
+        format!("multipart/x-mixed-replace; boundary=\"{}\"", self.boundary)
+    }
+
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// Encodes `image` with `options` and sends it as the next frame.
+    pub fn write_image(
+        &mut self,
+        image: &PngImage,
+        options: &EncodeOptions,
+    ) -> Result<(), PngError> {
+        let mut encoded = Vec::new();
+        image.write_with_options(&mut encoded, options)?;
+        self.write_png(&encoded)
+    }
+
+    /// Sends an already encoded PNG file as the next frame.
+    pub fn write_png(&mut self, png: &[u8]) -> Result<(), PngError> {
+        // The length lets clients take the frame without scanning it for
+        // the boundary
+        write!(
+            self.inner,
+            "--{}\r\nContent-Type: image/png\r\nContent-Length: {}\r\n\r\n",
+            self.boundary,
+            png.len()
+        )?;
+        self.inner.write_all(png)?;
+        self.inner.write_all(b"\r\n")?;
+        self.inner.flush()?;
+        Ok(())
+    }
+
+    /// Writes the closing boundary and returns the underlying writer.
+    /// Streams that end when the client goes away needn't call this.
+    pub fn finish(mut self) -> Result<W, PngError> {
+        write!(self.inner, "--{}--\r\n", self.boundary)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::Decoder;
+    use crate::test_images::noise;
+    use crate::{BitDepth, ColorType};
+
+    #[test]
+    fn frames_each_png_between_boundaries() {
+        let mut writer = MultipartWriter::new(Vec::new())
+            .with_boundary("frame")
+            .unwrap();
+        assert_eq!(
+            writer.content_type(),
+            "multipart/x-mixed-replace; boundary=\"frame\""
+        );
+        writer.write_png(b"first").unwrap();
+        writer.write_png(b"second!").unwrap();
+        let body = writer.finish().unwrap();
+        assert_eq!(
+            body,
+            b"--frame\r\nContent-Type: image/png\r\nContent-Length: 5\r\n\r\nfirst\r\n\
+              --frame\r\nContent-Type: image/png\r\nContent-Length: 7\r\n\r\nsecond!\r\n\
+              --frame--\r\n"
+        );
+    }
+
+    #[test]
+    fn written_images_decode_from_their_parts() {
+        let image = noise(20, 10, ColorType::Rgb, BitDepth::Eight, 3);
+        let mut writer = MultipartWriter::new(Vec::new());
+        assert_eq!(writer.boundary(), DEFAULT_BOUNDARY);
+        writer
+            .write_image(&image, &EncodeOptions::default())
+            .unwrap();
+        let body = writer.finish().unwrap();
+
+        let header_end = body.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        let header = std::str::from_utf8(&body[..header_end]).unwrap();
+        let length: usize = header
+            .lines()
+            .find_map(|line| line.strip_prefix("Content-Length: "))
+            .unwrap()
+            .parse()
+            .unwrap();
+        let png = &body[header_end..header_end + length];
+        let decoded = Decoder::new(png).decode().unwrap();
+        assert_eq!(decoded.data(), image.data());
+        assert_eq!(
+            &body[header_end + length..],
+            format!("\r\n--{DEFAULT_BOUNDARY}--\r\n").as_bytes()
+        );
+    }
+
+    #[test]
+    fn rejects_boundaries_rfc_2046_forbids() {
+        let too_long = "a".repeat(71);
+        for boundary in [
+            "",
+            "ends in space ",
+            "semi;colon",
+            "quote\"",
+            too_long.as_str(),
+        ] {
+            assert!(
+                matches!(
+                    MultipartWriter::new(Vec::new()).with_boundary(boundary),
+                    Err(PngError::InvalidBoundary(b)) if b == boundary
+                ),
+                "{boundary:?}"
+            );
+        }
+        let longest = "a".repeat(70);
+        for boundary in ["a b", "x'()+_,-./:=?", longest.as_str()] {
+            assert!(MultipartWriter::new(Vec::new())
+                .with_boundary(boundary)
+                .is_ok());
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::HashSet;
+use std::io::Cursor;
+
+use crate::chunks::{chunk_crc, Chunk, ChunkReader, PNG_SIGNATURE};
+use crate::error::PngError;
+use crate::filter::{FilterStrategy, FilterType};
+use crate::options::EncodeOptions;
+use crate::{BitDepth, ColorType, PngImage};
+
+/// Settings for [`optimize`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OptimizeOptions {
+    /// zlib compression level used for every trial encoding
+    pub compression_level: u8,
+    /// Remove ancillary chunks. tRNS is always kept since it changes how the
+    /// pixels look, and so is anything listed in `keep_chunks`.
+    pub strip: bool,
+    pub keep_chunks: Vec<[u8; 4]>,
+    /// Try lossless color type and bit depth reductions
+    pub reduce: bool,
+}
+
+impl Default for OptimizeOptions {
+    fn default() -> Self {
+        Self {
+            compression_level: 9,
+            strip: false,
+            keep_chunks: Vec::new(),
+            reduce: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OptimizeResult {
+    pub data: Vec<u8>,
+    pub original_size: usize,
+    pub optimized_size: usize,
+}
+
+const TRIAL_FILTERS: [FilterStrategy; 6] = [
+    FilterStrategy::Fixed(FilterType::None),
+    FilterStrategy::Fixed(FilterType::Sub),
+    FilterStrategy::Fixed(FilterType::Up),
+    FilterStrategy::Fixed(FilterType::Average),
+    FilterStrategy::Fixed(FilterType::Paeth),
+    FilterStrategy::Adaptive,
+];
+
+/// Losslessly re-encodes a PNG file, trying every filter strategy and (if
+/// enabled) smaller color types, and returns the smallest result. The output
+/// is never larger than the input with the requested chunks stripped, and
+/// is decoded and checked against the input's pixels before it's returned.
+pub fn optimize(input: &[u8], options: &OptimizeOptions) -> Result<OptimizeResult, PngError> {
+    let chunks: Vec<Chunk> = ChunkReader::new(input)?.collect::<Result<_, _>>()?;
+    let image = PngImage::read_from_file(input)?;
+
+    let keep: HashSet<[u8; 4]> = options.keep_chunks.iter().copied().collect();
+    let kept = |chunk: &Chunk| {
+        chunk.is_critical()
+            || !options.strip
+            || &chunk.chunk_type == b"tRNS"
+            || keep.contains(&chunk.chunk_type)
+    };
+
+    // Ancillary chunks with an uppercase fourth letter are unsafe to copy once
+    // the critical chunks change, and tRNS can't be carried across a color
+    // type change, so files using it skip reductions
+    let has_transparency = chunks.iter().any(|c| &c.chunk_type == b"tRNS");
+    let mut candidates = vec![image.clone()];
+    if options.reduce && !has_transparency {
+        candidates.extend(reductions(&image));
+    }
+
+    // Fall back to the original stream, minus stripped chunks
+    let mut best = assemble(&chunks, None, &kept);
+
+    for candidate in &candidates {
+        let changed = candidate.color_type != image.color_type
+            || candidate.bit_depth != image.bit_depth
+            || candidate.palette != image.palette;
+
+        for filter in TRIAL_FILTERS {
+            let encode_options = EncodeOptions {
+                compression_level: options.compression_level,
+                filter,
+                ..EncodeOptions::default()
+            };
+            let mut encoded = Cursor::new(Vec::new());
+            candidate.write_with_options(&mut encoded, &encode_options)?;
+            let encoded: Vec<Chunk> =
+                ChunkReader::new(encoded.get_ref().as_slice())?.collect::<Result<_, _>>()?;
+
+            let output = assemble(&chunks, Some(&encoded), &|chunk: &Chunk| {
+                kept(chunk)
+                    && !(changed
+                        && !chunk.is_critical()
+                        && chunk.chunk_type[3].is_ascii_uppercase())
+            });
+            if output.len() < best.len() {
+                best = output;
+            }
+        }
+    }
+
+    image.verify_encoding(&best)?;
+    Ok(OptimizeResult {
+        original_size: input.len(),
+        optimized_size: best.len(),
+        data: best,
+    })
+}
+
+// Writes the original ancillary chunks around either the original critical
+// chunks or a replacement IHDR/PLTE/IDAT set. Ancillary chunks stay on the
+// same side of PLTE and IDAT as they were in the source.
+fn assemble(
+    original: &[Chunk],
+    replacement: Option<&[Chunk]>,
+    kept: &dyn Fn(&Chunk) -> bool,
+) -> Vec<u8> {
+    let mut out = PNG_SIGNATURE.to_vec();
+    let mut push = |chunk: &Chunk| {
+        out.extend_from_slice(&(chunk.data.len() as u32).to_be_bytes());
+        out.extend_from_slice(&chunk.chunk_type);
+        out.extend_from_slice(&chunk.data);
+        out.extend_from_slice(&chunk_crc(&chunk.chunk_type, &chunk.data).to_be_bytes());
+    };
+
+    let Some(replacement) = replacement else {
+        for chunk in original.iter().filter(|c| kept(c)) {
+            push(chunk);
+        }
+        return out;
+    };
+
+    // 0: before PLTE, 1: between PLTE and IDAT, 2: after IDAT
+    let mut sections: [Vec<&Chunk>; 3] = Default::default();
+    let mut section = 0;
+    for chunk in original {
+        match &chunk.chunk_type {
+            b"PLTE" => section = section.max(1),
+            b"IDAT" => section = 2,
+            _ if !chunk.is_critical() && kept(chunk) => sections[section].push(chunk),
+            _ => {}
+        }
+    }
+
+    let order: [(&[u8; 4], Option<&[&Chunk]>); 4] = [
+        (b"IHDR", Some(&sections[0])),
+        (b"PLTE", Some(&sections[1])),
+        (b"IDAT", Some(&sections[2])),
+        (b"IEND", None),
+    ];
+    for (chunk_type, following) in order {
+        for chunk in replacement.iter().filter(|c| &c.chunk_type == chunk_type) {
+            push(chunk);
+        }
+        for chunk in following.unwrap_or_default() {
+            push(chunk);
+        }
+    }
+    out
+}
+
+// Lossless alternatives to `image`, each reduction applied on top of the last
+fn reductions(image: &PngImage) -> Vec<PngImage> {
+    let mut results = Vec::new();
+    let mut current = image.clone();
+
+    if current.bit_depth == BitDepth::Sixteen
+        && current.data.chunks_exact(2).all(|pair| pair[0] == pair[1])
+    {
+        let data: Vec<u8> = current.data.iter().step_by(2).copied().collect();
+        current.data = data.into();
+        current.bit_depth = BitDepth::Eight;
+        results.push(current.clone());
+    }
+
+    if current.bit_depth != BitDepth::Eight {
+        return results;
+    }
+
+    let opaque =
+        |data: &[u8], channels: usize| data.chunks_exact(channels).all(|p| p[channels - 1] == 255);
+    match current.color_type {
+        ColorType::Rgba if opaque(&current.data, 4) => {
+            current.convert_to(ColorType::Rgb).ok();
+            results.push(current.clone());
+        }
+        ColorType::GrayscaleAlpha if opaque(&current.data, 2) => {
+            current.convert_to(ColorType::Grayscale).ok();
+            results.push(current.clone());
+        }
+        _ => {}
+    }
+
+    let gray = |data: &[u8], channels: usize| {
+        data.chunks_exact(channels)
+            .all(|p| p[0] == p[1] && p[1] == p[2])
+    };
+    match current.color_type {
+        ColorType::Rgb if gray(&current.data, 3) => {
+            current.convert_to(ColorType::Grayscale).ok();
+            results.push(current.clone());
+        }
+        ColorType::Rgba if gray(&current.data, 4) => {
+            current.convert_to(ColorType::GrayscaleAlpha).ok();
+            results.push(current.clone());
+        }
+        _ => {}
+    }
+
+    if current.color_type == ColorType::Rgb {
+        if let Some(indexed) = to_indexed(&current) {
+            results.push(indexed);
+        }
+    }
+
+    if current.color_type == ColorType::Indexed {
+        let entries = current.palette.as_ref().map_or(0, |p| p.len() / 3);
+        if current
+            .convert_bit_depth(smallest_index_depth(entries))
+            .is_ok()
+        {
+            results.push(current);
+        }
+    }
+
+    results
+}
+
+// Builds a palette image if `image` (8-bit RGB) uses at most 256 colors
+fn to_indexed(image: &PngImage) -> Option<PngImage> {
+    let mut palette: Vec<[u8; 3]> = Vec::new();
+    let mut lookup = std::collections::HashMap::new();
+    let mut indices = Vec::with_capacity(image.data.len() / 3);
+
+    for pixel in image.data.chunks_exact(3) {
+        let color = [pixel[0], pixel[1], pixel[2]];
+        let index = match lookup.get(&color) {
+            Some(&index) => index,
+            None => {
+                if palette.len() == 256 {
+                    return None;
+                }
+                palette.push(color);
+                lookup.insert(color, (palette.len() - 1) as u8);
+                (palette.len() - 1) as u8
+            }
+        };
+        indices.push(index);
+    }
+
+    let mut indexed = PngImage::new(image.width, image.height, ColorType::Indexed).ok()?;
+    indexed.set_palette(&palette.concat()).ok()?;
+    indexed.data = indices.into();
+
+    indexed
+        .convert_bit_depth(smallest_index_depth(palette.len()))
+        .ok()?;
+    Some(indexed)
+}
+
+// Packs indices into fewer bits when the palette is small enough
+fn smallest_index_depth(entries: usize) -> BitDepth {
+    match entries {
+        0..=2 => BitDepth::One,
+        3..=4 => BitDepth::Two,
+        5..=16 => BitDepth::Four,
+        _ => BitDepth::Eight,
+    }
+}
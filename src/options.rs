@@ -0,0 +1,77 @@
+use crate::dictionary::CompressionDictionary;
+use crate::filter::{FilterStrategy, FilterType};
+
+/// How the encoder treats output that is valid but questionable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Strictness {
+    /// Fail with [`PngError::StrictViolation`](crate::PngError::StrictViolation)
+    /// on unregistered text keywords, repeated chunks that should appear once,
+    /// ancillary chunks too long to store, and images with no sRGB, gAMA, or
+    /// iCCP chunk describing their color space. The output is also encoded
+    /// in memory and checked with a decode before any of it is written.
+    Strict,
+    /// Write whatever can be written, dropping ancillary chunks that are too
+    /// long to store
+    #[default]
+    Permissive,
+}
+
+/// Settings that affect how an image is encoded but not its pixel content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodeOptions {
+    /// zlib compression level, from 0 (store) to 9 (smallest). Level 0
+    /// writes stored deflate blocks directly, without running the
+    /// compressor at all.
+    pub compression_level: u8,
+    pub filter: FilterStrategy,
+    pub strictness: Strictness,
+    /// Make the output depend only on the image and these options: tIME
+    /// chunks are left out and ancillary chunks are written grouped by type
+    /// (in the order they were added within a type). Output is then
+    /// byte-identical across runs and platforms for a given version of this
+    /// crate and its compressor.
+    pub deterministic: bool,
+    /// Encode indexed images as if [`PngImage::optimize_palette`] had been
+    /// called on them first, leaving the image itself unchanged
+    ///
+    /// [`PngImage::optimize_palette`]: crate::PngImage::optimize_palette
+    pub optimize_palette: bool,
+    /// Filter rows on the calling thread while a second thread compresses
+    /// the rows already filtered, so the two overlap on multicore machines.
+    /// The output is the same either way; progress hooks then only see the
+    /// filtering phase.
+    pub pipelined: bool,
+    /// Compress image data against this preset dictionary. Output is then
+    /// not standard PNG; see [`CompressionDictionary`]. Strict mode refuses
+    /// it.
+    pub dictionary: Option<CompressionDictionary>,
+}
+
+impl EncodeOptions {
+    /// The quickest encoding there is, for when time matters far more than
+    /// size, such as debug dumps: image data in stored deflate blocks and
+    /// no filtering, which would only cost time. Files are about as large
+    /// as the raw pixels, and still valid PNG.
+    pub fn uncompressed() -> Self {
+        Self {
+            compression_level: 0,
+            filter: FilterStrategy::Fixed(FilterType::None),
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        Self {
+            compression_level: 6,
+            filter: FilterStrategy::default(),
+            strictness: Strictness::default(),
+            deterministic: false,
+            optimize_palette: false,
+            pipelined: false,
+            dictionary: None,
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use crate::error::PngError;
+use crate::PngImage;
+
+const ORIENTATION_TAG: u16 = 0x0112;
+
+/// How stored pixels must be transformed for display, as in the EXIF
+/// Orientation tag. Rotations are clockwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Orientation {
+    #[default]
+    Normal = 1,
+    FlipHorizontal = 2,
+    Rotate180 = 3,
+    FlipVertical = 4,
+    /// Flipped across the top-left to bottom-right diagonal
+    Transpose = 5,
+    Rotate90 = 6,
+    /// Flipped across the top-right to bottom-left diagonal
+    Transverse = 7,
+    Rotate270 = 8,
+}
+
+impl Orientation {
+    /// The orientation for an EXIF tag value from 1 to 8.
+    pub fn from_exif(value: u16) -> Option<Self> {
+        Some(match value {
+            1 => Orientation::Normal,
+            2 => Orientation::FlipHorizontal,
+            3 => Orientation::Rotate180,
+            4 => Orientation::FlipVertical,
+            5 => Orientation::Transpose,
+            6 => Orientation::Rotate90,
+            7 => Orientation::Transverse,
+            8 => Orientation::Rotate270,
+            _ => return None,
+        })
+    }
+
+    fn swaps_axes(self) -> bool {
+        matches!(
+            self,
+            Orientation::Transpose
+                | Orientation::Rotate90
+                | Orientation::Transverse
+                | Orientation::Rotate270
+        )
+    }
+}
+
+impl PngImage {
+    /// The Orientation tag of the image's eXIf chunk, if it has one.
+    pub fn exif_orientation(&self) -> Option<Orientation> {
+        let exif = self.chunks.iter().find(|c| &c.chunk_type == b"eXIf")?;
+        read_orientation(&exif.data)
+    }
+
+    /// Rotates or flips the pixels according to the eXIf Orientation tag and
+    /// resets the tag to normal, keeping the rest of the EXIF data. Returns
+    /// whether anything changed.
+    pub fn auto_orient(&mut self) -> Result<bool, PngError> {
+        let orientation = self.exif_orientation().unwrap_or_default();
+        if orientation == Orientation::Normal {
+            return Ok(false);
+        }
+        self.apply_orientation(orientation)?;
+
+        if let Some(exif) = self.chunks.iter_mut().find(|c| &c.chunk_type == b"eXIf") {
+            if let Some((offset, big_endian)) = find_orientation(&exif.data) {
+                let normal = Orientation::Normal as u16;
+                let bytes = if big_endian {
+                    normal.to_be_bytes()
+                } else {
+                    normal.to_le_bytes()
+                };
+                exif.data[offset..offset + 2].copy_from_slice(&bytes);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Transforms the pixels so an image stored with `orientation` displays
+    /// upright, swapping width and height for quarter turns.
+    pub fn apply_orientation(&mut self, orientation: Orientation) -> Result<(), PngError> {
+        if orientation == Orientation::Normal {
+            return Ok(());
+        }
+        self.check_complete()?;
+
+        let (width, height) = (self.width as usize, self.height as usize);
+        let (out_width, out_height) = if orientation.swaps_axes() {
+            (height, width)
+        } else {
+            (width, height)
+        };
+        let bpp = self.bytes_per_pixel();
+        let mut data = Vec::with_capacity(self.data.len());
+        for y in 0..out_height {
+            for x in 0..out_width {
+                let (sx, sy) = match orientation {
+                    Orientation::Normal => (x, y),
+                    Orientation::FlipHorizontal => (width - 1 - x, y),
+                    Orientation::Rotate180 => (width - 1 - x, height - 1 - y),
+                    Orientation::FlipVertical => (x, height - 1 - y),
+                    Orientation::Transpose => (y, x),
+                    Orientation::Rotate90 => (y, height - 1 - x),
+                    Orientation::Transverse => (width - 1 - y, height - 1 - x),
+                    Orientation::Rotate270 => (width - 1 - y, x),
+                };
+                let start = (sy * width + sx) * bpp;
+                data.extend_from_slice(&self.data[start..start + bpp]);
+            }
+        }
+
+        self.data.replace(data)?;
+        self.width = out_width as u32;
+        self.height = out_height as u32;
+        Ok(())
+    }
+
+    pub fn rotate_90(&mut self) -> Result<(), PngError> {
+        self.apply_orientation(Orientation::Rotate90)
+    }
+
+    pub fn rotate_180(&mut self) -> Result<(), PngError> {
+        self.apply_orientation(Orientation::Rotate180)
+    }
+
+    pub fn rotate_270(&mut self) -> Result<(), PngError> {
+        self.apply_orientation(Orientation::Rotate270)
+    }
+
+    pub fn flip_horizontal(&mut self) -> Result<(), PngError> {
+        self.apply_orientation(Orientation::FlipHorizontal)
+    }
+
+    pub fn flip_vertical(&mut self) -> Result<(), PngError> {
+        self.apply_orientation(Orientation::FlipVertical)
+    }
+}
+
+// The Orientation tag of a TIFF-format EXIF block
+pub(crate) fn read_orientation(exif: &[u8]) -> Option<Orientation> {
+    let (offset, big_endian) = find_orientation(exif)?;
+    let bytes = [exif[offset], exif[offset + 1]];
+    Orientation::from_exif(if big_endian {
+        u16::from_be_bytes(bytes)
+    } else {
+        u16::from_le_bytes(bytes)
+    })
+}
+
+// Offset of the Orientation value in a TIFF-format EXIF block, and whether
+// the block is big-endian. Only IFD0 is searched, where the tag belongs.
+fn find_orientation(exif: &[u8]) -> Option<(usize, bool)> {
+    let big_endian = match exif.get(..4)? {
+        b"MM\0*" => true,
+        b"II*\0" => false,
+        _ => return None,
+    };
+    let u16_at = |offset: usize| {
+        let bytes = [*exif.get(offset)?, *exif.get(offset + 1)?];
+        Some(if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    };
+    let bytes: [u8; 4] = exif.get(4..8)?.try_into().ok()?;
+    let ifd = if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    } as usize;
+
+    let count = u16_at(ifd)? as usize;
+    (0..count)
+        .map(|i| ifd + 2 + i * 12)
+        // SHORT values sit at the start of the entry's 4-byte value field
+        .find(|&entry| u16_at(entry) == Some(ORIENTATION_TAG) && u16_at(entry + 2) == Some(3))
+        .map(|entry| entry + 8)
+        .filter(|&value| value + 2 <= exif.len())
+        .map(|value| (value, big_endian))
+}
@@ -0,0 +1,64 @@
+use std::fmt;
+
+use crate::color::Color;
+use crate::error::PngError;
+use crate::transform::ResizeFilter;
+use crate::{BitDepth, ColorType, PngImage};
+
+/// A 64-bit perceptual hash from [`PngImage::average_hash`] or
+/// [`PngImage::difference_hash`]. Similar-looking images have hashes that
+/// differ in few bits, even after resizing, recompression, or small edits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ImageHash(pub u64);
+
+impl ImageHash {
+    /// The number of bits that differ, from 0 for the same hash to 64.
+    /// Only hashes of the same kind are comparable; as a rule of thumb, up
+    /// to about 10 suggests the same picture.
+    pub fn distance(self, other: ImageHash) -> u32 {
+        (self.0 ^ other.0).count_ones()
+    }
+}
+
+impl fmt::Display for ImageHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl PngImage {
+    /// Average hash (aHash): the image is shrunk to 8x8 gray pixels, and
+    /// each bit says whether a pixel is brighter than their mean. Quick,
+    /// and robust to scaling and compression, but thrown by brightness and
+    /// contrast changes more than [`difference_hash`](Self::difference_hash).
+    pub fn average_hash(&self) -> Result<ImageHash, PngError> {
+        let pixels = self.hash_pixels(8, 8)?;
+        let mean = pixels.iter().map(|&p| p as u32).sum::<u32>() / 64;
+        Ok(ImageHash(pixels.iter().fold(0, |hash, &p| {
+            (hash << 1) | (p as u32 > mean) as u64
+        })))
+    }
+
+    /// Difference hash (dHash): the image is shrunk to 9x8 gray pixels, and
+    /// each bit says whether a pixel is darker than its right neighbor,
+    /// capturing the gradients, which survive brightness and contrast
+    /// changes.
+    pub fn difference_hash(&self) -> Result<ImageHash, PngError> {
+        let pixels = self.hash_pixels(9, 8)?;
+        Ok(ImageHash(pixels.chunks_exact(9).fold(0, |hash, row| {
+            row.windows(2)
+                .fold(hash, |hash, pair| (hash << 1) | (pair[0] < pair[1]) as u64)
+        })))
+    }
+
+    // The image flattened over white, in 8-bit gray, shrunk to the given size
+    fn hash_pixels(&self, width: u32, height: u32) -> Result<Vec<u8>, PngError> {
+        self.check_complete()?;
+        let mut gray = self.clone();
+        gray.flatten(Color::WHITE)?;
+        gray.convert_to(ColorType::Grayscale)?;
+        gray.convert_bit_depth(BitDepth::Eight)?;
+        let small = gray.resize(width, height, ResizeFilter::Bilinear)?;
+        Ok(small.data.to_vec())
+    }
+}
@@ -0,0 +1,207 @@
+use crate::error::PngError;
+use crate::{image_size, BitDepth, ColorType, PngImage};
+
+/// A pixel with a fixed color type and bit depth, for writing code once over
+/// every format with [`FromPixel`] and [`IntoPixel`] to convert between
+/// them.
+pub trait Pixel: Copy {
+    const COLOR_TYPE: ColorType;
+    const BIT_DEPTH: BitDepth;
+
+    /// The pixel as RGBA at 16 bits per sample.
+    fn to_rgba16(self) -> [u16; 4];
+
+    /// The pixel closest to `rgba`, using Rec. 601 luma weights for gray
+    /// and dropping alpha for types without it, as
+    /// [`PngImage::convert_to`] does.
+    fn from_rgba16(rgba: [u16; 4]) -> Self;
+
+    /// Appends the samples as [`PngImage::add_pixel`] takes them.
+    fn write_bytes(self, out: &mut Vec<u8>);
+
+    /// Reads one pixel laid out as for `write_bytes`.
+    fn read_bytes(bytes: &[u8]) -> Self;
+}
+
+/// Converts from another pixel type; implemented for every pair of
+/// [`Pixel`] types.
+pub trait FromPixel<P> {
+    fn from_pixel(pixel: P) -> Self;
+}
+
+/// The reverse of [`FromPixel`], like `Into` for `From`.
+pub trait IntoPixel<Q> {
+    fn into_pixel(self) -> Q;
+}
+
+impl<P: Pixel, Q: Pixel> FromPixel<P> for Q {
+    fn from_pixel(pixel: P) -> Self {
+        Q::from_rgba16(pixel.to_rgba16())
+    }
+}
+
+impl<P, Q: FromPixel<P>> IntoPixel<Q> for P {
+    fn into_pixel(self) -> Q {
+        Q::from_pixel(self)
+    }
+}
+
+// Rec. 601 luma weights
+fn luma(r: u16, g: u16, b: u16) -> u16 {
+    ((r as u32 * 299 + g as u32 * 587 + b as u32 * 114 + 500) / 1000) as u16
+}
+
+// 8 <-> 16 bit sample scaling, rounding as convert_bit_depth does
+fn widen(v: u8) -> u16 {
+    v as u16 * 257
+}
+
+fn narrow(v: u16) -> u8 {
+    ((v as u32 * 255 + 32767) / 65535) as u8
+}
+
+macro_rules! pixel_types {
+    ($(
+        $(#[$doc:meta])*
+        $name:ident($sample:ty; $channels:literal), $color_type:ident, $bit_depth:ident;
+    )*) => {$(
+        $(#[$doc])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+        pub struct $name(pub [$sample; $channels]);
+
+        impl Pixel for $name {
+            const COLOR_TYPE: ColorType = ColorType::$color_type;
+            const BIT_DEPTH: BitDepth = BitDepth::$bit_depth;
+
+            fn to_rgba16(self) -> [u16; 4] {
+                match self.0.map(Sample::to_u16).as_slice() {
+                    &[v] => [v, v, v, u16::MAX],
+                    &[v, a] => [v, v, v, a],
+                    &[r, g, b] => [r, g, b, u16::MAX],
+                    &[r, g, b, a, ..] => [r, g, b, a],
+                    _ => unreachable!(),
+                }
+            }
+
+            fn from_rgba16([r, g, b, a]: [u16; 4]) -> Self {
+                let samples: [u16; 4] = match $channels {
+                    1 => [luma(r, g, b); 4],
+                    2 => [luma(r, g, b), a, 0, 0],
+                    _ => [r, g, b, a],
+                };
+                let mut pixel = [<$sample>::default(); $channels];
+                for (out, &v) in pixel.iter_mut().zip(&samples) {
+                    *out = <$sample as Sample>::from_u16(v);
+                }
+                $name(pixel)
+            }
+
+            fn write_bytes(self, out: &mut Vec<u8>) {
+                for sample in self.0 {
+                    sample.write(out);
+                }
+            }
+
+            fn read_bytes(bytes: &[u8]) -> Self {
+                let size = std::mem::size_of::<$sample>();
+                let mut pixel = [<$sample>::default(); $channels];
+                for (out, sample) in pixel.iter_mut().zip(bytes.chunks_exact(size)) {
+                    *out = <$sample as Sample>::read(sample);
+                }
+                $name(pixel)
+            }
+        }
+    )*};
+}
+
+// The two sample widths pixel types are built from
+trait Sample: Copy + Default {
+    fn to_u16(self) -> u16;
+    fn from_u16(v: u16) -> Self;
+    fn write(self, out: &mut Vec<u8>);
+    fn read(bytes: &[u8]) -> Self;
+}
+
+impl Sample for u8 {
+    fn to_u16(self) -> u16 {
+        widen(self)
+    }
+
+    fn from_u16(v: u16) -> Self {
+        narrow(v)
+    }
+
+    fn write(self, out: &mut Vec<u8>) {
+        out.push(self);
+    }
+
+    fn read(bytes: &[u8]) -> Self {
+        bytes[0]
+    }
+}
+
+impl Sample for u16 {
+    fn to_u16(self) -> u16 {
+        self
+    }
+
+    fn from_u16(v: u16) -> Self {
+        v
+    }
+
+    fn write(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_be_bytes());
+    }
+
+    fn read(bytes: &[u8]) -> Self {
+        u16::from_be_bytes([bytes[0], bytes[1]])
+    }
+}
+
+pixel_types! {
+    Luma8(u8; 1), Grayscale, Eight;
+    /// Gray and alpha
+    LumaA8(u8; 2), GrayscaleAlpha, Eight;
+    Rgb8(u8; 3), Rgb, Eight;
+    Rgba8(u8; 4), Rgba, Eight;
+    Luma16(u16; 1), Grayscale, Sixteen;
+    /// Gray and alpha
+    LumaA16(u16; 2), GrayscaleAlpha, Sixteen;
+    Rgb16(u16; 3), Rgb, Sixteen;
+    Rgba16(u16; 4), Rgba, Sixteen;
+}
+
+impl PngImage {
+    /// Creates an image in `P`'s format from a complete set of pixels, row
+    /// by row.
+    pub fn from_pixels<P: Pixel>(width: u32, height: u32, pixels: &[P]) -> Result<Self, PngError> {
+        let mut image = Self::with_bit_depth(width, height, P::COLOR_TYPE, P::BIT_DEPTH)?;
+        let expected = image_size(width, height, 1)?;
+        if pixels.len() != expected {
+            return Err(PngError::PixelCountMismatch {
+                expected,
+                actual: pixels.len(),
+                dimensions: (width, height),
+            });
+        }
+        let mut data = Vec::with_capacity(expected * image.bytes_per_pixel());
+        for pixel in pixels {
+            pixel.write_bytes(&mut data);
+        }
+        image.data.replace(data)?;
+        Ok(image)
+    }
+
+    /// Every pixel added so far, converted to `P` as by
+    /// [`PngImage::convert_to`] and [`PngImage::convert_bit_depth`].
+    pub fn pixels<P: Pixel>(&self) -> Result<Vec<P>, PngError> {
+        let mut image = self.clone();
+        image.convert_to(P::COLOR_TYPE)?;
+        image.convert_bit_depth(P::BIT_DEPTH)?;
+        Ok(image
+            .data
+            .chunks_exact(image.bytes_per_pixel())
+            .map(P::read_bytes)
+            .collect())
+    }
+}
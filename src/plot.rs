@@ -0,0 +1,628 @@
+use crate::color::Color;
+use crate::error::PngError;
+use crate::{ColorType, PngImage};
+
+// Space between the plot area and the image's top and right edges, and
+// between tick labels and tick marks, before scaling
+const PADDING: u32 = 8;
+const TICK_LENGTH: u32 = 4;
+
+// Glyphs are 3x5 pixels plus a column of spacing, before scaling
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+const GLYPH_ADVANCE: u32 = 4;
+
+/// How a [`Plot`] series is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeriesKind {
+    /// Points joined in order by straight lines
+    Line,
+    /// A dot at each point
+    Scatter,
+    /// A bar from zero up (or down) to each value, at x = 0, 1, 2, ...
+    /// Bar series are drawn side by side.
+    Bar,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Series {
+    kind: SeriesKind,
+    points: Vec<(f64, f64)>,
+    color: Color,
+}
+
+/// A line chart, scatter plot, bar chart, or a mix of them, rendered
+/// straight into an image with axes and labeled ticks, for quick
+/// server-side charts. Add series with [`line`](Self::line),
+/// [`scatter`](Self::scatter), and [`bars`](Self::bars), then
+/// [`render`](Self::render).
+///
+/// Axis ranges fit the data, rounded out to tick marks, unless set. Points
+/// that aren't finite are skipped, and anything outside the ranges is
+/// clipped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Plot {
+    width: u32,
+    height: u32,
+    series: Vec<Series>,
+    x_range: Option<(f64, f64)>,
+    y_range: Option<(f64, f64)>,
+    background: Color,
+    axis_color: Color,
+    scale: u32,
+}
+
+impl Plot {
+    /// An empty white `width`x`height` chart.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            series: Vec::new(),
+            x_range: None,
+            y_range: None,
+            background: Color::WHITE,
+            axis_color: Color::BLACK,
+            scale: 1,
+        }
+    }
+
+    pub fn line(self, points: &[(f64, f64)], color: Color) -> Self {
+        self.series(SeriesKind::Line, points.to_vec(), color)
+    }
+
+    pub fn scatter(self, points: &[(f64, f64)], color: Color) -> Self {
+        self.series(SeriesKind::Scatter, points.to_vec(), color)
+    }
+
+    /// Bars for `values`, the first at x = 0.
+    pub fn bars(self, values: &[f64], color: Color) -> Self {
+        let points = values
+            .iter()
+            .enumerate()
+            .map(|(i, &value)| (i as f64, value))
+            .collect();
+        self.series(SeriesKind::Bar, points, color)
+    }
+
+    fn series(mut self, kind: SeriesKind, points: Vec<(f64, f64)>, color: Color) -> Self {
+        self.series.push(Series {
+            kind,
+            points,
+            color,
+        });
+        self
+    }
+
+    pub fn x_range(mut self, min: f64, max: f64) -> Self {
+        self.x_range = Some((min, max));
+        self
+    }
+
+    pub fn y_range(mut self, min: f64, max: f64) -> Self {
+        self.y_range = Some((min, max));
+        self
+    }
+
+    pub fn background(mut self, color: Color) -> Self {
+        self.background = color;
+        self
+    }
+
+    /// The color of the axes, ticks, and labels.
+    pub fn axis_color(mut self, color: Color) -> Self {
+        self.axis_color = color;
+        self
+    }
+
+    /// Draws text, lines, and markers this many times larger (at least 1),
+    /// for big charts or high-density screens.
+    pub fn scale(mut self, scale: u32) -> Self {
+        self.scale = scale.max(1);
+        self
+    }
+
+    /// Renders the chart as an 8-bit RGBA image. Fails if a color is a
+    /// palette index, or the image is too small to fit the axes.
+    pub fn render(&self) -> Result<PngImage, PngError> {
+        let rgba = |color: Color| -> Result<[u8; 4], PngError> {
+            let (r, g, b, a) = color.to_rgba().ok_or(PngError::ColorTypeError)?;
+            Ok([r, g, b, a])
+        };
+        let mut image = PngImage::new(self.width, self.height, ColorType::Rgba)?;
+        let mut canvas = Canvas {
+            width: self.width,
+            height: self.height,
+            pixels: rgba(self.background)?.repeat(self.width as usize * self.height as usize),
+        };
+        let axis = rgba(self.axis_color)?;
+        let s = self.scale;
+        let too_small = || PngError::InvalidDimensions(self.width, self.height);
+
+        let bars = self
+            .series
+            .iter()
+            .filter(|series| series.kind == SeriesKind::Bar)
+            .count();
+        let finite = |series: &Series| -> Vec<(f64, f64)> {
+            series
+                .points
+                .iter()
+                .copied()
+                .filter(|(x, y)| x.is_finite() && y.is_finite())
+                .collect()
+        };
+        let (mut xs, mut ys) = (Vec::new(), Vec::new());
+        for series in &self.series {
+            for (x, y) in finite(series) {
+                xs.push(x);
+                ys.push(y);
+                if series.kind == SeriesKind::Bar {
+                    // Room for the bar's width, and its base at zero
+                    xs.extend([x - 0.5, x + 0.5]);
+                    ys.push(0.0);
+                }
+            }
+        }
+
+        // The y axis first, since its labels decide where the plot area
+        // starts on the left
+        let label_height = GLYPH_HEIGHT * s;
+        let top = PADDING * s;
+        let bottom = self
+            .height
+            .checked_sub(PADDING * s + label_height + TICK_LENGTH * s + 1)
+            .filter(|&bottom| bottom > top)
+            .ok_or_else(too_small)?;
+        let y_axis = Axis::fit(&ys, self.y_range, (bottom - top) / (40 * s), false);
+        let y_labels: Vec<String> = y_axis.ticks.iter().map(|&t| y_axis.label(t)).collect();
+        let label_width = y_labels
+            .iter()
+            .map(|label| text_width(label, s))
+            .max()
+            .unwrap_or(0);
+        let left = PADDING * s + label_width + TICK_LENGTH * s;
+        let right = self
+            .width
+            .checked_sub(PADDING * s + 1)
+            .filter(|&right| right > left)
+            .ok_or_else(too_small)?;
+        let x_axis = Axis::fit(&xs, self.x_range, (right - left) / (60 * s), bars > 0);
+
+        let plot = Area {
+            left,
+            top,
+            right,
+            bottom,
+        };
+        let to_pixel = |x: f64, y: f64| {
+            (
+                left as f64 + x_axis.fraction(x) * (right - left) as f64,
+                bottom as f64 - y_axis.fraction(y) * (bottom - top) as f64,
+            )
+        };
+
+        // Bars first, so lines and dots show on top of them
+        let mut bar_index = 0;
+        for series in &self.series {
+            if series.kind != SeriesKind::Bar {
+                continue;
+            }
+            let color = rgba(series.color)?;
+            // Bars take 80% of each slot, split between the bar series
+            let slot = 0.8 / bars as f64;
+            let offset = -0.4 + slot * bar_index as f64;
+            for (x, y) in finite(series) {
+                let (x0, base) = to_pixel(x + offset, 0.0);
+                let (x1, end) = to_pixel(x + offset + slot, y);
+                canvas.fill(
+                    &plot,
+                    (x0.round(), base.min(end).round()),
+                    ((x1.round() - 1.0).max(x0.round()), base.max(end).round()),
+                    color,
+                );
+            }
+            bar_index += 1;
+        }
+        for series in &self.series {
+            let color = rgba(series.color)?;
+            let points: Vec<(f64, f64)> = finite(series)
+                .into_iter()
+                .map(|(x, y)| to_pixel(x, y))
+                .collect();
+            match series.kind {
+                SeriesKind::Line => {
+                    for pair in points.windows(2) {
+                        canvas.line(&plot, pair[0], pair[1], s, color);
+                    }
+                    if let [point] = points[..] {
+                        canvas.dot(&plot, point, s, color);
+                    }
+                }
+                SeriesKind::Scatter => {
+                    for &point in &points {
+                        canvas.dot(&plot, point, 2 * s, color);
+                    }
+                }
+                SeriesKind::Bar => {}
+            }
+        }
+
+        // Axes and ticks go over the data, and labels outside the plot area
+        let whole = Area {
+            left: 0,
+            top: 0,
+            right: self.width - 1,
+            bottom: self.height - 1,
+        };
+        canvas.fill(
+            &whole,
+            (left as f64, top as f64),
+            (left as f64, bottom as f64),
+            axis,
+        );
+        canvas.fill(
+            &whole,
+            (left as f64, bottom as f64),
+            (right as f64, bottom as f64),
+            axis,
+        );
+        for (&tick, label) in y_axis.ticks.iter().zip(&y_labels) {
+            let (_, y) = to_pixel(x_axis.min, tick);
+            let y = y.round();
+            canvas.fill(
+                &whole,
+                ((left - TICK_LENGTH * s) as f64, y),
+                (left as f64, y),
+                axis,
+            );
+            canvas.text(
+                label,
+                left as i64 - (TICK_LENGTH * s) as i64 - text_width(label, s) as i64 - s as i64,
+                y as i64 - (label_height / 2) as i64,
+                s,
+                axis,
+            );
+        }
+        for &tick in &x_axis.ticks {
+            let (x, _) = to_pixel(tick, y_axis.min);
+            let x = x.round();
+            canvas.fill(
+                &whole,
+                (x, bottom as f64),
+                (x, (bottom + TICK_LENGTH * s) as f64),
+                axis,
+            );
+            let label = x_axis.label(tick);
+            // Centered under the tick, but kept inside the image
+            let label_width = text_width(&label, s) as i64;
+            let label_left = (x as i64 - label_width / 2)
+                .min(self.width as i64 - label_width)
+                .max(0);
+            canvas.text(
+                &label,
+                label_left,
+                (bottom + TICK_LENGTH * s + s) as i64,
+                s,
+                axis,
+            );
+        }
+
+        image.data.replace(canvas.pixels)?;
+        Ok(image)
+    }
+}
+
+// An axis's range and where its ticks go
+struct Axis {
+    min: f64,
+    max: f64,
+    step: f64,
+    ticks: Vec<f64>,
+}
+
+impl Axis {
+    // Fits `values` unless `range` is given, with about `ticks` ticks at
+    // round numbers. Bar positions get whole-number ticks, and the range
+    // isn't rounded out, which would leave empty slots at the ends.
+    fn fit(values: &[f64], range: Option<(f64, f64)>, ticks: u32, bars: bool) -> Self {
+        let (mut min, mut max) = range.unwrap_or_else(|| {
+            values
+                .iter()
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &v| {
+                    (min.min(v), max.max(v))
+                })
+        });
+        if !(max - min).is_finite() {
+            (min, max) = (0.0, 1.0);
+        }
+        if min > max {
+            (min, max) = (max, min);
+        }
+        if min == max {
+            let pad = if min == 0.0 { 1.0 } else { min.abs() / 10.0 };
+            (min, max) = (min - pad, max + pad);
+        }
+
+        let mut step = nice_step((max - min) / ticks.max(2) as f64);
+        if bars {
+            step = step.max(1.0);
+        }
+        if range.is_none() && !bars {
+            min = (min / step).floor() * step;
+            max = (max / step).ceil() * step;
+        }
+        let first = (min / step).ceil() as i64;
+        let last = (max / step).floor() as i64;
+        let ticks = (first..=last).map(|i| i as f64 * step).collect();
+        Self {
+            min,
+            max,
+            step,
+            ticks,
+        }
+    }
+
+    // Where `value` falls between the ends, from 0 to 1
+    fn fraction(&self, value: f64) -> f64 {
+        (value - self.min) / (self.max - self.min)
+    }
+
+    // As many decimals as the step needs
+    fn label(&self, value: f64) -> String {
+        let decimals = (-self.step.log10().floor()).max(0.0) as usize;
+        // Avoid "-0" from rounding error
+        let value = if value.abs() < self.step / 2.0 {
+            0.0
+        } else {
+            value
+        };
+        format!("{:.*}", decimals, value)
+    }
+}
+
+// 1, 2, or 5 times a power of ten, near `raw`
+fn nice_step(raw: f64) -> f64 {
+    let magnitude = 10f64.powf(raw.log10().floor());
+    let normalized = raw / magnitude;
+    let nice = if normalized < 1.5 {
+        1.0
+    } else if normalized < 3.0 {
+        2.0
+    } else if normalized < 7.0 {
+        5.0
+    } else {
+        10.0
+    };
+    nice * magnitude
+}
+
+// Pixel bounds, inclusive
+struct Area {
+    left: u32,
+    top: u32,
+    right: u32,
+    bottom: u32,
+}
+
+struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn set(&mut self, x: i64, y: i64, color: [u8; 4]) {
+        if x >= 0 && y >= 0 && x < self.width as i64 && y < self.height as i64 {
+            let start = (y as usize * self.width as usize + x as usize) * 4;
+            self.pixels[start..start + 4].copy_from_slice(&color);
+        }
+    }
+
+    // Fills the rectangle between two corners, clipped to `area`
+    fn fill(&mut self, area: &Area, from: (f64, f64), to: (f64, f64), color: [u8; 4]) {
+        let clamp_x = |x: f64| x.clamp(area.left as f64, area.right as f64) as i64;
+        let clamp_y = |y: f64| y.clamp(area.top as f64, area.bottom as f64) as i64;
+        if from.0.min(to.0) > area.right as f64
+            || from.0.max(to.0) < area.left as f64
+            || from.1.min(to.1) > area.bottom as f64
+            || from.1.max(to.1) < area.top as f64
+        {
+            return;
+        }
+        let (x0, x1) = (clamp_x(from.0.min(to.0)), clamp_x(from.0.max(to.0)));
+        let (y0, y1) = (clamp_y(from.1.min(to.1)), clamp_y(from.1.max(to.1)));
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                self.set(x, y, color);
+            }
+        }
+    }
+
+    // A square of `size` pixels centered on each step along the line,
+    // clipped to `area`
+    fn line(&mut self, area: &Area, from: (f64, f64), to: (f64, f64), size: u32, color: [u8; 4]) {
+        let steps = (to.0 - from.0).abs().max((to.1 - from.1).abs()).ceil();
+        // Lines far outside the area would take too long to walk
+        if steps.is_nan() || steps >= 1e6 {
+            return;
+        }
+        let steps = steps.max(1.0);
+        let half = (size as f64 - 1.0) / 2.0;
+        for i in 0..=steps as u32 {
+            let t = i as f64 / steps;
+            let x = (from.0 + (to.0 - from.0) * t - half).round();
+            let y = (from.1 + (to.1 - from.1) * t - half).round();
+            let end = size as f64 - 1.0;
+            if x + end >= area.left as f64
+                && x <= area.right as f64
+                && y + end >= area.top as f64
+                && y <= area.bottom as f64
+            {
+                self.fill(area, (x, y), (x + end, y + end), color);
+            }
+        }
+    }
+
+    // A filled circle, clipped to `area`
+    fn dot(&mut self, area: &Area, center: (f64, f64), radius: u32, color: [u8; 4]) {
+        let r = radius as i64;
+        let (cx, cy) = (center.0.round(), center.1.round());
+        if !(cx.is_finite() && cy.is_finite()) {
+            return;
+        }
+        let (cx, cy) = (cx as i64, cy as i64);
+        for dy in -r..=r {
+            for dx in -r..=r {
+                let (x, y) = (cx + dx, cy + dy);
+                if dx * dx + dy * dy <= r * r + r
+                    && x >= area.left as i64
+                    && x <= area.right as i64
+                    && y >= area.top as i64
+                    && y <= area.bottom as i64
+                {
+                    self.set(x, y, color);
+                }
+            }
+        }
+    }
+
+    // Draws `text` with its top-left corner at (`x`, `y`)
+    fn text(&mut self, text: &str, x: i64, y: i64, scale: u32, color: [u8; 4]) {
+        let s = scale as i64;
+        for (i, c) in text.chars().enumerate() {
+            let left = x + i as i64 * (GLYPH_ADVANCE * scale) as i64;
+            for (row, bits) in glyph(c).iter().enumerate() {
+                for column in 0..GLYPH_WIDTH as i64 {
+                    if bits & (0b100 >> column) == 0 {
+                        continue;
+                    }
+                    for py in 0..s {
+                        for px in 0..s {
+                            self.set(left + column * s + px, y + row as i64 * s + py, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn text_width(text: &str, scale: u32) -> u32 {
+    let chars = text.chars().count() as u32;
+    (chars * GLYPH_ADVANCE).saturating_sub(GLYPH_ADVANCE - GLYPH_WIDTH) * scale
+}
+
+// Rows of three pixels, top to bottom, for the characters tick labels use
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT as usize] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        _ => [0; GLYPH_HEIGHT as usize],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::Decoder;
+    use crate::options::EncodeOptions;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const WHITE: [u8; 4] = [255; 4];
+    const BLACK: [u8; 4] = [0, 0, 0, 255];
+
+    // Renders, encodes, and decodes `plot`, so the checks see the output
+    // a client would
+    fn rendered(plot: &Plot) -> PngImage {
+        let mut encoded = Vec::new();
+        plot.render()
+            .unwrap()
+            .write_with_options(&mut encoded, &EncodeOptions::default())
+            .unwrap();
+        Decoder::new(encoded.as_slice()).decode().unwrap()
+    }
+
+    fn pixel(image: &PngImage, x: u32, y: u32) -> [u8; 4] {
+        let start = (y * image.width() + x) as usize * 4;
+        image.data()[start..start + 4].try_into().unwrap()
+    }
+
+    // For a 200x100 chart at scale 1 with "0.0", "0.5", "1.0" on the
+    // y axis: the plot area runs from (23, 8) to (191, 82)
+    const LEFT: u32 = 23;
+    const TOP: u32 = 8;
+    const RIGHT: u32 = 191;
+    const BOTTOM: u32 = 82;
+
+    #[test]
+    fn draws_axes_around_the_plot_area() {
+        let image = rendered(&Plot::new(200, 100));
+        assert_eq!((image.width(), image.height()), (200, 100));
+        assert_eq!(image.color_type(), ColorType::Rgba);
+        for y in TOP..=BOTTOM {
+            assert_eq!(pixel(&image, LEFT, y), BLACK, "y axis at {y}");
+        }
+        for x in LEFT..=RIGHT {
+            assert_eq!(pixel(&image, x, BOTTOM), BLACK, "x axis at {x}");
+        }
+        assert_eq!(pixel(&image, LEFT + 1, BOTTOM - 1), WHITE);
+        assert_eq!(pixel(&image, RIGHT, TOP), WHITE);
+        assert_eq!(pixel(&image, 199, 0), WHITE);
+    }
+
+    #[test]
+    fn lines_pass_through_their_points() {
+        let image = rendered(
+            &Plot::new(200, 100)
+                .line(&[(0.0, 0.0), (1.0, 1.0)], Color::Rgb(255, 0, 0))
+                .x_range(0.0, 1.0)
+                .y_range(0.0, 1.0),
+        );
+        let (mid_x, mid_y) = ((LEFT + RIGHT) / 2, (TOP + BOTTOM) / 2);
+        assert_eq!(pixel(&image, mid_x, mid_y), RED);
+        assert_eq!(pixel(&image, RIGHT, TOP), RED);
+        assert_eq!(pixel(&image, mid_x, TOP + 4), WHITE);
+        assert_eq!(pixel(&image, mid_x, BOTTOM - 4), WHITE);
+    }
+
+    #[test]
+    fn bars_fill_from_zero_to_their_value() {
+        let image = rendered(
+            &Plot::new(200, 100)
+                .bars(&[1.0], Color::Rgb(255, 0, 0))
+                .y_range(0.0, 1.0),
+        );
+        // One bar over x = -0.5..0.5 takes the middle 80% of the width
+        let width = RIGHT - LEFT;
+        let (x0, x1) = (LEFT + width / 10 + 1, RIGHT - width / 10 - 2);
+        for y in [TOP, (TOP + BOTTOM) / 2, BOTTOM - 1] {
+            assert_eq!(pixel(&image, x0, y), RED);
+            assert_eq!(pixel(&image, x1, y), RED);
+            assert_eq!(pixel(&image, LEFT + 2, y), WHITE);
+            assert_eq!(pixel(&image, RIGHT - 2, y), WHITE);
+        }
+    }
+
+    #[test]
+    fn refuses_palette_colors_and_tiny_images() {
+        assert!(matches!(
+            Plot::new(200, 100).background(Color::Index(0)).render(),
+            Err(PngError::ColorTypeError)
+        ));
+        assert!(matches!(
+            Plot::new(20, 20).render(),
+            Err(PngError::InvalidDimensions(20, 20))
+        ));
+    }
+}
@@ -0,0 +1,272 @@
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::decoder::Decoder;
+use crate::error::PngError;
+use crate::limits::DecodeLimits;
+use crate::options::EncodeOptions;
+use crate::PngImage;
+
+type Encoded = Result<Vec<u8>, PngError>;
+type Job = (PngImage, EncodeOptions, Sender<Encoded>);
+
+/// A fixed set of worker threads that encode images handed to them. Images
+/// are encoded with the options they carry, as
+/// [`PngImage::write_to_file`] does, unless submitted with others through
+/// [`submit_with_options`](Self::submit_with_options). Dropping the pool
+/// waits for queued images to finish.
+pub struct EncoderPool {
+    jobs: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+/// An image queued on an [`EncoderPool`].
+pub struct EncodeHandle(Receiver<Encoded>);
+
+impl EncodeHandle {
+    /// Blocks until the image has been encoded.
+    pub fn wait(self) -> Encoded {
+        self.0
+            .recv()
+            .unwrap_or_else(|_| Err(PngError::Io(io::Error::other("encoder thread panicked"))))
+    }
+}
+
+impl EncoderPool {
+    /// Starts `threads` workers, or one per CPU if `threads` is 0.
+    pub fn new(threads: usize) -> Self {
+        let threads = match threads {
+            0 => thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        };
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let queue = Arc::new(Mutex::new(queue));
+
+        let workers = (0..threads)
+            .map(|_| {
+                let queue = Arc::clone(&queue);
+                thread::spawn(move || loop {
+                    // Hold the lock only while taking a job
+                    let job = queue.lock().map(|queue| queue.recv());
+                    let Ok(Ok((image, options, result))) = job else {
+                        return;
+                    };
+                    let mut encoded = Vec::new();
+                    let outcome = image
+                        .write_with_options(&mut encoded, &options)
+                        .map(|_| encoded);
+                    // The caller may have dropped its handle
+                    let _ = result.send(outcome);
+                })
+            })
+            .collect();
+
+        Self {
+            jobs: Some(jobs),
+            workers,
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Queues `image` for encoding with the options it carries.
+    pub fn submit(&self, image: PngImage) -> EncodeHandle {
+        let options = image.options.clone();
+        self.submit_with_options(image, options)
+    }
+
+    /// Queues `image` for encoding with `options`.
+    pub fn submit_with_options(&self, image: PngImage, options: EncodeOptions) -> EncodeHandle {
+        let (result, handle) = mpsc::channel();
+        if let Some(jobs) = &self.jobs {
+            // Only fails if every worker has panicked, which `wait` reports
+            let _ = jobs.send((image, options, result));
+        }
+        EncodeHandle(handle)
+    }
+
+    /// Encodes every image, returning the results in the same order.
+    pub fn encode_all(&self, images: impl IntoIterator<Item = PngImage>) -> Vec<Encoded> {
+        let handles: Vec<EncodeHandle> = images.into_iter().map(|i| self.submit(i)).collect();
+        handles.into_iter().map(EncodeHandle::wait).collect()
+    }
+
+    /// Encodes every image and writes it to `dir`, which is created if
+    /// needed, returning the paths written. File names come from `template`
+    /// with its placeholder replaced by the image's position: `{}` as is, or
+    /// `{:04}` zero-padded to four digits, as in `frame_{:04}.png`. Stops at
+    /// the first error, possibly after writing some files.
+    pub fn write_all_to_dir(
+        &self,
+        images: impl IntoIterator<Item = PngImage>,
+        dir: impl AsRef<Path>,
+        template: &str,
+    ) -> Result<Vec<PathBuf>, PngError> {
+        // Check the template before doing any work
+        format_file_name(template, 0)?;
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+
+        let handles: Vec<EncodeHandle> = images.into_iter().map(|i| self.submit(i)).collect();
+        handles
+            .into_iter()
+            .enumerate()
+            .map(|(index, handle)| {
+                let path = dir.join(format_file_name(template, index)?);
+                fs::write(&path, handle.wait()?)?;
+                Ok(path)
+            })
+            .collect()
+    }
+}
+
+// Replaces the single `{}` or `{:0N}` placeholder in `template`
+fn format_file_name(template: &str, index: usize) -> Result<String, PngError> {
+    let invalid = || PngError::InvalidTemplate(template.to_string());
+    let start = template.find('{').ok_or_else(invalid)?;
+    let end = start + template[start..].find('}').ok_or_else(invalid)?;
+    let (prefix, suffix) = (&template[..start], &template[end + 1..]);
+    if suffix.contains(['{', '}']) {
+        return Err(invalid());
+    }
+
+    let number = match &template[start + 1..end] {
+        "" => index.to_string(),
+        spec => {
+            let width = spec.strip_prefix(":0").ok_or_else(invalid)?;
+            let width: usize = width.parse().map_err(|_| invalid())?;
+            format!("{:0width$}", index)
+        }
+    };
+    Ok(format!("{}{}{}", prefix, number, suffix))
+}
+
+impl Drop for EncoderPool {
+    fn drop(&mut self) {
+        // Closing the queue stops each worker once it is empty
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Settings for [`decode_many_with_options`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DecodeManyOptions {
+    /// Files decoded at once, or one per CPU if 0
+    pub threads: usize,
+    /// Applied to each file on its own
+    pub limits: DecodeLimits,
+    /// Files larger than this many bytes fail with
+    /// [`PngError::LimitExceeded`] without being read
+    pub max_file_size: Option<u64>,
+}
+
+/// Decodes every file on a thread per CPU, returning the results in the
+/// same order as `paths`; see [`decode_many_with_options`].
+pub fn decode_many<P: AsRef<Path> + Sync>(paths: &[P]) -> Vec<Result<PngImage, PngError>> {
+    decode_many_with_options(paths, &DecodeManyOptions::default())
+}
+
+/// Decodes every file in parallel, returning the results in the same order
+/// as `paths`. A file that fails doesn't stop the others.
+pub fn decode_many_with_options<P: AsRef<Path> + Sync>(
+    paths: &[P],
+    options: &DecodeManyOptions,
+) -> Vec<Result<PngImage, PngError>> {
+    let threads = match options.threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+
+    let next = AtomicUsize::new(0);
+    let decoded: Vec<(usize, Result<PngImage, PngError>)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.min(paths.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut decoded = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(path) = paths.get(i) else {
+                            return decoded;
+                        };
+                        decoded.push((i, decode_file(path.as_ref(), options)));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| {
+                // Files a panicked worker took are reported below
+                worker.join().unwrap_or_default()
+            })
+            .collect()
+    });
+
+    let mut results: Vec<Option<Result<PngImage, PngError>>> =
+        std::iter::repeat_with(|| None).take(paths.len()).collect();
+    for (i, result) in decoded {
+        results[i] = Some(result);
+    }
+    results
+        .into_iter()
+        .map(|result| {
+            result.unwrap_or_else(|| Err(PngError::Io(io::Error::other("decoder thread panicked"))))
+        })
+        .collect()
+}
+
+// The most memory set aside for a file before reading it
+const MAX_PREALLOCATION: u64 = 64 << 20;
+
+fn decode_file(path: &Path, options: &DecodeManyOptions) -> Result<PngImage, PngError> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    if let Some(max) = options.max_file_size.filter(|&max| size > max) {
+        return Err(PngError::LimitExceeded(format!(
+            "file is {} bytes, more than {}",
+            size, max
+        )));
+    }
+    // The size is only a hint: a sparse file can claim far more than it
+    // holds, and reading grows the buffer past this as the data arrives
+    let mut data = Vec::with_capacity(size.min(MAX_PREALLOCATION) as usize);
+    file.read_to_end(&mut data)?;
+    Decoder::new(data.as_slice())
+        .limits(options.limits)
+        .decode_borrowed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_images::noise;
+    use crate::{BitDepth, ColorType};
+
+    #[test]
+    fn pool_encodes_with_submitted_options() {
+        let pool = EncoderPool::new(2);
+        // Flat gray, which compresses to almost nothing unless stored
+        let mut image = noise(64, 64, ColorType::Rgb, BitDepth::Eight, 5);
+        image.data_mut().fill(128);
+        let stored = pool
+            .submit_with_options(image.clone(), EncodeOptions::uncompressed())
+            .wait()
+            .unwrap();
+        let default = pool.submit(image.clone()).wait().unwrap();
+        assert!(stored.len() > default.len());
+        for encoded in [stored, default] {
+            let decoded = PngImage::read_from_file(encoded.as_slice()).unwrap();
+            assert_eq!(decoded.data(), image.data());
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::error::PngError;
+
+/// Signals a running encode or decode to stop. Clones share the same flag,
+/// so one can be kept by a UI thread while another is given to the work.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes the operation fail with
+    /// [`PngError::Cancelled`](crate::PngError::Cancelled) at the next
+    /// scanline.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Filtering,
+    Compressing,
+    Decoding,
+}
+
+/// How far an operation has got, in scanlines of the current phase.
+/// Interlaced images count the scanlines of every pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub phase: Phase,
+    pub rows_done: usize,
+    pub total_rows: usize,
+}
+
+/// A progress callback and cancellation token for
+/// [`PngImage::write_with_progress`](crate::PngImage::write_with_progress)
+/// and [`Decoder::progress`](crate::Decoder::progress).
+#[derive(Default)]
+pub struct ProgressHook {
+    callback: Option<Box<dyn FnMut(Progress) + Send>>,
+    every: usize,
+    cancel: Option<CancelToken>,
+    current: Option<Progress>,
+}
+
+impl ProgressHook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Calls `callback` every `rows` scanlines, and at the end of each phase.
+    pub fn on_progress(
+        mut self,
+        rows: usize,
+        callback: impl FnMut(Progress) + Send + 'static,
+    ) -> Self {
+        self.callback = Some(Box::new(callback));
+        self.every = rows.max(1);
+        self
+    }
+
+    /// Checks `token` before every scanline.
+    pub fn cancel_token(mut self, token: CancelToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    pub(crate) fn begin(&mut self, phase: Phase, total_rows: usize) -> Result<(), PngError> {
+        self.current = Some(Progress {
+            phase,
+            rows_done: 0,
+            total_rows,
+        });
+        self.check_cancelled()
+    }
+
+    // Called after each scanline of the current phase
+    pub(crate) fn advance(&mut self) -> Result<(), PngError> {
+        let Some(progress) = &mut self.current else {
+            return Ok(());
+        };
+        progress.rows_done += 1;
+        if let Some(callback) = &mut self.callback {
+            if progress.rows_done % self.every == 0 || progress.rows_done == progress.total_rows {
+                callback(*progress);
+            }
+        }
+        self.check_cancelled()
+    }
+
+    fn check_cancelled(&self) -> Result<(), PngError> {
+        if self.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+            return Err(PngError::Cancelled);
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for ProgressHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProgressHook")
+            .field("callback", &self.callback.is_some())
+            .field("every", &self.every)
+            .field("cancel", &self.cancel)
+            .field("current", &self.current)
+            .finish()
+    }
+}
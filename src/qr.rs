@@ -0,0 +1,150 @@
+use qrcode::{Color, EcLevel, QrCode};
+
+use crate::error::PngError;
+use crate::{BitDepth, ColorType, PngImage};
+
+// Light modules around the symbol, the minimum the QR specification asks
+// for so scanners can find its edges
+const QUIET_ZONE: usize = 4;
+
+/// How much of a QR code can be damaged or covered and still scan. Higher
+/// levels make the code denser for the same data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QrErrorCorrection {
+    /// About 7% of the code can be recovered
+    Low,
+    /// About 15%
+    #[default]
+    Medium,
+    /// About 25%
+    Quartile,
+    /// About 30%, enough to put a small logo over the middle
+    High,
+}
+
+impl PngImage {
+    /// A QR code holding `data`, as a 1-bit grayscale image with each module
+    /// `module_size` pixels square, black on white, and the four-module
+    /// quiet zone scanners need around it. The smallest QR version that
+    /// fits the data is used.
+    pub fn qr(
+        data: &[u8],
+        module_size: u32,
+        ec_level: QrErrorCorrection,
+    ) -> Result<PngImage, PngError> {
+        let ec_level = match ec_level {
+            QrErrorCorrection::Low => EcLevel::L,
+            QrErrorCorrection::Medium => EcLevel::M,
+            QrErrorCorrection::Quartile => EcLevel::Q,
+            QrErrorCorrection::High => EcLevel::H,
+        };
+        let code = QrCode::with_error_correction_level(data, ec_level)
+            .map_err(|e| PngError::QrCode(e.to_string()))?;
+        let modules = code.width();
+        let colors = code.to_colors();
+
+        let side = (modules + 2 * QUIET_ZONE) as u64 * module_size as u64;
+        let side = u32::try_from(side).unwrap_or(u32::MAX);
+        let mut image = PngImage::with_bit_depth(side, side, ColorType::Grayscale, BitDepth::One)?;
+        let module_size = module_size as usize;
+        let mut pixels = Vec::with_capacity(side as usize * side as usize);
+        for y in 0..side as usize {
+            let row = (y / module_size).checked_sub(QUIET_ZONE);
+            for x in 0..side as usize {
+                let column = (x / module_size).checked_sub(QUIET_ZONE);
+                let is_dark = match (row, column) {
+                    (Some(row), Some(column)) if row < modules && column < modules => {
+                        colors[row * modules + column] == Color::Dark
+                    }
+                    _ => false,
+                };
+                pixels.push(!is_dark as u8);
+            }
+        }
+        image.data.replace(pixels)?;
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::Decoder;
+    use crate::options::EncodeOptions;
+
+    // Encodes and decodes a code so the checks see what a scanner would
+    fn decoded(data: &[u8], module_size: u32, ec_level: QrErrorCorrection) -> PngImage {
+        let image = PngImage::qr(data, module_size, ec_level).unwrap();
+        let mut encoded = Vec::new();
+        image
+            .write_with_options(&mut encoded, &EncodeOptions::default())
+            .unwrap();
+        Decoder::new(encoded.as_slice()).decode().unwrap()
+    }
+
+    // Whether the module at (`column`, `row`) of the symbol, not counting
+    // the quiet zone, is dark in every pixel
+    fn dark(image: &PngImage, module_size: u32, column: usize, row: usize) -> bool {
+        let side = image.width() as usize;
+        let module_size = module_size as usize;
+        let (left, top) = (
+            (column + QUIET_ZONE) * module_size,
+            (row + QUIET_ZONE) * module_size,
+        );
+        (top..top + module_size)
+            .all(|y| (left..left + module_size).all(|x| image.data()[y * side + x] == 0))
+    }
+
+    #[test]
+    fn draws_modules_at_the_given_size_inside_a_quiet_zone() {
+        let image = decoded(b"hello", 3, QrErrorCorrection::Medium);
+        // Version 1 is 21 modules across
+        assert_eq!((image.width(), image.height()), (87, 87));
+        assert_eq!(image.color_type(), ColorType::Grayscale);
+        assert_eq!(image.bit_depth(), BitDepth::One);
+
+        let code = QrCode::with_error_correction_level(b"hello", EcLevel::M).unwrap();
+        let colors = code.to_colors();
+        for row in 0..21 {
+            for column in 0..21 {
+                assert_eq!(
+                    dark(&image, 3, column, row),
+                    colors[row * 21 + column] == Color::Dark,
+                    "module ({column}, {row})"
+                );
+            }
+        }
+        let quiet = (QUIET_ZONE * 3) as u32;
+        for y in 0..87 {
+            for x in 0..87 {
+                if x < quiet || y < quiet || x >= 87 - quiet || y >= 87 - quiet {
+                    assert_eq!(image.data()[(y * 87 + x) as usize], 1, "({x}, {y})");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn finder_patterns_sit_in_three_corners() {
+        let image = decoded(b"https://example.com", 2, QrErrorCorrection::Low);
+        let modules = image.width() as usize / 2 - 2 * QUIET_ZONE;
+        for (left, top) in [(0, 0), (modules - 7, 0), (0, modules - 7)] {
+            for i in 0..7 {
+                // The dark outer ring
+                assert!(dark(&image, 2, left + i, top));
+                assert!(dark(&image, 2, left + i, top + 6));
+                assert!(dark(&image, 2, left, top + i));
+                assert!(dark(&image, 2, left + 6, top + i));
+            }
+            // A light ring, then a dark 3x3 center
+            assert!(!dark(&image, 2, left + 1, top + 1));
+            assert!(dark(&image, 2, left + 3, top + 3));
+        }
+    }
+
+    #[test]
+    fn reports_data_too_long_for_any_version() {
+        let result = PngImage::qr(&[b'x'; 4000], 1, QrErrorCorrection::High);
+        assert!(matches!(result, Err(PngError::QrCode(_))));
+    }
+}
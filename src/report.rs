@@ -0,0 +1,54 @@
+use std::time::Duration;
+
+use crate::filter::FilterType;
+use crate::info::ChunkSummary;
+
+/// What [`PngImage::write_with_report`](crate::PngImage::write_with_report)
+/// wrote, for tuning encoder settings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodeReport {
+    /// Every chunk in the output, with offsets from the start of the stream
+    pub chunks: Vec<ChunkSummary>,
+    /// Scanlines per filter type, indexed by `FilterType as usize`
+    pub filters: [usize; 5],
+    /// Filtered scanline bytes passed to the compressor, filter bytes included
+    pub raw_bytes: usize,
+    /// Compressed image data, across all IDAT chunks
+    pub compressed_bytes: usize,
+    /// Size of the whole stream
+    pub total_bytes: u64,
+    pub elapsed: Duration,
+}
+
+impl EncodeReport {
+    pub fn filter_count(&self, filter: FilterType) -> usize {
+        self.filters[filter as usize]
+    }
+
+    /// Raw scanline bytes per compressed byte.
+    pub fn compression_ratio(&self) -> f64 {
+        self.raw_bytes as f64 / self.compressed_bytes.max(1) as f64
+    }
+}
+
+/// What an animation encodes to, from
+/// [`Animation::estimate_size`](crate::Animation::estimate_size).
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationSize {
+    /// Bytes each frame adds, its fcTL chunk included
+    pub frame_bytes: Vec<u64>,
+    /// Size of the whole stream
+    pub total_bytes: u64,
+    /// Time to play the animation once
+    pub duration_ms: f64,
+}
+
+impl AnimationSize {
+    /// Average bitrate over one play, or 0 for an animation with no delays.
+    pub fn bits_per_second(&self) -> f64 {
+        if self.duration_ms <= 0.0 {
+            return 0.0;
+        }
+        self.total_bytes as f64 * 8.0 * 1000.0 / self.duration_ms
+    }
+}
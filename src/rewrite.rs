@@ -0,0 +1,217 @@
+use std::io::{self, Read, Write};
+
+use crate::chunks::{check_ancillary, follows_palette, Chunk, ChunkReader, PNG_SIGNATURE};
+use crate::error::PngError;
+use crate::text::{is_text_chunk, TextChunk};
+
+/// Classes of metadata chunks removed by [`Rewriter::strip`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StripPolicy {
+    /// tEXt, zTXt, and iTXt, which includes XMP
+    pub text: bool,
+    /// tIME
+    pub time: bool,
+    /// eXIf
+    pub exif: bool,
+    /// Private chunks, whose second letter is lowercase
+    pub private: bool,
+}
+
+impl StripPolicy {
+    /// Everything above, for scrubbing files before publishing them.
+    pub const ALL: StripPolicy = StripPolicy {
+        text: true,
+        time: true,
+        exif: true,
+        private: true,
+    };
+
+    pub fn matches(&self, chunk: &Chunk) -> bool {
+        let chunk_type = &chunk.chunk_type;
+        !chunk.is_critical()
+            && ((self.text && is_text_chunk(chunk_type))
+                || (self.time && chunk_type == b"tIME")
+                || (self.exif && chunk_type == b"eXIf")
+                || (self.private && chunk_type[1].is_ascii_lowercase()))
+    }
+}
+
+/// Copies `reader` to `writer` without the chunks `policy` matches; see
+/// [`Rewriter`].
+pub fn strip_chunks<R: Read, W: Write>(
+    reader: R,
+    writer: &mut W,
+    policy: StripPolicy,
+) -> Result<(), PngError> {
+    Rewriter::new().strip(policy).rewrite(reader, writer)
+}
+
+/// A chunk whose CRC [`repair_crcs`] replaced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CrcRepair {
+    pub chunk_type: [u8; 4],
+    /// Byte offset of the chunk's length field
+    pub offset: u64,
+    pub stored: u32,
+    pub computed: u32,
+}
+
+/// Copies `reader` to `writer`, replacing every chunk CRC that doesn't
+/// match its chunk with the correct one, and returns the chunks it fixed.
+/// Everything else, including any bytes after IEND, is copied unchanged.
+///
+/// This makes readers accept a file whose CRCs were damaged, but a
+/// transfer that garbled the chunk data as well leaves the image corrupt;
+/// decode the result to check.
+pub fn repair_crcs<R: Read, W: Write>(
+    reader: R,
+    writer: &mut W,
+) -> Result<Vec<CrcRepair>, PngError> {
+    let mut chunks = ChunkReader::new(reader)?;
+    writer.write_all(&PNG_SIGNATURE)?;
+    let mut repairs = Vec::new();
+    while let Some(mut chunk) = chunks.next_chunk()? {
+        if !chunk.crc_ok() {
+            repairs.push(CrcRepair {
+                chunk_type: chunk.chunk_type,
+                offset: chunk.offset,
+                stored: chunk.crc,
+                computed: chunk.expected_crc(),
+            });
+            chunk.crc = chunk.expected_crc();
+        }
+        chunk.write_stored(writer)?;
+    }
+    io::copy(&mut chunks.into_inner(), writer)?;
+    Ok(repairs)
+}
+
+/// Copies the PNG stream in `reader`, through its IEND chunk, to `writer`
+/// and returns whatever followed IEND, so that appended data can be
+/// stripped or saved for inspection; see
+/// [`PngInfo::trailing`](crate::PngInfo::trailing). Chunks are copied as
+/// stored.
+pub fn split_trailing_data<R: Read, W: Write>(
+    reader: R,
+    writer: &mut W,
+) -> Result<Vec<u8>, PngError> {
+    let mut chunks = ChunkReader::new(reader)?;
+    writer.write_all(&PNG_SIGNATURE)?;
+    while let Some(chunk) = chunks.next_chunk()? {
+        chunk.write_stored(writer)?;
+    }
+    let mut trailing = Vec::new();
+    chunks.into_inner().read_to_end(&mut trailing)?;
+    Ok(trailing)
+}
+
+/// Copies a PNG stream chunk by chunk while adding, removing, or replacing
+/// ancillary chunks. Image data is never decompressed, so the cost is that
+/// of copying the file, and chunks that aren't touched keep their stored
+/// CRC.
+///
+/// New chunks are placed where they are valid: bKGD, hIST, and tRNS just
+/// before the first IDAT and everything else before PLTE (or the first IDAT
+/// if there is no palette).
+#[derive(Debug, Clone, Default)]
+pub struct Rewriter {
+    inserted: Vec<Chunk>,
+    texts: Vec<TextChunk>,
+    removed: Vec<[u8; 4]>,
+    removed_text: Vec<String>,
+    strip: StripPolicy,
+}
+
+impl Rewriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an ancillary chunk, keeping any existing chunks of its type.
+    pub fn insert(mut self, chunk_type: [u8; 4], data: Vec<u8>) -> Self {
+        self.inserted.push(Chunk::new(chunk_type, data));
+        self
+    }
+
+    /// Removes every existing chunk of `chunk_type`, then adds the new one.
+    pub fn replace(self, chunk_type: [u8; 4], data: Vec<u8>) -> Self {
+        self.remove(chunk_type).insert(chunk_type, data)
+    }
+
+    /// Removes every existing chunk of `chunk_type`. Chunks added through
+    /// this rewriter are kept.
+    pub fn remove(mut self, chunk_type: [u8; 4]) -> Self {
+        self.removed.push(chunk_type);
+        self
+    }
+
+    /// Adds a text entry, replacing existing entries with the same keyword.
+    pub fn set_text(mut self, text: TextChunk) -> Self {
+        self.texts.push(text);
+        self
+    }
+
+    /// Removes text entries with `keyword`, in any of the three text chunks.
+    pub fn remove_text(mut self, keyword: &str) -> Self {
+        self.removed_text.push(keyword.to_string());
+        self
+    }
+
+    /// Removes existing chunks in the classes `policy` selects, on top of
+    /// any earlier `strip`. Chunks added through this rewriter are kept.
+    pub fn strip(mut self, policy: StripPolicy) -> Self {
+        self.strip.text |= policy.text;
+        self.strip.time |= policy.time;
+        self.strip.exif |= policy.exif;
+        self.strip.private |= policy.private;
+        self
+    }
+
+    /// Copies `reader` to `writer` with the edits applied.
+    pub fn rewrite<R: Read, W: Write>(&self, reader: R, writer: &mut W) -> Result<(), PngError> {
+        let mut inserted = self.inserted.clone();
+        for text in &self.texts {
+            inserted.push(Chunk::new(text.chunk_type(), text.encode()?));
+        }
+        for chunk in &inserted {
+            check_ancillary(&chunk.chunk_type)?;
+        }
+        let (mut late, mut early): (Vec<Chunk>, Vec<Chunk>) = inserted
+            .into_iter()
+            .partition(|c| follows_palette(&c.chunk_type));
+
+        let mut chunks = ChunkReader::new(reader)?;
+        writer.write_all(&PNG_SIGNATURE)?;
+        while let Some(chunk) = chunks.next_chunk()? {
+            let pending: Vec<Chunk> = match &chunk.chunk_type {
+                b"PLTE" => std::mem::take(&mut early),
+                b"IDAT" | b"IEND" => early.drain(..).chain(late.drain(..)).collect(),
+                _ => Vec::new(),
+            };
+            for new_chunk in &pending {
+                new_chunk.write_stored(writer)?;
+            }
+            if !self.removes(&chunk)? {
+                chunk.write_stored(writer)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn removes(&self, chunk: &Chunk) -> Result<bool, PngError> {
+        if chunk.is_critical() {
+            return Ok(false);
+        }
+        if self.removed.contains(&chunk.chunk_type) || self.strip.matches(chunk) {
+            return Ok(true);
+        }
+        if is_text_chunk(&chunk.chunk_type)
+            && !(self.texts.is_empty() && self.removed_text.is_empty())
+        {
+            let keyword = TextChunk::parse(&chunk.chunk_type, &chunk.data)?.keyword;
+            return Ok(self.removed_text.contains(&keyword)
+                || self.texts.iter().any(|t| t.keyword == keyword));
+        }
+        Ok(false)
+    }
+}
@@ -0,0 +1,65 @@
+use crate::error::PngError;
+use crate::{image_size, BitDepth, ColorType, PngImage};
+
+impl PngImage {
+    /// Creates a 16-bit image from native `u16` samples, row by row with no
+    /// padding.
+    pub fn from_raw_u16(
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+        samples: &[u16],
+    ) -> Result<Self, PngError> {
+        let mut image = Self::with_bit_depth(width, height, color_type, BitDepth::Sixteen)?;
+        let channels = color_type.channels();
+        let expected = image_size(width, height, channels)?;
+        if samples.len() != expected {
+            return Err(PngError::PixelCountMismatch {
+                expected: expected / channels,
+                actual: samples.len() / channels,
+                dimensions: (width, height),
+            });
+        }
+        image.data.replace(image.samples_to_bytes(samples)?)?;
+        Ok(image)
+    }
+
+    /// Like [`PngImage::add_pixel`], but with one `u16` per sample instead of
+    /// byte pairs. Below 16 bits each value must fit the bit depth.
+    pub fn add_pixel_u16(&mut self, components: &[u16]) -> Result<(), PngError> {
+        if components.len() != self.color_type.channels() {
+            return Err(PngError::ComponentCountMismatch {
+                expected: self.color_type.channels(),
+                actual: components.len(),
+                color_type: self.color_type,
+            });
+        }
+        let components = self.samples_to_bytes(components)?;
+        self.add_pixel(&components)
+    }
+
+    /// Like [`PngImage::set_row`], but with one `u16` per sample.
+    pub fn set_row_u16(&mut self, y: u32, row: &[u16]) -> Result<(), PngError> {
+        let row = self.samples_to_bytes(row)?;
+        self.set_row(y, &row)
+    }
+
+    // Converts samples to the byte layout `add_pixel` takes
+    fn samples_to_bytes(&self, samples: &[u16]) -> Result<Vec<u8>, PngError> {
+        if self.bit_depth == BitDepth::Sixteen {
+            return Ok(samples.iter().flat_map(|s| s.to_be_bytes()).collect());
+        }
+        samples
+            .iter()
+            .map(|&value| {
+                if value > self.bit_depth.max_value() {
+                    return Err(PngError::SampleOutOfRange {
+                        value,
+                        bit_depth: self.bit_depth.bits(),
+                    });
+                }
+                Ok(value as u8)
+            })
+            .collect()
+    }
+}
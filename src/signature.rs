@@ -0,0 +1,116 @@
+use std::io::{Read, Write};
+
+use crate::chunks::{Chunk, ChunkReader, PNG_SIGNATURE};
+use crate::error::PngError;
+
+// Layout used here: the first dSIG directly follows IHDR and holds the
+// signature, the second directly precedes IEND and is empty. The signed
+// content is IHDR and every chunk between the pair, exactly as stored
+// (length, type, data, and CRC), so any change to them breaks the signature.
+
+/// Copies a PNG stream, adding a dSIG pair with the signature `sign`
+/// returns for the content. Any existing dSIG chunks are replaced.
+pub fn add_signature<R, W, F>(reader: R, writer: &mut W, sign: F) -> Result<(), PngError>
+where
+    R: Read,
+    W: Write,
+    F: FnOnce(&[u8]) -> Result<Vec<u8>, PngError>,
+{
+    let chunks: Vec<Chunk> = ChunkReader::new(reader)?
+        .filter(|c| !matches!(c, Ok(chunk) if &chunk.chunk_type == b"dSIG"))
+        .collect::<Result<_, _>>()?;
+    let (header, rest) = split_header(&chunks)?;
+    let body = match rest.split_last() {
+        Some((last, body)) if &last.chunk_type == b"IEND" => body,
+        _ => rest,
+    };
+
+    let content = signed_content(header, body)?;
+    let signature = sign(&content)?;
+
+    writer.write_all(&PNG_SIGNATURE)?;
+    header.write_stored(writer)?;
+    Chunk::new(*b"dSIG", signature).write_stored(writer)?;
+    for chunk in body {
+        chunk.write_stored(writer)?;
+    }
+    Chunk::new(*b"dSIG", Vec::new()).write_stored(writer)?;
+    Chunk::new(*b"IEND", Vec::new()).write_stored(writer)?;
+    Ok(())
+}
+
+/// Checks the dSIG pair written by [`add_signature`], passing the signed
+/// content and the stored signature to `verify`. Fails with
+/// [`PngError::MissingChunk`] if the stream isn't signed.
+pub fn verify_signature<R, F>(reader: R, verify: F) -> Result<bool, PngError>
+where
+    R: Read,
+    F: FnOnce(&[u8], &[u8]) -> Result<bool, PngError>,
+{
+    let chunks: Vec<Chunk> = ChunkReader::new(reader)?.collect::<Result<_, _>>()?;
+    let (header, rest) = split_header(&chunks)?;
+
+    let positions: Vec<usize> = rest
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| &c.chunk_type == b"dSIG")
+        .map(|(i, _)| i)
+        .collect();
+    let (first, last) = match positions.as_slice() {
+        [] => {
+            return Err(PngError::MissingChunk {
+                chunk_type: *b"dSIG",
+            })
+        }
+        [first, last] => (*first, *last),
+        // A lone dSIG, or the third of several
+        _ => {
+            let chunk = &rest[*positions.get(2).unwrap_or(&positions[0])];
+            return Err(PngError::UnexpectedChunk {
+                chunk_type: chunk.chunk_type,
+                offset: chunk.offset,
+                reason: "dSIG chunks must come in a single pair",
+            });
+        }
+    };
+    if first != 0 {
+        return Err(PngError::UnexpectedChunk {
+            chunk_type: *b"dSIG",
+            offset: rest[first].offset,
+            reason: "the first dSIG must directly follow IHDR",
+        });
+    }
+    if rest.get(last + 1).map(|c| &c.chunk_type) != Some(b"IEND") {
+        return Err(PngError::UnexpectedChunk {
+            chunk_type: *b"dSIG",
+            offset: rest[last].offset,
+            reason: "the last dSIG must directly precede IEND",
+        });
+    }
+
+    let content = signed_content(header, &rest[first + 1..last])?;
+    verify(&content, &rest[first].data)
+}
+
+fn split_header(chunks: &[Chunk]) -> Result<(&Chunk, &[Chunk]), PngError> {
+    match chunks.split_first() {
+        Some((header, rest)) if &header.chunk_type == b"IHDR" => Ok((header, rest)),
+        Some((chunk, _)) => Err(PngError::UnexpectedChunk {
+            chunk_type: chunk.chunk_type,
+            offset: chunk.offset,
+            reason: "IHDR must be the first chunk",
+        }),
+        None => Err(PngError::MissingChunk {
+            chunk_type: *b"IHDR",
+        }),
+    }
+}
+
+fn signed_content(header: &Chunk, body: &[Chunk]) -> Result<Vec<u8>, PngError> {
+    let mut content = Vec::new();
+    header.write_stored(&mut content)?;
+    for chunk in body {
+        chunk.write_stored(&mut content)?;
+    }
+    Ok(content)
+}
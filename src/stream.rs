@@ -0,0 +1,699 @@
+use std::io::Write;
+use std::time::Instant;
+
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+
+use crate::chunks::{ChunkWriter, PNG_SIGNATURE};
+use crate::dictionary::{zlib_header, Adler32, ZlibWriter};
+use crate::error::PngError;
+use crate::filter::RowFilter;
+use crate::info::ImageHeader;
+use crate::options::{EncodeOptions, Strictness};
+use crate::{pack_samples, BitDepth, ColorType, PngImage};
+use crate::{ChunkSummary, EncodeReport, Phase, ProgressHook};
+
+// Compressed data is written out once this much has built up
+pub(crate) const IDAT_SIZE: usize = 64 << 10;
+
+const CHECKPOINT_MAGIC: &[u8; 8] = b"PNGCKPT1";
+
+/// Encodes an image a row at a time as the rows are produced, for captures
+/// and renders too large or too slow to hold whole, writing image data out
+/// as it compresses.
+///
+/// The image's format, palette, and ancillary chunks come from a template
+/// [`PngImage`] whose pixels are ignored, so it can be left empty. Of the
+/// [`EncodeOptions`], the filter strategy, compression level, and
+/// deterministic chunk order apply, and pipelining makes no difference.
+/// Strict mode, palette optimization, and preset dictionaries are refused:
+/// the first two need the whole image, and resuming needs a plain zlib
+/// stream.
+///
+/// A long capture can stop with [`finish_partial`](Self::finish_partial),
+/// keeping the output written so far and a [`StreamCheckpoint`], and
+/// carry on in the same file with [`resume`](Self::resume), even from
+/// another process after a restart.
+pub struct StreamWriter<W: Write> {
+    inner: W,
+    width: u32,
+    height: u32,
+    color_type: ColorType,
+    bit_depth: BitDepth,
+    // Indexed images' palette size, to check rows against
+    palette_entries: Option<usize>,
+    header: Vec<u8>,
+    rows: u32,
+    filter: RowFilter,
+    encoder: DeflateEncoder<Vec<u8>>,
+    checksum: Adler32,
+    // Compressed bytes not yet written as IDAT
+    pending: Vec<u8>,
+    // Bytes written to `inner`, counting any written before a resume
+    offset: u64,
+    packed: Vec<u8>,
+    filtered: Vec<u8>,
+}
+
+/// Where a [`StreamWriter`] stopped: the rows written, the length of the
+/// output so far, and the state needed to continue it. Save it with
+/// [`to_bytes`](Self::to_bytes) to resume after a restart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamCheckpoint {
+    header: Vec<u8>,
+    rows: u32,
+    output_len: u64,
+    checksum: u32,
+    previous_row: Vec<u8>,
+}
+
+impl<W: Write> StreamWriter<W> {
+    /// Writes the signature and every chunk that goes before the image data
+    /// for `image`, ready for its first row.
+    pub fn new(inner: W, image: &PngImage, options: &EncodeOptions) -> Result<Self, PngError> {
+        let row_length = image.packed_row_length();
+        let mut writer = Self::start(inner, image, options, vec![0; row_length])?;
+        writer.inner.write_all(&PNG_SIGNATURE)?;
+        writer.offset = PNG_SIGNATURE.len() as u64;
+        for (chunk_type, data) in image.chunks_before_data(options) {
+            writer.write_chunk(&chunk_type, &data)?;
+        }
+        writer
+            .pending
+            .extend_from_slice(&zlib_header(options.compression_level, false));
+        Ok(writer)
+    }
+
+    /// Carries on an encode stopped by [`finish_partial`](Self::finish_partial)
+    /// with the same template `image`. `inner` must append to the output as
+    /// it was then, exactly [`StreamCheckpoint::output_len`] bytes long;
+    /// anything written after the checkpoint has to be cut off first.
+    pub fn resume(
+        inner: W,
+        image: &PngImage,
+        options: &EncodeOptions,
+        checkpoint: &StreamCheckpoint,
+    ) -> Result<Self, PngError> {
+        if checkpoint.header != image.generate_ihdr() {
+            return Err(PngError::InvalidCheckpoint(
+                "checkpoint is for an image of another size or format".to_string(),
+            ));
+        }
+        let mut writer = Self::start(inner, image, options, checkpoint.previous_row.clone())?;
+        writer.rows = checkpoint.rows;
+        writer.offset = checkpoint.output_len;
+        writer.checksum = Adler32::from_value(checkpoint.checksum);
+        Ok(writer)
+    }
+
+    fn start(
+        inner: W,
+        image: &PngImage,
+        options: &EncodeOptions,
+        previous_row: Vec<u8>,
+    ) -> Result<Self, PngError> {
+        if image.color_type == ColorType::Indexed && image.palette.is_none() {
+            return Err(PngError::InvalidPalette(
+                "Palette required for indexed color".to_string(),
+            ));
+        }
+        if options.compression_level > 9 {
+            return Err(PngError::Compression(format!(
+                "Invalid compression level {}",
+                options.compression_level
+            )));
+        }
+        let unsupported = if options.strictness == Strictness::Strict {
+            Some("strict mode checks a whole encoded image, which a stream never holds")
+        } else if options.optimize_palette {
+            Some("palette optimization needs every pixel before the palette is written")
+        } else if options.dictionary.is_some() {
+            Some("streams can't be compressed against a preset dictionary")
+        } else {
+            None
+        };
+        if let Some(reason) = unsupported {
+            return Err(PngError::UnsupportedOption(reason.to_string()));
+        }
+        let level = Compression::new(options.compression_level as u32);
+        // Filters operate on whole bytes, so sub-byte pixels use a distance of 1
+        let bytes_per_pixel = image.bytes_per_pixel().max(1);
+        Ok(Self {
+            inner,
+            width: image.width,
+            height: image.height,
+            color_type: image.color_type,
+            bit_depth: image.bit_depth,
+            palette_entries: image
+                .palette
+                .as_ref()
+                .filter(|_| image.color_type == ColorType::Indexed)
+                .map(|p| p.len() / 3),
+            header: image.generate_ihdr(),
+            rows: 0,
+            filter: RowFilter::resume(options.filter, previous_row, bytes_per_pixel),
+            encoder: DeflateEncoder::new(Vec::new(), level),
+            checksum: Adler32::new(),
+            pending: Vec::new(),
+            offset: 0,
+            packed: Vec::new(),
+            filtered: Vec::new(),
+        })
+    }
+
+    /// Encodes the next row, laid out as for [`PngImage::add_pixel`].
+    pub fn write_row(&mut self, row: &[u8]) -> Result<(), PngError> {
+        if self.rows == self.height {
+            return Err(PngError::PixelCountMismatch {
+                expected: self.width as usize * self.height as usize,
+                actual: self.width as usize * (self.rows as usize + 1),
+                dimensions: (self.width, self.height),
+            });
+        }
+        let samples_per_row = self.width as usize * self.color_type.channels();
+        let row_length = samples_per_row * self.bit_depth.bytes_per_sample();
+        if row.len() != row_length {
+            return Err(PngError::RowLengthMismatch {
+                expected: row_length,
+                actual: row.len(),
+            });
+        }
+
+        if let Some(entries) = self.palette_entries {
+            let mut bad = row
+                .iter()
+                .enumerate()
+                .filter(|&(_, &index)| index as usize >= entries);
+            if let Some((x, &index)) = bad.next() {
+                return Err(PngError::InvalidPaletteEntry {
+                    index,
+                    x: x as u32,
+                    y: self.rows,
+                    offset: self.rows as usize * self.width as usize + x,
+                    count: 1 + bad.count(),
+                    palette_entries: entries,
+                });
+            }
+        }
+
+        let bits = self.bit_depth.bits() as usize;
+        let row = if bits < 8 {
+            if let Some(&value) = row.iter().find(|&&v| v as u16 > self.bit_depth.max_value()) {
+                return Err(PngError::SampleOutOfRange {
+                    value: value as u16,
+                    bit_depth: bits as u8,
+                });
+            }
+            self.packed.clear();
+            self.packed.resize((samples_per_row * bits).div_ceil(8), 0);
+            pack_samples(row, bits, &mut self.packed);
+            &self.packed
+        } else {
+            row
+        };
+
+        self.filtered.clear();
+        self.filter.filter(row, &mut self.filtered);
+        self.checksum.update(&self.filtered);
+        self.encoder.write_all(&self.filtered)?;
+        self.rows += 1;
+
+        self.pending.append(self.encoder.get_mut());
+        if self.pending.len() >= IDAT_SIZE {
+            self.write_pending()?;
+        }
+        Ok(())
+    }
+
+    pub fn rows_written(&self) -> u32 {
+        self.rows
+    }
+
+    /// Writes the compressed data ready so far as a complete IDAT chunk and
+    /// flushes the underlying writer. This costs nothing in file size, but
+    /// the compressor may still be holding back the last rows written; see
+    /// [`sync_flush`](Self::sync_flush).
+    pub fn flush_frame(&mut self) -> Result<(), PngError> {
+        self.write_pending()?;
+        self.inner.flush()?;
+        Ok(())
+    }
+
+    /// Makes the compressor give up everything it holds, then writes it out
+    /// as [`flush_frame`](Self::flush_frame) does, so that a receiver can
+    /// decode every row written so far, as over a slow link. Each flush adds
+    /// a few bytes and can cost some compression, so flushing every row is
+    /// best avoided.
+    pub fn sync_flush(&mut self) -> Result<(), PngError> {
+        self.encoder.flush()?;
+        self.pending.append(self.encoder.get_mut());
+        self.flush_frame()
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Ends the image data and writes IEND, once every row has been
+    /// written, returning the underlying writer.
+    pub fn finish(mut self) -> Result<W, PngError> {
+        if self.rows != self.height {
+            return Err(PngError::PixelCountMismatch {
+                expected: self.width as usize * self.height as usize,
+                actual: self.width as usize * self.rows as usize,
+                dimensions: (self.width, self.height),
+            });
+        }
+        self.encoder.try_finish()?;
+        self.pending.append(self.encoder.get_mut());
+        self.pending
+            .extend_from_slice(&self.checksum.value().to_be_bytes());
+        self.write_pending()?;
+        self.write_chunk(b"IEND", &[])?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    /// Stops encoding, leaving every row written so far in complete IDAT
+    /// chunks, and returns the underlying writer with a checkpoint for
+    /// [`resume`](Self::resume). The output isn't a finished PNG until a
+    /// resumed writer is finished.
+    pub fn finish_partial(mut self) -> Result<(W, StreamCheckpoint), PngError> {
+        // A sync flush ends the compressed data on a byte boundary with
+        // nothing held back, so a fresh compressor can carry on from it
+        self.sync_flush()?;
+        let checkpoint = StreamCheckpoint {
+            header: self.header,
+            rows: self.rows,
+            output_len: self.offset,
+            checksum: self.checksum.value(),
+            previous_row: self.filter.previous().to_vec(),
+        };
+        Ok((self.inner, checkpoint))
+    }
+
+    fn write_pending(&mut self) -> Result<(), PngError> {
+        if !self.pending.is_empty() {
+            let pending = std::mem::take(&mut self.pending);
+            self.write_chunk(b"IDAT", &pending)?;
+        }
+        Ok(())
+    }
+
+    fn write_chunk(&mut self, chunk_type: &[u8; 4], data: &[u8]) -> Result<(), PngError> {
+        ChunkWriter::write_chunk(&mut self.inner, chunk_type, data)?;
+        // Length, type, and CRC around the data
+        self.offset += 12 + data.len() as u64;
+        Ok(())
+    }
+}
+
+impl StreamCheckpoint {
+    pub fn rows_written(&self) -> u32 {
+        self.rows
+    }
+
+    /// Bytes of output written up to the checkpoint.
+    pub fn output_len(&self) -> u64 {
+        self.output_len
+    }
+
+    /// The checkpoint in a compact form to store until resuming.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(37 + self.previous_row.len());
+        bytes.extend_from_slice(CHECKPOINT_MAGIC);
+        bytes.extend_from_slice(&self.header);
+        bytes.extend_from_slice(&self.rows.to_be_bytes());
+        bytes.extend_from_slice(&self.output_len.to_be_bytes());
+        bytes.extend_from_slice(&self.checksum.to_be_bytes());
+        bytes.extend_from_slice(&self.previous_row);
+        bytes
+    }
+
+    /// Reads a checkpoint stored with [`to_bytes`](Self::to_bytes).
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PngError> {
+        let invalid = |reason: &str| PngError::InvalidCheckpoint(reason.to_string());
+        let rest = bytes
+            .strip_prefix(CHECKPOINT_MAGIC)
+            .ok_or_else(|| invalid("not a stream checkpoint"))?;
+        if rest.len() < 29 {
+            return Err(invalid("truncated"));
+        }
+        let (header, rest) = rest.split_at(13);
+        let (rows, rest) = rest.split_at(4);
+        let (output_len, rest) = rest.split_at(8);
+        let (checksum, previous_row) = rest.split_at(4);
+        let checkpoint = Self {
+            header: header.to_vec(),
+            rows: u32::from_be_bytes(rows.try_into().unwrap()),
+            output_len: u64::from_be_bytes(output_len.try_into().unwrap()),
+            checksum: u32::from_be_bytes(checksum.try_into().unwrap()),
+            previous_row: previous_row.to_vec(),
+        };
+
+        // The saved row and count must fit the image the header describes
+        let header = ImageHeader::parse(header).map_err(|_| invalid("bad image header"))?;
+        let row_bits = header.width as u64
+            * header.color_type.channels() as u64
+            * header.bit_depth.bits() as u64;
+        if previous_row.len() as u64 != row_bits.div_ceil(8) || checkpoint.rows > header.height {
+            return Err(invalid("doesn't match its image header"));
+        }
+        Ok(checkpoint)
+    }
+}
+
+impl PngImage {
+    // Encodes the image a row at a time, as StreamWriter does, writing
+    // image data out as it compresses so that neither the filtered nor the
+    // compressed data is held whole in memory
+    pub(crate) fn write_streamed<W: Write>(
+        &self,
+        writer: &mut W,
+        options: &EncodeOptions,
+        progress: &mut ProgressHook,
+    ) -> Result<EncodeReport, PngError> {
+        let start = Instant::now();
+        writer.write_all(&PNG_SIGNATURE)?;
+        let mut summaries = Vec::new();
+        let mut offset = PNG_SIGNATURE.len() as u64;
+        let mut write = |chunk_type: &[u8; 4], data: &[u8]| -> Result<(), PngError> {
+            ChunkWriter::write_chunk(writer, chunk_type, data)?;
+            summaries.push(ChunkSummary {
+                chunk_type: *chunk_type,
+                length: data.len() as u32,
+                offset,
+            });
+            // Length, type, and CRC around the data
+            offset += 12 + data.len() as u64;
+            Ok(())
+        };
+        for (chunk_type, data) in self.chunks_before_data(options) {
+            write(&chunk_type, &data)?;
+        }
+
+        let bits = self.bit_depth.bits() as usize;
+        let row_length = self.packed_row_length();
+        // Filters operate on whole bytes, so sub-byte pixels use a distance of 1
+        let mut filter = RowFilter::new(options.filter, row_length, self.bytes_per_pixel().max(1));
+        let mut encoder = ZlibWriter::new(options.compression_level, options.dictionary.as_ref())?;
+        let mut packed = vec![0; row_length];
+        let mut line = Vec::with_capacity(row_length + 1);
+        let mut pending = Vec::new();
+        let mut filters = [0; 5];
+        let mut compressed_bytes = 0;
+        progress.begin(Phase::Filtering, self.height as usize)?;
+        for row in self
+            .data
+            .chunks_exact(self.width as usize * self.bytes_per_pixel())
+        {
+            let row = if bits < 8 {
+                packed.fill(0);
+                pack_samples(row, bits, &mut packed);
+                &packed
+            } else {
+                row
+            };
+            line.clear();
+            filter.filter(row, &mut line);
+            filters[line[0] as usize] += 1;
+            encoder.write_all(&line)?;
+            pending.append(&mut encoder.take_output());
+            if pending.len() >= IDAT_SIZE {
+                compressed_bytes += pending.len();
+                write(b"IDAT", &pending)?;
+                pending.clear();
+            }
+            progress.advance()?;
+        }
+        pending.append(&mut encoder.finish()?);
+        compressed_bytes += pending.len();
+        write(b"IDAT", &pending)?;
+        write(b"IEND", &[])?;
+
+        Ok(EncodeReport {
+            chunks: summaries,
+            filters,
+            raw_bytes: (row_length + 1) * self.height as usize,
+            compressed_bytes,
+            total_bytes: offset,
+            elapsed: start.elapsed(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::Decoder;
+    use crate::test_images::{formats, noise};
+    use crate::{FilterStrategy, TextChunk};
+
+    fn rows(image: &PngImage) -> impl Iterator<Item = &[u8]> {
+        image
+            .data()
+            .chunks_exact(image.width() as usize * image.bytes_per_pixel())
+    }
+
+    // The image's format and palette, without pixels
+    fn template(image: &PngImage) -> PngImage {
+        let mut template = PngImage::with_bit_depth(
+            image.width(),
+            image.height(),
+            image.color_type(),
+            image.bit_depth(),
+        )
+        .unwrap();
+        if let Some(palette) = &image.palette {
+            template.set_palette(palette).unwrap();
+        }
+        template
+    }
+
+    fn assert_decodes_to(encoded: &[u8], image: &PngImage) {
+        let decoded = Decoder::new(encoded).decode().unwrap();
+        assert_eq!(decoded.color_type(), image.color_type());
+        assert_eq!(decoded.bit_depth(), image.bit_depth());
+        assert_eq!(decoded.palette(), image.palette());
+        assert_eq!(decoded.data(), image.data());
+    }
+
+    #[test]
+    fn streamed_rows_decode_for_every_format() {
+        for (color_type, bit_depth) in formats() {
+            let image = noise(13, 11, color_type, bit_depth, 1);
+            let mut writer =
+                StreamWriter::new(Vec::new(), &template(&image), &EncodeOptions::default())
+                    .unwrap();
+            for row in rows(&image) {
+                writer.write_row(row).unwrap();
+            }
+            assert_decodes_to(&writer.finish().unwrap(), &image);
+        }
+    }
+
+    #[test]
+    fn streams_large_images_with_metadata() {
+        let mut image = noise(300, 300, ColorType::Rgba, BitDepth::Eight, 2);
+        image
+            .add_text(&TextChunk::new("Title", "streamed"))
+            .unwrap();
+        for level in [0, 1, 9] {
+            let options = EncodeOptions {
+                compression_level: level,
+                filter: FilterStrategy::Adaptive,
+                ..EncodeOptions::default()
+            };
+            let mut writer = StreamWriter::new(Vec::new(), &image, &options).unwrap();
+            for row in rows(&image) {
+                writer.write_row(row).unwrap();
+            }
+            let encoded = writer.finish().unwrap();
+            assert_decodes_to(&encoded, &image);
+            let info = crate::PngInfo::read(encoded.as_slice()).unwrap();
+            assert_eq!(info.text[0].text, "streamed");
+            let idats = info.chunks.iter().filter(|c| &c.chunk_type == b"IDAT");
+            assert!(idats.count() > 1);
+        }
+    }
+
+    #[test]
+    fn resumes_from_a_saved_checkpoint() {
+        for (color_type, bit_depth) in formats() {
+            let image = noise(40, 30, color_type, bit_depth, 3);
+            let template = template(&image);
+            let options = EncodeOptions {
+                filter: FilterStrategy::Adaptive,
+                ..EncodeOptions::default()
+            };
+            let mut writer = StreamWriter::new(Vec::new(), &template, &options).unwrap();
+            let mut rows = rows(&image);
+            for row in rows.by_ref().take(12) {
+                writer.write_row(row).unwrap();
+            }
+            let (output, checkpoint) = writer.finish_partial().unwrap();
+            assert_eq!(checkpoint.output_len(), output.len() as u64);
+            let checkpoint = StreamCheckpoint::from_bytes(&checkpoint.to_bytes()).unwrap();
+            assert_eq!(checkpoint.rows_written(), 12);
+
+            // Stop once more partway through, as after a second restart
+            let mut writer =
+                StreamWriter::resume(output, &template, &options, &checkpoint).unwrap();
+            for row in rows.by_ref().take(1) {
+                writer.write_row(row).unwrap();
+            }
+            let (output, checkpoint) = writer.finish_partial().unwrap();
+            let mut writer =
+                StreamWriter::resume(output, &template, &options, &checkpoint).unwrap();
+            for row in rows {
+                writer.write_row(row).unwrap();
+            }
+            assert_decodes_to(&writer.finish().unwrap(), &image);
+        }
+    }
+
+    #[test]
+    fn rejects_missing_rows_and_mismatched_checkpoints() {
+        let image = noise(8, 4, ColorType::Rgb, BitDepth::Eight, 4);
+        let options = EncodeOptions::default();
+        let mut writer = StreamWriter::new(Vec::new(), &image, &options).unwrap();
+        writer.write_row(&image.data()[..24]).unwrap();
+        assert!(writer.write_row(&[0; 23]).is_err());
+        let (_, checkpoint) = writer.finish_partial().unwrap();
+
+        let other = noise(9, 4, ColorType::Rgb, BitDepth::Eight, 4);
+        assert!(StreamWriter::resume(Vec::new(), &other, &options, &checkpoint).is_err());
+        let mut bytes = checkpoint.to_bytes();
+        bytes.pop();
+        assert!(StreamCheckpoint::from_bytes(&bytes).is_err());
+        assert!(StreamCheckpoint::from_bytes(b"PNGCKPT1").is_err());
+
+        let writer = StreamWriter::new(Vec::new(), &image, &options).unwrap();
+        let error = writer.finish().unwrap_err();
+        assert!(matches!(error, PngError::PixelCountMismatch { .. }));
+    }
+
+    #[test]
+    fn rejects_bad_palette_indices_and_unsupported_options() {
+        let mut image = PngImage::new(4, 2, ColorType::Indexed).unwrap();
+        image.set_palette(&[0, 0, 0, 255, 255, 255]).unwrap();
+        let options = EncodeOptions::default();
+        let mut writer = StreamWriter::new(Vec::new(), &image, &options).unwrap();
+        writer.write_row(&[0, 1, 1, 0]).unwrap();
+        let error = writer.write_row(&[1, 2, 0, 5]).unwrap_err();
+        match error {
+            PngError::InvalidPaletteEntry {
+                index, x, y, count, ..
+            } => assert_eq!((index, x, y, count), (2, 1, 1, 2)),
+            other => panic!("unexpected error {other:?}"),
+        }
+        assert_eq!(writer.rows_written(), 1);
+
+        for options in [
+            EncodeOptions {
+                strictness: Strictness::Strict,
+                ..EncodeOptions::default()
+            },
+            EncodeOptions {
+                optimize_palette: true,
+                ..EncodeOptions::default()
+            },
+            EncodeOptions {
+                dictionary: Some(crate::CompressionDictionary::from_bytes(b"dictionary")),
+                ..EncodeOptions::default()
+            },
+        ] {
+            let error = StreamWriter::new(Vec::new(), &image, &options)
+                .err()
+                .unwrap();
+            assert!(matches!(error, PngError::UnsupportedOption(_)));
+        }
+    }
+
+    // The image data in the complete chunks of a stream cut off anywhere,
+    // inflated as far as it goes
+    fn inflate_available(stream: &[u8]) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        let mut rest = &stream[PNG_SIGNATURE.len()..];
+        while rest.len() >= 12 {
+            let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            if &rest[4..8] == b"IDAT" {
+                compressed.extend_from_slice(&rest[8..8 + length]);
+            }
+            rest = &rest[12 + length..];
+        }
+        let mut inflater = flate2::Decompress::new(true);
+        let mut raw = Vec::with_capacity(1 << 20);
+        inflater
+            .decompress_vec(&compressed, &mut raw, flate2::FlushDecompress::Sync)
+            .unwrap();
+        raw
+    }
+
+    #[test]
+    fn sync_flush_makes_every_row_so_far_available() {
+        let image = noise(64, 40, ColorType::Rgb, BitDepth::Eight, 5);
+        let row_length = 64 * 3 + 1;
+        let options = EncodeOptions {
+            compression_level: 9,
+            ..EncodeOptions::default()
+        };
+        let mut writer = StreamWriter::new(Vec::new(), &image, &options).unwrap();
+        for (y, row) in rows(&image).enumerate() {
+            writer.write_row(row).unwrap();
+            if y % 10 == 9 {
+                writer.flush_frame().unwrap();
+                let available = inflate_available(writer.get_ref());
+                assert!(available.len() <= (y + 1) * row_length);
+
+                writer.sync_flush().unwrap();
+                let available = inflate_available(writer.get_ref());
+                assert_eq!(available.len(), (y + 1) * row_length);
+            }
+        }
+        assert_decodes_to(&writer.finish().unwrap(), &image);
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn spilled_images_encode_row_by_row() {
+        let dictionary = crate::CompressionDictionary::from_bytes(&[7; 1000]);
+        for (color_type, bit_depth) in [
+            (ColorType::Rgba, BitDepth::Eight),
+            (ColorType::Grayscale, BitDepth::Two),
+            (ColorType::Indexed, BitDepth::Four),
+            (ColorType::Rgb, BitDepth::Sixteen),
+        ] {
+            let image = noise(200, 150, color_type, bit_depth, 6);
+            let mut spilled = image.try_clone().unwrap();
+            spilled.spill_to_disk().unwrap();
+            for options in [
+                EncodeOptions::default(),
+                EncodeOptions {
+                    compression_level: 0,
+                    filter: FilterStrategy::Adaptive,
+                    ..EncodeOptions::default()
+                },
+                EncodeOptions {
+                    dictionary: Some(dictionary.clone()),
+                    ..EncodeOptions::default()
+                },
+            ] {
+                let mut encoded = Vec::new();
+                let report = spilled.write_with_report(&mut encoded, &options).unwrap();
+                let mut expected = Vec::new();
+                let in_memory = image.write_with_report(&mut expected, &options).unwrap();
+                assert_eq!(report.filters, in_memory.filters);
+                assert_eq!(report.raw_bytes, in_memory.raw_bytes);
+                assert_eq!(report.total_bytes, encoded.len() as u64);
+
+                let mut decoder = Decoder::new(encoded.as_slice());
+                if let Some(dictionary) = &options.dictionary {
+                    decoder = decoder.dictionary(dictionary.clone());
+                }
+                let decoded = decoder.decode().unwrap();
+                assert_eq!(decoded.data(), image.data());
+            }
+        }
+    }
+}
@@ -0,0 +1,109 @@
+// Images for unit tests across the crate
+
+use std::io::Write;
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::chunks::{ChunkWriter, PNG_SIGNATURE};
+use crate::decoder::ADAM7_PASSES;
+use crate::{BitDepth, ColorType, PngImage};
+
+// Pseudo-random samples, reproducible from `seed`, in the range the bit
+// depth allows, with the palette filled in for indexed images
+pub(crate) fn noise(
+    width: u32,
+    height: u32,
+    color_type: ColorType,
+    bit_depth: BitDepth,
+    seed: u32,
+) -> PngImage {
+    let mut image = PngImage::with_bit_depth(width, height, color_type, bit_depth).unwrap();
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+        (state >> 16) as u16
+    };
+    let max = bit_depth.max_value();
+    if color_type == ColorType::Indexed {
+        let entries = max as usize + 1;
+        let palette: Vec<u8> = (0..entries * 3).map(|_| next() as u8).collect();
+        image.set_palette(&palette).unwrap();
+    }
+    let samples = width as usize * height as usize * color_type.channels();
+    let data = match bit_depth {
+        BitDepth::Sixteen => (0..samples).flat_map(|_| next().to_be_bytes()).collect(),
+        _ => (0..samples).map(|_| (next() % (max + 1)) as u8).collect(),
+    };
+    image.data.replace(data).unwrap();
+    image
+}
+
+// The image encoded with Adam7 interlacing, which the encoder doesn't
+// write, with every scanline unfiltered
+pub(crate) fn interlaced_png(image: &PngImage) -> Vec<u8> {
+    let bpp = image.bytes_per_pixel();
+    let bits = image.bit_depth.bits() as usize;
+    let width = image.width as usize;
+    let mut raw = Vec::new();
+    for (x0, y0, dx, dy) in ADAM7_PASSES {
+        let columns: Vec<usize> = (x0..width).step_by(dx).collect();
+        if columns.is_empty() {
+            continue;
+        }
+        for y in (y0..image.height as usize).step_by(dy) {
+            raw.push(0);
+            let pixel = |x: usize| &image.data[(y * width + x) * bpp..][..bpp];
+            if bits >= 8 {
+                for &x in &columns {
+                    raw.extend_from_slice(pixel(x));
+                }
+            } else {
+                // One sample per pixel, packed from the high bits down
+                for group in columns.chunks(8 / bits) {
+                    let mut byte = 0;
+                    for (i, &x) in group.iter().enumerate() {
+                        byte |= pixel(x)[0] << (8 - bits * (i + 1));
+                    }
+                    raw.push(byte);
+                }
+            }
+        }
+    }
+
+    let mut ihdr = image.generate_ihdr();
+    ihdr[12] = 1;
+    let mut zlib = ZlibEncoder::new(Vec::new(), Compression::default());
+    zlib.write_all(&raw).unwrap();
+    let mut png = PNG_SIGNATURE.to_vec();
+    ChunkWriter::write_chunk(&mut png, b"IHDR", &ihdr).unwrap();
+    if let Some(palette) = &image.palette {
+        ChunkWriter::write_chunk(&mut png, b"PLTE", palette).unwrap();
+    }
+    ChunkWriter::write_chunk(&mut png, b"IDAT", &zlib.finish().unwrap()).unwrap();
+    ChunkWriter::write_chunk(&mut png, b"IEND", &[]).unwrap();
+    png
+}
+
+// Every color type at every bit depth it allows
+pub(crate) fn formats() -> Vec<(ColorType, BitDepth)> {
+    let color_types = [
+        ColorType::Grayscale,
+        ColorType::Rgb,
+        ColorType::Indexed,
+        ColorType::GrayscaleAlpha,
+        ColorType::Rgba,
+    ];
+    let bit_depths = [
+        BitDepth::One,
+        BitDepth::Two,
+        BitDepth::Four,
+        BitDepth::Eight,
+        BitDepth::Sixteen,
+    ];
+    color_types
+        .into_iter()
+        .flat_map(|c| bit_depths.into_iter().map(move |b| (c, b)))
+        .filter(|(c, b)| c.allows_bit_depth(*b))
+        .collect()
+}
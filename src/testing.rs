@@ -0,0 +1,117 @@
+//! Visual regression testing against stored images, enabled by the
+//! `testing` feature.
+
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::compare::{compare, diff_image};
+use crate::decoder::Decoder;
+use crate::error::PngError;
+use crate::options::EncodeOptions;
+use crate::PngImage;
+
+/// Set this environment variable to any value to write images as the new
+/// golden files instead of comparing against them.
+pub const UPDATE_GOLDEN_VAR: &str = "PNG_UPDATE_GOLDEN";
+
+/// Panics unless `image` matches the PNG at `path` to within `tolerance`
+/// per channel, as measured by [`compare`](crate::compare).
+///
+/// On failure `image` is written next to the golden file as
+/// `<name>.actual.png`, along with `<name>.diff.png` from
+/// [`diff_image`](crate::diff_image) when the sizes match. These are removed
+/// again once the test passes. A missing golden file fails the test too,
+/// unless [`UPDATE_GOLDEN_VAR`] is set, in which case `image` is saved as
+/// the golden file and the test passes.
+#[track_caller]
+pub fn assert_matches_golden(image: &PngImage, path: impl AsRef<Path>, tolerance: u16) {
+    let path = path.as_ref();
+    let actual_path = sibling(path, "actual");
+    let diff_path = sibling(path, "diff");
+
+    if std::env::var_os(UPDATE_GOLDEN_VAR).is_some() {
+        save(image, path).unwrap_or_else(|e| panic!("writing {}: {}", path.display(), e));
+        return;
+    }
+
+    let golden = match fs::File::open(path) {
+        Ok(file) => Decoder::new(std::io::BufReader::new(file))
+            .decode()
+            .unwrap_or_else(|e| panic!("reading {}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            save_or_warn(image, &actual_path);
+            panic!(
+                "golden file {} doesn't exist; image written to {}, set {}=1 to accept it",
+                path.display(),
+                actual_path.display(),
+                UPDATE_GOLDEN_VAR
+            );
+        }
+        Err(e) => panic!("reading {}: {}", path.display(), e),
+    };
+
+    let comparison = match compare(&golden, image, tolerance) {
+        Ok(comparison) => comparison,
+        Err(e) => {
+            save_or_warn(image, &actual_path);
+            panic!(
+                "image doesn't match {}: {}; image written to {}",
+                path.display(),
+                e,
+                actual_path.display()
+            );
+        }
+    };
+    if comparison.is_match() {
+        // Clean up after an earlier failure
+        let _ = fs::remove_file(&actual_path);
+        let _ = fs::remove_file(&diff_path);
+        return;
+    }
+
+    save_or_warn(image, &actual_path);
+    match diff_image(&golden, image, tolerance) {
+        Ok(diff) => save_or_warn(&diff, &diff_path),
+        Err(e) => eprintln!("couldn't render diff: {}", e),
+    }
+    panic!(
+        "image doesn't match {}: {} of {} pixels differ by more than {} \
+         (max difference {}, PSNR {:.2} dB); image written to {}, differences to {}",
+        path.display(),
+        comparison.mismatched_pixels,
+        comparison.total_pixels,
+        tolerance,
+        comparison.max_difference,
+        comparison.psnr,
+        actual_path.display(),
+        diff_path.display()
+    );
+}
+
+// `dir/name.png` -> `dir/name.<suffix>.png`
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}.{}.png", stem, suffix))
+}
+
+// Deterministic so regenerated golden files only change with their pixels
+fn save(image: &PngImage, path: &Path) -> Result<(), PngError> {
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let options = EncodeOptions {
+        deterministic: true,
+        ..EncodeOptions::default()
+    };
+    let mut writer = BufWriter::new(fs::File::create(path)?);
+    image.write_with_options(&mut writer, &options)?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn save_or_warn(image: &PngImage, path: &Path) {
+    if let Err(e) = save(image, path) {
+        eprintln!("couldn't write {}: {}", path.display(), e);
+    }
+}
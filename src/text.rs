@@ -0,0 +1,305 @@
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use crate::error::PngError;
+use crate::metadata::Timestamp;
+
+/// Keywords predefined by the PNG specification and its registered extensions.
+pub(crate) const REGISTERED_KEYWORDS: [&str; 11] = [
+    "Title",
+    "Author",
+    "Description",
+    "Copyright",
+    "Creation Time",
+    "Software",
+    "Disclaimer",
+    "Warning",
+    "Source",
+    "Comment",
+    "XML:com.adobe.xmp",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextKind {
+    /// tEXt: uncompressed Latin-1
+    Plain,
+    /// zTXt: zlib-compressed Latin-1
+    Compressed,
+    /// iTXt: UTF-8, optionally compressed
+    International,
+}
+
+/// A textual metadata entry from a tEXt, zTXt, or iTXt chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextChunk {
+    pub keyword: String,
+    pub text: String,
+    pub kind: TextKind,
+    /// Language tag (iTXt only)
+    pub language: String,
+    /// Keyword translated into `language` (iTXt only)
+    pub translated_keyword: String,
+}
+
+/// Per-file values for [`TextChunk::from_template`], as in a batch job.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateValues {
+    /// The input's file name, or path
+    pub file_name: String,
+    /// The file's position in the batch, from 0
+    pub index: usize,
+    pub timestamp: Timestamp,
+}
+
+impl TextChunk {
+    /// Creates an entry like [`TextChunk::new`] whose text is `template`
+    /// with placeholders filled in from `values`: `{filename}`, `{stem}`
+    /// (the file name without its directory or extension), `{index}` or
+    /// zero-padded `{index:04}`, and `{timestamp}` as
+    /// `YYYY-MM-DDTHH:MM:SSZ`. `{{` and `}}` stand for literal braces.
+    /// Other placeholders fail with [`PngError::InvalidTemplate`].
+    pub fn from_template(
+        keyword: &str,
+        template: &str,
+        values: &TemplateValues,
+    ) -> Result<Self, PngError> {
+        let invalid = || PngError::InvalidTemplate(template.to_string());
+        let mut text = String::with_capacity(template.len());
+        let mut chars = template.chars();
+        let path = Path::new(&values.file_name);
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest.find('}').ok_or_else(invalid)?;
+                    match &rest[..end] {
+                        "filename" => text.push_str(&values.file_name),
+                        "stem" => {
+                            text.push_str(&path.file_stem().unwrap_or_default().to_string_lossy())
+                        }
+                        "timestamp" => text.push_str(&values.timestamp.to_string()),
+                        "index" => text.push_str(&values.index.to_string()),
+                        spec => {
+                            let width = spec.strip_prefix("index:0").ok_or_else(invalid)?;
+                            let width: usize = width.parse().map_err(|_| invalid())?;
+                            text.push_str(&format!("{:0width$}", values.index));
+                        }
+                    }
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => return Err(invalid()),
+                c => text.push(c),
+            }
+        }
+        Ok(Self::new(keyword, &text))
+    }
+
+    /// Creates an uncompressed entry, stored as tEXt when the text is Latin-1
+    /// and as iTXt otherwise.
+    pub fn new(keyword: &str, text: &str) -> Self {
+        let kind = if text.chars().all(|c| (c as u32) < 256) {
+            TextKind::Plain
+        } else {
+            TextKind::International
+        };
+        Self {
+            keyword: keyword.to_string(),
+            text: text.to_string(),
+            kind,
+            language: String::new(),
+            translated_keyword: String::new(),
+        }
+    }
+
+    pub fn chunk_type(&self) -> [u8; 4] {
+        match self.kind {
+            TextKind::Plain => *b"tEXt",
+            TextKind::Compressed => *b"zTXt",
+            TextKind::International => *b"iTXt",
+        }
+    }
+
+    /// Serializes the entry as the data of the chunk named by [`chunk_type`].
+    ///
+    /// [`chunk_type`]: TextChunk::chunk_type
+    pub fn encode(&self) -> Result<Vec<u8>, PngError> {
+        validate_keyword(&self.keyword)?;
+        let mut data = string_to_latin1(&self.keyword)?;
+        data.push(0);
+
+        match self.kind {
+            TextKind::Plain => data.extend(string_to_latin1(&self.text)?),
+            TextKind::Compressed => {
+                data.push(0);
+                data.extend(deflate(&string_to_latin1(&self.text)?)?);
+            }
+            TextKind::International => {
+                // Compression flag and method; iTXt text is written uncompressed
+                data.extend_from_slice(&[0, 0]);
+                data.extend_from_slice(self.language.as_bytes());
+                data.push(0);
+                data.extend_from_slice(self.translated_keyword.as_bytes());
+                data.push(0);
+                data.extend_from_slice(self.text.as_bytes());
+            }
+        }
+        Ok(data)
+    }
+
+    /// Parses the data of a tEXt, zTXt, or iTXt chunk.
+    pub fn parse(chunk_type: &[u8; 4], data: &[u8]) -> Result<Self, PngError> {
+        Self::parse_capped(chunk_type, data, usize::MAX)
+    }
+
+    // Parses a text chunk, decompressing at most `max_text` bytes of text so
+    // that a small chunk can't expand without bound; the text is cut short
+    // past that
+    pub(crate) fn parse_capped(
+        chunk_type: &[u8; 4],
+        data: &[u8],
+        max_text: usize,
+    ) -> Result<Self, PngError> {
+        let (keyword, rest) = split_null(data)
+            .ok_or_else(|| PngError::Decode("Text chunk keyword is not terminated".to_string()))?;
+        let keyword = latin1_to_string(keyword);
+
+        match chunk_type {
+            b"tEXt" => Ok(Self {
+                keyword,
+                text: latin1_to_string(rest),
+                kind: TextKind::Plain,
+                language: String::new(),
+                translated_keyword: String::new(),
+            }),
+            b"zTXt" => {
+                let (&method, compressed) = rest
+                    .split_first()
+                    .ok_or_else(|| PngError::Decode("zTXt chunk is truncated".to_string()))?;
+                if method != 0 {
+                    return Err(PngError::Decode(format!(
+                        "Unknown zTXt compression method {}",
+                        method
+                    )));
+                }
+                Ok(Self {
+                    keyword,
+                    text: latin1_to_string(&inflate(compressed, max_text)?),
+                    kind: TextKind::Compressed,
+                    language: String::new(),
+                    translated_keyword: String::new(),
+                })
+            }
+            b"iTXt" => {
+                if rest.len() < 2 {
+                    return Err(PngError::Decode("iTXt chunk is truncated".to_string()));
+                }
+                let (compressed, method) = (rest[0], rest[1]);
+                let (language, rest) = split_null(&rest[2..]).ok_or_else(|| {
+                    PngError::Decode("iTXt language tag is not terminated".to_string())
+                })?;
+                let (translated, text) = split_null(rest).ok_or_else(|| {
+                    PngError::Decode("iTXt translated keyword is not terminated".to_string())
+                })?;
+
+                let text = match (compressed, method) {
+                    (0, _) => text.to_vec(),
+                    (1, 0) => inflate(text, max_text)?,
+                    _ => {
+                        return Err(PngError::Decode(format!(
+                            "Unknown iTXt compression method {}",
+                            method
+                        )))
+                    }
+                };
+
+                Ok(Self {
+                    keyword,
+                    text: String::from_utf8_lossy(&text).into_owned(),
+                    kind: TextKind::International,
+                    language: String::from_utf8_lossy(language).into_owned(),
+                    translated_keyword: String::from_utf8_lossy(translated).into_owned(),
+                })
+            }
+            _ => Err(PngError::Decode(format!(
+                "{} is not a text chunk",
+                String::from_utf8_lossy(chunk_type)
+            ))),
+        }
+    }
+}
+
+pub(crate) fn is_text_chunk(chunk_type: &[u8; 4]) -> bool {
+    matches!(chunk_type, b"tEXt" | b"zTXt" | b"iTXt")
+}
+
+// Keywords are 1-79 printable Latin-1 characters with no leading, trailing,
+// or repeated spaces
+fn validate_keyword(keyword: &str) -> Result<(), PngError> {
+    let invalid = |reason: &str| {
+        Err(PngError::InvalidMetadata(format!(
+            "Keyword '{}' {}",
+            keyword, reason
+        )))
+    };
+
+    let length = keyword.chars().count();
+    if length == 0 || length > 79 {
+        return invalid("must be 1 to 79 characters");
+    }
+    if !keyword
+        .chars()
+        .all(|c| matches!(c as u32, 32..=126 | 161..=255))
+    {
+        return invalid("contains characters outside printable Latin-1");
+    }
+    if keyword.starts_with(' ') || keyword.ends_with(' ') || keyword.contains("  ") {
+        return invalid("has leading, trailing, or repeated spaces");
+    }
+    Ok(())
+}
+
+fn split_null(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let pos = data.iter().position(|&b| b == 0)?;
+    Some((&data[..pos], &data[pos + 1..]))
+}
+
+// Latin-1 maps byte-for-byte onto the first 256 Unicode code points
+fn latin1_to_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+fn string_to_latin1(text: &str) -> Result<Vec<u8>, PngError> {
+    text.chars()
+        .map(|c| {
+            u8::try_from(c as u32).map_err(|_| {
+                PngError::InvalidMetadata(format!("'{}' is not a Latin-1 character", c))
+            })
+        })
+        .collect()
+}
+
+fn deflate(data: &[u8]) -> Result<Vec<u8>, PngError> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+fn inflate(data: &[u8], max_len: usize) -> Result<Vec<u8>, PngError> {
+    let mut out = Vec::new();
+    ZlibDecoder::new(data)
+        .take(max_len as u64)
+        .read_to_end(&mut out)
+        .map_err(|e| PngError::Decode(format!("Invalid compressed text: {}", e)))?;
+    Ok(out)
+}
@@ -0,0 +1,138 @@
+use std::io::{self, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A writer that passes bytes on to another at no more than a set rate,
+/// sleeping as needed, for polite background uploads or for watching a
+/// decoder render a file that arrives slowly. Works with anything that
+/// takes a writer, such as [`PngImage::write_with_options`].
+///
+/// Bytes go out in pieces of at most `burst` bytes. Time spent idle only
+/// earns one burst's worth of credit, so the rate holds over any stretch
+/// longer than a burst takes.
+///
+/// [`PngImage::write_with_options`]: crate::PngImage::write_with_options
+#[derive(Debug)]
+pub struct ThrottledWriter<W: Write> {
+    inner: W,
+    bytes_per_second: u64,
+    burst: usize,
+    // Bytes that may be written without waiting, as of `refilled`
+    credit: f64,
+    refilled: Instant,
+}
+
+impl<W: Write> ThrottledWriter<W> {
+    /// Caps writes to `inner` at `bytes_per_second` (at least 1), in bursts
+    /// of a tenth of a second's worth.
+    pub fn new(inner: W, bytes_per_second: u64) -> Self {
+        let bytes_per_second = bytes_per_second.max(1);
+        let burst = (bytes_per_second / 10).clamp(1, usize::MAX as u64) as usize;
+        Self {
+            inner,
+            bytes_per_second,
+            burst,
+            credit: burst as f64,
+            refilled: Instant::now(),
+        }
+    }
+
+    /// Sets the most bytes written in one go (at least 1). Smaller bursts
+    /// even out the flow; larger ones mean fewer, bigger writes.
+    pub fn with_burst(mut self, burst: usize) -> Self {
+        self.burst = burst.max(1);
+        self.credit = self.credit.min(self.burst as f64);
+        self
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let earned = (now - self.refilled).as_secs_f64() * self.bytes_per_second as f64;
+        self.credit = (self.credit + earned).min(self.burst as f64);
+        self.refilled = now;
+    }
+}
+
+impl<W: Write> Write for ThrottledWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let len = buf.len().min(self.burst);
+        self.refill();
+        if self.credit < len as f64 {
+            let wait = (len as f64 - self.credit) / self.bytes_per_second as f64;
+            thread::sleep(Duration::from_secs_f64(wait));
+            self.refill();
+        }
+        let written = self.inner.write(&buf[..len])?;
+        self.credit -= written as f64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Records the size of every write it's given
+    #[derive(Default)]
+    struct Recorder {
+        data: Vec<u8>,
+        writes: Vec<usize>,
+    }
+
+    impl Write for Recorder {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.data.extend_from_slice(buf);
+            self.writes.push(buf.len());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn paces_writes_to_the_rate_in_bursts() {
+        let data: Vec<u8> = (0..6000).map(|i| i as u8).collect();
+        let mut writer = ThrottledWriter::new(Recorder::default(), 20_000).with_burst(1000);
+        let start = Instant::now();
+        writer.write_all(&data).unwrap();
+        let elapsed = start.elapsed();
+
+        // The first burst is free, the other 5000 bytes take a quarter second
+        assert!(elapsed >= Duration::from_millis(240), "{elapsed:?}");
+        assert!(elapsed < Duration::from_secs(2), "{elapsed:?}");
+        let recorder = writer.into_inner();
+        assert_eq!(recorder.data, data);
+        assert_eq!(recorder.writes, vec![1000; 6]);
+    }
+
+    #[test]
+    fn idle_time_earns_only_one_burst() {
+        let mut writer = ThrottledWriter::new(Recorder::default(), 10_000).with_burst(1000);
+        thread::sleep(Duration::from_millis(300));
+        let start = Instant::now();
+        writer.write_all(&[0; 4000]).unwrap();
+        // 3000 bytes beyond the one burst of credit, at 10 KB/s
+        assert!(start.elapsed() >= Duration::from_millis(290));
+    }
+}
@@ -0,0 +1,73 @@
+use std::io::{Read, Write};
+
+use crate::chunks::{Chunk, ChunkReader};
+use crate::color::Color;
+use crate::decoder::Decoder;
+use crate::error::PngError;
+use crate::options::EncodeOptions;
+use crate::report::EncodeReport;
+use crate::rewrite::StripPolicy;
+use crate::{BitDepth, ColorType};
+
+/// Settings for [`transcode`]. The defaults re-encode the image unchanged
+/// apart from compression.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TranscodeOptions {
+    /// Convert to this color type, as with
+    /// [`PngImage::convert_to`](crate::PngImage::convert_to)
+    pub color_type: Option<ColorType>,
+    /// Composite dropped alpha over this color instead of discarding it
+    pub background: Option<Color>,
+    /// Rescale to this bit depth after any color conversion
+    pub bit_depth: Option<BitDepth>,
+    /// Ancillary chunks to leave out of the output
+    pub strip: StripPolicy,
+    pub encode: EncodeOptions,
+}
+
+/// Decodes a PNG stream and encodes it again with new settings, carrying
+/// over ancillary chunks that `options.strip` doesn't match. If the color
+/// type or bit depth changes, chunks that are unsafe to copy (those whose
+/// fourth letter is uppercase, such as bKGD and sBIT) are dropped too, since
+/// they describe the old pixel format. Animation chunks are always dropped,
+/// leaving only the default image of an APNG.
+///
+/// The whole image is held in memory while it is converted.
+pub fn transcode<R: Read, W: Write>(
+    mut reader: R,
+    writer: &mut W,
+    options: &TranscodeOptions,
+) -> Result<EncodeReport, PngError> {
+    let mut input = Vec::new();
+    reader.read_to_end(&mut input)?;
+    let chunks: Vec<Chunk> = ChunkReader::new(input.as_slice())?.collect::<Result<_, _>>()?;
+    let mut image = Decoder::new(input.as_slice()).decode()?;
+    let format = (image.color_type, image.bit_depth);
+
+    if let Some(color_type) = options.color_type {
+        match options.background {
+            Some(background) => image.convert_to_over(color_type, background)?,
+            None => image.convert_to(color_type)?,
+        }
+    }
+    if let Some(bit_depth) = options.bit_depth {
+        image.convert_bit_depth(bit_depth)?;
+    }
+    let changed = (image.color_type, image.bit_depth) != format;
+
+    // The decoder has already kept tRNS where it still applies
+    let has_transparency = image.chunks.iter().any(|c| &c.chunk_type == b"tRNS");
+    let keep = |chunk: &Chunk| {
+        if chunk.is_critical() || options.strip.matches(chunk) {
+            return false;
+        }
+        match &chunk.chunk_type {
+            b"acTL" | b"fcTL" | b"fdAT" => false,
+            b"tRNS" if has_transparency => false,
+            chunk_type => !(changed && chunk_type[3].is_ascii_uppercase()),
+        }
+    };
+    image.chunks.extend(chunks.into_iter().filter(keep));
+
+    image.write_with_report(writer, &options.encode)
+}
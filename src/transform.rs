@@ -0,0 +1,515 @@
+use std::f64::consts::PI;
+
+use crate::decoder::expand_transparency;
+use crate::error::PngError;
+use crate::{image_size, BitDepth, ColorType, PngImage};
+
+/// Resampling filter used by [`PngImage::resize`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResizeFilter {
+    /// Copies the closest source pixel; the only filter that keeps an
+    /// indexed image indexed
+    Nearest,
+    Bilinear,
+    /// Catmull-Rom cubic
+    Bicubic,
+    Lanczos3,
+}
+
+impl ResizeFilter {
+    // Kernel radius in source pixels when not downscaling
+    fn support(&self) -> f64 {
+        match self {
+            ResizeFilter::Nearest => 0.5,
+            ResizeFilter::Bilinear => 1.0,
+            ResizeFilter::Bicubic => 2.0,
+            ResizeFilter::Lanczos3 => 3.0,
+        }
+    }
+
+    fn weight(&self, x: f64) -> f64 {
+        let x = x.abs();
+        match self {
+            ResizeFilter::Nearest => 1.0,
+            ResizeFilter::Bilinear => (1.0 - x).max(0.0),
+            ResizeFilter::Bicubic => {
+                if x < 1.0 {
+                    1.5 * x * x * x - 2.5 * x * x + 1.0
+                } else if x < 2.0 {
+                    -0.5 * x * x * x + 2.5 * x * x - 4.0 * x + 2.0
+                } else {
+                    0.0
+                }
+            }
+            ResizeFilter::Lanczos3 => {
+                if x == 0.0 {
+                    1.0
+                } else if x < 3.0 {
+                    let px = PI * x;
+                    3.0 * px.sin() * (px / 3.0).sin() / (px * px)
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+/// Widths of the fixed borders around the stretchable center of a
+/// nine-patch image; see [`PngImage::nine_patch`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Insets {
+    pub left: u32,
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+}
+
+impl PngImage {
+    /// Copies the `width`x`height` region whose top-left corner is at
+    /// (`x`, `y`) into a new image with the same format, palette and
+    /// transparency.
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Result<PngImage, PngError> {
+        self.check_region(x, y, width, height)?;
+
+        let mut cropped = PngImage::with_bit_depth(width, height, self.color_type, self.bit_depth)?;
+        cropped.copy_palette_from(self);
+
+        let bpp = self.bytes_per_pixel();
+        let stride = self.width as usize * bpp;
+        for row in y as usize..(y + height) as usize {
+            let start = row * stride + x as usize * bpp;
+            cropped
+                .data
+                .extend_from_slice(&self.data[start..start + width as usize * bpp])?;
+        }
+        Ok(cropped)
+    }
+
+    /// Scales the image to `width`x`height`. Every filter other than
+    /// `Nearest` expands indexed images to RGB (RGBA if the palette has
+    /// transparency) first, and grayscale or RGB images with a tRNS
+    /// transparent color to grayscale-alpha or RGBA, since blended pixels
+    /// can't keep an exact key; other color types and
+    /// bit depths are kept. Alpha is premultiplied while filtering so fully
+    /// transparent pixels don't bleed their color into their neighbors.
+    pub fn resize(
+        &self,
+        width: u32,
+        height: u32,
+        filter: ResizeFilter,
+    ) -> Result<PngImage, PngError> {
+        if (width, height) == (self.width, self.height) {
+            return Ok(self.clone());
+        }
+        if filter == ResizeFilter::Nearest {
+            return self.resize_nearest(width, height);
+        }
+
+        let mut source = self.clone();
+        if source.color_type == ColorType::Indexed {
+            source.expand_palette()?;
+        }
+        let key = source
+            .chunks
+            .iter()
+            .find(|c| &c.chunk_type == b"tRNS")
+            .map(|c| c.data.clone());
+        if let Some(key) = key {
+            source.chunks.retain(|c| &c.chunk_type != b"tRNS");
+            expand_transparency(&mut source, &key)?;
+        }
+        let mut resized =
+            PngImage::with_bit_depth(width, height, source.color_type, source.bit_depth)?;
+
+        let channels = source.color_type.channels();
+        let alpha = matches!(
+            source.color_type,
+            ColorType::GrayscaleAlpha | ColorType::Rgba
+        );
+        let max = source.bit_depth.max_value() as f64;
+        let sixteen = source.bit_depth == BitDepth::Sixteen;
+
+        let mut samples: Vec<f64> = if sixteen {
+            source
+                .data
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]) as f64 / max)
+                .collect()
+        } else {
+            source.data.iter().map(|&b| b as f64 / max).collect()
+        };
+        if alpha {
+            for pixel in samples.chunks_exact_mut(channels) {
+                let a = pixel[channels - 1];
+                for v in &mut pixel[..channels - 1] {
+                    *v *= a;
+                }
+            }
+        }
+
+        let (src_w, src_h) = (source.width as usize, source.height as usize);
+        let (dst_w, dst_h) = (width as usize, height as usize);
+
+        // Horizontal pass into a dst_w x src_h buffer, then vertical
+        let columns = weights(src_w, dst_w, filter);
+        let mut horizontal = vec![0.0; image_size(width, source.height, channels)?];
+        for y in 0..src_h {
+            for (x, (first, taps)) in columns.iter().enumerate() {
+                let out = (y * dst_w + x) * channels;
+                for (k, &w) in taps.iter().enumerate() {
+                    let src = (y * src_w + first + k) * channels;
+                    for c in 0..channels {
+                        horizontal[out + c] += w * samples[src + c];
+                    }
+                }
+            }
+        }
+
+        let rows = weights(src_h, dst_h, filter);
+        let mut pixel = vec![0.0; channels];
+        for (first, taps) in &rows {
+            for x in 0..dst_w {
+                pixel.fill(0.0);
+                for (k, &w) in taps.iter().enumerate() {
+                    let src = ((first + k) * dst_w + x) * channels;
+                    for c in 0..channels {
+                        pixel[c] += w * horizontal[src + c];
+                    }
+                }
+
+                if alpha {
+                    let a = pixel[channels - 1].clamp(0.0, 1.0);
+                    for v in &mut pixel[..channels - 1] {
+                        *v = if a > 0.0 { *v / a } else { 0.0 };
+                    }
+                }
+                for &v in &pixel {
+                    let sample = (v.clamp(0.0, 1.0) * max).round() as u16;
+                    if sixteen {
+                        resized.data.extend_from_slice(&sample.to_be_bytes())?;
+                    } else {
+                        resized.data.push(sample as u8)?;
+                    }
+                }
+            }
+        }
+        Ok(resized)
+    }
+
+    fn resize_nearest(&self, width: u32, height: u32) -> Result<PngImage, PngError> {
+        let mut resized = PngImage::with_bit_depth(width, height, self.color_type, self.bit_depth)?;
+        resized.copy_palette_from(self);
+
+        let bpp = self.bytes_per_pixel();
+        let source_index = |dst: u32, dst_len: u32, src_len: u32| {
+            ((dst as u64 * 2 + 1) * src_len as u64 / (dst_len as u64 * 2)) as usize
+        };
+        for y in 0..height {
+            let row = source_index(y, height, self.height) * self.width as usize;
+            for x in 0..width {
+                let start = (row + source_index(x, width, self.width)) * bpp;
+                resized
+                    .data
+                    .extend_from_slice(&self.data[start..start + bpp])?;
+            }
+        }
+        Ok(resized)
+    }
+
+    /// Scales the image to `width`x`height` as an Android-style nine-patch:
+    /// the corners outside `insets` are copied unchanged, the top and bottom
+    /// edges are scaled horizontally, the left and right edges vertically,
+    /// and the center in both directions. Indexed images are expanded as
+    /// for [`PngImage::resize`] unless `filter` is `Nearest`.
+    pub fn nine_patch(
+        &self,
+        width: u32,
+        height: u32,
+        insets: Insets,
+        filter: ResizeFilter,
+    ) -> Result<PngImage, PngError> {
+        let Insets {
+            left,
+            top,
+            right,
+            bottom,
+        } = insets;
+        let fits = |a: u32, b: u32, limit: u32| a.checked_add(b).is_some_and(|n| n <= limit);
+        if !fits(left, right, self.width) || !fits(top, bottom, self.height) {
+            return Err(PngError::RegionOutOfBounds {
+                region: (
+                    left,
+                    top,
+                    self.width.saturating_sub(left.saturating_add(right)),
+                    self.height.saturating_sub(top.saturating_add(bottom)),
+                ),
+                dimensions: (self.width, self.height),
+            });
+        }
+        if !fits(left, right, width) || !fits(top, bottom, height) {
+            return Err(PngError::InvalidDimensions(width, height));
+        }
+
+        let mut source = self.clone();
+        if source.color_type == ColorType::Indexed && filter != ResizeFilter::Nearest {
+            source.expand_palette()?;
+        }
+        let mut patched =
+            PngImage::with_bit_depth(width, height, source.color_type, source.bit_depth)?;
+        patched.copy_palette_from(&source);
+
+        // Source and destination offsets and lengths of the three bands
+        // along each axis
+        let bands = |before: u32, after: u32, src: u32, dst: u32| {
+            [
+                (0, 0, before, before),
+                (before, before, src - before - after, dst - before - after),
+                (src - after, dst - after, after, after),
+            ]
+        };
+        for (src_y, dst_y, src_h, dst_h) in bands(top, bottom, self.height, height) {
+            for (src_x, dst_x, src_w, dst_w) in bands(left, right, self.width, width) {
+                if src_w == 0 || src_h == 0 || dst_w == 0 || dst_h == 0 {
+                    continue;
+                }
+                let piece = source
+                    .crop(src_x, src_y, src_w, src_h)?
+                    .resize(dst_w, dst_h, filter)?;
+                patched.blit(&piece, dst_x as i32, dst_y as i32)?;
+            }
+        }
+        Ok(patched)
+    }
+
+    /// Reads an Android `.9.png`, whose one-pixel border marks the
+    /// stretchable region with opaque black along the top and left edges.
+    /// Returns the image inside the border and the insets around the
+    /// marked region, ready for [`PngImage::nine_patch`]. Several marked
+    /// runs on an edge are merged into one from the first mark to the last;
+    /// the right and bottom edges, which mark content padding, are ignored.
+    pub fn split_nine_patch(&self) -> Result<(PngImage, Insets), PngError> {
+        if self.width < 3 || self.height < 3 {
+            return Err(PngError::InvalidDimensions(self.width, self.height));
+        }
+        // The first and last marked pixels along an edge, not counting the
+        // corners
+        let marks = |mut edge: PngImage| -> Result<Option<(u32, u32)>, PngError> {
+            edge.convert_to(ColorType::Rgba)?;
+            edge.convert_bit_depth(BitDepth::Eight)?;
+            let marked: Vec<u32> = edge
+                .data
+                .chunks_exact(4)
+                .enumerate()
+                .skip(1)
+                .take(edge.data.len() / 4 - 2)
+                .filter(|(_, pixel)| pixel == &[0, 0, 0, 255])
+                .map(|(i, _)| i as u32)
+                .collect();
+            Ok(marked.first().zip(marked.last()).map(|(&a, &b)| (a, b)))
+        };
+        let columns = marks(self.crop(0, 0, self.width, 1)?)?
+            .ok_or_else(|| PngError::InvalidNinePatch("no marks on the top edge".to_string()))?;
+        let rows = marks(self.crop(0, 0, 1, self.height)?)?
+            .ok_or_else(|| PngError::InvalidNinePatch("no marks on the left edge".to_string()))?;
+
+        let content = self.crop(1, 1, self.width - 2, self.height - 2)?;
+        let insets = Insets {
+            left: columns.0 - 1,
+            top: rows.0 - 1,
+            right: self.width - 2 - columns.1,
+            bottom: self.height - 2 - rows.1,
+        };
+        Ok((content, insets))
+    }
+
+    // Carries the palette and tRNS over to an image of the same format
+    fn copy_palette_from(&mut self, source: &PngImage) {
+        self.palette = source.palette.clone();
+        self.chunks.extend(
+            source
+                .chunks
+                .iter()
+                .filter(|c| &c.chunk_type == b"tRNS")
+                .cloned(),
+        );
+    }
+}
+
+// For each output position, the first contributing source index and the
+// normalized weights of it and the following sources
+fn weights(src_len: usize, dst_len: usize, filter: ResizeFilter) -> Vec<(usize, Vec<f64>)> {
+    let scale = src_len as f64 / dst_len as f64;
+    // Widen the kernel when shrinking so every source pixel contributes
+    let stretch = scale.max(1.0);
+    let support = filter.support() * stretch;
+
+    (0..dst_len)
+        .map(|i| {
+            let center = (i as f64 + 0.5) * scale - 0.5;
+            let first = ((center - support).ceil().max(0.0)) as usize;
+            let last = ((center + support).floor() as usize).min(src_len - 1);
+
+            let mut taps: Vec<f64> = (first..=last)
+                .map(|j| filter.weight((j as f64 - center) / stretch))
+                .collect();
+            let total: f64 = taps.iter().sum();
+            if total.abs() > f64::EPSILON {
+                for w in &mut taps {
+                    *w /= total;
+                }
+            } else {
+                // Degenerate kernel: fall back to the nearest source
+                taps = vec![0.0; last - first + 1];
+                let nearest = (center.round().max(0.0) as usize).clamp(first, last);
+                taps[nearest - first] = 1.0;
+            }
+            (first, taps)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resize_keeps_transparent_color() {
+        // Transparent key color on the left half, opaque red on the right
+        let mut image = PngImage::new(4, 2, ColorType::Rgb).unwrap();
+        for _ in 0..2 {
+            for x in 0..4 {
+                image
+                    .add_pixel(if x < 2 { &[0, 255, 0] } else { &[255, 0, 0] })
+                    .unwrap();
+            }
+        }
+        image.add_chunk(*b"tRNS", vec![0, 0, 0, 255, 0, 0]).unwrap();
+
+        let resized = image.resize(8, 4, ResizeFilter::Bilinear).unwrap();
+        assert_eq!(resized.color_type(), ColorType::Rgba);
+        let alpha = |x: usize| resized.data()[x * 4 + 3];
+        assert_eq!(alpha(0), 0);
+        assert_eq!(alpha(7), 255);
+        // Nothing of the key color shows through where it blends
+        for pixel in resized.data().chunks_exact(4) {
+            assert!(pixel[3] == 0 || pixel[1] == 0);
+        }
+    }
+
+    #[test]
+    fn resize_keeps_sub_byte_transparent_gray() {
+        let mut image =
+            PngImage::with_bit_depth(4, 4, ColorType::Grayscale, BitDepth::One).unwrap();
+        for i in 0..16 {
+            image.add_pixel(&[(i % 4 >= 2) as u8]).unwrap();
+        }
+        image.add_chunk(*b"tRNS", vec![0, 0]).unwrap();
+
+        let resized = image.resize(2, 2, ResizeFilter::Bicubic).unwrap();
+        assert_eq!(resized.color_type(), ColorType::GrayscaleAlpha);
+        for row in resized.data().chunks_exact(4) {
+            // Mostly transparent on the left, mostly opaque white on the right
+            assert!(row[1] < 32 && row[3] > 223);
+            assert_eq!(row[2], 255);
+        }
+    }
+
+    // An 8x6 .9.png: a transparent border marked in black at `columns` along
+    // the top and `rows` down the left, around content whose pixels are
+    // (x, y, 0, 255) in content coordinates
+    fn nine_patch_source(columns: &[u32], rows: &[u32]) -> PngImage {
+        let mut image = PngImage::new(8, 6, ColorType::Rgba).unwrap();
+        for y in 0..6 {
+            for x in 0..8 {
+                let border = x == 0 || y == 0 || x == 7 || y == 5;
+                let marked = (y == 0 && columns.contains(&x)) || (x == 0 && rows.contains(&y));
+                let pixel = match (border, marked) {
+                    (_, true) => [0, 0, 0, 255],
+                    (true, false) => [0, 0, 0, 0],
+                    (false, false) => [x as u8 - 1, y as u8 - 1, 0, 255],
+                };
+                image.add_pixel(&pixel).unwrap();
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn split_nine_patch_reads_the_marked_region() {
+        let (content, insets) = nine_patch_source(&[3, 4], &[2, 3])
+            .split_nine_patch()
+            .unwrap();
+        assert_eq!((content.width(), content.height()), (6, 4));
+        assert_eq!(&content.data()[..8], &[0, 0, 0, 255, 1, 0, 0, 255]);
+        assert_eq!(
+            insets,
+            Insets {
+                left: 2,
+                top: 1,
+                right: 2,
+                bottom: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn split_nine_patch_merges_runs_and_ignores_other_colors() {
+        let mut source = nine_patch_source(&[2, 5], &[1, 4]);
+        // A gray pixel between the runs on the top edge isn't a mark
+        source.data[4 * 4..4 * 4 + 4].copy_from_slice(&[128, 128, 128, 255]);
+        let (_, insets) = source.split_nine_patch().unwrap();
+        assert_eq!(
+            insets,
+            Insets {
+                left: 1,
+                top: 0,
+                right: 1,
+                bottom: 0,
+            }
+        );
+
+        // Marks in the corners don't count
+        let unmarked = nine_patch_source(&[0, 7], &[3]);
+        assert!(matches!(
+            unmarked.split_nine_patch(),
+            Err(PngError::InvalidNinePatch(_))
+        ));
+        let tiny = PngImage::new(2, 8, ColorType::Rgba).unwrap();
+        assert!(matches!(
+            tiny.split_nine_patch(),
+            Err(PngError::InvalidDimensions(2, 8))
+        ));
+    }
+
+    #[test]
+    fn nine_patch_keeps_corners_and_stretches_the_center() {
+        let (content, insets) = nine_patch_source(&[3, 4], &[2, 3])
+            .split_nine_patch()
+            .unwrap();
+        let patched = content
+            .nine_patch(12, 8, insets, ResizeFilter::Nearest)
+            .unwrap();
+        let pixel = |x: usize, y: usize| {
+            let start = (y * 12 + x) * 4;
+            &patched.data()[start..start + 2]
+        };
+        // Corners are copied as they are
+        assert_eq!(pixel(0, 0), &[0, 0]);
+        assert_eq!(pixel(1, 0), &[1, 0]);
+        assert_eq!(pixel(11, 7), &[5, 3]);
+        assert_eq!(pixel(10, 7), &[4, 3]);
+        // The two-pixel center columns cover the middle eight
+        for x in 2..10 {
+            assert!(pixel(x, 0) == [2, 0] || pixel(x, 0) == [3, 0]);
+            assert_eq!(pixel(x, 7)[1], 3);
+        }
+        for y in 1..7 {
+            assert!(pixel(0, y)[1] == 1 || pixel(0, y)[1] == 2);
+        }
+        assert!(matches!(
+            content.nine_patch(3, 8, insets, ResizeFilter::Nearest),
+            Err(PngError::InvalidDimensions(3, 8))
+        ));
+    }
+}
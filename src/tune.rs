@@ -0,0 +1,168 @@
+use std::io::Write;
+
+use crate::dictionary::ZlibWriter;
+use crate::error::PngError;
+use crate::filter::{FilterStrategy, FilterType, RowFilter};
+use crate::options::EncodeOptions;
+use crate::PngImage;
+
+/// A filter strategy and compression level [`PngImage::tune`] tries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TunePreset {
+    pub filter: FilterStrategy,
+    pub compression_level: u8,
+}
+
+/// Settings for [`PngImage::tune`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TuneOptions {
+    /// Presets to try. The one whose sample compresses smallest wins, and
+    /// ties go to the earlier one, so list cheaper presets first.
+    pub presets: Vec<TunePreset>,
+    /// Rows are sampled in this many bands spread evenly down the image
+    pub bands: usize,
+    /// Consecutive rows per band; images with no more rows than the bands
+    /// hold in total are tried whole
+    pub rows_per_band: usize,
+}
+
+impl TuneOptions {
+    /// Every filter strategy at `compression_level`.
+    pub fn filters_at(compression_level: u8) -> Vec<TunePreset> {
+        FilterType::ALL
+            .into_iter()
+            .map(FilterStrategy::Fixed)
+            .chain([FilterStrategy::Adaptive])
+            .map(|filter| TunePreset {
+                filter,
+                compression_level,
+            })
+            .collect()
+    }
+}
+
+impl Default for TuneOptions {
+    fn default() -> Self {
+        Self {
+            presets: Self::filters_at(6),
+            bands: 8,
+            rows_per_band: 16,
+        }
+    }
+}
+
+impl PngImage {
+    /// Picks encode settings by compressing a sample of rows under each of
+    /// `options.presets` and keeping the smallest, which usually lands close
+    /// to what encoding the whole image every way would, at a fraction of
+    /// the cost. Returns `base` with the winner's filter and compression
+    /// level, ready for [`PngImage::write_with_options`]; similar images
+    /// can reuse it.
+    pub fn tune(
+        &self,
+        base: &EncodeOptions,
+        options: &TuneOptions,
+    ) -> Result<EncodeOptions, PngError> {
+        self.check_complete()?;
+        let sample = self.sample_rows(options.bands, options.rows_per_band);
+        let row_length = self.packed_row_length();
+        // Filters operate on whole bytes, so sub-byte pixels use a distance of 1
+        let bytes_per_pixel = self.bytes_per_pixel().max(1);
+
+        // Presets often share a filter strategy, which only needs running once
+        let mut filtered: Vec<(FilterStrategy, Vec<u8>)> = Vec::new();
+        let mut best: Option<(usize, TunePreset)> = None;
+        for &preset in &options.presets {
+            let index = match filtered.iter().position(|(f, _)| *f == preset.filter) {
+                Some(index) => index,
+                None => {
+                    let rows = filter_sample(&sample, row_length, bytes_per_pixel, preset.filter);
+                    filtered.push((preset.filter, rows));
+                    filtered.len() - 1
+                }
+            };
+            let mut encoder = ZlibWriter::new(preset.compression_level, base.dictionary.as_ref())?;
+            encoder.write_all(&filtered[index].1)?;
+            let size = encoder.finish()?.len();
+            #[cfg(feature = "tracing")]
+            tracing::debug!(?preset, size, "sample compressed");
+            if best.is_none_or(|(smallest, _)| size < smallest) {
+                best = Some((size, preset));
+            }
+        }
+
+        let mut tuned = base.clone();
+        if let Some((_, preset)) = best {
+            tuned.filter = preset.filter;
+            tuned.compression_level = preset.compression_level;
+        }
+        Ok(tuned)
+    }
+
+    /// [`tune`](Self::tune)s with the default [`TuneOptions`], then encodes
+    /// the image with the result, which is returned for reuse.
+    pub fn write_tuned<W: Write>(
+        &self,
+        writer: &mut W,
+        base: &EncodeOptions,
+    ) -> Result<EncodeOptions, PngError> {
+        let tuned = self.tune(base, &TuneOptions::default())?;
+        self.write_with_options(writer, &tuned)?;
+        Ok(tuned)
+    }
+
+    // Packed rows in bands, each preceded by the row above it (if any) so
+    // that filters see the same neighbors they would in the full image
+    fn sample_rows(&self, bands: usize, rows_per_band: usize) -> Vec<Band> {
+        let rows = self.packed_rows();
+        let row_length = self.packed_row_length();
+        let height = self.height as usize;
+        let bands = bands.max(1);
+        let rows_per_band = rows_per_band.max(1);
+        if height <= bands.saturating_mul(rows_per_band) {
+            return vec![Band {
+                above: None,
+                rows: rows.into_owned(),
+            }];
+        }
+
+        (0..bands)
+            .map(|band| {
+                // Spread the bands so the first starts at the top and the
+                // last ends at the bottom
+                let start = band * (height - rows_per_band) / (bands - 1).max(1);
+                Band {
+                    above: start
+                        .checked_sub(1)
+                        .map(|above| rows[above * row_length..][..row_length].to_vec()),
+                    rows: rows[start * row_length..][..rows_per_band * row_length].to_vec(),
+                }
+            })
+            .collect()
+    }
+}
+
+struct Band {
+    above: Option<Vec<u8>>,
+    rows: Vec<u8>,
+}
+
+// The sampled bands' filtered scanlines, end to end
+fn filter_sample(
+    sample: &[Band],
+    row_length: usize,
+    bytes_per_pixel: usize,
+    strategy: FilterStrategy,
+) -> Vec<u8> {
+    let mut out = Vec::new();
+    for band in sample {
+        let mut filter = RowFilter::new(strategy, row_length, bytes_per_pixel);
+        if let Some(above) = &band.above {
+            filter.filter(above, &mut Vec::new());
+        }
+        for row in band.rows.chunks_exact(row_length) {
+            filter.filter(row, &mut out);
+        }
+    }
+    out
+}
@@ -0,0 +1,51 @@
+use crate::decoder::Decoder;
+use crate::error::PngError;
+use crate::options::EncodeOptions;
+use crate::pixel::Rgba16;
+use crate::PngImage;
+
+impl PngImage {
+    /// Encodes the image with `options`, decodes the result, and checks that
+    /// every pixel comes back unchanged, returning the verified encoding.
+    /// Fails with [`PngError::RoundTripMismatch`] if it doesn't, which would
+    /// be a bug in this crate.
+    ///
+    /// Ancillary chunks aren't compared, since encoding options can leave
+    /// some out.
+    pub fn round_trip_check(&self, options: &EncodeOptions) -> Result<Vec<u8>, PngError> {
+        let mut encoded = Vec::new();
+        self.write_with_options(&mut encoded, options)?;
+        self.verify_encoding(&encoded)?;
+        Ok(encoded)
+    }
+
+    // Checks that `encoded` decodes to the same pixels as the image. The
+    // format may differ, as after palette optimization or a lossless
+    // reduction, as long as every pixel has the same color.
+    pub(crate) fn verify_encoding(&self, encoded: &[u8]) -> Result<(), PngError> {
+        let decoded = Decoder::new(encoded)
+            .decode()
+            .map_err(|e| PngError::RoundTripMismatch(format!("output doesn't decode: {}", e)))?;
+        if (decoded.width, decoded.height) != (self.width, self.height) {
+            return Err(PngError::RoundTripMismatch(format!(
+                "decoded {}x{}, expected {}x{}",
+                decoded.width, decoded.height, self.width, self.height
+            )));
+        }
+
+        let same_format = decoded.color_type == self.color_type
+            && decoded.bit_depth == self.bit_depth
+            && decoded.palette == self.palette;
+        let matches = if same_format {
+            decoded.data[..] == self.data[..]
+        } else {
+            decoded.pixels::<Rgba16>()? == self.pixels::<Rgba16>()?
+        };
+        if !matches {
+            return Err(PngError::RoundTripMismatch(
+                "decoded pixels differ".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,161 @@
+use std::io::{ErrorKind, Read};
+
+use crate::apng::{Animation, Frame};
+use crate::error::PngError;
+use crate::{ColorType, PngImage};
+
+/// How the planes of a raw 4:2:0 video frame are laid out. Both start with
+/// a full-size Y plane, followed by chroma planes at half the width and
+/// height (rounded up).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum YuvLayout {
+    /// Separate U and V planes, as in ffmpeg's `yuv420p`
+    I420,
+    /// One plane of interleaved U and V samples, as hardware encoders and
+    /// capture APIs often produce
+    Nv12,
+}
+
+impl YuvLayout {
+    /// Bytes in one `width` x `height` frame.
+    pub fn frame_size(self, width: u32, height: u32) -> usize {
+        let (chroma_width, chroma_height) = chroma_size(width, height);
+        width as usize * height as usize + 2 * chroma_width * chroma_height
+    }
+}
+
+fn chroma_size(width: u32, height: u32) -> (usize, usize) {
+    (width.div_ceil(2) as usize, height.div_ceil(2) as usize)
+}
+
+impl PngImage {
+    /// Converts one raw 8-bit 4:2:0 video frame to an RGB image, taking the
+    /// samples as limited-range BT.601 as most SD video and webcams use.
+    pub fn from_yuv(
+        data: &[u8],
+        layout: YuvLayout,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, PngError> {
+        let mut image = PngImage::new(width, height, ColorType::Rgb)?;
+        let expected = layout.frame_size(width, height);
+        if data.len() != expected {
+            return Err(PngError::Import(format!(
+                "{:?} frame of {}x{} needs {} bytes, got {}",
+                layout,
+                width,
+                height,
+                expected,
+                data.len()
+            )));
+        }
+
+        let (width, height) = (width as usize, height as usize);
+        let (chroma_width, chroma_height) = chroma_size(width as u32, height as u32);
+        let (luma, chroma) = data.split_at(width * height);
+        let chroma_at = |x: usize, y: usize| -> (u8, u8) {
+            let (cx, cy) = (x / 2, y / 2);
+            match layout {
+                YuvLayout::I420 => {
+                    let plane = chroma_width * chroma_height;
+                    let i = cy * chroma_width + cx;
+                    (chroma[i], chroma[plane + i])
+                }
+                YuvLayout::Nv12 => {
+                    let i = (cy * chroma_width + cx) * 2;
+                    (chroma[i], chroma[i + 1])
+                }
+            }
+        };
+
+        let mut rgb = Vec::with_capacity(width * height * 3);
+        for y in 0..height {
+            for x in 0..width {
+                let (u, v) = chroma_at(x, y);
+                rgb.extend_from_slice(&yuv_to_rgb(luma[y * width + x], u, v));
+            }
+        }
+        image.data.replace(rgb)?;
+        Ok(image)
+    }
+}
+
+// Fixed-point BT.601 with Y in 16..=235 and chroma in 16..=240
+fn yuv_to_rgb(y: u8, u: u8, v: u8) -> [u8; 3] {
+    let c = 298 * (y as i32 - 16);
+    let d = u as i32 - 128;
+    let e = v as i32 - 128;
+    let clamp = |value: i32| ((value + 128) >> 8).clamp(0, 255) as u8;
+    [
+        clamp(c + 409 * e),
+        clamp(c - 100 * d - 208 * e),
+        clamp(c + 516 * d),
+    ]
+}
+
+impl Animation {
+    /// Reads raw 4:2:0 frames back to back from `reader` until it ends, as
+    /// `ffmpeg -f rawvideo` or a capture pipeline writes them, and turns
+    /// each into an RGB frame as [`PngImage::from_yuv`] does. `frame_rate`
+    /// is frames per second as a fraction, such as `(30000, 1001)` for NTSC
+    /// video, and sets every frame's delay exactly. A partial frame at the
+    /// end is an error.
+    pub fn from_yuv_frames<R: Read>(
+        mut reader: R,
+        layout: YuvLayout,
+        width: u32,
+        height: u32,
+        frame_rate: (u16, u16),
+    ) -> Result<Self, PngError> {
+        let (frames, seconds) = frame_rate;
+        if frames == 0 || seconds == 0 {
+            return Err(PngError::Import(format!(
+                "Invalid frame rate {}/{}",
+                frames, seconds
+            )));
+        }
+        let mut animation = Animation::new(width, height)?;
+        let mut buffer = vec![0; layout.frame_size(width, height)];
+
+        loop {
+            let filled = read_full(&mut reader, &mut buffer)?;
+            if filled == 0 {
+                break;
+            }
+            if filled < buffer.len() {
+                return Err(PngError::Import(format!(
+                    "Video ends partway through frame {}: {} of {} bytes",
+                    animation.frames().len() + 1,
+                    filled,
+                    buffer.len()
+                )));
+            }
+            let image = PngImage::from_yuv(&buffer, layout, width, height)?;
+            animation.add_frame(Frame {
+                // One frame lasts the reciprocal of the frame rate
+                delay_num: seconds,
+                delay_den: frames,
+                ..Frame::new(image, 0)
+            })?;
+        }
+
+        if animation.frames().is_empty() {
+            return Err(PngError::Import("Video has no frames".to_string()));
+        }
+        Ok(animation)
+    }
+}
+
+// Reads until `buffer` is full or the input ends, returning the bytes read
+fn read_full<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<usize, PngError> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(filled)
+}
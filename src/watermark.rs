@@ -0,0 +1,93 @@
+use crate::error::PngError;
+use crate::{BitDepth, ColorType, PngImage};
+
+/// Where [`PngImage::watermark`] puts the logo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatermarkPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
+    /// Repeated edge to edge from the top-left corner
+    Tile,
+}
+
+impl PngImage {
+    /// Composites `logo` over the image at `position`, with the logo's
+    /// alpha scaled by `opacity` (0.0 leaves the image unchanged, 1.0 uses
+    /// the logo's own alpha). The image becomes RGB, or RGBA if it had an
+    /// alpha channel or palette transparency, at 16 bits if it was 16-bit
+    /// and 8 bits otherwise. Parts of the logo outside the image are
+    /// clipped.
+    pub fn watermark(
+        &mut self,
+        logo: &PngImage,
+        position: WatermarkPosition,
+        opacity: f64,
+    ) -> Result<(), PngError> {
+        self.check_complete()?;
+        logo.check_complete()?;
+        let keep_alpha = match self.color_type {
+            ColorType::GrayscaleAlpha | ColorType::Rgba => true,
+            ColorType::Indexed => self
+                .palette()
+                .is_some_and(|palette| palette.iter().any(|&(_, _, _, a)| a < 255)),
+            ColorType::Grayscale | ColorType::Rgb => false,
+        };
+        if self.bit_depth.bits() < 8 {
+            self.convert_bit_depth(BitDepth::Eight)?;
+        }
+        self.convert_to(ColorType::Rgba)?;
+        let mut logo = logo.clone();
+        logo.convert_to(ColorType::Rgba)?;
+        logo.convert_bit_depth(self.bit_depth)?;
+
+        let (width, height) = (self.width as i64, self.height as i64);
+        let (logo_width, logo_height) = (logo.width as i64, logo.height as i64);
+        let origins: Vec<(i64, i64)> = match position {
+            WatermarkPosition::TopLeft => vec![(0, 0)],
+            WatermarkPosition::TopRight => vec![(width - logo_width, 0)],
+            WatermarkPosition::BottomLeft => vec![(0, height - logo_height)],
+            WatermarkPosition::BottomRight => vec![(width - logo_width, height - logo_height)],
+            WatermarkPosition::Center => {
+                vec![((width - logo_width) / 2, (height - logo_height) / 2)]
+            }
+            WatermarkPosition::Tile => (0..height)
+                .step_by(logo_height.max(1) as usize)
+                .flat_map(|y| {
+                    (0..width)
+                        .step_by(logo_width.max(1) as usize)
+                        .map(move |x| (x, y))
+                })
+                .collect(),
+        };
+
+        let opacity = opacity.clamp(0.0, 1.0);
+        let mut samples = self.samples();
+        let mark = logo.samples();
+        for (x0, y0) in origins {
+            for y in y0.max(0)..(y0 + logo_height).min(height) {
+                for x in x0.max(0)..(x0 + logo_width).min(width) {
+                    let src = &mark[(((y - y0) * logo_width + x - x0) * 4) as usize..][..4];
+                    let dst = &mut samples[((y * width + x) * 4) as usize..][..4];
+                    let src_alpha = src[3] * opacity;
+                    let out_alpha = src_alpha + dst[3] * (1.0 - src_alpha);
+                    if out_alpha > 0.0 {
+                        for c in 0..3 {
+                            dst[c] = (src[c] * src_alpha + dst[c] * dst[3] * (1.0 - src_alpha))
+                                / out_alpha;
+                        }
+                    }
+                    dst[3] = out_alpha;
+                }
+            }
+        }
+        self.store_samples(&samples)?;
+
+        if !keep_alpha {
+            self.convert_to(ColorType::Rgb)?;
+        }
+        Ok(())
+    }
+}
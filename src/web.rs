@@ -0,0 +1,295 @@
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use axum_core::response::IntoResponse;
+use bytes::Bytes;
+use http::header::CONTENT_TYPE;
+use http::{HeaderValue, Response};
+use http_body::{Body, Frame};
+
+use crate::error::PngError;
+use crate::options::EncodeOptions;
+use crate::{PngImage, StreamWriter};
+
+// Encoder output goes to the client in pieces of at most this many bytes,
+// with at most this many pieces waiting, so a slow client holds back the
+// encoder instead of the output piling up in memory
+const PIECE_SIZE: usize = 64 << 10;
+const QUEUE_DEPTH: usize = 4;
+
+/// An HTTP response body that encodes an image on a background thread a
+/// row at a time with a [`StreamWriter`], sending the output as it
+/// compresses, so that no more of the encoded file than a few pieces
+/// waiting for the client is held in memory. Options a `StreamWriter`
+/// refuses, such as strict mode, need the whole image at once; with those
+/// the image is encoded whole first, then streamed. It implements
+/// [`http_body::Body`] for hyper and similar servers, and axum's
+/// `IntoResponse` with `Content-Type: image/png`.
+///
+/// Encoding fails after the headers have gone out, so errors show up as a
+/// body error, which servers handle by cutting the connection short.
+/// Dropping the body, as servers do when the client goes away, stops the
+/// encoder.
+pub struct PngBody {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    // Signalled when the body takes a piece, or is dropped
+    taken: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    pieces: VecDeque<Bytes>,
+    finished: Option<Result<(), PngError>>,
+    dropped: bool,
+    waker: Option<Waker>,
+}
+
+impl PngBody {
+    /// Starts encoding `image` with `options`.
+    pub fn new(image: PngImage, options: EncodeOptions) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::default()),
+            taken: Condvar::new(),
+        });
+        let sender = Arc::clone(&shared);
+        thread::spawn(move || {
+            let mut writer = BodyWriter {
+                shared: &sender,
+                piece: Vec::with_capacity(PIECE_SIZE),
+            };
+            let result = encode(&image, &options, &mut writer)
+                .and_then(|()| writer.flush().map_err(PngError::from));
+            let mut state = sender.lock();
+            state.finished = Some(result);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        });
+        Self { shared }
+    }
+
+    /// The body in a `200 OK` response with `Content-Type: image/png`, for
+    /// servers that take an [`http::Response`].
+    pub fn into_http_response(self) -> Response<PngBody> {
+        let mut response = Response::new(self);
+        response
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static("image/png"));
+        response
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        // The state stays consistent even if a holder panicked
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Body for PngBody {
+    type Data = Bytes;
+    type Error = PngError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, PngError>>> {
+        let mut state = self.shared.lock();
+        if let Some(piece) = state.pieces.pop_front() {
+            self.shared.taken.notify_one();
+            return Poll::Ready(Some(Ok(Frame::data(piece))));
+        }
+        match state.finished.take() {
+            // An error is reported once, then the stream ends
+            Some(result) => {
+                state.finished = Some(Ok(()));
+                Poll::Ready(result.err().map(Err))
+            }
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        let state = self.shared.lock();
+        state.pieces.is_empty() && matches!(state.finished, Some(Ok(())))
+    }
+}
+
+impl Drop for PngBody {
+    fn drop(&mut self) {
+        self.shared.lock().dropped = true;
+        self.shared.taken.notify_one();
+    }
+}
+
+impl IntoResponse for PngBody {
+    fn into_response(self) -> axum_core::response::Response {
+        self.into_http_response().map(axum_core::body::Body::new)
+    }
+}
+
+impl IntoResponse for PngImage {
+    /// Streams the image encoded with the options it was built with, as
+    /// [`PngBody`] does.
+    fn into_response(self) -> axum_core::response::Response {
+        let options = self.options.clone();
+        PngBody::new(self, options).into_response()
+    }
+}
+
+fn encode(
+    image: &PngImage,
+    options: &EncodeOptions,
+    writer: &mut BodyWriter<'_>,
+) -> Result<(), PngError> {
+    let mut stream = match StreamWriter::new(&mut *writer, image, options) {
+        Ok(stream) => stream,
+        Err(PngError::UnsupportedOption(_)) => return image.write_with_options(writer, options),
+        Err(e) => return Err(e),
+    };
+    let row_length = image.width() as usize * image.bytes_per_pixel();
+    for row in image.data().chunks_exact(row_length) {
+        stream.write_row(row)?;
+    }
+    stream.finish()?;
+    Ok(())
+}
+
+// Collects encoder output into pieces and queues them for the body,
+// waiting while the queue is full
+struct BodyWriter<'a> {
+    shared: &'a Shared,
+    piece: Vec<u8>,
+}
+
+impl BodyWriter<'_> {
+    fn send(&mut self) -> io::Result<()> {
+        let piece = Bytes::from(std::mem::replace(
+            &mut self.piece,
+            Vec::with_capacity(PIECE_SIZE),
+        ));
+        let mut state = self.shared.lock();
+        while state.pieces.len() >= QUEUE_DEPTH && !state.dropped {
+            state = self
+                .shared
+                .taken
+                .wait(state)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+        if state.dropped {
+            return Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "response body dropped",
+            ));
+        }
+        state.pieces.push_back(piece);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        Ok(())
+    }
+}
+
+impl Write for BodyWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(PIECE_SIZE - self.piece.len());
+        self.piece.extend_from_slice(&buf[..len]);
+        if self.piece.len() == PIECE_SIZE {
+            self.send()?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.piece.is_empty() {
+            self.send()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Wake;
+    use std::thread::Thread;
+
+    use http::StatusCode;
+
+    use super::*;
+    use crate::decoder::Decoder;
+    use crate::test_images::noise;
+    use crate::{BitDepth, ColorType};
+
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    // Polls `body` to the end on this thread, as a server would, returning
+    // its data frames
+    fn collect<B: Body<Data = Bytes> + Unpin>(mut body: B) -> Result<Vec<Bytes>, B::Error> {
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut frames = Vec::new();
+        loop {
+            match Pin::new(&mut body).poll_frame(&mut cx) {
+                Poll::Ready(Some(Ok(frame))) => frames.extend(frame.into_data().ok()),
+                Poll::Ready(Some(Err(e))) => return Err(e),
+                Poll::Ready(None) => return Ok(frames),
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn streams_the_encoded_image_in_pieces() {
+        let image = noise(400, 300, ColorType::Rgba, BitDepth::Eight, 1);
+        let response = PngBody::new(image.clone(), EncodeOptions::default()).into_http_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], "image/png");
+
+        let pieces = collect(response.into_body()).unwrap();
+        assert!(pieces.len() > 1);
+        assert!(pieces.iter().all(|piece| piece.len() <= PIECE_SIZE));
+        let encoded = pieces.concat();
+        let decoded = Decoder::new(encoded.as_slice()).decode().unwrap();
+        assert_eq!(decoded.data(), image.data());
+    }
+
+    #[test]
+    fn encodes_whole_images_for_options_streams_refuse() {
+        let mut image = noise(64, 64, ColorType::Indexed, BitDepth::Eight, 2);
+        image.options.optimize_palette = true;
+        let response = image.clone().into_response();
+        assert_eq!(response.headers()[CONTENT_TYPE], "image/png");
+
+        let encoded = collect(response.into_body()).unwrap().concat();
+        let mut decoded = Decoder::new(encoded.as_slice()).decode().unwrap();
+        decoded.convert_to(ColorType::Rgb).unwrap();
+        image.convert_to(ColorType::Rgb).unwrap();
+        assert_eq!(decoded.data(), image.data());
+    }
+
+    #[test]
+    fn reports_encoding_errors_as_body_errors() {
+        // No pixels added, so the encoder runs out of rows
+        let image = PngImage::new(8, 8, ColorType::Rgb).unwrap();
+        let body = PngBody::new(image, EncodeOptions::default());
+        let error = collect(body).unwrap_err();
+        assert!(matches!(error, PngError::PixelCountMismatch { .. }));
+    }
+}